@@ -0,0 +1,337 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::{BTreeMap, HashSet};
+
+use opcode::{OpCode, OpCodeArgs, INSTR_SIZE};
+use variant::Variant;
+
+/// How many instruction states the analysis visits before giving up on a program,
+/// so one that's mostly data can't take forever
+const MAX_VISITED: usize = 0x10000;
+
+/// Some memory that a DRW draws as a sprite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteRef {
+    /// where the sprite starts
+    pub addr: usize,
+    /// how many pixels wide it's drawn, 8, or 16 if any DRW draws it as a 16x16 sprite
+    pub width: usize,
+    /// how many rows tall it's drawn, the most of any DRW that draws it
+    pub height: usize,
+    /// the addresses of the DRW instructions that draw it, lowest first
+    pub drawn_at: Vec<usize>,
+}
+
+impl SpriteRef {
+    /// How many bytes of memory the sprite takes up
+    pub fn length(&self) -> usize {
+        self.height * self.width / 8
+    }
+}
+
+/// Finds the sprites a program draws by following every path through its code
+/// from the start address, without running it. Along each path the analysis keeps
+/// track of where LD I, addr last pointed I, and notes the memory there whenever
+/// a DRW comes along. Paths stop where they can't be followed: at RET, JP V0,
+/// anything that isn't an instruction, and the end of memory. Wherever I was moved
+/// by anything but LD I, addr, the analysis loses track of it until the next one.
+///
+/// Subroutines are assumed to return, and both sides of every skip are taken, so
+/// the sprites found may include some the program never actually gets round to
+/// drawing, and will miss any drawn from computed addresses. On variants with
+/// 16x16 sprites, a DRW with a height of 0 draws one, 32 bytes long.
+pub fn find_sprites(memory: &[u8], start: usize, variant: Variant) -> Vec<SpriteRef> {
+    let mut sprites: BTreeMap<usize, SpriteRef> = BTreeMap::new();
+
+    visit_paths(memory, start, variant, |pc, instruction, i_register| {
+        let (width, height) = match (instruction & 0x000F) as usize {
+            0 if variant.has_big_sprites() => (16, 16),
+            n => (8, n),
+        };
+        if let (0xD000, IValue::At(addr), true) = (instruction & 0xF000, i_register, height > 0) {
+            let sprite = sprites.entry(addr).or_insert_with(|| SpriteRef { addr: addr, width: 8, height: 0, drawn_at: Vec::new() });
+            sprite.width = sprite.width.max(width);
+            sprite.height = sprite.height.max(height);
+            if !sprite.drawn_at.contains(&pc) {
+                sprite.drawn_at.push(pc);
+            }
+        }
+    });
+
+    sprites.into_values().map(|mut sprite| {
+        sprite.drawn_at.sort();
+        sprite
+    }).collect()
+}
+
+/// What the analysis makes of some bytes of a program
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ByteUse {
+    /// instructions on some path through the program
+    Code,
+    /// drawn as a sprite, and not on any path as instructions
+    SpriteData,
+    /// both on a path as instructions and drawn as a sprite
+    CodeAndSprite,
+    /// on no path and never drawn, so either dead or only reached in ways the analysis can't follow
+    Unreached,
+}
+
+/// A run of bytes the analysis makes the same of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    /// the address just past the region
+    pub end: usize,
+    pub kind: ByteUse,
+}
+
+/// Sorts every byte of a program, from where it's loaded to the end of memory
+/// given, into code, sprite data, both, or neither, following its paths as
+/// find_sprites does. Code reached only through JP V0 or computed returns shows
+/// up as unreached, so it's worth a look before it's cut out.
+pub fn classify(memory: &[u8], load_addr: usize, start: usize, variant: Variant) -> Vec<Region> {
+    let mut code = HashSet::new();
+    visit_paths(memory, start, variant, |pc, instruction, _| code.extend(pc..pc + instruction_size(instruction, variant)));
+
+    let mut sprite_bytes = HashSet::new();
+    for sprite in find_sprites(memory, start, variant) {
+        sprite_bytes.extend(sprite.addr..sprite.addr + sprite.length());
+    }
+
+    let mut regions: Vec<Region> = Vec::new();
+    for addr in load_addr..memory.len() {
+        let kind = match (code.contains(&addr), sprite_bytes.contains(&addr)) {
+            (true, false) => ByteUse::Code,
+            (false, true) => ByteUse::SpriteData,
+            (true, true) => ByteUse::CodeAndSprite,
+            (false, false) => ByteUse::Unreached,
+        };
+
+        match regions.last_mut() {
+            Some(r) if r.kind == kind => r.end = addr + 1,
+            _ => regions.push(Region { start: addr, end: addr + 1, kind: kind }),
+        }
+    }
+
+    regions
+}
+
+/// What's known about I at some point on a path through a program
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IValue {
+    /// nothing on the path has set it yet
+    Unset,
+    /// LD I, addr last set it to this
+    At(usize),
+    /// something moved it somewhere the analysis can't know
+    Unknown,
+}
+
+/// A CALL in a program
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// where the CALL is
+    pub site: usize,
+    /// the subroutine it calls
+    pub target: usize,
+}
+
+/// Follows every path through a program from the start address, as described
+/// for find_sprites, calling back with the address of every instruction on one,
+/// the instruction, and what's known about I when it runs. An instruction is
+/// visited once for each different thing known about I there.
+pub fn visit_paths<F: FnMut(usize, u16, IValue)>(memory: &[u8], start: usize, variant: Variant, mut visit: F) {
+    let mut visited = HashSet::new();
+    let mut pending = vec![(start, IValue::Unset)];
+
+    while let Some((pc, i_register)) = pending.pop() {
+        if visited.len() >= MAX_VISITED || !visited.insert((pc, i_register)) {
+            continue;
+        }
+
+        let instruction = match fetch(memory, pc, variant) {
+            Some(i) => i,
+            None => continue,
+        };
+        visit(pc, instruction, i_register);
+
+        let args = OpCodeArgs::from_u16(instruction);
+        let i_register = match instruction & 0xF000 {
+            0xA000 => IValue::At(args.nnn),
+            // ADD I, Vx, LD F, Vx and LD HF, Vx move I somewhere we can't know, and so does
+            // Mega-Chip's LDHI I with its 24-bit address
+            0xF000 if args.kk == 0x1E || args.kk == 0x29 || args.kk == 0x30 => IValue::Unknown,
+            0x0000 if is_ldhi(instruction, variant) => IValue::Unknown,
+            _ => i_register,
+        };
+
+        let (next, call) = successors(pc, instruction, variant);
+        pending.extend(call.map(|target| (target, i_register)));
+        pending.extend(next.into_iter().map(|addr| (addr, i_register)));
+    }
+}
+
+/// The instructions that go to an address, found along every path through a
+/// program as visit_paths follows them
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XRefs {
+    /// the addresses of the CALLs to it, lowest first
+    pub called_from: Vec<usize>,
+    /// the addresses of the JPs to it, lowest first
+    pub jumped_to_from: Vec<usize>,
+}
+
+/// The cross-references in a program, keyed by the address gone to. Only the
+/// CALLs and JPs the program can reach count, and JP V0 can't be followed, so it
+/// never does.
+pub fn xrefs(memory: &[u8], start: usize, variant: Variant) -> BTreeMap<usize, XRefs> {
+    let mut xrefs: BTreeMap<usize, XRefs> = BTreeMap::new();
+    let mut seen = HashSet::new();
+
+    visit_paths(memory, start, variant, |pc, instruction, _| {
+        if !seen.insert(pc) {
+            return;
+        }
+
+        let target = (instruction & 0x0FFF) as usize;
+        match instruction & 0xF000 {
+            0x1000 => xrefs.entry(target).or_default().jumped_to_from.push(pc),
+            0x2000 => xrefs.entry(target).or_default().called_from.push(pc),
+            _ => (),
+        }
+    });
+
+    for x in xrefs.values_mut() {
+        x.called_from.sort();
+        x.jumped_to_from.sort();
+    }
+
+    xrefs
+}
+
+/// The calls each subroutine makes, keyed by the subroutine's address, found by
+/// following every path through it. The start address counts as a subroutine, so
+/// every subroutine the program can reach by CALL is in here.
+pub fn call_graph(memory: &[u8], start: usize, variant: Variant) -> BTreeMap<usize, Vec<Call>> {
+    let mut graph = BTreeMap::new();
+    let mut pending = vec![start];
+
+    while let Some(entry) = pending.pop() {
+        if graph.contains_key(&entry) {
+            continue;
+        }
+
+        let mut calls = Vec::new();
+        let mut visited = HashSet::new();
+        let mut paths = vec![entry];
+        while let Some(pc) = paths.pop() {
+            if visited.len() >= MAX_VISITED || !visited.insert(pc) {
+                continue;
+            }
+
+            let instruction = match fetch(memory, pc, variant) {
+                Some(i) => i,
+                None => continue,
+            };
+
+            let (next, call) = successors(pc, instruction, variant);
+            if let Some(target) = call {
+                calls.push(Call { site: pc, target: target });
+                pending.push(target);
+            }
+            paths.extend(next);
+        }
+
+        calls.sort_by_key(|c| c.site);
+        graph.insert(entry, calls);
+    }
+
+    graph
+}
+
+/// The instruction at an address, if there's one there the variant knows
+fn fetch(memory: &[u8], pc: usize, variant: Variant) -> Option<u16> {
+    if pc + 1 >= memory.len() {
+        return None;
+    }
+
+    let instruction = ((memory[pc] as u16) << 8) | (memory[pc + 1] as u16);
+    OpCode::from_u16(instruction, variant).map(|_| instruction)
+}
+
+/// Whether an instruction is Mega-Chip's LDHI I, which is followed by 16 more bits of its address
+fn is_ldhi(instruction: u16, variant: Variant) -> bool {
+    variant == Variant::MegaChip && instruction & 0xFF00 == 0x0100
+}
+
+/// How many bytes an instruction takes up
+fn instruction_size(instruction: u16, variant: Variant) -> usize {
+    if is_ldhi(instruction, variant) {
+        INSTR_SIZE * 2
+    } else {
+        INSTR_SIZE
+    }
+}
+
+/// Where a path can go after the instruction at pc without leaving the subroutine,
+/// and the subroutine the instruction calls, if it's a CALL. Paths end at RET and
+/// JP V0, the one because it's the caller's business where to go next and the
+/// other because where it goes can't be known.
+fn successors(pc: usize, instruction: u16, variant: Variant) -> (Vec<usize>, Option<usize>) {
+    let next = pc + instruction_size(instruction, variant);
+    let nnn = (instruction & 0x0FFF) as usize;
+
+    match instruction & 0xF000 {
+        0x0000 if instruction == 0x00EE => (Vec::new(), None),
+        0x1000 => (vec![nnn], None),
+        0x2000 => (vec![next], Some(nnn)),
+        0x3000 | 0x4000 | 0x5000 | 0x9000 | 0xE000 => (vec![next, next + INSTR_SIZE], None),
+        0xB000 => (Vec::new(), None),
+        _ => (vec![next], None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Memory for the variant holding a program that points I just past itself,
+    /// draws with the given height, and then loops forever
+    fn program(variant: Variant, height: u16) -> (Vec<u8>, usize) {
+        let start = variant.start_addr();
+        let sprite_addr = start + 6;
+        let code = [0xA000 | sprite_addr as u16, 0xD010 | height, 0x1000 | (start + 4) as u16];
+
+        let mut memory = vec![0; variant.memory_length()];
+        for (n, word) in code.iter().enumerate() {
+            memory[start + n * 2] = (word >> 8) as u8;
+            memory[start + n * 2 + 1] = *word as u8;
+        }
+        (memory, sprite_addr)
+    }
+
+    #[test]
+    fn finds_a_sprite_with_the_height_drawn() {
+        let (memory, addr) = program(Variant::Chip8, 5);
+        let sprites = find_sprites(&memory, Variant::Chip8.start_addr(), Variant::Chip8);
+        assert_eq!(sprites, vec![SpriteRef { addr: addr, width: 8, height: 5, drawn_at: vec![addr - 4] }]);
+    }
+
+    #[test]
+    fn height_0_is_a_big_sprite_only_where_drw_draws_one() {
+        let (memory, _) = program(Variant::Chip8, 0);
+        assert!(find_sprites(&memory, Variant::Chip8.start_addr(), Variant::Chip8).is_empty());
+
+        let variant = Variant::HiresChip8;
+        let (memory, addr) = program(variant, 0);
+        let sprites = find_sprites(&memory, variant.start_addr(), variant);
+        assert_eq!(sprites, vec![SpriteRef { addr: addr, width: 16, height: 16, drawn_at: vec![addr - 4] }]);
+        assert_eq!(sprites[0].length(), 32);
+
+        let regions = classify(&memory, variant.load_addr(), variant.start_addr(), variant);
+        assert!(regions.contains(&Region { start: addr, end: addr + 32, kind: ByteUse::SpriteData }));
+    }
+}
@@ -0,0 +1,205 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{self, Value};
+use ureq;
+
+use font::Font;
+use romconfig::RomConfig;
+use variant::Variant;
+
+/// Where the CHIP-8 Archive lists its programs
+pub const INDEX_URL: &str = "https://johnearnest.github.io/chip8Archive/programs.json";
+/// Where the CHIP-8 Archive keeps its programs, each at <key>.ch8
+pub const ROMS_URL: &str = "https://johnearnest.github.io/chip8Archive/roms/";
+/// The largest program that will be downloaded, which is all of XO-CHIP memory
+const MAX_PROGRAM_LENGTH: u64 = 0x10000;
+/// How many frames run each second, which the archive's tick rates are counted in
+const FRAMES_PER_SECOND: u32 = 60;
+
+/// Reasons the archive couldn't be read or a program couldn't be downloaded
+#[derive(Debug)]
+pub enum ArchiveError {
+    IoError(io::Error),
+    /// the request to the archive failed
+    Http(String),
+    /// the list of programs isn't in the form it should be
+    BadIndex(String),
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(err: io::Error) -> Self {
+        ArchiveError::IoError(err)
+    }
+}
+
+impl From<ureq::Error> for ArchiveError {
+    fn from(err: ureq::Error) -> Self {
+        ArchiveError::Http(err.to_string())
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            ArchiveError::Http(ref message) => write!(f, "couldn't reach the archive: {}", message),
+            ArchiveError::BadIndex(ref message) => write!(f, "couldn't read the archive's list of programs: {}", message),
+        }
+    }
+}
+
+/// A program listed in the CHIP-8 Archive, with the settings it's meant to be played with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveProgram {
+    /// the name the archive files the program under
+    pub key: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    /// when the program was released, if the archive says
+    pub release: Option<String>,
+    /// the machine the program was written for, like chip8, schip or xochip
+    pub platform: String,
+    /// how many instructions the program is meant to run each frame, if the archive says
+    pub tickrate: Option<u32>,
+    /// the font the program is meant to be played with, if the archive says
+    pub font_style: Option<String>,
+}
+
+impl ArchiveProgram {
+    /// Reads a program's entry in the archive's list
+    fn from_json(key: &str, entry: &Value) -> Result<ArchiveProgram, ArchiveError> {
+        let text = |field: &str| entry.get(field).and_then(Value::as_str).map(|s| s.to_owned());
+        let options = entry.get("options");
+
+        // the key names the downloaded file, so it mustn't lead anywhere else
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(ArchiveError::BadIndex(format!("{} isn't a name a program can be saved under", key)));
+        }
+
+        let title = match text("title") {
+            Some(t) => t,
+            None => return Err(ArchiveError::BadIndex(format!("{} has no title", key))),
+        };
+
+        let authors = match entry.get("authors").and_then(Value::as_array) {
+            Some(a) => a.iter().filter_map(Value::as_str).map(|s| s.to_owned()).collect(),
+            None => Vec::new(),
+        };
+
+        Ok(ArchiveProgram {
+            key: key.to_owned(),
+            title: title,
+            authors: authors,
+            release: text("release"),
+            platform: text("platform").unwrap_or_else(|| "chip8".to_owned()),
+            tickrate: options.and_then(|o| o.get("tickrate")).and_then(Value::as_u64).map(|t| t as u32),
+            font_style: options.and_then(|o| o.get("fontStyle")).and_then(Value::as_str).map(|s| s.to_owned()),
+        })
+    }
+
+    /// The variant that runs the program, if one here can
+    pub fn variant(&self) -> Option<Variant> {
+        match self.platform.as_str() {
+            "chip8" => Some(Variant::Chip8),
+            "xochip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The settings the archive recommends for the program, as a config to keep next to it
+    pub fn rom_config(&self) -> RomConfig {
+        let mut config = RomConfig::new();
+
+        config.variant = self.variant();
+        config.speed = self.tickrate.map(|t| t * FRAMES_PER_SECOND);
+        // only fonts we have are worth recording
+        config.font = self.font_style.clone().filter(|f| Font::from_name(f).is_some());
+        config.title = Some(self.title.clone());
+        if !self.authors.is_empty() {
+            config.author = Some(self.authors.join(", "));
+        }
+        config.release = self.release.clone();
+
+        config
+    }
+
+    /// Downloads the program into the given directory, along with a config holding its
+    /// recommended settings, unless it's already there. Returns the path of the program.
+    pub fn download(&self, cache_dir: &Path) -> Result<PathBuf, ArchiveError> {
+        let path = cache_dir.join(format!("{}.ch8", self.key));
+        if path.exists() {
+            return Ok(path);
+        }
+
+        fs::create_dir_all(cache_dir)?;
+
+        let mut program = Vec::new();
+        ureq::get(&format!("{}{}.ch8", ROMS_URL, self.key)).call()?
+            .into_reader()
+            .take(MAX_PROGRAM_LENGTH)
+            .read_to_end(&mut program)?;
+
+        if let Err(e) = self.rom_config().save_file(&RomConfig::path_for(&path.to_string_lossy())) {
+            warn!(target: "config", "Couldn't save the program's settings. Error message: {}", e);
+        }
+
+        // written last, so a failed download is tried again next time
+        File::create(&path)?.write_all(&program)?;
+
+        Ok(path)
+    }
+}
+
+impl fmt::Display for ArchiveProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title)?;
+        if !self.authors.is_empty() {
+            write!(f, " by {}", self.authors.join(", "))?;
+        }
+        if let Some(ref release) = self.release {
+            write!(f, " ({})", release)?;
+        }
+        write!(f, " [{}]", self.platform)
+    }
+}
+
+/// Fetches the list of programs in the archive, sorted by title
+pub fn fetch_index() -> Result<Vec<ArchiveProgram>, ArchiveError> {
+    let body = ureq::get(INDEX_URL).call()?.into_string()?;
+
+    let index: Value = match serde_json::from_str(&body) {
+        Ok(v) => v,
+        Err(e) => return Err(ArchiveError::BadIndex(e.to_string())),
+    };
+
+    let entries = match index.as_object() {
+        Some(e) => e,
+        None => return Err(ArchiveError::BadIndex("expected an object of programs".to_owned())),
+    };
+
+    let mut programs = entries.iter()
+        .map(|(key, entry)| ArchiveProgram::from_json(key, entry))
+        .collect::<Result<Vec<_>, _>>()?;
+    programs.sort_by_key(|p| p.title.to_lowercase());
+
+    Ok(programs)
+}
+
+/// The directory downloaded programs are kept in
+pub fn cache_dir() -> PathBuf {
+    let base = match (env::var_os("XDG_CACHE_HOME"), env::var_os("HOME")) {
+        (Some(cache), _) => PathBuf::from(cache),
+        (None, Some(home)) => Path::new(&home).join(".cache"),
+        (None, None) => env::temp_dir(),
+    };
+
+    base.join("chip8_this_time_in_rust").join("archive")
+}
@@ -0,0 +1,840 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use linemap::LineMap;
+use symbols::SymbolTable;
+
+/// How deep macros may call other macros before we decide one calls itself forever
+const MAX_MACRO_DEPTH: usize = 16;
+/// How deep constants may be defined in terms of other constants before we decide one is defined in terms of itself
+const MAX_CONSTANT_DEPTH: usize = 64;
+
+/// A problem with a program that stops it being assembled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    /// the source line the problem is on, counting from 1
+    pub line: usize,
+    /// the column the offending token starts at, counting from 1
+    pub column: usize,
+    /// the offending token, or nothing if it's the line as a whole
+    pub token: String,
+    pub message: String,
+    /// what was probably meant, if the token is a near miss for something the assembler knows
+    pub suggestion: Option<String>,
+}
+
+impl AssembleError {
+    /// The source line the problem is on, with the offending token underlined:
+    ///
+    /// ```text
+    ///     LOD V0, 5
+    ///     ^^^
+    /// ```
+    pub fn excerpt(&self, source: &str) -> String {
+        let line = match self.line.checked_sub(1).and_then(|i| source.lines().nth(i)) {
+            Some(l) => l,
+            None => return String::new(),
+        };
+
+        // keep any tabs, so the underline lines up however wide they're shown
+        let indent: String = line.chars().take(self.column - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        format!("    {}\n    {}{}", line, indent, "^".repeat(self.token.chars().count().max(1)))
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)?;
+        match self.suggestion {
+            Some(ref s) => write!(f, " (did you mean {}?)", s),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Error for AssembleError {}
+
+/// A macro's parameters and the lines it expands to
+struct Macro {
+    params: Vec<String>,
+    /// the body's lines, with the source line each is on
+    body: Vec<(usize, String)>,
+}
+
+/// An instruction or directive, after macros are expanded
+struct Statement {
+    line: usize,
+    /// the address it's assembled to
+    addr: usize,
+    mnemonic: String,
+    operands: Vec<String>,
+}
+
+/// Everything the source defines, and the statements to encode
+struct Program {
+    macros: HashMap<String, Macro>,
+    labels: HashMap<String, usize>,
+    /// each constant's expression and the line it's defined on
+    constants: HashMap<String, (String, usize)>,
+    statements: Vec<Statement>,
+    /// the address the next statement goes at
+    addr: usize,
+    /// the source, line by line, to find the columns of tokens in
+    source: Vec<String>,
+}
+
+/// A program assembled, with where its parts came from
+pub struct Assembled {
+    pub bytes: Vec<u8>,
+    /// the labels, with the addresses they name
+    pub symbols: SymbolTable,
+    /// the source line each instruction and piece of data was assembled from
+    pub line_map: LineMap,
+}
+
+/// Assembles a program written with the same mnemonics the disassembler shows,
+/// to be loaded at the given address. Besides instructions, the source can have:
+///
+/// ```text
+/// ; comments, to the end of the line
+/// loop:                         a label, naming the address of what follows
+/// SPRITE_H = 5                  a constant, which can use labels and other constants
+/// LD V0, SPRITE_H*2             expressions with + - * / & | << >>, unary - and brackets
+/// DB 3C, %01111110, SPRITE_H    bytes, in hex, or in binary after %
+/// DW loop                       16-bit words, high byte first
+/// MACRO move x, dx              a macro, whose parameters are replaced wherever
+///   ADD x, dx                   they appear in its body
+/// ENDM
+/// move V1, 2                    and a use of it
+/// ```
+///
+/// Numbers are in hex, with or without 0x, as everywhere else in the emulator.
+/// Names that are also hex numbers, like "beef", mean the label or constant when
+/// there is one.
+///
+/// Rather than stopping at the first problem, every line is looked at, so all the
+/// problems found come back together, in the order they're in the source.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, Vec<AssembleError>> {
+    assemble_with_symbols(source, origin).map(|a| a.bytes)
+}
+
+/// Assembles a program as assemble does, keeping its labels and which line
+/// everything came from too, for debugging it by name and by source line
+pub fn assemble_with_symbols(source: &str, origin: usize) -> Result<Assembled, Vec<AssembleError>> {
+    let mut program = Program {
+        macros: HashMap::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        statements: Vec::new(),
+        addr: origin,
+        source: source.lines().map(|l| l.to_owned()).collect(),
+    };
+    let mut errors = Vec::new();
+
+    let lines: Vec<(usize, String)> = source.lines().enumerate()
+        .map(|(i, l)| (i + 1, strip_comment(l).trim().to_owned()))
+        .collect();
+
+    let mut i = 0;
+    while i < lines.len() {
+        let (number, ref line) = lines[i];
+        i += 1;
+
+        let mut words = line.split_whitespace();
+        if !words.next().is_some_and(|w| w.eq_ignore_ascii_case("MACRO")) {
+            if let Err(e) = program.read_line(number, line, 0) {
+                errors.push(e);
+            }
+            continue;
+        }
+
+        // a macro definition runs up to its ENDM
+        let header = line[5..].trim();
+        let (name, params) = match header.find(char::is_whitespace) {
+            Some(n) => (&header[..n], split_operands(&header[n..])),
+            None => (header, Vec::new()),
+        };
+        if !is_identifier(name) {
+            errors.push(program.error(number, name, "expected a name for the macro".to_owned(), None));
+        }
+
+        let mut body = Vec::new();
+        loop {
+            i += 1;
+            match lines.get(i - 1) {
+                Some((_, l)) if l.eq_ignore_ascii_case("ENDM") => break,
+                Some(&(n, ref l)) => body.push((n, l.clone())),
+                None => {
+                    errors.push(program.error(number, "MACRO", format!("the macro {} has no ENDM", name), None));
+                    break;
+                },
+            }
+        }
+
+        program.macros.insert(name.to_lowercase(), Macro { params: params, body: body });
+    }
+
+    let mut bytes = Vec::new();
+    for statement in program.statements.iter() {
+        if let Err(e) = program.encode(statement, &mut bytes) {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        let mut symbols = SymbolTable::new();
+        let mut labels: Vec<(&String, &usize)> = program.labels.iter().collect();
+        labels.sort_by_key(|&(name, &addr)| (addr, name));
+        for (name, &addr) in labels {
+            symbols.insert(name, addr);
+        }
+
+        let mut line_map = LineMap::new();
+        for statement in program.statements.iter() {
+            line_map.insert(statement.addr, statement.line);
+        }
+
+        Ok(Assembled { bytes: bytes, symbols: symbols, line_map: line_map })
+    } else {
+        errors.sort_by_key(|e| (e.line, e.column));
+        Err(errors)
+    }
+}
+
+impl Program {
+    /// Reads one line of source: a label, then a constant, a macro use or a statement
+    fn read_line(&mut self, number: usize, line: &str, depth: usize) -> Result<(), AssembleError> {
+        let mut line = line;
+
+        if let Some(colon) = line.find(':') {
+            let label = line[..colon].trim();
+            if is_identifier(label) {
+                if self.labels.contains_key(label) || self.constants.contains_key(label) {
+                    return Err(self.error(number, label, format!("{} is already defined", label), None));
+                }
+                self.labels.insert(label.to_owned(), self.addr);
+                line = line[colon + 1..].trim();
+            }
+        }
+
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(equals) = line.find('=') {
+            let name = line[..equals].trim();
+            if is_identifier(name) {
+                if self.labels.contains_key(name) || self.constants.contains_key(name) {
+                    return Err(self.error(number, name, format!("{} is already defined", name), None));
+                }
+                self.constants.insert(name.to_owned(), (line[equals + 1..].trim().to_owned(), number));
+                return Ok(());
+            }
+        }
+
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(n) => (&line[..n], &line[n..]),
+            None => (line, ""),
+        };
+        let operands = split_operands(rest);
+
+        let expansion = match self.macros.get(&mnemonic.to_lowercase()) {
+            Some(m) if m.params.len() != operands.len() => {
+                return Err(self.error(number, mnemonic, format!("the macro {} takes {} argument(s) but was given {}", mnemonic, m.params.len(), operands.len()), None));
+            },
+            Some(m) => Some(m.body.iter().map(|&(n, ref l)| (n, substitute(l, &m.params, &operands))).collect::<Vec<_>>()),
+            None => None,
+        };
+
+        if let Some(expansion) = expansion {
+            if depth >= MAX_MACRO_DEPTH {
+                return Err(self.error(number, mnemonic, format!("the macro {} is used inside itself too deeply", mnemonic), None));
+            }
+            for (n, l) in expansion {
+                self.read_line(n, &l, depth + 1)?;
+            }
+            return Ok(());
+        }
+
+        let mnemonic = mnemonic.to_uppercase();
+        let addr = self.addr;
+        self.addr += match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            "LDHI" => 4,
+            _ => 2,
+        };
+        self.statements.push(Statement { line: number, addr: addr, mnemonic: mnemonic, operands: operands });
+
+        Ok(())
+    }
+
+    /// Appends the bytes a statement assembles to
+    fn encode(&self, statement: &Statement, bytes: &mut Vec<u8>) -> Result<(), AssembleError> {
+        let line = statement.line;
+        let error = |token: &str, message: String| self.error(line, token, message, None);
+        let ops: Vec<&str> = statement.operands.iter().map(|o| o.as_str()).collect();
+
+        let value = |expr: &str, max: i64| -> Result<u16, AssembleError> {
+            let v = self.eval(expr, line, 0)?;
+            // negative bytes wrap round, so ADD V0, -1 subtracts one
+            if max == 0xFF && (-0x80..0).contains(&v) {
+                return Ok((v & 0xFF) as u16);
+            }
+            if v < 0 || v > max {
+                return Err(error(expr, format!("{} is {:X}, which doesn't fit between 0 and {:X}", expr, v, max)));
+            }
+            Ok(v as u16)
+        };
+        let addr = |expr: &str| value(expr, 0xFFF);
+        let byte = |expr: &str| value(expr, 0xFF);
+        let nibble = |expr: &str| value(expr, 0xF);
+        let is = |op: &str, name: &str| op.eq_ignore_ascii_case(name);
+
+        let instruction = match (statement.mnemonic.as_str(), ops.as_slice()) {
+            ("DB", _) => {
+                for op in ops.iter() {
+                    bytes.push(byte(op)? as u8);
+                }
+                return Ok(());
+            },
+            ("DW", _) => {
+                for op in ops.iter() {
+                    let word = value(op, 0xFFFF)?;
+                    bytes.push((word >> 8) as u8);
+                    bytes.push(word as u8);
+                }
+                return Ok(());
+            },
+            ("LDHI", &[i, expr]) if is(i, "I") => {
+                let a = self.eval(expr, line, 0)?;
+                if !(0..=0xFF_FFFF).contains(&a) {
+                    return Err(error(expr, format!("{} is {:X}, which doesn't fit in 24 bits", expr, a)));
+                }
+                bytes.extend_from_slice(&[0x01, (a >> 16) as u8, (a >> 8) as u8, a as u8]);
+                return Ok(());
+            },
+
+            ("CLS", &[]) => 0x00E0,
+            ("RET", &[]) => 0x00EE,
+            ("SCR", &[]) => 0x00FB,
+            ("SCL", &[]) => 0x00FC,
+            ("MEGAOFF", &[]) => 0x0010,
+            ("MEGAON", &[]) => 0x0011,
+            ("STOPSND", &[]) => 0x0700,
+            ("AUDIO", &[]) => 0xF002,
+            ("SYS", &[a]) => addr(a)?,
+            ("JP", &[v0, a]) if is(v0, "V0") => 0xB000 | addr(a)?,
+            ("JP", &[a]) => 0x1000 | addr(a)?,
+            ("CALL", &[a]) => 0x2000 | addr(a)?,
+            ("SCU", &[n]) => 0x00B0 | nibble(n)?,
+            ("SCD", &[n]) => 0x00C0 | nibble(n)?,
+            ("LDPAL", &[b]) => 0x0200 | byte(b)?,
+            ("SPRW", &[b]) => 0x0300 | byte(b)?,
+            ("SPRH", &[b]) => 0x0400 | byte(b)?,
+            ("ALPHA", &[b]) => 0x0500 | byte(b)?,
+            ("DIGISND", &[n]) => 0x0600 | nibble(n)?,
+            ("BMODE", &[n]) => 0x0800 | nibble(n)?,
+            ("CCOL", &[b]) => 0x0900 | byte(b)?,
+
+            ("SE", &[x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => 0x5000 | x << 8 | y << 4,
+                (Some(x), None) => 0x3000 | x << 8 | byte(y)?,
+                _ => return Err(self.register_error(x, line, &[])),
+            },
+            ("SNE", &[x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => 0x9000 | x << 8 | y << 4,
+                (Some(x), None) => 0x4000 | x << 8 | byte(y)?,
+                _ => return Err(self.register_error(x, line, &[])),
+            },
+            ("ADD", &[i, x]) if is(i, "I") => 0xF01E | self.register(x, line)? << 8,
+            ("ADD", &[x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => 0x8004 | x << 8 | y << 4,
+                (Some(x), None) => 0x7000 | x << 8 | byte(y)?,
+                _ => return Err(self.register_error(x, line, &["I"])),
+            },
+
+            ("LD", &[i, a]) if is(i, "I") => 0xA000 | addr(a)?,
+            ("LD", &[dt, x]) if is(dt, "DT") => 0xF015 | self.register(x, line)? << 8,
+            ("LD", &[st, x]) if is(st, "ST") => 0xF018 | self.register(x, line)? << 8,
+            ("LD", &[f, x]) if is(f, "F") => 0xF029 | self.register(x, line)? << 8,
+            ("LD", &[hf, x]) if is(hf, "HF") => 0xF030 | self.register(x, line)? << 8,
+            ("LD", &[b, x]) if is(b, "B") => 0xF033 | self.register(x, line)? << 8,
+            ("LD", &[i, x]) if is(i, "[I]") => 0xF055 | self.register(x, line)? << 8,
+            ("LD", &[x, dt]) if is(dt, "DT") => 0xF007 | self.register(x, line)? << 8,
+            ("LD", &[x, k]) if is(k, "K") => 0xF00A | self.register(x, line)? << 8,
+            ("LD", &[x, i]) if is(i, "[I]") => 0xF065 | self.register(x, line)? << 8,
+            ("LD", &[x, y]) => match (register(x), register(y)) {
+                (Some(x), Some(y)) => 0x8000 | x << 8 | y << 4,
+                (Some(x), None) => 0x6000 | x << 8 | byte(y)?,
+                _ => return Err(self.register_error(x, line, &["I", "DT", "ST", "F", "HF", "B", "[I]"])),
+            },
+
+            ("OR", &[x, y]) => 0x8001 | self.registers(x, y, line)?,
+            ("AND", &[x, y]) => 0x8002 | self.registers(x, y, line)?,
+            ("XOR", &[x, y]) => 0x8003 | self.registers(x, y, line)?,
+            ("SUB", &[x, y]) => 0x8005 | self.registers(x, y, line)?,
+            ("SHR", &[x, y]) => 0x8006 | self.registers(x, y, line)?,
+            ("SHR", &[x]) => 0x8006 | self.registers(x, x, line)?,
+            ("SUBN", &[x, y]) => 0x8007 | self.registers(x, y, line)?,
+            ("SHL", &[x, y]) => 0x800E | self.registers(x, y, line)?,
+            ("SHL", &[x]) => 0x800E | self.registers(x, x, line)?,
+            ("RND", &[x, b]) => 0xC000 | self.register(x, line)? << 8 | byte(b)?,
+            ("DRW", &[x, y, n]) => 0xD000 | self.registers(x, y, line)? | nibble(n)?,
+            ("SKP", &[x]) => 0xE09E | self.register(x, line)? << 8,
+            ("SKNP", &[x]) => 0xE0A1 | self.register(x, line)? << 8,
+            ("PITCH", &[x]) => 0xF03A | self.register(x, line)? << 8,
+
+            (mnemonic, _) if KNOWN_MNEMONICS.contains(&mnemonic) => {
+                let message = match ops.first() {
+                    Some(_) => format!("{} doesn't take the operands {}", mnemonic, ops.join(", ")),
+                    None => format!("{} needs operands", mnemonic),
+                };
+                return Err(error(ops.first().cloned().unwrap_or(mnemonic), message));
+            },
+            (mnemonic, _) => {
+                let names = KNOWN_MNEMONICS.iter().cloned().chain(self.macros.keys().map(|k| k.as_str()));
+                return Err(self.error(line, mnemonic, format!("unknown instruction {}", mnemonic), closest(mnemonic, names)));
+            },
+        };
+
+        bytes.push((instruction >> 8) as u8);
+        bytes.push(instruction as u8);
+        Ok(())
+    }
+
+    /// An error at a token on a line, found in the source to give its column
+    fn error(&self, line: usize, token: &str, message: String, suggestion: Option<String>) -> AssembleError {
+        let text = line.checked_sub(1).and_then(|i| self.source.get(i)).map(|l| l.as_str()).unwrap_or("");
+
+        // a token that isn't in the line, like one from a macro's body, points at the start of it
+        let start = match find_token(text, token) {
+            Some(n) => n,
+            None => text.len() - text.trim_start().len(),
+        };
+
+        AssembleError {
+            line: line,
+            column: text[..start].chars().count() + 1,
+            token: token.to_owned(),
+            message: message,
+            suggestion: suggestion,
+        }
+    }
+
+    /// The error for an operand that should have been a V register, or one of the other names given
+    fn register_error(&self, op: &str, line: usize, others: &[&str]) -> AssembleError {
+        let v_registers: Vec<String> = (0..16).map(|n| format!("V{:X}", n)).collect();
+        let names = v_registers.iter().map(|r| r.as_str()).chain(others.iter().cloned());
+
+        let expected = match others.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("a V register, {} or {}", rest.join(", "), last),
+            Some((last, _)) => format!("a V register or {}", last),
+            None => "a V register".to_owned(),
+        };
+        self.error(line, op, format!("expected {} but found {}", expected, op), closest(op, names))
+    }
+
+    /// The number of a V register operand
+    fn register(&self, op: &str, line: usize) -> Result<u16, AssembleError> {
+        register(op).ok_or_else(|| self.register_error(op, line, &[]))
+    }
+
+    /// The x and y fields of an instruction taking two V registers
+    fn registers(&self, x: &str, y: &str, line: usize) -> Result<u16, AssembleError> {
+        Ok(self.register(x, line)? << 8 | self.register(y, line)? << 4)
+    }
+
+    /// Works out the value of an expression
+    fn eval(&self, expr: &str, line: usize, depth: usize) -> Result<i64, AssembleError> {
+        let mut parser = ExprParser { program: self, chars: expr.chars().collect(), position: 0, line: line, depth: depth };
+
+        let value = parser.binary(0)?;
+        parser.skip_spaces();
+        match parser.chars.get(parser.position) {
+            None => Ok(value),
+            Some(c) => Err(self.error(line, &c.to_string(), format!("didn't expect {} in {}", c, expr), None)),
+        }
+    }
+
+    /// The value of a label or constant
+    fn symbol(&self, name: &str, line: usize, depth: usize) -> Option<Result<i64, AssembleError>> {
+        if let Some(&addr) = self.labels.get(name) {
+            return Some(Ok(addr as i64));
+        }
+
+        self.constants.get(name).map(|&(ref expr, defined_at)| {
+            if depth >= MAX_CONSTANT_DEPTH {
+                return Err(self.error(line, name, format!("{} is defined in terms of itself", name), None));
+            }
+            self.eval(expr, defined_at, depth + 1)
+        })
+    }
+}
+
+/// Every mnemonic and directive the assembler knows
+const KNOWN_MNEMONICS: [&str; 38] = [
+    "DB", "DW", "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "SCR", "SCL", "SCU", "SCD", "MEGAOFF", "MEGAON", "LDHI",
+    "LDPAL", "SPRW", "SPRH", "ALPHA", "DIGISND", "STOPSND", "BMODE", "CCOL", "AUDIO",
+];
+
+/// Reads an expression by precedence climbing
+struct ExprParser<'a> {
+    program: &'a Program,
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    /// how deep in constants defined by other constants we are
+    depth: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.position).is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, token: &str, message: String) -> AssembleError {
+        self.program.error(self.line, token, message, None)
+    }
+
+    /// The binary operator next, if there is one, with how tightly it binds
+    fn operator(&mut self) -> Option<(&'static str, u8)> {
+        self.skip_spaces();
+        let rest: String = self.chars[self.position..].iter().take(2).collect();
+
+        [("<<", 3), (">>", 3), ("*", 4), ("/", 4), ("+", 3), ("-", 3), ("&", 2), ("^", 1), ("|", 0)].iter()
+            .find(|&&(op, _)| rest.starts_with(op))
+            .map(|&(op, precedence)| (op, precedence))
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<i64, AssembleError> {
+        let mut value = self.unary()?;
+
+        while let Some((op, precedence)) = self.operator() {
+            if precedence < min_precedence {
+                break;
+            }
+            self.position += op.len();
+
+            let right = self.binary(precedence + 1)?;
+            value = match op {
+                "<<" => value << (right & 63),
+                ">>" => value >> (right & 63),
+                "*" => value.wrapping_mul(right),
+                "/" => match value.checked_div(right) {
+                    Some(v) => v,
+                    None if right == 0 => return Err(self.error("/", "division by zero".to_owned())),
+                    None => return Err(self.error("/", format!("{} / {} is too big", value, right))),
+                },
+                "+" => value.wrapping_add(right),
+                "-" => value.wrapping_sub(right),
+                "&" => value & right,
+                "^" => value ^ right,
+                _ => value | right,
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, AssembleError> {
+        self.skip_spaces();
+
+        match self.chars.get(self.position) {
+            Some('-') => {
+                self.position += 1;
+                Ok(self.unary()?.wrapping_neg())
+            },
+            Some('(') => {
+                self.position += 1;
+                let value = self.binary(0)?;
+                self.skip_spaces();
+                if self.chars.get(self.position) != Some(&')') {
+                    return Err(self.error("(", "expected a ) to match this (".to_owned()));
+                }
+                self.position += 1;
+                Ok(value)
+            },
+            Some('%') => {
+                self.position += 1;
+                let digits = self.word();
+                i64::from_str_radix(&digits, 2).map_err(|_| self.error(&format!("%{}", digits), format!("%{} isn't a binary number", digits)))
+            },
+            Some(_) => {
+                let word = self.word();
+                if word.is_empty() {
+                    let c = self.chars[self.position].to_string();
+                    return Err(self.error(&c, format!("expected a number or a name but found {}", c)));
+                }
+
+                if let Some(value) = self.program.symbol(&word, self.line, self.depth) {
+                    return value;
+                }
+
+                let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(&word);
+                i64::from_str_radix(digits, 16).map_err(|_| {
+                    let names = self.program.labels.keys().chain(self.program.constants.keys()).map(|n| n.as_str());
+                    self.program.error(self.line, &word, format!("{} isn't a label, constant or hex number", word), closest(&word, names))
+                })
+            },
+            None => Err(self.error("", "expected a number or a name".to_owned())),
+        }
+    }
+
+    /// Reads a run of letters, digits and underscores
+    fn word(&mut self) -> String {
+        let start = self.position;
+        while self.chars.get(self.position).is_some_and(|&c| c.is_ascii_alphanumeric() || c == '_') {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+}
+
+/// The number of a V register, like V3 or vA
+fn register(op: &str) -> Option<u16> {
+    let mut chars = op.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V'), Some(n), None) | (Some('v'), Some(n), None) => n.to_digit(16).map(|n| n as u16),
+        _ => None,
+    }
+}
+
+/// Where a token is in a line, ignoring case, preferring where it's a whole word
+/// to where it's part of a longer one
+fn find_token(line: &str, token: &str) -> Option<usize> {
+    if token.is_empty() {
+        return None;
+    }
+
+    let upper = line.to_ascii_uppercase();
+    let token = token.to_ascii_uppercase();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut found = upper.match_indices(&token).map(|(n, _)| n);
+    let first = found.next()?;
+    let whole = Some(first).into_iter().chain(found).find(|&n| {
+        !upper[..n].ends_with(is_word) && !upper[n + token.len()..].starts_with(is_word)
+    });
+
+    whole.or(Some(first))
+}
+
+/// The one name that's a near miss for a word, if exactly one is. How near
+/// depends on how long the word is, so short words only get one typo.
+pub fn closest<'a, I: Iterator<Item = &'a str>>(word: &str, names: I) -> Option<String> {
+    let allowed = if word.chars().count() <= 3 { 1 } else { 2 };
+    let word = word.to_uppercase();
+
+    let mut best: Option<(usize, &str)> = None;
+    let mut tied = false;
+    for name in names {
+        let distance = edit_distance(&word, &name.to_uppercase());
+        if distance == 0 || distance > allowed {
+            continue;
+        }
+
+        match best {
+            Some((d, _)) if d < distance => {},
+            Some((d, _)) if d == distance => tied = true,
+            _ => {
+                best = Some((distance, name));
+                tied = false;
+            },
+        }
+    }
+
+    match best {
+        Some((_, name)) if !tied => Some(name.to_owned()),
+        _ => None,
+    }
+}
+
+/// How many characters have to be added, removed or changed to turn one word into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Whether a word can name a label, constant or macro
+fn is_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Cuts a line off at its comment
+fn strip_comment(line: &str) -> &str {
+    match line.find(';') {
+        Some(n) => &line[..n],
+        None => line,
+    }
+}
+
+/// Splits the operands after a mnemonic at their commas
+fn split_operands(operands: &str) -> Vec<String> {
+    if operands.trim().is_empty() {
+        return Vec::new();
+    }
+
+    operands.split(',').map(|o| o.trim().to_owned()).collect()
+}
+
+/// Replaces every whole-word use of a macro's parameters in a line of its body with the arguments
+fn substitute(line: &str, params: &[String], args: &[String]) -> String {
+    let mut result = String::new();
+    let mut word = String::new();
+
+    for c in line.chars().chain(Some('\n')) {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+
+        match params.iter().position(|p| *p == word) {
+            Some(n) => result.push_str(&args[n]),
+            None => result.push_str(&word),
+        }
+        word.clear();
+
+        if c != '\n' {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// The problems found assembling a program at 0x200
+    fn errors(source: &str) -> Vec<AssembleError> {
+        assemble(source, 0x200).unwrap_err()
+    }
+
+    #[test]
+    fn expressions_follow_precedence() {
+        let cases = [
+            ("LD V0, 2+3*4", 0x600E),
+            ("LD V0, (2+3)*4", 0x6014),
+            ("LD V0, 1<<4|1", 0x6011),
+            ("LD V0, 14/5", 0x6004),
+            ("LD V0, FF & F0 ^ 30", 0x60C0),
+            ("LD V0, %101 - 1", 0x6004),
+            ("ADD V0, -1", 0x70FF),
+            ("ADD V0, -(2-5)", 0x7003),
+        ];
+        for &(source, word) in cases.iter() {
+            assert_eq!(assemble(source, 0x200), Ok(vec![(word >> 8) as u8, word as u8]), "{}", source);
+        }
+    }
+
+    #[test]
+    fn arithmetic_that_overflows_is_an_error_rather_than_a_panic() {
+        let source = "MIN = 0 - 7FFFFFFFFFFFFFFF - 1\nLD V0, -MIN\nLD V1, MIN / -1\nLD V2, 5 / (1-1)";
+        let messages: Vec<String> = errors(source).iter().map(|e| e.message.clone()).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("doesn't fit"), "{}", messages[0]);
+        assert!(messages[1].contains("too big"), "{}", messages[1]);
+        assert_eq!(messages[2], "division by zero");
+    }
+
+    #[test]
+    fn constants_and_labels_can_be_used_before_theyre_defined() {
+        let source = "LD I, sprite + OFFSET\nOFFSET = ROWS * 2\nROWS = 3\nsprite: DB 1";
+        assert_eq!(assemble(source, 0x200), Ok(vec![0xA2, 0x08, 0x01]));
+
+        let e = errors("A = B + 1\nB = A\nLD V0, A");
+        assert!(e[0].message.contains("defined in terms of itself"), "{}", e[0].message);
+    }
+
+    #[test]
+    fn macros_expand_with_their_arguments() {
+        let source = "MACRO move x, dx\n  ADD x, dx\n  DRW x, V1, 5\nENDM\nmove V2, 3\nmove V3, 4";
+        assert_eq!(assemble(source, 0x200), Ok(vec![0x72, 0x03, 0xD2, 0x15, 0x73, 0x04, 0xD3, 0x15]));
+
+        let e = errors("MACRO twice x\n  ADD x, x\nENDM\ntwice V1, V2");
+        assert_eq!((e[0].line, e[0].column), (4, 1));
+        assert!(e[0].message.contains("takes 1 argument(s) but was given 2"), "{}", e[0].message);
+
+        let e = errors("MACRO forever\n  CLS");
+        assert!(e[0].message.contains("has no ENDM"), "{}", e[0].message);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        let source = "CLS\n    LOD V0, 5\n\tJP nowhere";
+        let e = errors(source);
+
+        assert_eq!((e[0].line, e[0].column, e[0].token.as_str()), (2, 5, "LOD"));
+        assert_eq!(e[0].excerpt(source), "        LOD V0, 5\n        ^^^");
+        assert_eq!((e[1].line, e[1].column, e[1].token.as_str()), (3, 5, "nowhere"));
+        assert_eq!(e[1].excerpt(source), "    \tJP nowhere\n    \t   ^^^^^^^");
+    }
+
+    #[test]
+    fn near_misses_get_suggestions() {
+        let e = errors("LOD V0, 5");
+        assert_eq!(e[0].suggestion.as_deref(), Some("LD"));
+
+        let e = errors("loop: CLS\nJP lop");
+        assert_eq!(e[0].suggestion.as_deref(), Some("loop"));
+        assert_eq!(e[0].to_string(), "line 2, column 4: lop isn't a label, constant or hex number (did you mean loop?)");
+
+        let e = errors("ADD W3, 1");
+        assert_eq!(e[0].suggestion.as_deref(), Some("V3"));
+
+        let e = errors("JP somewhere_else_entirely");
+        assert_eq!(e[0].suggestion, None);
+    }
+
+    #[test]
+    fn saved_symbols_and_line_map_load_back() {
+        let source = "start: LD V0, 1\n\nloop: ADD V0, 1\n  JP loop\nsprite: DB F0, 90\n";
+        let assembled = assemble_with_symbols(source, 0x200).unwrap();
+        assert_eq!(assembled.bytes, vec![0x60, 0x01, 0x70, 0x01, 0x12, 0x02, 0xF0, 0x90]);
+
+        let dir = env::temp_dir().join(format!("chip8-assembler-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("game.asm");
+        fs::write(&source_path, source).unwrap();
+        let rom_path = dir.join("game.ch8");
+        let rom = rom_path.to_str().unwrap();
+
+        assembled.symbols.save_file(&SymbolTable::path_for(rom)).unwrap();
+        assembled.line_map.save_file(&LineMap::path_for(rom), &source_path).unwrap();
+        let symbols = SymbolTable::load_file(&SymbolTable::path_for(rom)).unwrap();
+        let line_map = LineMap::load_file(&LineMap::path_for(rom)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(symbols.addr_of("start"), Some(0x200));
+        assert_eq!(symbols.addr_of("loop"), Some(0x202));
+        assert_eq!(symbols.addr_of("sprite"), Some(0x206));
+        assert_eq!([0x200, 0x202, 0x204, 0x206].iter().map(|&a| line_map.line_at(a)).collect::<Vec<_>>(),
+            vec![Some(1), Some(3), Some(4), Some(5)]);
+        assert_eq!(line_map.source_line(4), Some("  JP loop"));
+    }
+}
@@ -0,0 +1,241 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate sdl2;
+
+use sdl2::Sdl;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing;
+
+use chip8::audiosink::AudioSink;
+use chip8::wav::WavWriter;
+use chip8::xochip::PATTERN_LENGTH;
+
+/// The rate at which we ask SDL to consume audio samples
+pub const OUTPUT_SAMPLE_RATE: i32 = 44100;
+/// The value of an unsigned 8-bit sample that produces no sound
+const SILENCE: u8 = 0x80;
+/// The pitch of the buzzer, in Hz, unless the program or command line sets another
+pub const DEFAULT_BEEP_PITCH: f64 = 440.0;
+/// How far either side of silence the buzzer's square wave swings
+const BEEP_AMPLITUDE: u8 = 0x20;
+
+/// The SDL audio callback that streams digitized sound data to the sound card
+pub struct SamplePlayer {
+    /// the unsigned 8-bit sample data being played
+    data: Vec<u8>,
+    /// the position in the sample data, in samples
+    position: f64,
+    /// how far to advance through the sample data per output sample
+    step: f64,
+    /// whether the sound starts over once it reaches the end
+    looping: bool,
+    /// the output sample rate that SDL gave us
+    output_rate: i32,
+    /// whether the buzzer is sounding, which it does when no sample data is playing
+    beeping: bool,
+    /// how far through a cycle of the buzzer's square wave we are, from 0 to 1
+    beep_phase: f64,
+    /// the pitch of the buzzer, in Hz
+    beep_pitch: f64,
+    /// the one-bit samples the buzzer loops instead of its square wave, if any
+    beep_pattern: Option<[u8; PATTERN_LENGTH]>,
+    /// how far through the pattern the buzzer is, in samples of the pattern
+    pattern_position: f64,
+    /// how far to advance through the pattern per output sample
+    pattern_step: f64,
+    /// where everything played is also being written, if anywhere
+    recording: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl SamplePlayer {
+    /// The next sample of the buzzer's pattern, or of its square wave if it has no pattern
+    fn next_beep_sample(&mut self) -> u8 {
+        let high = match self.beep_pattern {
+            Some(ref pattern) => {
+                let bit = self.pattern_position as usize;
+                self.pattern_position = (self.pattern_position + self.pattern_step) % (PATTERN_LENGTH * 8) as f64;
+
+                pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+            },
+            None => {
+                self.beep_phase = (self.beep_phase + self.beep_pitch / self.output_rate as f64) % 1.0;
+
+                self.beep_phase < 0.5
+            },
+        };
+
+        if high { SILENCE + BEEP_AMPLITUDE } else { SILENCE - BEEP_AMPLITUDE }
+    }
+}
+
+impl AudioCallback for SamplePlayer {
+    type Channel = u8;
+
+    fn callback(&mut self, out: &mut [u8]) {
+        let _span = tracing::debug_span!("audio").entered();
+        for x in out.iter_mut() {
+            if self.position as usize >= self.data.len() {
+                if self.looping && !self.data.is_empty() {
+                    self.position = 0.0;
+                } else {
+                    *x = if self.beeping { self.next_beep_sample() } else { SILENCE };
+                    continue;
+                }
+            }
+
+            *x = self.data[self.position as usize];
+            self.position += self.step;
+        }
+
+        let failed = match self.recording {
+            Some(ref mut wav) => wav.write_samples(out).is_err(),
+            None => false,
+        };
+        if failed {
+            error!(target: "audio", "Couldn't write the audio recording, so it stops here.");
+            if let Some(wav) = self.recording.take() {
+                let _ = wav.finish();
+            }
+        }
+    }
+}
+
+/// A structure to manage playing the system's sound through SDL
+pub struct Audio {
+    device: AudioDevice<SamplePlayer>,
+    /// the shortest the buzzer sounds for, however briefly the sound timer runs
+    min_beep: Duration,
+    /// when the buzzer last started
+    beep_started: Instant,
+    /// when the buzzer should stop, if it's been asked to but hasn't sounded for long enough yet
+    beep_stop: Option<Instant>,
+}
+
+impl Audio {
+    /// Construct a new Audio object whose buzzer sounds for at least the given time
+    pub fn new(sdl_context: &Sdl, min_beep: Duration) -> Result<Audio, String> {
+        let audio_subsystem = sdl_context.audio()?;
+
+        let desired_spec = AudioSpecDesired {
+            freq: Some(OUTPUT_SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+            SamplePlayer {
+                data: Vec::new(),
+                position: 0.0,
+                step: 0.0,
+                looping: false,
+                output_rate: spec.freq,
+                beeping: false,
+                beep_phase: 0.0,
+                beep_pitch: DEFAULT_BEEP_PITCH,
+                beep_pattern: None,
+                pattern_position: 0.0,
+                pattern_step: 0.0,
+                recording: None,
+            }
+        })?;
+
+        device.resume();
+
+        Ok(Audio {
+            device: device,
+            min_beep: min_beep,
+            beep_started: Instant::now(),
+            beep_stop: None,
+        })
+    }
+
+    /// Starts playing unsigned 8-bit sample data at the given rate, replacing any sound already playing
+    pub fn play_samples(&mut self, samples: Vec<u8>, sample_rate: u32, looping: bool) {
+        let mut player = self.device.lock();
+
+        player.data = samples;
+        player.position = 0.0;
+        player.step = sample_rate as f64 / player.output_rate as f64;
+        player.looping = looping;
+    }
+
+    /// Has the buzzer loop a pattern of one-bit samples, played at the given rate,
+    /// instead of its square wave. None goes back to the square wave.
+    pub fn set_beep_pattern(&mut self, pattern: Option<[u8; PATTERN_LENGTH]>, sample_rate: f64) {
+        let mut player = self.device.lock();
+
+        player.beep_pattern = pattern;
+        player.pattern_step = sample_rate / player.output_rate as f64;
+    }
+
+    /// Stops the buzzer if it was asked to stop and has now sounded for long enough
+    pub fn update(&mut self) {
+        if let Some(stop) = self.beep_stop {
+            if Instant::now() >= stop {
+                self.device.lock().beeping = false;
+                self.beep_stop = None;
+            }
+        }
+    }
+
+    /// Starts writing everything played, beeps and silence alike, to a WAV file at
+    /// the given path, finishing any recording already under way
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        let mut player = self.device.lock();
+        let wav = WavWriter::create(path, player.output_rate as u32)?;
+
+        if let Some(previous) = player.recording.replace(wav) {
+            previous.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// Finishes the recording under way, if any, returning how many seconds it lasted
+    pub fn finish_recording(&mut self) -> io::Result<Option<f64>> {
+        let mut player = self.device.lock();
+
+        match player.recording.take() {
+            Some(wav) => {
+                let seconds = wav.len() as f64 / player.output_rate as f64;
+                wav.finish()?;
+
+                Ok(Some(seconds))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Stops the sound that's playing, if any
+    pub fn stop(&mut self) {
+        let mut player = self.device.lock();
+
+        player.data.clear();
+        player.position = 0.0;
+    }
+}
+
+impl AudioSink for Audio {
+    fn start_beep(&mut self) {
+        self.beep_started = Instant::now();
+        self.beep_stop = None;
+        self.device.lock().beeping = true;
+    }
+
+    /// A buzzer stopped too soon after it started keeps sounding until it has
+    /// lasted the minimum time; see update.
+    fn stop_beep(&mut self) {
+        self.beep_stop = Some(self.beep_started + self.min_beep);
+        self.update();
+    }
+
+    fn set_pitch(&mut self, pitch: f64) {
+        self.device.lock().beep_pitch = pitch;
+    }
+}
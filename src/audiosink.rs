@@ -0,0 +1,152 @@
+//
+// Author: Joshua Holmes
+//
+
+use frontend::Frontend;
+use observer::Observer;
+
+/// Somewhere the buzzer can be sounded: the speakers in the SDL frontend, or a
+/// recording that tests can check the timing of the beeps against
+pub trait AudioSink {
+    /// The sound timer has been set, so the buzzer should start
+    fn start_beep(&mut self);
+
+    /// The sound timer has run down, so the buzzer should stop
+    fn stop_beep(&mut self);
+
+    /// The buzzer should sound at the given pitch, in Hz, from now on
+    fn set_pitch(&mut self, pitch: f64);
+}
+
+/// Something that happened to a RecordingSink
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SinkEvent {
+    StartBeep,
+    StopBeep,
+    SetPitch(f64),
+}
+
+/// A sink that makes no sound, but remembers what it was asked to do
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecordingSink {
+    /// what the sink was asked to do, oldest first
+    pub events: Vec<SinkEvent>,
+}
+
+impl RecordingSink {
+    /// Construct a sink that hasn't recorded anything yet
+    pub fn new() -> RecordingSink {
+        RecordingSink {
+            events: Vec::new(),
+        }
+    }
+}
+
+impl AudioSink for RecordingSink {
+    fn start_beep(&mut self) {
+        self.events.push(SinkEvent::StartBeep);
+    }
+
+    fn stop_beep(&mut self) {
+        self.events.push(SinkEvent::StopBeep);
+    }
+
+    fn set_pitch(&mut self, pitch: f64) {
+        self.events.push(SinkEvent::SetPitch(pitch));
+    }
+}
+
+/// An observer, and a frontend with no display or input, that passes the sound
+/// timer starting and stopping, and the pitch changing, on to a sink
+pub struct SinkObserver<S> {
+    pub sink: S,
+}
+
+impl<S: AudioSink> SinkObserver<S> {
+    /// Construct an observer that sounds the given sink
+    pub fn new(sink: S) -> SinkObserver<S> {
+        SinkObserver {
+            sink: sink,
+        }
+    }
+}
+
+impl<S: AudioSink> Observer for SinkObserver<S> {
+    fn on_sound_start(&mut self) {
+        self.sink.start_beep();
+    }
+
+    fn on_sound_stop(&mut self) {
+        self.sink.stop_beep();
+    }
+
+    fn on_pitch_change(&mut self, rate: f64) {
+        self.sink.set_pitch(rate);
+    }
+}
+
+impl<S: AudioSink> Frontend for SinkObserver<S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::Cpu;
+    use emulator::Emulator;
+    use machine::MachineConfig;
+    use variant::Variant;
+    use xochip::XoChip;
+
+    /// Runs the program for the given number of frames, returning what the sink
+    /// was asked to do along with the frame it happened in, counting from 0
+    fn run(variant: Variant, program: Vec<u8>, frames: u32) -> Vec<(u32, SinkEvent)> {
+        let cpu = Cpu::init_from_buffer(program, Some(MachineConfig::new(variant))).unwrap();
+        let mut emulator = Emulator::new(cpu);
+        let mut observer = SinkObserver::new(RecordingSink::new());
+        let mut events = Vec::new();
+
+        for frame in 0..frames {
+            emulator.run_frame_observed(&mut observer).unwrap();
+            events.extend(observer.sink.events.drain(..).map(|e| (frame, e)));
+        }
+
+        events
+    }
+
+    #[test]
+    fn beep_lasts_as_many_frames_as_the_sound_timer() {
+        // LD V0, 3; LD ST, V0; JP 0x204
+        let events = run(Variant::Chip8, vec![0x60, 0x03, 0xF0, 0x18, 0x12, 0x04], 10);
+
+        assert_eq!(events, vec![(0, SinkEvent::StartBeep), (2, SinkEvent::StopBeep)]);
+    }
+
+    #[test]
+    fn beep_starts_again_when_the_timer_is_set_again() {
+        // LD V0, 2; LD ST, V0; LD V1, 5; LD DT, V1;
+        // wait: LD V1, DT; SE V1, 0; JP wait; LD ST, V0; JP 0x210
+        let program = vec![0x60, 0x02, 0xF0, 0x18, 0x61, 0x05, 0xF1, 0x15,
+                           0xF1, 0x07, 0x31, 0x00, 0x12, 0x08, 0xF0, 0x18, 0x12, 0x10];
+        let events = run(Variant::Chip8, program, 20);
+
+        assert_eq!(events, vec![(0, SinkEvent::StartBeep), (1, SinkEvent::StopBeep),
+                                (5, SinkEvent::StartBeep), (6, SinkEvent::StopBeep)]);
+    }
+
+    #[test]
+    fn pitch_changes_are_passed_on_before_the_beep() {
+        // LD V0, 112; PITCH V0; LD ST, V0; JP 0x206
+        let events = run(Variant::XoChip, vec![0x60, 0x70, 0xF0, 0x3A, 0xF0, 0x18, 0x12, 0x06], 1);
+
+        let mut xo = XoChip::new();
+        xo.pitch = 112;
+        assert_eq!(events, vec![(0, SinkEvent::SetPitch(xo.playback_rate())), (0, SinkEvent::StartBeep)]);
+    }
+
+    #[test]
+    fn unchanged_pitch_isnt_passed_on() {
+        // LD V0, 3; LD ST, V0; JP 0x204
+        let events = run(Variant::XoChip, vec![0x60, 0x03, 0xF0, 0x18, 0x12, 0x04], 5);
+
+        assert_eq!(events, vec![(0, SinkEvent::StartBeep), (2, SinkEvent::StopBeep)]);
+    }
+}
@@ -0,0 +1,46 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// How many threads to run a batch on when nobody says, one for each core
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs a job for each of the items given, spread over up to the given number of
+/// threads, and returns the results in the same order as the items. Each thread
+/// takes the next item nobody has started on whenever it finishes one, so a few
+/// slow items don't leave the other threads idle. Anything the jobs need, like an
+/// emulator, they make for themselves, so nothing is shared between them.
+pub fn run<T, R, F>(items: Vec<T>, jobs: usize, job: F) -> Vec<R>
+    where T: Send, R: Send, F: Fn(T) -> R + Sync
+{
+    let count = items.len();
+    let items: Vec<Mutex<Option<T>>> = items.into_iter().map(|item| Mutex::new(Some(item))).collect();
+    let results: Vec<Mutex<Option<R>>> = (0..count).map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(count) {
+            scope.spawn(|| loop {
+                let n = next.fetch_add(1, Ordering::SeqCst);
+                if n >= count {
+                    break;
+                }
+
+                // each index is handed out once, so the item is always still there
+                if let Some(item) = items[n].lock().unwrap().take() {
+                    let result = job(item);
+                    *results[n].lock().unwrap() = Some(result);
+                }
+            });
+        }
+    });
+
+    // a job that panicked takes its thread down, and scope passes the panic on, so every result is here
+    results.into_iter().filter_map(|r| r.into_inner().unwrap()).collect()
+}
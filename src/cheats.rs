@@ -0,0 +1,163 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use cpu::Cpu;
+
+/// What a cheat does to its address
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheatKind {
+    /// the address is set back to the value every frame
+    Freeze,
+    /// the address is set to the value once, when cheats are switched on
+    Poke,
+}
+
+/// A single memory cheat
+#[derive(Debug, Clone)]
+pub struct Cheat {
+    pub kind: CheatKind,
+    pub address: usize,
+    pub value: u8,
+    /// what the cheat is for, e.g. "infinite lives"
+    pub description: String,
+}
+
+/// Reasons a cheat file couldn't be loaded
+#[derive(Debug)]
+pub enum CheatError {
+    IoError(io::Error),
+    /// a line of the cheat file couldn't be understood
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl From<io::Error> for CheatError {
+    fn from(err: io::Error) -> Self {
+        CheatError::IoError(err)
+    }
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CheatError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            CheatError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+/// The cheats defined for the running program. Freezes are re-applied every
+/// frame, after the frame's instructions have executed.
+pub struct Cheats {
+    pub cheats: Vec<Cheat>,
+    /// whether the cheats are currently switched on
+    pub enabled: bool,
+    /// whether the pokes have been applied since cheats were last switched on
+    poked: bool,
+}
+
+impl Default for Cheats {
+    fn default() -> Cheats {
+        Cheats::new()
+    }
+}
+
+impl Cheats {
+    /// Construct an empty, enabled set of cheats
+    pub fn new() -> Cheats {
+        Cheats {
+            cheats: Vec::new(),
+            enabled: true,
+            poked: false,
+        }
+    }
+
+    /// The path a program's cheats are kept at: next to the program, with a .cheats extension
+    pub fn path_for(program_path: &str) -> PathBuf {
+        Path::new(program_path).with_extension("cheats")
+    }
+
+    /// Loads cheats from a file. Each line is `freeze <address> <value> [description]`
+    /// or `poke <address> <value> [description]`, with the numbers in hex.
+    /// Blank lines and lines starting with # are ignored.
+    pub fn load_file(path: &Path) -> Result<Cheats, CheatError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut cheats = Cheats::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_error = |message: &str| CheatError::Parse { line: i + 1, message: message.to_owned() };
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            let kind = match words[0] {
+                "freeze" => CheatKind::Freeze,
+                "poke" => CheatKind::Poke,
+                _ => return Err(parse_error("expected freeze or poke")),
+            };
+
+            let address = match words.get(1).and_then(|w| parse_hex(w)) {
+                Some(a) => a,
+                None => return Err(parse_error("expected a hex address")),
+            };
+
+            let value = match words.get(2).and_then(|w| parse_hex(w)) {
+                Some(v) if v <= 0xFF => v as u8,
+                _ => return Err(parse_error("expected a hex byte value")),
+            };
+
+            cheats.cheats.push(Cheat {
+                kind: kind,
+                address: address,
+                value: value,
+                description: if words.len() > 3 { words[3..].join(" ") } else { String::new() },
+            });
+        }
+
+        Ok(cheats)
+    }
+
+    /// Switches the cheats on or off. Pokes are applied again the next time they're switched on.
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.poked = false;
+    }
+
+    /// Writes the cheat values into memory. Call this once per frame.
+    pub fn apply(&mut self, cpu: &mut Cpu) {
+        if !self.enabled {
+            return;
+        }
+
+        for cheat in self.cheats.iter() {
+            if cheat.kind == CheatKind::Poke && self.poked {
+                continue;
+            }
+
+            if cheat.address < cpu.memory.len() {
+                cpu.memory[cheat.address] = cheat.value;
+            }
+        }
+
+        self.poked = true;
+    }
+}
+
+/// Parses a hex number, with or without a leading 0x
+pub fn parse_hex(s: &str) -> Option<usize> {
+    let digits = if s.starts_with("0x") || s.starts_with("0X") { &s[2..] } else { s };
+    usize::from_str_radix(digits, 16).ok()
+}
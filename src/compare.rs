@@ -0,0 +1,104 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate sdl2;
+
+use chip8::cpu::Cpu;
+use chip8::emulator::Emulator;
+use chip8::frame::Frame;
+use chip8::frontend::Frontend;
+use chip8::observer::Observer;
+use display::{Display, WindowOptions};
+use sdl2::Sdl;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+/// One of the two machines being compared, keeping hold of its latest screen
+struct Side {
+    /// what the side is called in messages
+    name: &'static str,
+    /// the screen as it was last drawn
+    screen: Frame,
+}
+
+impl Observer for Side {
+    fn on_draw(&mut self, cpu: &Cpu) {
+        self.screen = Frame::capture(cpu);
+    }
+}
+
+impl Frontend for Side {}
+
+impl Side {
+    /// Runs a frame of the side's emulator. Returns false once it has stopped.
+    fn run_frame(&mut self, emulator: &mut Emulator) -> bool {
+        match emulator.run_frame_with(self) {
+            Ok(keep_running) => {
+                if !keep_running {
+                    info!(target: "cpu", "The {} side finished.", self.name);
+                }
+
+                keep_running
+            },
+            Err(e) => {
+                error!(target: "cpu", "The {} side stopped. {}", self.name, e);
+
+                if let Some(state) = e.state() {
+                    info!(target: "cpu", "{}", state);
+                }
+
+                false
+            },
+        }
+    }
+}
+
+/// Runs two emulators side by side in one window, frame for frame with the same
+/// input, so it's easy to see where two sets of quirks make a program behave
+/// differently. Both should be running the same program with the same random seed.
+/// The terminal says when the screens start and stop differing.
+pub fn run(sdl_context: &Sdl, mut left: Emulator, mut right: Emulator, window: WindowOptions) {
+    let mut display = Display::with_panes(sdl_context, left.cpu.display_height, left.palette, 2, window);
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let mut left_side = Side { name: "left", screen: Frame::capture(&left.cpu) };
+    let mut right_side = Side { name: "right", screen: Frame::capture(&right.cpu) };
+
+    let mut frame_count: u64 = 0;
+    let mut differing = false;
+
+    'running: loop {
+        if !left_side.run_frame(&mut left) || !right_side.run_frame(&mut right) {
+            break;
+        }
+
+        frame_count += 1;
+
+        if (left_side.screen != right_side.screen) != differing {
+            differing = !differing;
+            info!(target: "cpu", "Frame {}: the screens {} (left PC 0x{:03X}, right PC 0x{:03X})",
+                frame_count, if differing { "differ" } else { "match again" },
+                left.cpu.program_counter, right.cpu.program_counter);
+        }
+
+        display.draw_frames(&[&left_side.screen, &right_side.screen]);
+
+        // both sides get the same input
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                Event::KeyDown { keycode: Some(key), .. } => {
+                    left.update_key(key, true);
+                    right.update_key(key, true);
+                },
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    left.update_key(key, false);
+                    right.update_key(key, false);
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
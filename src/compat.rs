@@ -0,0 +1,186 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use cpu::{EmulationError, VIRTUAL_DISPLAY_WIDTH};
+use emulator::Emulator;
+use observer::NullObserver;
+use png;
+
+/// How many frames in a row the machine has to stay exactly as it was before a
+/// run is counted as hung, or as waiting for a key for good
+const STILL_FRAMES: u32 = 120;
+
+/// How a program fared in a compatibility run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// still going at the end of the run
+    Running,
+    /// stopped waiting for a key, as most games do on their title screen
+    WaitingForKey,
+    /// ran off the end of the program
+    Finished,
+    /// stopped on an instruction the variant doesn't have, at the given address
+    UnknownOpcode(u16, usize),
+    /// stopped with some other error
+    Crashed(String),
+    /// went round and round without changing anything, and without waiting for a key
+    Hung,
+}
+
+impl Verdict {
+    /// A word for the verdict, for sorting and filtering reports by
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Verdict::Running => "running",
+            Verdict::WaitingForKey => "waiting for key",
+            Verdict::Finished => "finished",
+            Verdict::UnknownOpcode(..) => "unknown opcode",
+            Verdict::Crashed(_) => "crashed",
+            Verdict::Hung => "hung",
+        }
+    }
+
+    /// More about the verdict, where there's more to say
+    pub fn detail(&self) -> String {
+        match *self {
+            Verdict::UnknownOpcode(instruction, addr) => format!("{:04X} at 0x{:03X}", instruction, addr),
+            Verdict::Crashed(ref message) => message.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// What happened when a program was run for a compatibility report
+#[derive(Debug, Clone)]
+pub struct CompatResult {
+    pub path: PathBuf,
+    pub verdict: Verdict,
+    /// how many frames ran before the run stopped
+    pub frames: u64,
+    /// whether anything was ever drawn on the screen
+    pub drew: bool,
+    /// the screen at the end of the run, a byte a pixel, row by row
+    pub screen: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Runs a program for up to the given number of 60Hz frames, as fast as possible
+/// and with no input, and reports how it went. The run stops early once the
+/// program stops, or once nothing about the machine has changed for a couple of
+/// seconds, since nothing will without input.
+pub fn run(emulator: &mut Emulator, path: &Path, frames: u64) -> CompatResult {
+    let per_frame = cmp::max(1, emulator.speed() / 60);
+    let mut verdict = Verdict::Running;
+    let mut drew = false;
+    let mut last_checksum = None;
+    let mut still_frames = 0;
+    let mut frames_run = 0;
+
+    'frames: while frames_run < frames {
+        frames_run += 1;
+
+        for _ in 0..per_frame {
+            match emulator.cycle(&mut NullObserver) {
+                Ok(true) => (),
+                Ok(false) => {
+                    verdict = Verdict::Finished;
+                    break 'frames;
+                },
+                Err(EmulationError::UnknownOpcode(instruction, ref state)) => {
+                    verdict = Verdict::UnknownOpcode(instruction, state.program_counter);
+                    break 'frames;
+                },
+                Err(e) => {
+                    verdict = Verdict::Crashed(e.to_string());
+                    break 'frames;
+                },
+            }
+            emulator.cpu.warnings.clear();
+        }
+        emulator.tick_timers(&mut NullObserver);
+
+        drew |= emulator.cpu.vram.iter().any(|&row| row != 0);
+
+        let checksum = emulator.cpu.checksum();
+        still_frames = if last_checksum == Some(checksum) { still_frames + 1 } else { 0 };
+        last_checksum = Some(checksum);
+
+        if still_frames >= STILL_FRAMES {
+            verdict = if emulator.cpu.current_instruction & 0xF0FF == 0xF00A { Verdict::WaitingForKey } else { Verdict::Hung };
+            break;
+        }
+    }
+
+    let cpu = &emulator.cpu;
+    let (width, height) = (VIRTUAL_DISPLAY_WIDTH, cpu.display_height);
+    let mut screen = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            screen.push(if cpu.pixel(x, y) { 0xFF } else { 0x00 });
+        }
+    }
+
+    CompatResult {
+        path: path.to_path_buf(),
+        verdict: verdict,
+        frames: frames_run,
+        drew: drew,
+        screen: screen,
+        width: width as u32,
+        height: height as u32,
+    }
+}
+
+/// Writes a compatibility report into a directory: report.csv, report.html, and
+/// a screenshot of how each program's screen ended up for the HTML to show
+pub fn write_report(dir: &Path, results: &[CompatResult]) -> io::Result<()> {
+    fs::create_dir_all(dir.join("screens"))?;
+
+    let mut csv = BufWriter::new(File::create(dir.join("report.csv"))?);
+    writeln!(csv, "program,verdict,detail,frames,drew")?;
+
+    let mut html = BufWriter::new(File::create(dir.join("report.html"))?);
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html><head><meta charset=\"utf-8\"><title>Compatibility report</title>")?;
+    writeln!(html, "<style>body {{ font-family: sans-serif; }} td {{ padding: 4px 12px; }} img {{ width: 256px; image-rendering: pixelated; border: 1px solid #888; }} .running, .waiting {{ background: #dfd; }} .finished, .hung {{ background: #ffd; }} .crashed, .unknown {{ background: #fdd; }}</style>")?;
+    writeln!(html, "</head><body><h1>Compatibility report</h1><table>")?;
+    writeln!(html, "<tr><th>Screen</th><th>Program</th><th>Verdict</th><th>Detail</th><th>Frames</th></tr>")?;
+
+    for (n, result) in results.iter().enumerate() {
+        let name = result.path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+        let verdict = result.verdict.name();
+        let detail = if result.drew { result.verdict.detail() } else { format!("{} (never drew anything)", result.verdict.detail()).trim().to_owned() };
+
+        let screen = format!("screens/{:04}.png", n);
+        png::write_grayscale(&mut BufWriter::new(File::create(dir.join(&screen))?), result.width, result.height, &result.screen)?;
+
+        writeln!(csv, "{},{},{},{},{}", csv_field(&result.path.to_string_lossy()), verdict, csv_field(&detail), result.frames, result.drew)?;
+        writeln!(html, "<tr class=\"{}\"><td><img src=\"{}\"></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            verdict.split(' ').next().unwrap_or(""), screen, escape_html(&name), verdict, escape_html(&detail), result.frames)?;
+    }
+
+    writeln!(html, "</table></body></html>")?;
+    csv.flush()?;
+    html.flush()
+}
+
+/// A CSV field, quoted if it needs to be
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') || text.contains('\n') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Text with the characters HTML gives meaning to escaped
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
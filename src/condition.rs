@@ -0,0 +1,251 @@
+//
+// Author: Joshua Holmes
+//
+
+use cheats::parse_hex;
+use cpu::{Cpu, NUM_REGISTERS};
+
+/// A register that conditions and the debugger can name
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+    /// one of V0 to VF
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+impl Register {
+    /// Finds a register by its name, in either case
+    pub fn from_name(name: &str) -> Option<Register> {
+        let name = name.to_lowercase();
+
+        match name.as_str() {
+            "i" => Some(Register::I),
+            "pc" => Some(Register::Pc),
+            "sp" => Some(Register::Sp),
+            "dt" => Some(Register::Dt),
+            "st" => Some(Register::St),
+            _ if name.len() == 2 => match name.strip_prefix('v').and_then(|n| usize::from_str_radix(n, 16).ok()) {
+                Some(n) if n < NUM_REGISTERS => Some(Register::V(n)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The register's value in the given machine
+    pub fn value(&self, cpu: &Cpu) -> usize {
+        match *self {
+            Register::V(n) => cpu.data_registers[n] as usize,
+            Register::I => cpu.i_register,
+            Register::Pc => cpu.program_counter,
+            Register::Sp => cpu.stack_pointer,
+            Register::Dt => cpu.delay_timer as usize,
+            Register::St => cpu.sound_timer as usize,
+        }
+    }
+}
+
+/// Something a condition compares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Number(usize),
+    /// the byte at the address the inner operand gives, or 0 past the end of memory
+    Memory(Box<Operand>),
+}
+
+impl Operand {
+    fn value(&self, cpu: &Cpu) -> usize {
+        match *self {
+            Operand::Register(r) => r.value(cpu),
+            Operand::Number(n) => n,
+            Operand::Memory(ref addr) => cpu.memory.get(addr.value(cpu)).cloned().unwrap_or(0) as usize,
+        }
+    }
+}
+
+/// How a condition compares its operands
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A test of the machine's state, like a breakpoint's. Written out, it compares
+/// registers, numbers and bytes of memory, and joins comparisons with && and ||,
+/// with && binding tighter and brackets to group them:
+///
+/// ```text
+/// v3 == 0 && dt > 0
+/// (i >= 300 || [i] != FF) && pc != 2A4
+/// ```
+///
+/// Numbers are in hex, like everywhere else in the debugger. [x] is the byte at
+/// address x in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, Comparison, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Parses a condition written out as above
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens: tokens, position: 0 };
+
+        let condition = parser.or()?;
+        match parser.peek() {
+            None => Ok(condition),
+            Some(t) => Err(format!("didn't expect {} there", t)),
+        }
+    }
+
+    /// Whether the condition holds for the given machine
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        match *self {
+            Condition::Compare(ref a, comparison, ref b) => {
+                let (a, b) = (a.value(cpu), b.value(cpu));
+                match comparison {
+                    Comparison::Equal => a == b,
+                    Comparison::NotEqual => a != b,
+                    Comparison::Less => a < b,
+                    Comparison::LessOrEqual => a <= b,
+                    Comparison::Greater => a > b,
+                    Comparison::GreaterOrEqual => a >= b,
+                }
+            },
+            Condition::And(ref a, ref b) => a.holds(cpu) && b.holds(cpu),
+            Condition::Or(ref a, ref b) => a.holds(cpu) || b.holds(cpu),
+        }
+    }
+}
+
+/// Splits a condition into words and symbols
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else if "()[]".contains(c) {
+            tokens.push(c.to_string());
+            i += 1;
+        } else {
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = match pair.as_str() {
+                "==" | "!=" | "<=" | ">=" | "&&" | "||" => pair,
+                _ if c == '<' || c == '>' => c.to_string(),
+                _ => return Err(format!("didn't expect {} there", c)),
+            };
+            i += symbol.len();
+            tokens.push(symbol);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a condition from its tokens, from the loosest binding operator down
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.position) {
+            Some(t) => {
+                self.position += 1;
+                Ok(t.clone())
+            },
+            None => Err("the condition ends too soon".to_owned()),
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.next()? {
+            ref t if t == token => Ok(()),
+            t => Err(format!("expected {} but found {}", token, t)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.peek() == Some("||") {
+            self.position += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.position += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.comparison()?));
+        }
+        Ok(condition)
+    }
+
+    fn comparison(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some("(") {
+            self.position += 1;
+            let condition = self.or()?;
+            self.expect(")")?;
+            return Ok(condition);
+        }
+
+        let a = self.operand()?;
+        let comparison = match self.next()?.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterOrEqual,
+            t => return Err(format!("expected a comparison but found {}", t)),
+        };
+        let b = self.operand()?;
+
+        Ok(Condition::Compare(a, comparison, b))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self.next()?;
+        if token == "[" {
+            let addr = self.operand()?;
+            self.expect("]")?;
+            return Ok(Operand::Memory(Box::new(addr)));
+        }
+
+        match (Register::from_name(&token), parse_hex(&token)) {
+            (Some(r), _) => Ok(Operand::Register(r)),
+            (None, Some(n)) => Ok(Operand::Number(n)),
+            (None, None) => Err(format!("expected a register, number or [address] but found {}", token)),
+        }
+    }
+}
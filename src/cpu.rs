@@ -2,19 +2,29 @@
 // Author: Joshua Holmes
 //
 
-use rand;
 use rand::distributions::{IndependentSample, Range};
-use std::cmp::Ordering;
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, Read};
 use std::path::Path;
-use std::thread;
-use std::time::{SystemTime, Duration};
+use std::slice;
+use tracing;
 
-use display::Display;
+use crash;
+use device::Devices;
+use font::{self, Font};
+use frame;
+use history::InstructionHistory;
 use keyboard::Keyboard;
-use opcode::OpCode;
+use machine::{FetchCheck, MachineConfig, SpriteOverrun};
+use megachip::MegaChip;
+use opcode::{OpCode, INSTR_SIZE};
+use savestate::Fnv;
+use serialization;
+use variant::Variant;
+use xochip::XoChip;
+use xorshift::Xorshift;
 
 /// How many bytes of system memory there are
 pub const MEMORY_LENGTH: usize = 0xFFF;
@@ -22,14 +32,24 @@ pub const MEMORY_LENGTH: usize = 0xFFF;
 pub const STACK_LENGTH: usize = 0x10;
 /// The number of data registers we have
 pub const NUM_REGISTERS: usize = 0x10;
-/// The address at which the system's font data starts in memory
+/// The default address at which the system's font data starts in memory
 pub const FONT_SET_START_ADDR: usize = 0x000;
-/// The address in memory where the user program begins
+/// The default address in memory where the user program begins
 pub const USER_PROGRAM_START_ADDR: usize = 0x200;
+/// The address in memory where ETI-660 programs begin
+pub const ETI660_PROGRAM_START_ADDR: usize = 0x600;
+/// The address where hi-res programs begin executing, just past their interpreter patch
+pub const HIRES_PROGRAM_START_ADDR: usize = 0x2C0;
+/// How many recently executed instructions are kept by default for post-mortem debugging
+pub const HISTORY_LENGTH: usize = 200;
 /// The number of pixels in our virtual display width
 pub const VIRTUAL_DISPLAY_WIDTH: usize = 64;
 /// The number of pixels in our virtual display height
 pub const VIRTUAL_DISPLAY_HEIGHT: usize = 32;
+/// The number of pixels in our virtual display height in hi-res mode
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+/// The VRAM row with every pixel off
+const BLANK_ROW: u64 = 0;
 /// The fontset of the interpreter that can be referenced by user programs
 pub const FONT_SET: [u8; 80] = [ 0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0x0
                                  0x20, 0x60, 0x20, 0x20, 0x70,   // 0x1
@@ -51,6 +71,8 @@ pub const FONT_SET: [u8; 80] = [ 0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0x0
 #[derive(Debug)]
 pub enum ProgramLoadError {
     IoError(io::Error),
+    /// the program is this many bytes, more than fit in memory after where it's loaded
+    TooBig(usize),
 }
 
 impl From<io::Error> for ProgramLoadError {
@@ -59,10 +81,231 @@ impl From<io::Error> for ProgramLoadError {
     }
 }
 
+impl fmt::Display for ProgramLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProgramLoadError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            ProgramLoadError::TooBig(size) => write!(f, "the program is too big to fit into system memory. Size: {}", size),
+        }
+    }
+}
+
+impl Error for ProgramLoadError {}
+
+/// A snapshot of the CPU's registers, stack and recent history, for diagnosing faults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuState {
+    pub variant: Variant,
+    pub data_registers: [u8; NUM_REGISTERS],
+    pub i_register: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub program_counter: usize,
+    pub stack_pointer: usize,
+    pub stack: [usize; STACK_LENGTH],
+    /// the (PC, instruction) pairs most recently executed, oldest first
+    pub history: Vec<(usize, u16)>,
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PC: 0x{:03X}  I: 0x{:03X}  SP: {}  DT: {}  ST: {}",
+            self.program_counter, self.i_register, self.stack_pointer, self.delay_timer, self.sound_timer)?;
+
+        for (i, v) in self.data_registers.iter().enumerate() {
+            write!(f, "V{:X}: {:02X}{}", i, v, if i % 8 == 7 { "\n" } else { "  " })?;
+        }
+
+        write!(f, "Stack:")?;
+        for addr in self.stack[..self.stack_pointer].iter() {
+            write!(f, " 0x{:03X}", addr)?;
+        }
+        writeln!(f)?;
+
+        writeln!(f, "Recent instructions:")?;
+        for &(pc, instruction) in self.history.iter() {
+            let disasm = match OpCode::from_u16(instruction, self.variant) {
+                Some(o) => o.disasm_str,
+                None => "???".to_owned(),
+            };
+
+            writeln!(f, "  0x{:03X}: {:04X}  {}", pc, instruction, disasm)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The kind of memory access an instruction tried to make
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+    /// the I register was pointed at an address
+    Pointer,
+}
+
+/// Errors that stop the user program from running any further
+#[derive(Debug)]
+pub enum EmulationError {
+    /// an instruction touched memory it isn't allowed to
+    MemoryFault {
+        address: usize,
+        access: MemoryAccess,
+        program_counter: usize,
+        instruction: String,
+    },
+    /// CALL was executed with the stack already full
+    StackOverflow(Box<CpuState>),
+    /// RET was executed with nothing on the stack
+    StackUnderflow(Box<CpuState>),
+    /// the instruction at the program counter isn't one we know how to execute
+    UnknownOpcode(u16, Box<CpuState>),
+    /// SYS was executed with SYS set to fault, calling the given address
+    SysCall(usize, Box<CpuState>),
+    /// an instruction was fetched from an odd address, reached from the instruction at the given address if known
+    MisalignedFetch(Option<usize>, Box<CpuState>),
+    /// an instruction was fetched from the last byte of memory, reached from the instruction at the given address if known
+    FetchPastEnd(Option<usize>, Box<CpuState>),
+    /// in paranoid mode, the instruction at the given address left the machine in a
+    /// state no program should be able to, as described
+    InvariantViolation(usize, String, Box<CpuState>),
+}
+
+impl EmulationError {
+    /// The CPU state captured when the fault happened, if the fault carries one
+    pub fn state(&self) -> Option<&CpuState> {
+        match *self {
+            EmulationError::StackOverflow(ref state) |
+            EmulationError::StackUnderflow(ref state) |
+            EmulationError::UnknownOpcode(_, ref state) |
+            EmulationError::SysCall(_, ref state) |
+            EmulationError::MisalignedFetch(_, ref state) |
+            EmulationError::FetchPastEnd(_, ref state) |
+            EmulationError::InvariantViolation(_, _, ref state) => Some(state),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmulationError::MemoryFault { address, access, program_counter, ref instruction } => {
+                write!(f, "Memory fault: {:?} of address 0x{:03X} by \"{}\" at 0x{:03X}", access, address, instruction, program_counter)
+            },
+            EmulationError::StackOverflow(ref state) => {
+                write!(f, "Stack overflow: no room to call another subroutine at 0x{:03X}", state.program_counter)
+            },
+            EmulationError::StackUnderflow(ref state) => {
+                write!(f, "Stack underflow: no address on the stack to return to at 0x{:03X}", state.program_counter)
+            },
+            EmulationError::UnknownOpcode(instruction, ref state) => {
+                write!(f, "Unimplemented opcode 0x{:04X} at 0x{:03X}", instruction, state.program_counter)
+            },
+            EmulationError::SysCall(address, ref state) => {
+                write!(f, "SYS call to machine code at 0x{:03X} from 0x{:03X}", address, state.program_counter)
+            },
+            EmulationError::MisalignedFetch(source, ref state) => {
+                write!(f, "Misaligned fetch: instruction at odd address 0x{:03X}{}", state.program_counter, reached_from(source))
+            },
+            EmulationError::FetchPastEnd(source, ref state) => {
+                write!(f, "Fetch past the end of memory: instruction at 0x{:03X}{}", state.program_counter, reached_from(source))
+            },
+            EmulationError::InvariantViolation(address, ref problem, _) => {
+                write!(f, "Interpreter bug: after the instruction at 0x{:03X}, {}", address, problem)
+            },
+        }
+    }
+}
+
+/// Problems the user program can carry on from, reported without stopping execution
+#[derive(Debug)]
+pub enum EmulationWarning {
+    /// a sprite ran past the end of memory, so the read was clamped or wrapped
+    SpriteOverrun {
+        address: usize,
+        length: usize,
+        program_counter: usize,
+        instruction: String,
+    },
+    /// SYS was executed, which does nothing here
+    SysCall {
+        address: usize,
+        program_counter: usize,
+    },
+    /// an instruction was fetched from an odd address
+    MisalignedFetch {
+        address: usize,
+        /// the instruction executed just before, which jumped there, if known
+        source: Option<usize>,
+    },
+    /// an instruction was fetched from the last byte of memory, so its second byte wrapped to the start
+    FetchPastEnd {
+        address: usize,
+        /// the instruction executed just before, if known
+        source: Option<usize>,
+    },
+}
+
+impl fmt::Display for EmulationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmulationWarning::SpriteOverrun { address, length, program_counter, ref instruction } => {
+                write!(f, "Sprite overrun: {} bytes at 0x{:03X} run past the end of memory in \"{}\" at 0x{:03X}", length, address, instruction, program_counter)
+            },
+            EmulationWarning::SysCall { address, program_counter } => {
+                write!(f, "Ignored SYS call to machine code at 0x{:03X} from 0x{:03X}", address, program_counter)
+            },
+            EmulationWarning::MisalignedFetch { address, source } => {
+                write!(f, "Misaligned fetch: instruction at odd address 0x{:03X}{}", address, reached_from(source))
+            },
+            EmulationWarning::FetchPastEnd { address, source } => {
+                write!(f, "Fetch past the end of memory: instruction at 0x{:03X}{}", address, reached_from(source))
+            },
+        }
+    }
+}
+
+/// Describes where a bad fetch was reached from, for the end of an error message
+fn reached_from(source: Option<usize>) -> String {
+    match source {
+        Some(addr) => format!(", reached from the instruction at 0x{:03X}", addr),
+        None => String::new(),
+    }
+}
+
+/// The bit of a VRAM row that holds the pixel in the given column
+pub fn pixel_bit(x: usize) -> u64 {
+    1 << (VIRTUAL_DISPLAY_WIDTH - 1 - x)
+}
+
+/// The columns of the pixels that are on in a VRAM row, left to right
+pub fn lit_pixels(row: u64) -> impl Iterator<Item = usize> {
+    (0..VIRTUAL_DISPLAY_WIDTH).filter(move |&x| row & pixel_bit(x) != 0)
+}
+
+/// Iterator over the pixels toggled by the last DRW or CLS, as (x, y, whether it's now on)
+pub struct ChangedPixels<'a> {
+    vram: &'a [u64; HIRES_DISPLAY_HEIGHT],
+    changes: slice::Iter<'a, (usize, usize)>,
+}
+
+impl<'a> Iterator for ChangedPixels<'a> {
+    type Item = (usize, usize, bool);
+
+    fn next(&mut self) -> Option<(usize, usize, bool)> {
+        self.changes.next().map(|&(x, y)| (x, y, self.vram[y] & pixel_bit(x) != 0))
+    }
+}
+
 /// Structure to represent the virtual CPU and perform execution
+#[derive(Serialize, Deserialize)]
 pub struct Cpu {
-    /// the main system memory
-    pub memory: [u8; MEMORY_LENGTH],
+    /// the layout of the machine being emulated
+    pub config: MachineConfig,
+    /// the main system memory, sized according to the machine configuration
+    pub memory: Vec<u8>,
     /// the system data registers, V0 through VF
     pub data_registers: [u8; NUM_REGISTERS],
     /// the I register, used for storing addresses
@@ -73,134 +316,473 @@ pub struct Cpu {
     pub sound_timer: u8,
     /// the program counter, points to the current instruction in memory
     pub program_counter: usize,
+    /// the instruction currently being executed
+    pub current_instruction: u16,
+    /// the (PC, instruction) pairs most recently executed
+    pub history: InstructionHistory,
     /// the stack pointer, points to the current index in the stack
     pub stack_pointer: usize,
     /// the call stack, stores return addresses from subroutines
     pub stack: [usize; STACK_LENGTH],
     /// use this to know if the PC is past the end of the program
     pub program_length: usize,
-    /// the system's "VRAM" -- the virtual screen buffer
-    pub vram: [[bool; VIRTUAL_DISPLAY_WIDTH]; VIRTUAL_DISPLAY_HEIGHT],
+    /// the system's "VRAM" -- the virtual screen buffer, big enough for hi-res mode,
+    /// as one 64-bit row per line with the leftmost pixel in the high bit
+    #[serde(with = "serialization::vram")]
+    pub vram: [u64; HIRES_DISPLAY_HEIGHT],
+    /// how many rows of the VRAM are actually on screen
+    pub display_height: usize,
     /// the flag that says whether we need to redraw the screen
     pub draw_flag: bool,
     /// the system's keyboard
     pub keyboard: Keyboard,
-    /// the timestamp of the last timer decrement
-    last_timer_decrease: SystemTime,
+    /// the Mega-Chip screen, palette and sound state
+    pub mega: MegaChip,
+    /// the XO-CHIP buzzer state
+    pub xo: XoChip,
+    /// warnings raised since the frontend last looked at them
+    #[serde(skip)]
+    pub warnings: Vec<EmulationWarning>,
+    /// the (x, y) of each pixel toggled by the last DRW or CLS; see changed_pixels
+    #[serde(skip)]
+    pub pixel_changes: Vec<(usize, usize)>,
+    /// the devices hooked up to memory, which aren't part of the machine's state; see Emulator::restore
+    #[serde(skip)]
+    pub devices: Devices,
+    /// the random number generator used by RND, saved so a restored machine rolls
+    /// the same numbers it would have
+    rng: Xorshift,
 }
 
 impl Cpu {
-    /// Init the system from a file path pointing to a CHIP-8 program file
-    pub fn init_from_file_path(filepath: &str) -> Result<Cpu, ProgramLoadError> {
-        let path = Path::new(filepath);
-
-        let mut file = match File::open(&path) {
-            Err(e) => panic!("Couldn't open program file. Error message: {}", Error::description(&e)),
-            Ok(file) => file,
-        };
+    /// Init the system from a file path pointing to a CHIP-8 program file. If no
+    /// machine configuration is given, the variant is detected from the program.
+    pub fn init_from_file_path(filepath: &str, config: Option<MachineConfig>) -> Result<Cpu, ProgramLoadError> {
+        let mut file = File::open(Path::new(filepath))?;
 
-        Cpu::init_from_file(&mut file)
+        Cpu::init_from_file(&mut file, config)
     }
 
     /// Init the system from a File that contains a CHIP-8 program
-    pub fn init_from_file(file: &mut File) -> Result<Cpu, ProgramLoadError> {
+    pub fn init_from_file(file: &mut File, config: Option<MachineConfig>) -> Result<Cpu, ProgramLoadError> {
         // read the program into a buffer
         let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
 
-        match file.read_to_end(&mut buf) {
-            Err(e) => panic!("Couldn't read program file. Error message: {}", Error::description(&e)),
-            Ok(_) => (),
-        };
-
-        Cpu::init_from_buffer(buf)
+        Cpu::init_from_buffer(buf, config)
     }
 
     /// Init the system from a byte vector containing a CHIP-8 program
-    pub fn init_from_buffer(buf: Vec<u8>) -> Result<Cpu, ProgramLoadError> {
+    pub fn init_from_buffer(buf: Vec<u8>, config: Option<MachineConfig>) -> Result<Cpu, ProgramLoadError> {
+        let config = match config {
+            Some(c) => c,
+            None => MachineConfig::new(Variant::detect(&buf)),
+        };
+
         // copy the user program into system memory
-        if buf.len() > MEMORY_LENGTH - USER_PROGRAM_START_ADDR {
-            panic!("Program file too big to fit into system memory. Size: {}", buf.len())
+        if buf.len() > config.memory_length.saturating_sub(config.program_start_addr) {
+            return Err(ProgramLoadError::TooBig(buf.len()));
         }
 
-        let mut memory = [0u8; MEMORY_LENGTH];
+        let mut memory = vec![0u8; config.memory_length];
 
         for (i, x) in buf.iter().enumerate() {
-            memory[USER_PROGRAM_START_ADDR + i] = *x;
+            memory[config.program_start_addr + i] = *x;
         }
 
         // copy the font set into system memory
-        for (arr_index, buf_index) in (FONT_SET_START_ADDR..FONT_SET_START_ADDR + FONT_SET.len()).enumerate() {
+        for (arr_index, buf_index) in (config.font_start_addr..config.font_start_addr + FONT_SET.len()).enumerate() {
             memory[buf_index] = FONT_SET[arr_index];
         }
+        if config.variant.has_big_font() {
+            let start = config.font_start_addr + FONT_SET.len();
+            memory[start..start + font::SCHIP_BIG_FONT.len()].copy_from_slice(&font::SCHIP_BIG_FONT);
+        }
 
         Ok(Cpu{
+            config: config,
             memory: memory,
             data_registers: [0u8; NUM_REGISTERS],
             i_register: 0,
             delay_timer: 0,
             sound_timer: 0,
-            program_counter: USER_PROGRAM_START_ADDR,
+            program_counter: config.execution_start_addr,
+            current_instruction: 0,
+            history: InstructionHistory::new(config.history_length),
             stack_pointer: 0,
             stack: [0; STACK_LENGTH],
             program_length: buf.len(),
-            last_timer_decrease: SystemTime::now(),
-            vram: [[false; VIRTUAL_DISPLAY_WIDTH]; VIRTUAL_DISPLAY_HEIGHT],
+            vram: [BLANK_ROW; HIRES_DISPLAY_HEIGHT],
+            display_height: config.variant.display_height(),
             draw_flag: false,
             keyboard: Keyboard::new(),
+            mega: MegaChip::new(),
+            xo: XoChip::new(),
+            warnings: Vec::new(),
+            pixel_changes: Vec::new(),
+            devices: Devices::new(),
+            rng: Xorshift::from_entropy(),
         })
     }
 
-    /// Fetches one opcode from memory and executes it.
-    pub fn fetch_and_execute(&mut self, display: &mut Display) -> bool {
+    /// Fetches one opcode from memory and executes it. Returns false once the
+    /// program has finished. Ticking the timers and drawing the screen are left
+    /// to the caller.
+    pub fn step(&mut self) -> Result<bool, EmulationError> {
         // if the program counter is past the program, then we've completed execution
-        if self.program_counter >= USER_PROGRAM_START_ADDR + self.program_length {
-            return false;
+        if self.program_counter >= self.config.program_start_addr + self.program_length {
+            return Ok(false);
         }
 
         // fetch the instruction and execute it
-        let instruction = ((self.memory[self.program_counter] as u16) << 8) | (self.memory[self.program_counter + 1] as u16);
-        let opcode = match OpCode::from_u16(instruction) {
+        let pc = self.program_counter;
+        let _span = tracing::trace_span!("instruction", pc = pc).entered();
+        self.check_fetch(pc)?;
+        let len = self.memory.len();
+        let instruction = ((self.memory[pc % len] as u16) << 8) | (self.memory[(pc + 1) % len] as u16);
+        self.current_instruction = instruction;
+
+        self.history.push(pc, instruction);
+
+        let opcode = match OpCode::from_u16(instruction, self.config.variant) {
             Some(o) => o,
-            None => panic!("Error! Unimplemented opcode 0x{:4X}", instruction),
+            None => return Err(EmulationError::UnknownOpcode(instruction, Box::new(self.state()))),
         };
 
-        //println!("{}", opcode.disasm_str);
-        (opcode.operation)(&opcode.args, &mut *self);
+        trace!(target: "decode", "0x{:03X}: {:04X}  {}", pc, instruction, opcode.disasm_str);
+        crash::begin_instruction(self, pc, instruction);
+        let result = (opcode.operation)(&opcode.args, &mut *self);
+        crash::end_instruction();
+        result?;
 
-        // see if we need to decrement the timers and draw the screen (both at 60Hz)
-        let curr_time = SystemTime::now();
+        Ok(true)
+    }
 
-        match curr_time.duration_since(self.last_timer_decrease).unwrap().cmp(&Duration::new(0, 16_666_666)) {
-            Ordering::Greater => {
-                // decrement the timers
-                if self.delay_timer > 0 {
-                    self.delay_timer -= 1;
-                }
+    /// Looks for an instruction fetched from an odd address or from past the end of
+    /// memory, and warns or faults as the quirks say, naming the instruction before it
+    fn check_fetch(&mut self, pc: usize) -> Result<(), EmulationError> {
+        let source = self.history.last().map(|(source_pc, _)| source_pc);
+        let past_end = pc + 1 >= self.memory.len();
+        // only the jump onto an odd address is reported, not every instruction run after it
+        let misaligned = pc % 2 == 1 && source.is_none_or(|s| s % 2 == 0);
 
-                if self.sound_timer > 0 {
-                    self.sound_timer -= 1;
-                }
+        if !past_end && !misaligned {
+            return Ok(());
+        }
+
+        match self.config.quirks.misaligned_fetch {
+            FetchCheck::Ignore => (),
+            FetchCheck::Warn if past_end => self.warnings.push(EmulationWarning::FetchPastEnd { address: pc, source: source }),
+            FetchCheck::Warn => self.warnings.push(EmulationWarning::MisalignedFetch { address: pc, source: source }),
+            FetchCheck::Fault if past_end => return Err(EmulationError::FetchPastEnd(source, Box::new(self.state()))),
+            FetchCheck::Fault => return Err(EmulationError::MisalignedFetch(source, Box::new(self.state()))),
+        }
+
+        Ok(())
+    }
+
+    /// Counts the delay and sound timers down by one 60Hz tick
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /// Looks for anything about the machine that no program, however badly behaved,
+    /// should be able to bring about, and describes the first thing found. Anything
+    /// found is a bug in the interpreter rather than in the program.
+    pub fn invariant_violation(&self) -> Option<String> {
+        if self.stack_pointer > STACK_LENGTH {
+            return Some(format!("SP is {}, past the end of the {}-entry stack", self.stack_pointer, STACK_LENGTH));
+        }
+        if let Some(addr) = self.stack[..self.stack_pointer].iter().find(|&&a| a >= self.memory.len()) {
+            return Some(format!("the stack holds the return address {:03X}, past the end of memory", addr));
+        }
+        // both bytes of the next instruction have to be in memory for it to be fetched whole
+        if self.program_counter + 1 >= self.memory.len() {
+            return Some(format!("PC is {:03X}, too near the end of memory to fetch an instruction", self.program_counter));
+        }
+        // without memory protection ADD I can take I anywhere, and only using it faults
+        if self.config.quirks.memory_protection && self.i_register >= self.memory.len() {
+            return Some(format!("I is {:03X}, past the end of memory, with memory protection on", self.i_register));
+        }
+
+        None
+    }
+
+    /// Looks for anything wrong with where the instruction at pc, which has just
+    /// run, left the PC, and describes the first thing found. Jumps and calls have
+    /// to land on their address, and any other instruction that carries on has to
+    /// move the PC forward by a whole number of instructions, to the next one or
+    /// past a skipped one, or leave it where it is to wait for a key.
+    pub fn step_violation(&self, pc: usize, instruction: u16) -> Option<String> {
+        let nnn = (instruction & 0x0FFF) as usize;
+        let new_pc = self.program_counter;
+
+        match instruction & 0xF000 {
+            0x1000 | 0x2000 if new_pc != nnn => Some(format!("PC is {:03X}, not the {:03X} it jumped to", new_pc, nnn)),
+            0x2000 if self.stack_pointer == 0 || self.stack[self.stack_pointer - 1] != pc => {
+                Some(format!("the CALL at {:03X} isn't on top of the stack to return to", pc))
+            },
+            0x1000 | 0x2000 | 0xB000 => None,
+            0x0000 if instruction == 0x00EE => None,
+            _ if new_pc < pc || new_pc > pc + 3 * INSTR_SIZE || !(new_pc - pc).is_multiple_of(INSTR_SIZE) => {
+                Some(format!("PC moved from {:03X} to {:03X}, which isn't to the next instruction or one after", pc, new_pc))
+            },
+            _ => None,
+        }
+    }
 
-                self.last_timer_decrease = curr_time;
+    /// The program as it was loaded into memory, or as it is now if it has modified itself
+    pub fn program(&self) -> &[u8] {
+        let start = self.config.program_start_addr;
+        &self.memory[start..start + self.program_length]
+    }
+
+    /// A 32-bit hash of everything the program can see or change: memory, registers,
+    /// timers, stack and the monochrome screen, plus the Mega-Chip screen, palette and
+    /// sound, or the XO-CHIP buzzer, on those machines. Two runs with the same checksum
+    /// at the same point have, as near as makes no difference, done the same thing.
+    pub fn checksum(&self) -> u32 {
+        let mut hash = Fnv::new();
+        hash.write(&self.memory);
+        hash.write(&self.data_registers);
+        hash.write(&(self.i_register as u32).to_be_bytes());
+        hash.write(&(self.program_counter as u32).to_be_bytes());
+        hash.write(&[self.delay_timer, self.sound_timer, self.stack_pointer as u8]);
+        for &addr in self.stack.iter() {
+            hash.write(&(addr as u32).to_be_bytes());
+        }
+        for row in self.vram.iter() {
+            hash.write(&row.to_be_bytes());
+        }
+
+        // only on the machines that have them, so CHIP-8 checksums stay as they were
+        match self.config.variant {
+            Variant::MegaChip => {
+                let mega = &self.mega;
+                hash.write(&[mega.enabled as u8, mega.alpha, mega.blend_mode as u8, mega.collision_color]);
+                hash.write(&(mega.sprite_width as u32).to_be_bytes());
+                hash.write(&(mega.sprite_height as u32).to_be_bytes());
+                for &color in mega.palette.iter().chain(mega.frame_buffer.iter()).chain(mega.screen_buffer.iter()) {
+                    hash.write(&color.to_be_bytes());
+                }
+                hash.write(&mega.index_buffer);
+                if let Some(ref sound) = mega.sound {
+                    hash.write(&sound.sample_rate.to_be_bytes());
+                    hash.write(&(sound.start as u32).to_be_bytes());
+                    hash.write(&(sound.length as u32).to_be_bytes());
+                    hash.write(&[sound.looping as u8]);
+                }
+            },
+            Variant::XoChip => {
+                if let Some(ref pattern) = self.xo.pattern {
+                    hash.write(pattern);
+                }
+                hash.write(&[self.xo.pitch]);
             },
-            _ => ()
+            _ => (),
+        }
+
+        hash.0
+    }
+
+    /// Takes a snapshot of the registers, stack and recent history
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            variant: self.config.variant,
+            data_registers: self.data_registers,
+            i_register: self.i_register,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            history: self.history.iter().cloned().collect(),
+        }
+    }
+
+    /// The rows of the screen on display packed into one 64-bit word each, with the
+    /// leftmost pixel in the high bit
+    pub fn packed_rows(&self) -> Vec<u64> {
+        self.vram[..self.display_height].to_vec()
+    }
+
+    /// The screen on display packed eight pixels to a byte, with the leftmost in the
+    /// high bit, row after row
+    pub fn packed_bytes(&self) -> Vec<u8> {
+        frame::pack_bytes(&self.vram[..self.display_height])
+    }
+
+    /// Whether the pixel at the given position is on. The position wraps around the
+    /// screen on display.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.vram[y % self.display_height] & pixel_bit(x % VIRTUAL_DISPLAY_WIDTH) != 0
+    }
+
+    /// Turns the pixel at the given position on or off. The position wraps around the
+    /// screen on display.
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        let bit = pixel_bit(x % VIRTUAL_DISPLAY_WIDTH);
+        let row = &mut self.vram[y % self.display_height];
+
+        if on {
+            *row |= bit;
+        } else {
+            *row &= !bit;
+        }
+    }
+
+    /// Turns every pixel off
+    pub fn clear_vram(&mut self) {
+        self.vram = [BLANK_ROW; HIRES_DISPLAY_HEIGHT];
+    }
+
+    /// Scrolls the screen on display right by dx pixels and down by dy rows, or left
+    /// and up for negative amounts. Whatever scrolls off is lost, and the space left
+    /// behind is blank.
+    pub fn scroll_vram(&mut self, dx: isize, dy: isize) {
+        let height = self.display_height as isize;
+        let old = self.vram;
+
+        for y in 0..height {
+            let src_y = y - dy;
+            let row = if src_y < 0 || src_y >= height { BLANK_ROW } else { old[src_y as usize] };
+
+            self.vram[y as usize] = match dx {
+                dx if dx.unsigned_abs() >= VIRTUAL_DISPLAY_WIDTH => BLANK_ROW,
+                dx if dx >= 0 => row >> dx,
+                dx => row << -dx,
+            };
+        }
+    }
+
+    /// Iterates over the pixels toggled by the last DRW or CLS, so a frontend can
+    /// redraw just those rather than the whole screen. Nothing else that changes
+    /// the screen, like loading a state, is covered, and in Mega-Chip mode the
+    /// monochrome screen isn't drawn on, so this is empty.
+    pub fn changed_pixels(&self) -> ChangedPixels<'_> {
+        ChangedPixels {
+            vram: &self.vram,
+            changes: self.pixel_changes.iter(),
+        }
+    }
+
+    /// Replaces the font in memory, and the big font after it if the font has one
+    pub fn load_font(&mut self, font: &Font) {
+        let start = self.config.font_start_addr;
+        self.memory[start..start + font.data.len()].copy_from_slice(&font.data);
+    }
+
+    /// Checks that the current instruction is allowed to access the given address.
+    /// Reading or writing past the end of memory is always a fault. With memory
+    /// protection on, so is pointing I outside of memory or touching the interpreter
+    /// area below the program (other than the font).
+    pub fn check_memory_access(&self, addr: usize, access: MemoryAccess) -> Result<(), EmulationError> {
+        let mut allowed = addr < self.memory.len() || (access == MemoryAccess::Pointer && !self.config.quirks.memory_protection);
+
+        if allowed && self.config.quirks.memory_protection && addr < self.config.program_start_addr {
+            let font_end = self.config.font_start_addr + font::MAX_FONT_LENGTH;
+            let in_font = addr >= self.config.font_start_addr && addr < font_end;
+
+            allowed = match access {
+                MemoryAccess::Read | MemoryAccess::Pointer => in_font,
+                MemoryAccess::Write => false,
+            };
+        }
+
+        if allowed {
+            return Ok(());
         }
 
-        // refresh the screen, if necessary
-        if self.draw_flag {
-            display.draw_screen(&mut *self);
-            self.draw_flag = false;
+        Err(EmulationError::MemoryFault {
+            address: addr,
+            access: access,
+            program_counter: self.program_counter,
+            instruction: self.current_disasm(),
+        })
+    }
+
+    /// Returns the disassembly of the instruction currently being executed
+    fn current_disasm(&self) -> String {
+        match OpCode::from_u16(self.current_instruction, self.config.variant) {
+            Some(o) => o.disasm_str,
+            None => format!("{:04X}", self.current_instruction),
         }
+    }
 
-        // terrible hack to make this thing run more slowly until proper timers are implemented
-        thread::sleep(Duration::from_millis(2));
+    /// Reads a byte of memory on behalf of the current instruction
+    pub fn read_memory(&mut self, addr: usize) -> Result<u8, EmulationError> {
+        self.check_memory_access(addr, MemoryAccess::Read)?;
+        Ok(self.devices.read(addr, self.memory[addr]))
+    }
 
-        true
+    /// Reads a run of bytes from memory on behalf of the current instruction
+    pub fn read_memory_range(&mut self, addr: usize, len: usize) -> Result<Vec<u8>, EmulationError> {
+        if len > 0 {
+            self.check_memory_access(addr, MemoryAccess::Read)?;
+            self.check_memory_access(addr + len - 1, MemoryAccess::Read)?;
+        }
+
+        let mut bytes = self.memory[addr..addr + len].to_vec();
+        if !self.devices.is_empty() {
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = self.devices.read(addr + i, *byte);
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a sprite for DRW. A sprite that runs past the end of memory is
+    /// clamped or wrapped according to the machine configuration, with a warning.
+    pub fn read_sprite(&mut self, addr: usize, len: usize) -> Result<Vec<u8>, EmulationError> {
+        if addr + len <= self.memory.len() {
+            return self.read_memory_range(addr, len);
+        }
+
+        if addr < self.memory.len() {
+            self.check_memory_access(addr, MemoryAccess::Read)?;
+        }
+
+        let warning = EmulationWarning::SpriteOverrun {
+            address: addr,
+            length: len,
+            program_counter: self.program_counter,
+            instruction: self.current_disasm(),
+        };
+        self.warnings.push(warning);
+
+        let memory_length = self.memory.len();
+        let sprite = (addr..addr + len).map(|a| {
+            match self.config.quirks.sprite_overrun {
+                SpriteOverrun::Clamp => if a < memory_length { self.devices.read(a, self.memory[a]) } else { 0 },
+                SpriteOverrun::Wrap => self.devices.read(a % memory_length, self.memory[a % memory_length]),
+            }
+        }).collect();
+
+        Ok(sprite)
+    }
+
+    /// Writes a byte of memory on behalf of the current instruction
+    pub fn write_memory(&mut self, addr: usize, value: u8) -> Result<(), EmulationError> {
+        self.check_memory_access(addr, MemoryAccess::Write)?;
+        self.memory[addr] = value;
+        self.devices.write(addr, value);
+        Ok(())
     }
 
     /// Returns a random byte, used for the RND opcode
-    pub fn get_random_byte(&self) -> u8 {
-        let mut rng = rand::thread_rng();
-        Range::new(0, 256).ind_sample(&mut rng) as u8
+    pub fn get_random_byte(&mut self) -> u8 {
+        Range::new(0, 256).ind_sample(&mut self.rng) as u8
+    }
+
+    /// Restarts the random number generator from a seed, so RND gives the same
+    /// sequence every run
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Xorshift::from_seed(seed);
     } 
 }
\ No newline at end of file
@@ -0,0 +1,138 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cpu;
+use cpu::{Cpu, CpuState, EmulationError, NUM_REGISTERS, STACK_LENGTH};
+use variant::Variant;
+
+/// How many bytes of memory are shown on each line of the memory dump
+const DUMP_BYTES_PER_LINE: usize = 16;
+
+/// The machine as the instruction being executed found it, kept small enough to
+/// copy every instruction so the panic hook has something to show
+#[derive(Copy, Clone)]
+struct InFlight {
+    variant: Variant,
+    pc: usize,
+    instruction: u16,
+    data_registers: [u8; NUM_REGISTERS],
+    i_register: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    stack_pointer: usize,
+    stack: [usize; STACK_LENGTH],
+}
+
+thread_local! {
+    /// the instruction this thread is executing, if it's executing one
+    static IN_FLIGHT: Cell<Option<InFlight>> = const { Cell::new(None) };
+}
+
+/// Notes the instruction about to be executed, and the machine as it stands, for
+/// the panic hook to print if executing it panics
+pub fn begin_instruction(cpu: &Cpu, pc: usize, instruction: u16) {
+    let in_flight = InFlight {
+        variant: cpu.config.variant,
+        pc: pc,
+        instruction: instruction,
+        data_registers: cpu.data_registers,
+        i_register: cpu.i_register,
+        delay_timer: cpu.delay_timer,
+        sound_timer: cpu.sound_timer,
+        stack_pointer: cpu.stack_pointer,
+        stack: cpu.stack,
+    };
+    IN_FLIGHT.with(|f| f.set(Some(in_flight)));
+}
+
+/// Notes that the instruction noted by begin_instruction has finished
+pub fn end_instruction() {
+    IN_FLIGHT.with(|f| f.set(None));
+}
+
+/// Installs a panic hook that, when a panic happens partway through an
+/// instruction, prints the machine as the instruction found it before the usual
+/// panic message, so a bug report has enough in it to act on. Panics anywhere
+/// else are reported as they always were.
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        if let Some(f) = IN_FLIGHT.with(|f| f.get()) {
+            let state = CpuState {
+                variant: f.variant,
+                data_registers: f.data_registers,
+                i_register: f.i_register,
+                delay_timer: f.delay_timer,
+                sound_timer: f.sound_timer,
+                program_counter: f.pc,
+                stack_pointer: f.stack_pointer.min(STACK_LENGTH),
+                stack: f.stack,
+                history: vec![(f.pc, f.instruction)],
+            };
+
+            let mut stderr = io::stderr();
+            let _ = writeln!(stderr, "The emulator crashed executing the instruction at 0x{:03X}. This is a bug in the emulator,", f.pc);
+            let _ = writeln!(stderr, "not the program. Please include the following in a bug report:");
+            let _ = writeln!(stderr);
+            let _ = writeln!(stderr, "Variant: {:?}", f.variant);
+            let _ = write!(stderr, "{}", state);
+            let _ = writeln!(stderr);
+        }
+
+        previous(info);
+    }));
+}
+
+/// Writes a crash report describing the fault, the CPU state, the recent instruction
+/// history and a memory dump next to the program file. Returns the path of the report.
+pub fn write_crash_report(cpu: &Cpu, error: &EmulationError, program_path: &str) -> io::Result<PathBuf> {
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+
+    let program_path = Path::new(program_path);
+    let stem = match program_path.file_stem() {
+        Some(s) => s.to_string_lossy().into_owned(),
+        None => "program".to_owned(),
+    };
+    let report_path = program_path.with_file_name(format!("{}-crash-{}.txt", stem, timestamp));
+
+    let mut file = File::create(&report_path)?;
+
+    writeln!(file, "CHIP-8 crash report")?;
+    writeln!(file, "Program: {}", program_path.display())?;
+    writeln!(file, "Variant: {:?}", cpu.config.variant)?;
+    writeln!(file)?;
+    writeln!(file, "{}", error)?;
+    writeln!(file)?;
+    write!(file, "{}", cpu.state())?;
+    writeln!(file)?;
+    writeln!(file, "Memory:")?;
+
+    // Mega-Chip memory is mostly empty, so only dump as far as the program goes
+    let program_end = cpu.config.program_start_addr + cpu.program_length;
+    let dump_end = if program_end > cpu::MEMORY_LENGTH { program_end } else { cpu::MEMORY_LENGTH };
+    let dump_end = if dump_end > cpu.memory.len() { cpu.memory.len() } else { dump_end };
+
+    for (line, bytes) in cpu.memory[..dump_end].chunks(DUMP_BYTES_PER_LINE).enumerate() {
+        write!(file, "{:06X}:", line * DUMP_BYTES_PER_LINE)?;
+
+        for b in bytes.iter() {
+            write!(file, " {:02X}", b)?;
+        }
+
+        writeln!(file)?;
+    }
+
+    Ok(report_path)
+}
@@ -0,0 +1,668 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate sdl2;
+
+use egui::{self, Color32, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, RichText, TextStyle, Ui, ViewportId, WidgetText};
+use egui_dock::{DockArea, DockState, NodeIndex, Style, TabViewer};
+use sdl2::{Sdl, VideoSubsystem};
+use sdl2::event::{Event, WindowEventId};
+use sdl2::keyboard::{self, Keycode, Mod};
+use sdl2::mouse::Mouse;
+use sdl2::video::{GLContext, Window};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use chip8::cheats::parse_hex;
+use chip8::cpu::{Cpu, NUM_REGISTERS, STACK_LENGTH};
+use chip8::debugger::Debugger;
+use emu_thread::InputMessage;
+use gui_painter::GuiPainter;
+
+/// The size the debugger's window opens at
+const WINDOW_SIZE: (u32, u32) = (960, 640);
+/// The shortest time between drawing the GUI, so it's no busier than the screen
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// How many instructions before the program counter the disassembly starts
+const DISASM_BEFORE: usize = 8;
+/// How many instructions the disassembly shows
+const DISASM_LENGTH: usize = 32;
+/// How many bytes make up a row of the memory view
+const MEMORY_ROW: usize = 16;
+/// How many points the panels scroll for each notch of the mouse wheel
+const SCROLL_STEP: f32 = 40.0;
+/// The color the instruction at the program counter is picked out in
+const PC_COLOR: Color32 = Color32::from_rgb(0xFF, 0x60, 0x60);
+/// The color the byte the I register points at is picked out in
+const I_COLOR: Color32 = Color32::from_rgb(0x60, 0xFF, 0x60);
+
+/// One line of the debugger's disassembly
+pub struct DisasmLine {
+    pub addr: usize,
+    pub instruction: u16,
+    pub text: String,
+    /// the name of the address, if it has one
+    pub label: Option<String>,
+}
+
+/// What the debugger GUI shows: the machine at the end of a frame, or wherever it's stopped
+pub struct DebugSnapshot {
+    pub registers: [u8; NUM_REGISTERS],
+    pub i_register: usize,
+    pub program_counter: usize,
+    /// the return addresses on the stack, oldest first
+    pub stack: Vec<usize>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub memory: Vec<u8>,
+    /// the instructions around the program counter
+    pub disassembly: Vec<DisasmLine>,
+    /// the address of each breakpoint, with how the debugger describes it
+    pub breakpoints: Vec<(usize, String)>,
+    /// whether emulation is paused, so the machine can be changed
+    pub paused: bool,
+}
+
+impl DebugSnapshot {
+    /// Takes a snapshot of the machine, naming addresses and listing breakpoints the way the debugger does
+    pub fn capture(cpu: &Cpu, debugger: &Debugger, paused: bool) -> DebugSnapshot {
+        let symbols = debugger.symbols();
+        let start = cpu.program_counter.saturating_sub(DISASM_BEFORE * 2);
+        let disassembly = (0..DISASM_LENGTH)
+            .map(|n| start + n * 2)
+            .take_while(|&addr| addr + 1 < cpu.memory.len())
+            .map(|addr| {
+                let instruction = ((cpu.memory[addr] as u16) << 8) | (cpu.memory[addr + 1] as u16);
+                DisasmLine {
+                    addr: addr,
+                    instruction: instruction,
+                    text: symbols.disasm(instruction, cpu.config.variant),
+                    label: symbols.name_of(addr).map(|name| name.to_owned()),
+                }
+            })
+            .collect();
+
+        let breakpoints = debugger.breakpoints().iter().map(|b| {
+            let description = match b.condition {
+                Some(_) => format!("{} if {}", debugger.describe(b.addr), b.condition_text),
+                None => debugger.describe(b.addr),
+            };
+            (b.addr, description)
+        }).collect();
+
+        DebugSnapshot {
+            registers: cpu.data_registers,
+            i_register: cpu.i_register,
+            program_counter: cpu.program_counter,
+            stack: cpu.stack[..cpu.stack_pointer.min(STACK_LENGTH)].to_vec(),
+            delay_timer: cpu.delay_timer,
+            sound_timer: cpu.sound_timer,
+            memory: cpu.memory.clone(),
+            disassembly: disassembly,
+            breakpoints: breakpoints,
+            paused: paused,
+        }
+    }
+}
+
+/// What the debugger GUI asks the emulator thread to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// start or stop sending a snapshot every frame
+    Watch(bool),
+    Pause,
+    Run,
+    /// execute one instruction, pausing first if running
+    Step,
+    /// set a breakpoint, given the way the break command takes one
+    SetBreakpoint(String),
+    /// remove the breakpoint with the given number, in the order they were set
+    RemoveBreakpoint(usize),
+    /// write a byte to memory, while paused
+    Poke(usize, u8),
+    /// set a register by name to a value, while paused
+    SetRegister(String, usize),
+}
+
+/// The panels the debugger's window is made of, which can be dragged around and docked
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Panel {
+    Registers,
+    Memory,
+    Disassembly,
+    Breakpoints,
+    Settings,
+}
+
+/// A window with the debugger's panels in, for stepping through a program and
+/// looking into the machine with the mouse rather than the terminal. It's drawn
+/// with egui in an OpenGL context of its own.
+pub struct DebugGui {
+    // the painter's textures and buffers live in the context, so it's dropped first
+    painter: GuiPainter,
+    gl_context: GLContext,
+    window: Window,
+    video: VideoSubsystem,
+    egui: egui::Context,
+    dock: DockState<Panel>,
+    panels: Panels,
+    /// what's happened in the window since it was last drawn
+    events: Vec<egui::Event>,
+    modifiers: Modifiers,
+    /// whether anything's changed since the window was last drawn
+    dirty: bool,
+    /// when egui wants drawing again even if nothing changes, for animations
+    repaint_at: Option<Instant>,
+    last_frame: Instant,
+    started: Instant,
+}
+
+impl DebugGui {
+    /// Opens the debugger's window
+    pub fn new(sdl_context: &Sdl) -> Result<DebugGui, String> {
+        let video = sdl_context.video()?;
+        let window = video.window("CHIP-8: Debugger", WINDOW_SIZE.0, WINDOW_SIZE.1)
+            .opengl()
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let gl_context = window.gl_create_context()?;
+        window.gl_make_current(&gl_context)?;
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const _);
+        // waiting for the display to swap would hold up the screen as well
+        video.gl_set_swap_interval(0);
+
+        let painter = GuiPainter::new()?;
+
+        // the disassembly gets the middle, with the registers and breakpoints to
+        // its left and the memory to its right. Each split gives the fraction to
+        // the part that was there before it.
+        let mut dock = DockState::new(vec![Panel::Disassembly]);
+        let surface = dock.main_surface_mut();
+        let [center, left] = surface.split_left(NodeIndex::root(), 0.7, vec![Panel::Registers, Panel::Settings]);
+        surface.split_below(left, 0.6, vec![Panel::Breakpoints]);
+        surface.split_right(center, 0.55, vec![Panel::Memory]);
+
+        Ok(DebugGui {
+            painter: painter,
+            gl_context: gl_context,
+            window: window,
+            video: video,
+            egui: egui::Context::default(),
+            dock: dock,
+            panels: Panels::new(),
+            events: Vec::new(),
+            modifiers: Modifiers::default(),
+            dirty: true,
+            repaint_at: None,
+            last_frame: Instant::now(),
+            started: Instant::now(),
+        })
+    }
+
+    /// Whether an event happened in the window
+    pub fn owns(&self, event: &Event) -> bool {
+        let window_id = match *event {
+            Event::Window { window_id, .. } |
+            Event::KeyDown { window_id, .. } |
+            Event::KeyUp { window_id, .. } |
+            Event::TextInput { window_id, .. } |
+            Event::MouseMotion { window_id, .. } |
+            Event::MouseButtonDown { window_id, .. } |
+            Event::MouseButtonUp { window_id, .. } |
+            Event::MouseWheel { window_id, .. } => window_id,
+            _ => return false,
+        };
+        window_id == self.window.id()
+    }
+
+    /// Shows the machine as it is in a snapshot
+    pub fn set_snapshot(&mut self, snapshot: DebugSnapshot) {
+        self.panels.snapshot = Some(snapshot);
+        self.dirty = true;
+    }
+
+    /// Takes an event that happened in the window. Returns false if it's the window being closed.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        let event = match *event {
+            Event::Window { win_event_id: WindowEventId::Close, .. } => return false,
+            Event::Window { win_event_id: WindowEventId::Leave, .. } => egui::Event::PointerGone,
+            Event::Window { .. } => {
+                self.dirty = true;
+                return true;
+            },
+            Event::MouseMotion { x, y, .. } => egui::Event::PointerMoved(Pos2::new(x as f32, y as f32)),
+            Event::MouseButtonDown { mouse_btn, x, y, .. } | Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                let button = match mouse_btn {
+                    Mouse::Left => PointerButton::Primary,
+                    Mouse::Right => PointerButton::Secondary,
+                    Mouse::Middle => PointerButton::Middle,
+                    _ => return true,
+                };
+                egui::Event::PointerButton {
+                    pos: Pos2::new(x as f32, y as f32),
+                    button: button,
+                    pressed: matches!(*event, Event::MouseButtonDown { .. }),
+                    modifiers: self.modifiers,
+                }
+            },
+            Event::MouseWheel { x, y, .. } => egui::Event::Scroll(egui::vec2(x as f32, y as f32) * SCROLL_STEP),
+            Event::TextInput { ref text, .. } => egui::Event::Text(text.clone()),
+            Event::KeyDown { keycode: Some(keycode), keymod, .. } | Event::KeyUp { keycode: Some(keycode), keymod, .. } => {
+                let pressed = matches!(*event, Event::KeyDown { .. });
+                self.modifiers = modifiers(keymod);
+
+                match (keycode, pressed && self.modifiers.command) {
+                    (Keycode::C, true) => egui::Event::Copy,
+                    (Keycode::X, true) => egui::Event::Cut,
+                    (Keycode::V, true) => match self.video.clipboard().clipboard_text() {
+                        Ok(text) => egui::Event::Paste(text),
+                        Err(_) => return true,
+                    },
+                    _ => match gui_key(keycode) {
+                        Some(key) => egui::Event::Key {
+                            key: key,
+                            physical_key: None,
+                            pressed: pressed,
+                            repeat: false,
+                            modifiers: self.modifiers,
+                        },
+                        None => return true,
+                    },
+                }
+            },
+            _ => return true,
+        };
+
+        self.events.push(event);
+        self.dirty = true;
+        true
+    }
+
+    /// Runs the GUI and draws it, if anything's changed since it was last drawn
+    /// and it's been long enough. Returns what the user asked the emulator to do.
+    pub fn update(&mut self) -> Vec<InputMessage> {
+        let repaint_due = self.repaint_at.is_some_and(|t| Instant::now() >= t);
+        if !(self.dirty || repaint_due) || self.last_frame.elapsed() < FRAME_INTERVAL {
+            return Vec::new();
+        }
+        self.dirty = false;
+        self.last_frame = Instant::now();
+
+        // egui works in points, which SDL's window size and mouse positions are in
+        let (width, height) = self.window.size();
+        let (pixel_width, pixel_height) = self.window.drawable_size();
+        let pixels_per_point = pixel_width as f32 / width.max(1) as f32;
+
+        let mut input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, egui::vec2(width as f32, height as f32))),
+            time: Some(self.started.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: mem::take(&mut self.events),
+            ..RawInput::default()
+        };
+        input.viewports.entry(ViewportId::ROOT).or_default().native_pixels_per_point = Some(pixels_per_point);
+
+        let dock = &mut self.dock;
+        let panels = &mut self.panels;
+        let output = self.egui.run(input, |ctx| {
+            DockArea::new(dock)
+                .style(Style::from_egui(ctx.style().as_ref()))
+                .show(ctx, panels);
+        });
+
+        if !output.platform_output.copied_text.is_empty() {
+            let _ = self.video.clipboard().set_clipboard_text(&output.platform_output.copied_text);
+        }
+
+        self.repaint_at = output.viewport_output.get(&ViewportId::ROOT)
+            .and_then(|v| Instant::now().checked_add(v.repaint_delay));
+
+        let primitives = self.egui.tessellate(output.shapes, output.pixels_per_point);
+        if let Err(e) = self.window.gl_make_current(&self.gl_context) {
+            error!(target: "display", "Couldn't draw the debugger. Error message: {}", e);
+            return Vec::new();
+        }
+        self.painter.paint((pixel_width, pixel_height), output.pixels_per_point, &primitives, &output.textures_delta);
+        self.window.gl_swap_window();
+
+        mem::take(&mut self.panels.commands)
+    }
+}
+
+impl Drop for DebugGui {
+    fn drop(&mut self) {
+        // the painter frees what it made in our context, not whichever one's current
+        let _ = self.window.gl_make_current(&self.gl_context);
+    }
+}
+
+/// What the panels show and what's been typed into them
+struct Panels {
+    snapshot: Option<DebugSnapshot>,
+    /// the address typed into the memory view, to go to
+    memory_addr: String,
+    /// the row the memory view should scroll to the next time it's shown
+    scroll_to_row: Option<usize>,
+    poke_addr: String,
+    poke_value: String,
+    register_name: String,
+    register_value: String,
+    breakpoint_spec: String,
+    /// what the user's asked for since the last frame
+    commands: Vec<InputMessage>,
+}
+
+impl Panels {
+    fn new() -> Panels {
+        Panels {
+            snapshot: None,
+            memory_addr: String::new(),
+            scroll_to_row: None,
+            poke_addr: String::new(),
+            poke_value: String::new(),
+            register_name: String::new(),
+            register_value: String::new(),
+            breakpoint_spec: String::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, command: DebugCommand) {
+        self.commands.push(InputMessage::Debug(command));
+    }
+
+    /// The run, pause and step buttons
+    fn controls(&mut self, ui: &mut Ui, paused: bool) {
+        ui.horizontal(|ui| {
+            if paused {
+                if ui.button("Run").clicked() {
+                    self.send(DebugCommand::Run);
+                }
+            } else if ui.button("Pause").clicked() {
+                self.send(DebugCommand::Pause);
+            }
+            if ui.button("Step").clicked() {
+                self.send(DebugCommand::Step);
+            }
+        });
+    }
+
+    fn registers(&mut self, ui: &mut Ui, snapshot: &DebugSnapshot) {
+        self.controls(ui, snapshot.paused);
+        ui.separator();
+
+        egui::Grid::new("registers").striped(true).show(ui, |ui| {
+            for (i, v) in snapshot.registers.iter().enumerate() {
+                ui.label(RichText::new(format!("V{:X}", i)).monospace().strong());
+                ui.label(RichText::new(format!("{:02X}", v)).monospace());
+                if i % 4 == 3 {
+                    ui.end_row();
+                }
+            }
+        });
+        ui.separator();
+
+        egui::Grid::new("special registers").show(ui, |ui| {
+            let special = [
+                ("PC", format!("{:03X}", snapshot.program_counter)),
+                ("I", format!("{:03X}", snapshot.i_register)),
+                ("DT", format!("{:02X}", snapshot.delay_timer)),
+                ("ST", format!("{:02X}", snapshot.sound_timer)),
+            ];
+            for &(name, ref value) in special.iter() {
+                ui.label(RichText::new(name).monospace().strong());
+                ui.label(RichText::new(value.as_str()).monospace());
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        ui.label(RichText::new(format!("Stack ({})", snapshot.stack.len())).strong());
+        for (depth, addr) in snapshot.stack.iter().enumerate().rev() {
+            ui.label(RichText::new(format!("{:2}: {:03X}", depth, addr)).monospace());
+        }
+        ui.separator();
+
+        ui.add_enabled_ui(snapshot.paused, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.register_name).desired_width(32.0).hint_text("V0"));
+                ui.label("=");
+                ui.add(egui::TextEdit::singleline(&mut self.register_value).desired_width(48.0).hint_text("hex"));
+                if ui.button("Set").clicked() {
+                    match parse_hex(self.register_value.trim()) {
+                        Some(value) => {
+                            let name = self.register_name.trim().to_owned();
+                            self.send(DebugCommand::SetRegister(name, value));
+                        },
+                        None => self.register_value.clear(),
+                    }
+                }
+            });
+        });
+    }
+
+    fn memory(&mut self, ui: &mut Ui, snapshot: &DebugSnapshot) {
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.memory_addr).desired_width(48.0).hint_text("addr"));
+            if ui.button("Go").clicked() {
+                self.scroll_to_row = parse_hex(self.memory_addr.trim()).map(|addr| addr / MEMORY_ROW);
+            }
+            if ui.button("PC").clicked() {
+                self.scroll_to_row = Some(snapshot.program_counter / MEMORY_ROW);
+            }
+            if ui.button("I").clicked() {
+                self.scroll_to_row = Some(snapshot.i_register / MEMORY_ROW);
+            }
+        });
+
+        ui.add_enabled_ui(snapshot.paused, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.poke_addr).desired_width(48.0).hint_text("addr"));
+                ui.label("=");
+                ui.add(egui::TextEdit::singleline(&mut self.poke_value).desired_width(32.0).hint_text("byte"));
+                if ui.button("Poke").clicked() {
+                    let addr = parse_hex(self.poke_addr.trim());
+                    let value = parse_hex(self.poke_value.trim()).filter(|&v| v <= 0xFF);
+                    if let (Some(addr), Some(value)) = (addr, value) {
+                        self.send(DebugCommand::Poke(addr, value as u8));
+                    }
+                }
+            });
+        });
+        ui.separator();
+
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let rows = snapshot.memory.len().div_ceil(MEMORY_ROW);
+        let mut scroll = egui::ScrollArea::vertical().auto_shrink([false; 2]);
+        if let Some(row) = self.scroll_to_row.take() {
+            scroll = scroll.vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+        }
+
+        scroll.show_rows(ui, row_height, rows, |ui, range| {
+            for row in range {
+                let start = row * MEMORY_ROW;
+                let end = (start + MEMORY_ROW).min(snapshot.memory.len());
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    ui.label(RichText::new(format!("{:04X}", start)).monospace().strong());
+                    for addr in start..end {
+                        let mut text = RichText::new(format!("{:02X}", snapshot.memory[addr])).monospace();
+                        if addr == snapshot.program_counter || addr == snapshot.program_counter + 1 {
+                            text = text.color(PC_COLOR);
+                        } else if addr == snapshot.i_register {
+                            text = text.color(I_COLOR);
+                        }
+                        ui.label(text);
+                    }
+                });
+            }
+        });
+    }
+
+    fn disassembly(&mut self, ui: &mut Ui, snapshot: &DebugSnapshot) {
+        self.controls(ui, snapshot.paused);
+        ui.label("Click the dot beside an instruction to set or clear a breakpoint there.");
+        ui.separator();
+
+        egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+            egui::Grid::new("disassembly").striped(true).show(ui, |ui| {
+                for line in snapshot.disassembly.iter() {
+                    let breakpoint = snapshot.breakpoints.iter().position(|&(addr, _)| addr == line.addr);
+                    let marker = RichText::new(if breakpoint.is_some() { "\u{25CF}" } else { "\u{25CB}" }).color(PC_COLOR);
+                    if ui.add(egui::Button::new(marker).frame(false)).clicked() {
+                        match breakpoint {
+                            Some(n) => self.send(DebugCommand::RemoveBreakpoint(n)),
+                            None => self.send(DebugCommand::SetBreakpoint(format!("{:X}", line.addr))),
+                        }
+                    }
+
+                    let at_pc = line.addr == snapshot.program_counter;
+                    let style = |text: String| {
+                        let text = RichText::new(text).monospace();
+                        if at_pc { text.color(PC_COLOR).strong() } else { text }
+                    };
+                    ui.label(style(format!("{:03X}", line.addr)));
+                    ui.label(style(format!("{:04X}", line.instruction)));
+                    ui.label(style(line.label.as_ref().map(|l| format!("{}:", l)).unwrap_or_default()));
+                    ui.label(style(line.text.clone()));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    fn breakpoints(&mut self, ui: &mut Ui, snapshot: &DebugSnapshot) {
+        ui.horizontal(|ui| {
+            let response = ui.add(egui::TextEdit::singleline(&mut self.breakpoint_spec).hint_text("2A4 if v3 == 0"));
+            let entered = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if (ui.button("Add").clicked() || entered) && !self.breakpoint_spec.trim().is_empty() {
+                let spec = mem::take(&mut self.breakpoint_spec);
+                self.send(DebugCommand::SetBreakpoint(spec.trim().to_owned()));
+            }
+        });
+        ui.label("An address, name or @line, and optionally a condition after if.");
+        ui.separator();
+
+        if snapshot.breakpoints.is_empty() {
+            ui.label("No breakpoints.");
+        }
+        for (n, (_, description)) in snapshot.breakpoints.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.small_button("Remove").clicked() {
+                    self.send(DebugCommand::RemoveBreakpoint(n));
+                }
+                ui.label(RichText::new(description.as_str()).monospace());
+            });
+        }
+    }
+
+    fn settings(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Speed");
+            if ui.button("Slower").clicked() {
+                self.commands.push(InputMessage::SlowDown);
+            }
+            if ui.button("Faster").clicked() {
+                self.commands.push(InputMessage::SpeedUp);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("State");
+            if ui.button("Save").clicked() {
+                self.commands.push(InputMessage::SaveState);
+            }
+            if ui.button("Load").clicked() {
+                self.commands.push(InputMessage::LoadState);
+            }
+        });
+        if ui.button("Toggle cheats").clicked() {
+            self.commands.push(InputMessage::ToggleCheats);
+        }
+        if ui.button("Toggle tracing").clicked() {
+            self.commands.push(InputMessage::ToggleTrace);
+        }
+        if ui.button("Next program").clicked() {
+            self.commands.push(InputMessage::NextProgram);
+        }
+        ui.separator();
+        egui::widgets::global_dark_light_mode_buttons(ui);
+    }
+}
+
+impl TabViewer for Panels {
+    type Tab = Panel;
+
+    fn title(&mut self, tab: &mut Panel) -> WidgetText {
+        format!("{:?}", tab).into()
+    }
+
+    fn ui(&mut self, ui: &mut Ui, tab: &mut Panel) {
+        if *tab == Panel::Settings {
+            return self.settings(ui);
+        }
+
+        // the snapshot's put back once the panel's done with it
+        let snapshot = match self.snapshot.take() {
+            Some(s) => s,
+            None => {
+                ui.label("Waiting for the emulator...");
+                return;
+            },
+        };
+
+        match *tab {
+            Panel::Registers => self.registers(ui, &snapshot),
+            Panel::Memory => self.memory(ui, &snapshot),
+            Panel::Disassembly => self.disassembly(ui, &snapshot),
+            Panel::Breakpoints => self.breakpoints(ui, &snapshot),
+            Panel::Settings => (),
+        }
+
+        self.snapshot = Some(snapshot);
+    }
+
+    // there's nowhere to open a closed panel again
+    fn closeable(&mut self, _tab: &mut Panel) -> bool {
+        false
+    }
+}
+
+/// egui's idea of which modifier keys are held
+fn modifiers(keymod: Mod) -> Modifiers {
+    let ctrl = keymod.intersects(keyboard::LCTRLMOD | keyboard::RCTRLMOD);
+    Modifiers {
+        alt: keymod.intersects(keyboard::LALTMOD | keyboard::RALTMOD),
+        ctrl: ctrl,
+        shift: keymod.intersects(keyboard::LSHIFTMOD | keyboard::RSHIFTMOD),
+        mac_cmd: false,
+        command: ctrl,
+    }
+}
+
+/// The egui key for an SDL key, for the keys the GUI's text fields and shortcuts use
+fn gui_key(keycode: Keycode) -> Option<Key> {
+    let key = match keycode {
+        Keycode::Left => Key::ArrowLeft,
+        Keycode::Right => Key::ArrowRight,
+        Keycode::Up => Key::ArrowUp,
+        Keycode::Down => Key::ArrowDown,
+        Keycode::Backspace => Key::Backspace,
+        Keycode::Delete => Key::Delete,
+        Keycode::Return | Keycode::KpEnter => Key::Enter,
+        Keycode::Tab => Key::Tab,
+        Keycode::Escape => Key::Escape,
+        Keycode::Home => Key::Home,
+        Keycode::End => Key::End,
+        Keycode::PageUp => Key::PageUp,
+        Keycode::PageDown => Key::PageDown,
+        Keycode::Space => Key::Space,
+        Keycode::A => Key::A,
+        Keycode::Z => Key::Z,
+        Keycode::Y => Key::Y,
+        _ => return None,
+    };
+    Some(key)
+}
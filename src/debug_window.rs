@@ -0,0 +1,191 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate sdl2;
+
+use sdl2::Sdl;
+use sdl2::mouse::Mouse;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::Renderer;
+use sdl2::render::Texture;
+
+/// The scale of the memory map in relation to one pixel per byte
+pub const DEBUG_WINDOW_SCALE: u32 = 8;
+/// How many bytes make up a row of the memory map
+const MAP_WIDTH: usize = 64;
+/// How many bytes the memory map shows, which is all of a standard machine's memory
+pub const MAP_LENGTH: usize = 4096;
+/// The color of the two bytes of the instruction at the program counter
+const PC_COLOR: (u8, u8, u8) = (0xFF, 0x40, 0x40);
+/// The color of the byte the I register points at
+const I_COLOR: (u8, u8, u8) = (0x40, 0xFF, 0x40);
+/// How many bytes, and so sprite rows, each column of the sprite viewer shows
+const SPRITE_ROWS: usize = 32;
+/// How many columns of sprite rows the sprite viewer shows, each carrying on from the last
+const SPRITE_COLUMNS: usize = 4;
+/// How many pixels wide a column of the sprite viewer is, counting the gap after it
+const SPRITE_COLUMN_WIDTH: usize = 9;
+/// The color of a lit pixel in the sprite viewer
+const SPRITE_ON_COLOR: (u8, u8, u8) = (0xFF, 0xFF, 0xFF);
+/// The color of an unlit pixel in the sprite viewer
+const SPRITE_OFF_COLOR: (u8, u8, u8) = (0x20, 0x20, 0x20);
+/// The color of the gaps between the sprite viewer's columns
+const SPRITE_GAP_COLOR: (u8, u8, u8) = (0x00, 0x00, 0x40);
+
+/// What the debug window shows: a copy of memory and where the registers point into it
+pub struct MemorySnapshot {
+    /// the start of memory, up to MAP_LENGTH bytes
+    pub memory: Vec<u8>,
+    pub program_counter: usize,
+    pub i_register: usize,
+}
+
+/// A second window for debugging tools, so they never cover up the game. It shows
+/// a map of memory with one pixel per byte, brighter for bigger values, with the
+/// instruction at the program counter in red and the byte at I in green.
+///
+/// Beside the map, a sprite viewer shows the memory at I, or at whichever byte of
+/// the map was last clicked, as 8-pixel-wide sprite rows, magnified, in columns
+/// that carry on from one to the next. Right-clicking goes back to following I.
+pub struct DebugWindow<'a> {
+    renderer: Renderer<'a>,
+    texture: Texture,
+    sprite_texture: Texture,
+    /// the address the sprite viewer starts at, or None to follow I
+    sprite_addr: Option<usize>,
+    /// the last memory we were given, kept to redraw when the sprite viewer moves
+    snapshot: Option<MemorySnapshot>,
+}
+
+impl<'a> DebugWindow<'a> {
+    /// Opens the debug window
+    pub fn new(sdl_context: &Sdl) -> DebugWindow<'a> {
+        let video_subsystem = sdl_context.video().unwrap();
+        let map_height = MAP_LENGTH / MAP_WIDTH;
+
+        let window = video_subsystem.window("CHIP-8: Memory",
+            DEBUG_WINDOW_SCALE * MAP_WIDTH as u32 + sprite_scale() * (SPRITE_COLUMN_WIDTH * SPRITE_COLUMNS) as u32,
+            DEBUG_WINDOW_SCALE * map_height as u32)
+            .opengl()
+            .build()
+            .unwrap();
+
+        let renderer = window.renderer().build().unwrap();
+
+        let texture = renderer.create_texture_streaming(
+            PixelFormatEnum::RGB24, MAP_WIDTH as u32, map_height as u32).unwrap();
+
+        let sprite_texture = renderer.create_texture_streaming(
+            PixelFormatEnum::RGB24, (SPRITE_COLUMN_WIDTH * SPRITE_COLUMNS) as u32, SPRITE_ROWS as u32).unwrap();
+
+        DebugWindow {
+            renderer: renderer,
+            texture: texture,
+            sprite_texture: sprite_texture,
+            sprite_addr: None,
+            snapshot: None,
+        }
+    }
+
+    /// The SDL id of the window, which tells its events apart from the main window's
+    pub fn window_id(&self) -> Option<u32> {
+        self.renderer.window().map(|w| w.id())
+    }
+
+    /// Draws the memory map and sprite viewer for the given memory
+    pub fn draw(&mut self, snapshot: MemorySnapshot) {
+        self.snapshot = Some(snapshot);
+        self.redraw();
+    }
+
+    /// Handles a click in the window: the left button points the sprite viewer at
+    /// the byte clicked in the memory map, and the right sets it following I again
+    pub fn click(&mut self, button: Mouse, x: i32, y: i32) {
+        let scale = DEBUG_WINDOW_SCALE as i32;
+
+        match button {
+            Mouse::Left if x >= 0 && y >= 0 && x < scale * MAP_WIDTH as i32 => {
+                let addr = (y / scale) as usize * MAP_WIDTH + (x / scale) as usize;
+                if addr < MAP_LENGTH {
+                    self.sprite_addr = Some(addr);
+                }
+            },
+            Mouse::Right => self.sprite_addr = None,
+            _ => return,
+        }
+
+        self.redraw();
+    }
+
+    /// Draws the last memory we were given again
+    fn redraw(&mut self) {
+        let snapshot = match self.snapshot {
+            Some(ref s) => s,
+            None => return,
+        };
+
+        self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (addr, &value) in snapshot.memory.iter().enumerate().take(MAP_LENGTH) {
+                let (r, g, b) = if addr == snapshot.program_counter || addr == snapshot.program_counter + 1 {
+                    PC_COLOR
+                } else if addr == snapshot.i_register {
+                    I_COLOR
+                } else {
+                    (value, value, value)
+                };
+
+                let offset = (addr / MAP_WIDTH) * pitch + (addr % MAP_WIDTH) * 3;
+                buffer[offset] = r;
+                buffer[offset + 1] = g;
+                buffer[offset + 2] = b;
+            }
+        }).unwrap();
+
+        let sprite_addr = self.sprite_addr.unwrap_or(snapshot.i_register);
+        self.sprite_texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for column in 0..SPRITE_COLUMNS {
+                for row in 0..SPRITE_ROWS {
+                    // anything past the end of the snapshot shows as blank
+                    let byte = snapshot.memory.get(sprite_addr + column * SPRITE_ROWS + row).cloned().unwrap_or(0);
+
+                    for x in 0..SPRITE_COLUMN_WIDTH {
+                        let (r, g, b) = if x == SPRITE_COLUMN_WIDTH - 1 {
+                            SPRITE_GAP_COLOR
+                        } else if byte & (0x80 >> x) != 0 {
+                            SPRITE_ON_COLOR
+                        } else {
+                            SPRITE_OFF_COLOR
+                        };
+
+                        let offset = row * pitch + (column * SPRITE_COLUMN_WIDTH + x) * 3;
+                        buffer[offset] = r;
+                        buffer[offset + 1] = g;
+                        buffer[offset + 2] = b;
+                    }
+                }
+            }
+        }).unwrap();
+
+        let title = match self.sprite_addr {
+            Some(addr) => format!("CHIP-8: Memory - sprites at {:03X}", addr),
+            None => format!("CHIP-8: Memory - sprites at I ({:03X})", sprite_addr),
+        };
+        if let Some(window) = self.renderer.window_mut() {
+            let _ = window.set_title(&title);
+        }
+
+        let map_width = DEBUG_WINDOW_SCALE * MAP_WIDTH as u32;
+        let map_height = DEBUG_WINDOW_SCALE * (MAP_LENGTH / MAP_WIDTH) as u32;
+        self.renderer.copy(&self.texture, None, Some(Rect::new(0, 0, map_width, map_height)));
+        self.renderer.copy(&self.sprite_texture, None, Some(Rect::new(map_width as i32, 0,
+            sprite_scale() * (SPRITE_COLUMN_WIDTH * SPRITE_COLUMNS) as u32, map_height)));
+        self.renderer.present();
+    }
+}
+
+/// How many window pixels across each pixel of the sprite viewer is, so it's as tall as the memory map
+fn sprite_scale() -> u32 {
+    DEBUG_WINDOW_SCALE * (MAP_LENGTH / MAP_WIDTH / SPRITE_ROWS) as u32
+}
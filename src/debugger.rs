@@ -0,0 +1,647 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::io::{self, BufRead, Write};
+
+use cheats::parse_hex;
+use condition::{Condition, Register};
+use cpu::{Cpu, STACK_LENGTH};
+use linemap::LineMap;
+use symbols::SymbolTable;
+
+/// How many memory search results are listed before the rest are elided
+const MAX_LISTED_RESULTS: usize = 32;
+/// How many bytes the mem command shows when no length is given
+const DEFAULT_DUMP_LENGTH: usize = 0x40;
+/// How many sprite rows the sprite command shows when no count is given, enough for the tallest sprite
+const DEFAULT_SPRITE_ROWS: usize = 0x10;
+
+/// What the emulator should do once the debugger prompt returns
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebuggerAction {
+    /// resume running normally
+    Continue,
+    /// execute this many instructions and come back to the prompt
+    Step(usize),
+    /// run until the program counter reaches this address, then come back to the prompt
+    RunTo(usize),
+    /// stop emulation
+    Quit,
+}
+
+/// A place the debugger stops, when the instruction there is next and the condition, if any, holds
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub addr: usize,
+    pub condition: Option<Condition>,
+    /// the condition as it was typed, to list it by
+    pub condition_text: String,
+}
+
+/// A set of instructions the debugger stops before wherever they are, written as
+/// four hex digits with x or ? for any digit, like Dxyn's "Dxxx" or "Fx0A"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionPattern {
+    /// the bits an instruction has to match
+    pub mask: u16,
+    /// what those bits have to be
+    pub value: u16,
+    /// the pattern as it was typed, to list it by
+    pub text: String,
+}
+
+impl InstructionPattern {
+    /// Parses a pattern like "Fx0A", returning None if it isn't four digits and wildcards
+    pub fn parse(text: &str) -> Option<InstructionPattern> {
+        if text.chars().count() != 4 {
+            return None;
+        }
+
+        let (mut mask, mut value) = (0u16, 0u16);
+        for c in text.chars() {
+            mask <<= 4;
+            value <<= 4;
+            match c {
+                'x' | 'X' | '?' => (),
+                c => {
+                    mask |= 0xF;
+                    value |= c.to_digit(16)? as u16;
+                },
+            }
+        }
+
+        Some(InstructionPattern {
+            mask: mask,
+            value: value,
+            text: text.to_uppercase().replace(['?', 'X'], "x"),
+        })
+    }
+
+    /// Whether the given instruction fits the pattern
+    pub fn matches(&self, instruction: u16) -> bool {
+        instruction & self.mask == self.value
+    }
+}
+
+/// A part of the screen the debugger stops after any DRW that toggles a pixel in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScreenWatch {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ScreenWatch {
+    /// Whether the pixel at the given position is inside
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// A terminal debugger that reads commands from stdin while emulation is paused
+pub struct Debugger {
+    /// the addresses still matching every search since the last reset, if a search is in progress
+    search_results: Option<Vec<usize>>,
+    breakpoints: Vec<Breakpoint>,
+    /// the kinds of instruction to stop before, wherever they are
+    catches: Vec<InstructionPattern>,
+    /// the parts of the screen to stop after a DRW touches
+    screen_watches: Vec<ScreenWatch>,
+    /// whether the trace command asked for tracing to be switched on or off, until the frontend sees to it
+    trace_request: Option<bool>,
+    /// the names of the program's addresses, which can be used in place of them
+    symbols: SymbolTable,
+    /// the source lines the program's instructions came from
+    line_map: LineMap,
+    /// the address the next command started from, while running to the next source line
+    source_step_from: Option<usize>,
+    /// why commands that change the machine are refused, if they are, like "while recording a movie"
+    read_only: Option<&'static str>,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// Construct a new debugger with no search in progress, no breakpoints, catches or watches
+    pub fn new() -> Debugger {
+        Debugger {
+            search_results: None,
+            breakpoints: Vec::new(),
+            catches: Vec::new(),
+            screen_watches: Vec::new(),
+            trace_request: None,
+            symbols: SymbolTable::new(),
+            line_map: LineMap::new(),
+            source_step_from: None,
+            read_only: None,
+        }
+    }
+
+    /// The breakpoint the machine has reached, if it's reached one, which is when
+    /// the next instruction is at its address and its condition holds
+    pub fn breakpoint_hit(&self, cpu: &Cpu) -> Option<&Breakpoint> {
+        self.breakpoints.iter().find(|b| {
+            b.addr == cpu.program_counter && b.condition.as_ref().is_none_or(|c| c.holds(cpu))
+        })
+    }
+
+    /// The pattern the next instruction matches, if it's one we were asked to catch
+    pub fn catch_hit(&self, cpu: &Cpu) -> Option<&InstructionPattern> {
+        let pc = cpu.program_counter;
+        if self.catches.is_empty() || pc + 1 >= cpu.memory.len() {
+            return None;
+        }
+
+        let instruction = ((cpu.memory[pc] as u16) << 8) | (cpu.memory[pc + 1] as u16);
+        self.catches.iter().find(|p| p.matches(instruction))
+    }
+
+    /// The names of the program's addresses
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Names the program's addresses, so they can be shown and typed by name
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Maps the program's instructions to the source lines they came from, so
+    /// they're shown alongside and breakpoints can be set by line
+    pub fn set_line_map(&mut self, line_map: LineMap) {
+        self.line_map = line_map;
+    }
+
+    /// Refuses the commands that change the machine, saying why, or allows them again
+    pub fn set_read_only(&mut self, read_only: Option<&'static str>) {
+        self.read_only = read_only;
+    }
+
+    /// Sets a breakpoint given the way the break command takes one, like "2A4",
+    /// "draw_player", "@12" or "2A4 if v3 == 0"
+    pub fn set_breakpoint(&mut self, spec: &str) -> Result<(), String> {
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        self.add_breakpoint(&words)
+    }
+
+    /// The breakpoints, in the order they were set
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Removes the breakpoint with the given number, as the delete command numbers
+    /// them, if there is one
+    pub fn remove_breakpoint(&mut self, n: usize) {
+        if n < self.breakpoints.len() {
+            self.breakpoints.remove(n);
+        }
+    }
+
+    /// Whether the next command has reached the start of another source line, and
+    /// so the prompt should come back
+    pub fn source_step_done(&mut self, cpu: &Cpu) -> bool {
+        let from = match self.source_step_from {
+            Some(from) => from,
+            None => return false,
+        };
+
+        let line = self.line_map.line_at(cpu.program_counter);
+        if line.is_some() && line != self.line_map.line_at(from) {
+            self.source_step_from = None;
+            return true;
+        }
+
+        false
+    }
+
+    /// An address with its name and source line, where it has them
+    pub fn describe(&self, addr: usize) -> String {
+        match self.line_map.line_at(addr) {
+            Some(line) => format!("{}, line {}", self.symbols.describe(addr), line),
+            None => self.symbols.describe(addr),
+        }
+    }
+
+    /// Parses an address given by name, as @ and a source line, or as a hex number
+    fn parse_addr(&self, s: &str) -> Option<usize> {
+        match s.strip_prefix('@') {
+            Some(line) => line.parse::<usize>().ok().and_then(|l| self.line_map.addr_of_line(l)),
+            None => self.symbols.parse_addr(s),
+        }
+    }
+
+    /// Whether the trace command asked for tracing to be switched on or off since last time
+    pub fn take_trace_request(&mut self) -> Option<bool> {
+        self.trace_request.take()
+    }
+
+    /// The watched part of the screen the instruction just executed drew in, if it
+    /// was a DRW that toggled any pixel inside one
+    pub fn screen_watch_hit(&self, cpu: &Cpu) -> Option<&ScreenWatch> {
+        if self.screen_watches.is_empty() || cpu.current_instruction & 0xF000 != 0xD000 {
+            return None;
+        }
+
+        self.screen_watches.iter().find(|w| cpu.pixel_changes.iter().any(|&(x, y)| w.contains(x, y)))
+    }
+
+    /// Lists the watches, numbered for the unwatch command
+    fn print_watches(&self) {
+        if self.screen_watches.is_empty() {
+            println!("No watches. Set one with watch screen <x> <y> <width> <height>.");
+        }
+
+        for (n, watch) in self.screen_watches.iter().enumerate() {
+            println!("  {}: screen {:X},{:X} to {:X},{:X}", n, watch.x, watch.y,
+                watch.x + watch.width - 1, watch.y + watch.height - 1);
+        }
+    }
+
+    /// Adds a breakpoint from a command like "2A4 if v3 == 0 && dt > 0"
+    fn add_breakpoint(&mut self, words: &[&str]) -> Result<(), String> {
+        let addr = match words.first().and_then(|w| self.parse_addr(w)) {
+            Some(addr) => addr,
+            None => return Err("Usage: break <address> [if <condition>]".to_owned()),
+        };
+
+        let (condition, condition_text) = match words.get(1) {
+            None => (None, String::new()),
+            Some(&"if") if words.len() > 2 => {
+                let text = words[2..].join(" ");
+                match Condition::parse(&text) {
+                    Ok(c) => (Some(c), text),
+                    Err(e) => return Err(format!("Couldn't understand the condition: {}", e)),
+                }
+            },
+            Some(_) => return Err("Usage: break <address> [if <condition>]".to_owned()),
+        };
+
+        self.breakpoints.push(Breakpoint {
+            addr: addr,
+            condition: condition,
+            condition_text: condition_text,
+        });
+        Ok(())
+    }
+
+    /// Lists the instruction patterns being caught, numbered for the uncatch command
+    fn print_catches(&self) {
+        if self.catches.is_empty() {
+            println!("Not catching any instructions. Catch some with catch <pattern>.");
+        }
+
+        for (n, pattern) in self.catches.iter().enumerate() {
+            println!("  {}: {}", n, pattern.text);
+        }
+    }
+
+    /// Lists the breakpoints, numbered for the delete command
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() {
+            println!("No breakpoints. Set one with break <address> [if <condition>].");
+        }
+
+        for (n, breakpoint) in self.breakpoints.iter().enumerate() {
+            match breakpoint.condition {
+                Some(_) => println!("  {}: {} if {}", n, self.describe(breakpoint.addr), breakpoint.condition_text),
+                None => println!("  {}: {}", n, self.describe(breakpoint.addr)),
+            }
+        }
+    }
+
+    /// Shows the next instruction and reads commands until one of them resumes or quits
+    pub fn repl(&mut self, cpu: &mut Cpu) -> DebuggerAction {
+        // whatever stopped us, any run to the next source line is over
+        self.source_step_from = None;
+        self.print_next_instruction(cpu);
+
+        let stdin = io::stdin();
+        loop {
+            print!("(chip8) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => return DebuggerAction::Quit,
+                Ok(_) => (),
+            }
+
+            if let Some(action) = self.execute(cpu, line.trim()) {
+                return action;
+            }
+        }
+    }
+
+    /// Runs a single debugger command. Returns an action if the command leaves the prompt.
+    pub fn execute(&mut self, cpu: &mut Cpu, line: &str) -> Option<DebuggerAction> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            return None;
+        }
+
+        match words[0] {
+            "c" | "continue" => return Some(DebuggerAction::Continue),
+            "s" | "step" => match words.get(1).map(|w| w.parse::<usize>()) {
+                None => return Some(DebuggerAction::Step(1)),
+                Some(Ok(n)) if n > 0 => return Some(DebuggerAction::Step(n)),
+                _ => println!("Usage: step [<count>]"),
+            },
+            "n" | "next" => {
+                if self.line_map.is_empty() {
+                    println!("There are no source lines to step by. Load a line map with --line-map <file>.");
+                } else {
+                    self.source_step_from = Some(cpu.program_counter);
+                    return Some(DebuggerAction::Continue);
+                }
+            },
+            "u" | "until" => match words.get(1).and_then(|w| self.parse_addr(w)) {
+                Some(addr) => return Some(DebuggerAction::RunTo(addr)),
+                None => println!("Usage: until <address>"),
+            },
+            "q" | "quit" => return Some(DebuggerAction::Quit),
+            "r" | "regs" => print!("{}", cpu.state()),
+            "m" | "mem" => {
+                match words.get(1).and_then(|w| self.parse_addr(w)) {
+                    Some(addr) => {
+                        let length = words.get(2).and_then(|w| parse_hex(w)).unwrap_or(DEFAULT_DUMP_LENGTH);
+                        dump_memory(cpu, addr, length);
+                    },
+                    None => println!("Usage: mem <address> [length]"),
+                }
+            },
+            "sprite" => {
+                let addr = match words.get(1) {
+                    Some(w) => self.parse_addr(w),
+                    None => Some(cpu.i_register),
+                };
+                match addr {
+                    Some(addr) => {
+                        let rows = words.get(2).and_then(|w| parse_hex(w)).unwrap_or(DEFAULT_SPRITE_ROWS);
+                        print_sprite(cpu, addr, rows);
+                    },
+                    None => println!("Usage: sprite [<address>] [rows]"),
+                }
+            },
+            "poke" | "set" if self.read_only.is_some() => println!("Can't change the machine {}.", self.read_only.unwrap_or_default()),
+            "poke" => {
+                let bytes: Option<Vec<u8>> = words.iter().skip(2)
+                    .map(|w| parse_hex(w).filter(|&v| v <= 0xFF).map(|v| v as u8))
+                    .collect();
+                match (words.get(1).and_then(|w| self.parse_addr(w)), bytes) {
+                    (Some(addr), Some(ref bytes)) if !bytes.is_empty() && addr.checked_add(bytes.len()).is_some_and(|end| end <= cpu.memory.len()) => {
+                        cpu.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+                        dump_memory(cpu, addr, bytes.len());
+                    },
+                    _ => println!("Usage: poke <address> <byte> [byte...]"),
+                }
+            },
+            "b" | "break" => {
+                if words.len() > 1 {
+                    if let Err(e) = self.add_breakpoint(&words[1..]) {
+                        println!("{}", e);
+                        return None;
+                    }
+                }
+                self.print_breakpoints();
+            },
+            "d" | "delete" => {
+                match words.get(1) {
+                    None => self.breakpoints.clear(),
+                    Some(w) => match w.parse::<usize>() {
+                        Ok(n) if n < self.breakpoints.len() => {
+                            self.breakpoints.remove(n);
+                        },
+                        _ => println!("Usage: delete [<breakpoint number>]"),
+                    },
+                }
+                self.print_breakpoints();
+            },
+            "catch" => {
+                if let Some(w) = words.get(1) {
+                    match InstructionPattern::parse(w) {
+                        Some(p) => self.catches.push(p),
+                        None => {
+                            println!("Usage: catch <pattern>, where the pattern is 4 hex digits with x for any digit, e.g. Dxxx");
+                            return None;
+                        },
+                    }
+                }
+                self.print_catches();
+            },
+            "uncatch" => {
+                match words.get(1) {
+                    None => self.catches.clear(),
+                    Some(w) => match w.parse::<usize>() {
+                        Ok(n) if n < self.catches.len() => {
+                            self.catches.remove(n);
+                        },
+                        _ => println!("Usage: uncatch [<catch number>]"),
+                    },
+                }
+                self.print_catches();
+            },
+            "watch" => {
+                if words.len() > 1 {
+                    let numbers: Vec<Option<usize>> = words[2..].iter().map(|w| parse_hex(w)).collect();
+                    match (words[1], numbers.as_slice()) {
+                        ("screen", &[Some(x), Some(y), Some(width), Some(height)]) if width > 0 && height > 0 => {
+                            self.screen_watches.push(ScreenWatch { x: x, y: y, width: width, height: height });
+                        },
+                        _ => {
+                            println!("Usage: watch screen <x> <y> <width> <height>");
+                            return None;
+                        },
+                    }
+                }
+                self.print_watches();
+            },
+            "unwatch" => {
+                match words.get(1) {
+                    None => self.screen_watches.clear(),
+                    Some(w) => match w.parse::<usize>() {
+                        Ok(n) if n < self.screen_watches.len() => {
+                            self.screen_watches.remove(n);
+                        },
+                        _ => println!("Usage: unwatch [<watch number>]"),
+                    },
+                }
+                self.print_watches();
+            },
+            "trace" => match words.get(1) {
+                Some(&"on") => self.trace_request = Some(true),
+                Some(&"off") => self.trace_request = Some(false),
+                _ => println!("Usage: trace on|off"),
+            },
+            "set" => {
+                match (words.get(1), words.get(2).and_then(|w| parse_hex(w))) {
+                    (Some(register), Some(value)) => match set_register(cpu, register, value) {
+                        Ok(()) => print!("{}", cpu.state()),
+                        Err(e) => println!("{}", e),
+                    },
+                    _ => println!("Usage: set <v0-vf|i|pc|sp|dt|st> <value>"),
+                }
+            },
+            "search" => {
+                match words.get(1) {
+                    None => self.print_search_results(),
+                    Some(&"reset") => {
+                        self.search_results = None;
+                        println!("Search reset.");
+                    },
+                    Some(w) => match parse_hex(w) {
+                        Some(value) if value <= 0xFF => {
+                            self.search(cpu, value as u8);
+                            self.print_search_results();
+                        },
+                        _ => println!("Usage: search [<byte>|reset]"),
+                    },
+                }
+            },
+            "h" | "help" => print_help(),
+            _ => println!("Unknown command. Type help for a list of commands."),
+        }
+
+        None
+    }
+
+    /// Finds the addresses that hold the value. The first search looks at all of memory;
+    /// later ones only keep the addresses that matched every search so far.
+    pub fn search(&mut self, cpu: &Cpu, value: u8) {
+        let results = match self.search_results.take() {
+            Some(previous) => previous.into_iter().filter(|&addr| cpu.memory[addr] == value).collect(),
+            None => (0..cpu.memory.len()).filter(|&addr| cpu.memory[addr] == value).collect(),
+        };
+
+        self.search_results = Some(results);
+    }
+
+    /// Lists the addresses left by the searches so far
+    fn print_search_results(&self) {
+        let results = match self.search_results {
+            Some(ref r) => r,
+            None => {
+                println!("No search in progress. Start one with search <byte>.");
+                return;
+            },
+        };
+
+        println!("{} matching address(es)", results.len());
+        for addr in results.iter().take(MAX_LISTED_RESULTS) {
+            println!("  0x{:03X}", addr);
+        }
+
+        if results.len() > MAX_LISTED_RESULTS {
+            println!("  ...");
+        }
+    }
+
+    /// Shows the instruction at the program counter, which is the next one to execute
+    fn print_next_instruction(&self, cpu: &Cpu) {
+        let pc = cpu.program_counter;
+        if pc + 1 >= cpu.memory.len() {
+            println!("0x{:03X}: <past the end of memory>", pc);
+            return;
+        }
+
+        if let Some(line) = self.line_map.line_at(pc) {
+            println!("{:>5} | {}", line, self.line_map.source_line(line).unwrap_or(""));
+        }
+
+        let instruction = ((cpu.memory[pc] as u16) << 8) | (cpu.memory[pc + 1] as u16);
+        println!("{}: {:04X}  {}", self.symbols.describe(pc), instruction, self.symbols.disasm(instruction, cpu.config.variant));
+    }
+}
+
+/// Prints a hex dump of memory, 16 bytes per line
+fn dump_memory(cpu: &Cpu, addr: usize, length: usize) {
+    let start = if addr > cpu.memory.len() { cpu.memory.len() } else { addr };
+    let end = if start.saturating_add(length) > cpu.memory.len() { cpu.memory.len() } else { start + length };
+
+    for (line, bytes) in cpu.memory[start..end].chunks(16).enumerate() {
+        print!("0x{:03X}:", start + line * 16);
+        for b in bytes.iter() {
+            print!(" {:02X}", b);
+        }
+        println!();
+    }
+}
+
+/// Sets a register by its name: V0 to VF, I, PC, SP, DT or ST, in either case.
+/// Fails, leaving the machine alone, for an unknown register or a value that
+/// doesn't fit it.
+pub fn set_register(cpu: &mut Cpu, name: &str, value: usize) -> Result<(), String> {
+    let register = match Register::from_name(name) {
+        Some(r) => r,
+        None => return Err(format!("There's no register called {}", name.to_uppercase())),
+    };
+
+    let limit = match register {
+        Register::I | Register::Pc => cpu.memory.len() - 1,
+        Register::Sp => STACK_LENGTH,
+        Register::V(_) | Register::Dt | Register::St => 0xFF,
+    };
+    if value > limit {
+        return Err(format!("{} can't be more than {:X}", name.to_uppercase(), limit));
+    }
+
+    match register {
+        Register::V(n) => cpu.data_registers[n] = value as u8,
+        Register::I => cpu.i_register = value,
+        Register::Pc => cpu.program_counter = value,
+        Register::Sp => cpu.stack_pointer = value,
+        Register::Dt => cpu.delay_timer = value as u8,
+        Register::St => cpu.sound_timer = value as u8,
+    }
+
+    Ok(())
+}
+
+/// Prints memory as sprite rows, eight pixels to a byte, with # for a lit pixel
+fn print_sprite(cpu: &Cpu, addr: usize, rows: usize) {
+    let start = if addr > cpu.memory.len() { cpu.memory.len() } else { addr };
+    let end = if start.saturating_add(rows) > cpu.memory.len() { cpu.memory.len() } else { start + rows };
+
+    for (row, &byte) in cpu.memory[start..end].iter().enumerate() {
+        let pixels: String = (0..8).map(|x| if byte & (0x80 >> x) != 0 { '#' } else { '.' }).collect();
+        println!("0x{:03X}: {:02X}  {}", start + row, byte, pixels);
+    }
+}
+
+/// Lists the debugger commands
+fn print_help() {
+    println!("Numbers are in hex. Addresses can also be given by name, from the --symbols file,");
+    println!("or as @ and a source line, like @12, from the --line-map file.");
+    println!("  c, continue             resume emulation");
+    println!("  s, step [n]             execute one instruction, or n of them (in decimal)");
+    println!("  n, next                 run to the start of the next source line");
+    println!("  u, until <addr>         run until the program counter reaches addr");
+    println!("  q, quit                 stop emulation");
+    println!("  r, regs                 show registers, stack and recent instructions");
+    println!("  m, mem <addr> [len]     dump memory");
+    println!("  sprite [addr] [rows]    draw memory as sprite rows, from I unless given an address");
+    println!("  poke <addr> <bytes...>  write bytes to memory, one after another from the address");
+    println!("  b, break                list breakpoints");
+    println!("  b, break <addr> [if <condition>]");
+    println!("                          stop before the instruction at addr, if the condition holds,");
+    println!("                          e.g. break 2A4 if v3 == 0 && (dt > 0 || [i] != FF)");
+    println!("  d, delete [n]           delete breakpoint n (numbered in decimal), or all of them");
+    println!("  catch                   list the instructions being caught");
+    println!("  catch <pattern>         stop before any instruction matching the pattern, wherever it is,");
+    println!("                          with x for any digit, e.g. Dxxx for any DRW or Fx0A");
+    println!("  uncatch [n]             stop catching pattern n (numbered in decimal), or all of them");
+    println!("  watch                   list the watches");
+    println!("  watch screen <x> <y> <w> <h>");
+    println!("                          stop after any DRW that toggles a pixel in the rectangle");
+    println!("  unwatch [n]             delete watch n (numbered in decimal), or all of them");
+    println!("  trace on|off            start or stop writing executed instructions to the --trace file");
+    println!("  set <reg> <value>       set V0-VF, I, PC, SP, DT or ST");
+    println!("  search <byte>           find addresses holding the byte, narrowing previous results");
+    println!("  search                  list the current search results");
+    println!("  search reset            start a new search");
+}
@@ -0,0 +1,65 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::mem;
+use std::ops::Range;
+
+use device::Device;
+
+/// How many addresses each of the port's windows takes up, one for each register
+/// LD [I], Vx can store
+const WINDOW_LENGTH: usize = 16;
+
+/// A device a program under development can print through, to debug it the way
+/// printf would. It takes up two windows of 16 addresses from the one it's at:
+///
+/// ```text
+/// port + 00..0F  text: each byte written is a character, a newline prints the
+///                line so far, and so does a zero if there's anything to print
+/// port + 10..1F  values: each byte written goes on the line in hex
+/// ```
+///
+/// So with I at the port, LD [I], Vx writes V0 to Vx as text, and with I at the
+/// values, LD [I], VF puts every register on the line.
+pub struct DebugPort {
+    /// the first address of the text window
+    addr: usize,
+    /// the line written so far
+    line: String,
+    /// whether the last thing on the line is a value, which the next one is spaced from
+    after_value: bool,
+}
+
+impl DebugPort {
+    /// Construct a port at the given address
+    pub fn new(addr: usize) -> DebugPort {
+        DebugPort { addr: addr, line: String::new(), after_value: false }
+    }
+
+    /// The addresses the port takes up
+    pub fn addrs(&self) -> Range<usize> {
+        self.addr..self.addr + WINDOW_LENGTH * 2
+    }
+}
+
+impl Device for DebugPort {
+    fn write(&mut self, addr: usize, value: u8) {
+        if addr >= self.addr + WINDOW_LENGTH {
+            if self.after_value {
+                self.line.push(' ');
+            }
+            self.line.push_str(&format!("{:02X}", value));
+            self.after_value = true;
+            return;
+        }
+
+        self.after_value = false;
+        match value {
+            0 if self.line.is_empty() => (),
+            b'\n' | 0 => info!(target: "debugport", "{}", mem::take(&mut self.line)),
+            b'\r' => (),
+            _ => self.line.push(value as char),
+        }
+    }
+}
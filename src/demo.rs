@@ -0,0 +1,47 @@
+//
+// Author: Joshua Holmes
+//
+
+/// A tiny program built into the emulator, run when no program is given so
+/// there's something to look at. It shows the sixteen font digits, then shows
+/// whichever key was pressed last in the top right corner. Written for this
+/// emulator and free for any use.
+///
+/// ```text
+/// 200: 00E0   CLS
+/// 202: 6000   LD V0, 00       ; the digit to draw
+/// 204: 6104   LD V1, 04       ; x
+/// 206: 6208   LD V2, 08       ; y
+/// 208: F029   LD F, V0
+/// 20A: D125   DRW V1, V2, 5
+/// 20C: 7001   ADD V0, 01
+/// 20E: 7107   ADD V1, 07
+/// 210: 3008   SE V0, 08       ; after eight digits, start the second row
+/// 212: 1218   JP 218
+/// 214: 6104   LD V1, 04
+/// 216: 6214   LD V2, 14
+/// 218: 3010   SE V0, 10
+/// 21A: 1208   JP 208
+/// 21C: 6338   LD V3, 38       ; where the last key pressed goes
+/// 21E: 6401   LD V4, 01
+/// 220: 6500   LD V5, 00
+/// 222: F529   LD F, V5
+/// 224: D345   DRW V3, V4, 5
+/// 226: E5A1   SKNP V5         ; wait for the key to be let go
+/// 228: 1226   JP 226
+/// 22A: F50A   LD V5, K
+/// 22C: D345   DRW V3, V4, 5   ; rub out the old key
+/// 22E: 1222   JP 222
+/// ```
+pub const DEMO_PROGRAM: [u8; 48] = [
+    0x00, 0xE0, 0x60, 0x00, 0x61, 0x04, 0x62, 0x08,
+    0xF0, 0x29, 0xD1, 0x25, 0x70, 0x01, 0x71, 0x07,
+    0x30, 0x08, 0x12, 0x18, 0x61, 0x04, 0x62, 0x14,
+    0x30, 0x10, 0x12, 0x08, 0x63, 0x38, 0x64, 0x01,
+    0x65, 0x00, 0xF5, 0x29, 0xD3, 0x45, 0xE5, 0xA1,
+    0x12, 0x26, 0xF5, 0x0A, 0xD3, 0x45, 0x12, 0x22,
+];
+
+/// The name the demo program goes by where a program's path would go, such as
+/// in the names of save-state files
+pub const DEMO_NAME: &str = "demo";
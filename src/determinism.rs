@@ -0,0 +1,91 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fmt;
+
+use batch;
+use emulator::Emulator;
+use input::KeyEvent;
+
+/// Where runs of a program that should have gone exactly the same didn't
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nondeterminism {
+    /// the run that went differently from the first, counting from 1
+    pub run: usize,
+    /// the frame it went differently in, counting from 1
+    pub frame: u64,
+    /// the state checksum the first run had at the end of the frame, or None if it had stopped
+    pub expected: Option<u32>,
+    /// the state checksum the other run had, or None if it had stopped
+    pub found: Option<u32>,
+}
+
+impl fmt::Display for Nondeterminism {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |checksum: Option<u32>| match checksum {
+            Some(c) => format!("{:08X}", c),
+            None => "stopped".to_owned(),
+        };
+
+        write!(f, "run {} went differently from run 1 in frame {}: the state checksum is {} but run 1 had {}",
+            self.run, self.frame, describe(self.found), describe(self.expected))
+    }
+}
+
+/// Runs a program the given number of times for up to the given number of 60Hz
+/// frames, as fast as possible and spread over up to jobs threads, and checks
+/// that every run ends every frame in exactly the same state. The build function
+/// makes a fresh emulator for each run, and has to give every one the same seed.
+/// The key events each frame starts with come from the input given, like a
+/// movie's, with none once it runs out.
+///
+/// Returns the state checksum the runs ended on, or where one first went
+/// differently from the first run.
+pub fn check<F: Fn() -> Emulator + Sync>(build: F, input: &[Vec<KeyEvent>], frames: u64, runs: usize, jobs: usize) -> Result<u32, Nondeterminism> {
+    let checksums = batch::run((0..runs).collect(), jobs, |_| run(&mut build(), input, frames));
+
+    let first = match checksums.first() {
+        Some(c) => c,
+        None => return Ok(0),
+    };
+
+    for (n, other) in checksums.iter().enumerate().skip(1) {
+        let len = first.len().max(other.len());
+        if let Some(frame) = (0..len).find(|&f| first.get(f) != other.get(f)) {
+            return Err(Nondeterminism {
+                run: n + 1,
+                frame: frame as u64 + 1,
+                expected: first.get(frame).cloned(),
+                found: other.get(frame).cloned(),
+            });
+        }
+    }
+
+    Ok(first.last().cloned().unwrap_or(0))
+}
+
+/// Runs a program, returning the state checksum at the end of every frame it got
+/// through before it finished or stopped with an error
+fn run(emulator: &mut Emulator, input: &[Vec<KeyEvent>], frames: u64) -> Vec<u32> {
+    let mut checksums = Vec::new();
+
+    for frame in 0..frames {
+        for &event in input.get(frame as usize).into_iter().flatten() {
+            emulator.input.push(event);
+        }
+
+        let halted = match emulator.run_frame() {
+            Ok(report) => report.halted,
+            Err(_) => true,
+        };
+        emulator.cpu.warnings.clear();
+
+        checksums.push(emulator.cpu.checksum());
+        if halted {
+            break;
+        }
+    }
+
+    checksums
+}
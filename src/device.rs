@@ -0,0 +1,65 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::ops::Range;
+
+/// Something a program talks to by reading and writing memory at addresses set
+/// aside for it, like a serial port a test ROM writes its results to, or a frame
+/// counter it reads. Every method does nothing by default.
+pub trait Device: Send {
+    /// The program is reading a byte at one of the device's addresses. The value
+    /// in memory there is given. Returns what the program reads, or None for the
+    /// value in memory.
+    fn read(&mut self, _addr: usize, _value: u8) -> Option<u8> {
+        None
+    }
+
+    /// The program has written a byte to one of the device's addresses. It's in
+    /// memory by the time this is called.
+    fn write(&mut self, _addr: usize, _value: u8) {}
+}
+
+/// The devices hooked up to a machine's memory. Only reads and writes made by the
+/// program's instructions reach them, after the usual checks on the address, and
+/// fetching instructions never does.
+#[derive(Default)]
+pub struct Devices {
+    /// each device, with the addresses it answers for
+    devices: Vec<(Range<usize>, Box<dyn Device>)>,
+}
+
+impl Devices {
+    /// Construct a set of devices with none hooked up
+    pub fn new() -> Devices {
+        Devices { devices: Vec::new() }
+    }
+
+    /// Hooks up a device to answer for a range of addresses. Where ranges overlap,
+    /// the device hooked up first answers reads.
+    pub fn attach(&mut self, addrs: Range<usize>, device: Box<dyn Device>) {
+        self.devices.push((addrs, device));
+    }
+
+    /// Whether there are no devices hooked up, so memory works as it always does
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// What the program reads from an address holding the given value in memory
+    pub fn read(&mut self, addr: usize, value: u8) -> u8 {
+        self.devices.iter_mut()
+            .filter(|d| d.0.contains(&addr))
+            .find_map(|d| d.1.read(addr, value))
+            .unwrap_or(value)
+    }
+
+    /// Tells every device answering for an address that the program wrote to it
+    pub fn write(&mut self, addr: usize, value: u8) {
+        for (addrs, device) in self.devices.iter_mut() {
+            if addrs.contains(&addr) {
+                device.write(addr, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+
+use analysis::{self, ByteUse};
+use assembler;
+use opcode::{OpCode, INSTR_SIZE};
+use variant::Variant;
+
+/// How many bytes of data go on each DB line
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// The terminal colours of the parts of a listing line
+const ADDR_COLOR: &str = "\x1b[34m";
+const BYTES_COLOR: &str = "\x1b[90m";
+const MNEMONIC_COLOR: &str = "\x1b[1;33m";
+const REGISTER_COLOR: &str = "\x1b[32m";
+const NUMBER_COLOR: &str = "\x1b[35m";
+const COMMENT_COLOR: &str = "\x1b[36m";
+
+/// How wide the instruction column is, before any comment
+const TEXT_WIDTH: usize = 24;
+const RESET: &str = "\x1b[0m";
+
+/// The operands that name registers rather than give a number. LD's F and B do
+/// too, but they're hex digits anywhere else.
+const REGISTER_OPERANDS: [&str; 7] = ["I", "[I]", "DT", "ST", "K", "HF", "R"];
+
+/// When a listing is shown in colour
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// only when it's going straight to a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// The choice with the given name: auto, always or never
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether a listing written to stdout should be in colour
+    pub fn enabled(&self) -> bool {
+        match *self {
+            ColorChoice::Auto => io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// How the lines of a listing are shown
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct ListingStyle {
+    /// whether mnemonics, registers, numbers and addresses get terminal colours
+    pub color: bool,
+    /// whether each line shows its bytes in hex
+    pub bytes: bool,
+    /// whether each line shows its bytes as ASCII, with '.' for anything unprintable
+    pub ascii: bool,
+}
+
+/// A line of a program's disassembly: an instruction, or some bytes of data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingLine {
+    /// where the line's bytes are
+    pub addr: usize,
+    pub bytes: Vec<u8>,
+    /// the line as the assembler reads it, like "DRW V0, V1, 5" or "DB 3C, 7E"
+    pub text: String,
+}
+
+/// A line of a disassembly that doesn't assemble back to the bytes it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub line: ListingLine,
+    /// what went wrong, like "assembles to 00E0"
+    pub problem: String,
+}
+
+/// Disassembles a program loaded at the given address into lines the assembler
+/// can read back. Bytes the program only ever draws as sprites, as far as
+/// analysis::classify can tell, are shown as DB lines, and so is anything that
+/// isn't an instruction.
+pub fn disassemble(program: &[u8], origin: usize, variant: Variant) -> Vec<ListingLine> {
+    let mut memory = vec![0; origin];
+    memory.extend_from_slice(program);
+
+    let end = memory.len();
+    let mut data = vec![false; end];
+    for region in analysis::classify(&memory, origin, variant.start_addr(), variant) {
+        if region.kind == ByteUse::SpriteData {
+            for flag in data[region.start..region.end].iter_mut() {
+                *flag = true;
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut addr = origin;
+    while addr < end {
+        let is_code = |a: usize| a < end && !data[a];
+
+        if !is_code(addr) || !is_code(addr + 1) {
+            // a run of data, stopping where an instruction could start
+            let mut length = 1;
+            while length < DATA_BYTES_PER_LINE && addr + length < end && (!is_code(addr + length) || !is_code(addr + length + 1)) {
+                length += 1;
+            }
+            lines.push(data_line(addr, &memory[addr..addr + length]));
+            addr += length;
+            continue;
+        }
+
+        let instruction = ((memory[addr] as u16) << 8) | (memory[addr + 1] as u16);
+        // Mega-Chip's LDHI I is followed by the low 16 bits of its address
+        let is_ldhi = variant == Variant::MegaChip && instruction & 0xFF00 == 0x0100;
+        let line = match OpCode::from_u16(instruction, variant) {
+            Some(_) if is_ldhi && is_code(addr + 2) && is_code(addr + 3) => {
+                let target = ((memory[addr + 1] as usize) << 16) | ((memory[addr + 2] as usize) << 8) | (memory[addr + 3] as usize);
+                ListingLine { addr: addr, bytes: memory[addr..addr + 4].to_vec(), text: format!("LDHI I, {:06X}", target) }
+            },
+            Some(ref o) if !is_ldhi => {
+                ListingLine { addr: addr, bytes: memory[addr..addr + INSTR_SIZE].to_vec(), text: o.disasm_str.clone() }
+            },
+            _ => data_line(addr, &memory[addr..addr + INSTR_SIZE]),
+        };
+
+        addr += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The source of a disassembly, a line of it to each line of the listing
+pub fn source(lines: &[ListingLine]) -> String {
+    lines.iter().map(|l| format!("{}\n", l.text)).collect()
+}
+
+/// Comments saying where a program goes to each address from, like "; called
+/// from 0x224, 0x3A0" on a subroutine's first instruction or "; jumped to from
+/// 0x2F2", keyed by the address, for the program loaded at the given address
+pub fn xref_comments(program: &[u8], origin: usize, variant: Variant) -> BTreeMap<usize, String> {
+    let mut memory = vec![0; origin];
+    memory.extend_from_slice(program);
+
+    let list = |addrs: &[usize]| addrs.iter().map(|a| format!("0x{:03X}", a)).collect::<Vec<String>>().join(", ");
+
+    analysis::xrefs(&memory, variant.start_addr(), variant).into_iter().map(|(addr, x)| {
+        let mut parts = Vec::new();
+        if !x.called_from.is_empty() {
+            parts.push(format!("called from {}", list(&x.called_from)));
+        }
+        if !x.jumped_to_from.is_empty() {
+            parts.push(format!("jumped to from {}", list(&x.jumped_to_from)));
+        }
+        (addr, format!("; {}", parts.join("; ")))
+    }).collect()
+}
+
+/// A line of a listing as it's shown, like
+///
+/// ```text
+/// 0x200: 00 E0  ..  CLS  ; jumped to from 0x20C
+/// ```
+///
+/// with the bytes and ASCII columns there only if the style asks for them, and
+/// the comment only if there is one
+pub fn format_line(line: &ListingLine, style: ListingStyle, comment: Option<&str>) -> String {
+    let paint = |color: &str, text: &str| if style.color { format!("{}{}{}", color, text, RESET) } else { text.to_owned() };
+
+    let mut out = paint(ADDR_COLOR, &format!("0x{:03X}:", line.addr));
+    if style.bytes {
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        out.push(' ');
+        out.push_str(&paint(BYTES_COLOR, &format!("{:<23}", bytes.join(" "))));
+    }
+    if style.ascii {
+        out.push_str("  ");
+        out.push_str(&paint(BYTES_COLOR, &format!("{:<8}", ascii(&line.bytes))));
+    }
+    out.push_str("  ");
+    let text_start = visible_len(&out);
+
+    let (mnemonic, operands) = match line.text.find(' ') {
+        Some(n) => (&line.text[..n], Some(&line.text[n + 1..])),
+        None => (line.text.as_str(), None),
+    };
+    out.push_str(&paint(MNEMONIC_COLOR, mnemonic));
+
+    if let Some(operands) = operands {
+        let operands: Vec<String> = operands.split(", ").map(|o| {
+            let is_register = (o.len() == 2 && o.starts_with('V')) || REGISTER_OPERANDS.contains(&o) || (mnemonic == "LD" && (o == "F" || o == "B"));
+            if is_register {
+                paint(REGISTER_COLOR, o)
+            } else if !o.is_empty() && o.chars().all(|c| c.is_ascii_hexdigit()) {
+                paint(NUMBER_COLOR, o)
+            } else {
+                o.to_owned()
+            }
+        }).collect();
+        out.push(' ');
+        out.push_str(&operands.join(", "));
+    }
+
+    if let Some(comment) = comment {
+        let width = visible_len(&out) - text_start;
+        out.push_str(&" ".repeat(TEXT_WIDTH.saturating_sub(width).max(2)));
+        out.push_str(&paint(COMMENT_COLOR, comment));
+    }
+
+    out
+}
+
+/// A listing of a disassembly in the fixed columns assembler listings usually
+/// have, with nothing in it that changes from one run to the next, so listings
+/// of two versions of a ROM can be diffed:
+///
+/// ```text
+/// 0200  00 E0                    ..        cls
+/// 0202  A2 0A                    ..        ld i, 20a
+/// 020A  3C 7E FF 41 42           <~.AB     db 3c, 7e, ff, 41, 42
+/// ```
+///
+/// The disassembly is in lower case, which the assembler reads just the same.
+pub fn listing(lines: &[ListingLine]) -> String {
+    lines.iter().map(|l| {
+        let bytes: Vec<String> = l.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{:04X}  {:<23}  {:<8}  {}\n", l.addr, bytes.join(" "), ascii(&l.bytes), l.text.to_lowercase())
+    }).collect()
+}
+
+/// Disassembles a program and assembles the disassembly again, returning every
+/// line that doesn't come back as the bytes it started as. Data comes back
+/// unchanged by definition, so it's the instructions both tools understand
+/// differently that show up here.
+pub fn roundtrip(program: &[u8], origin: usize, variant: Variant) -> Vec<Mismatch> {
+    let lines = disassemble(program, origin, variant);
+
+    let reassembled = match assembler::assemble(&source(&lines), origin) {
+        Ok(bytes) => bytes,
+        Err(errors) => {
+            return errors.into_iter()
+                .filter_map(|e| lines.get(e.line - 1).map(|l| Mismatch { line: l.clone(), problem: e.to_string() }))
+                .collect();
+        },
+    };
+
+    lines.into_iter().filter_map(|line| {
+        let offset = line.addr - origin;
+        let found = reassembled.get(offset..offset + line.bytes.len()).unwrap_or(&[]);
+        if found == line.bytes.as_slice() {
+            return None;
+        }
+
+        let found: Vec<String> = found.iter().map(|b| format!("{:02X}", b)).collect();
+        Some(Mismatch { line: line, problem: format!("assembles to {}", found.join(" ")) })
+    }).collect()
+}
+
+/// Some bytes as ASCII, with '.' for anything unprintable
+fn ascii(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| if (0x20..0x7F).contains(&b) { b as char } else { '.' }).collect()
+}
+
+/// How many columns some text takes up on a terminal, leaving out colour codes
+fn visible_len(text: &str) -> usize {
+    let mut len = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if !in_escape => len += 1,
+            _ => (),
+        }
+    }
+    len
+}
+
+/// A DB line for some bytes of data
+fn data_line(addr: usize, bytes: &[u8]) -> ListingLine {
+    let values: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    ListingLine { addr: addr, bytes: bytes.to_vec(), text: format!("DB {}", values.join(", ")) }
+}
@@ -4,69 +4,560 @@
 
 extern crate sdl2;
 
-use cpu;
-use cpu::Cpu;
+use chip8::cpu;
+use chip8::frame::Frame;
+use chip8::megachip;
+use chip8::palette::Palette;
+use chip8::rotation::Rotation;
+use osd;
+#[cfg(feature = "shaders")]
+use shader::PostShader;
 use sdl2::Sdl;
 use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::BlendMode;
 use sdl2::render::Renderer;
 use sdl2::render::Texture;
+#[cfg(feature = "shaders")]
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing;
 
-/// The display scale in relation to the native resolution of the system
-pub const DISPLAY_SCALE: u32 = 30;
+/// What the window is called, before the name of the program is added
+const WINDOW_TITLE: &str = "CHIP-8: This Time In Rust";
+/// The display scale in relation to the native resolution of the system, unless
+/// the command line or the program's config sets another
+pub const DEFAULT_DISPLAY_SCALE: u32 = 30;
+/// How many screen pixels across each pixel of message text is
+const MESSAGE_SCALE: u32 = 3;
+/// How far messages are kept from the edges of the window, and from each other
+const MESSAGE_MARGIN: u32 = 8;
+/// How long a message stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+/// How many messages are shown at once, newest at the bottom
+const MAX_MESSAGES: usize = 4;
+/// How many window pixels across the screen's pixels have to be for the grid to be drawn over them
+const MIN_GRID_CELL: u32 = 4;
+/// How many bytes of RGB24 texture eight pixels, one byte of a VRAM row, take up
+const BYTE_RGB_LENGTH: usize = 8 * 3;
+/// SDL_WINDOW_ALWAYS_ON_TOP, which SDL 2.0.5 added after these bindings were written
+const SDL_WINDOW_ALWAYS_ON_TOP: u32 = 0x0000_8000;
+/// SDL_WINDOWPOS_CENTERED_DISPLAY(0), which these bindings don't have; the monitor's index goes in the low bits
+const SDL_WINDOWPOS_CENTERED_DISPLAY: i32 = 0x2FFF_0000;
+
+/// How the window looks and where it sits among the others
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowOptions {
+    /// how many window pixels across each pixel of the virtual display is
+    pub scale: u32,
+    /// whether the window has no title bar or border
+    pub borderless: bool,
+    /// whether the window stays in front of every other window
+    pub always_on_top: bool,
+    /// whether the window fills the whole of its monitor
+    pub fullscreen: bool,
+    /// the index of the monitor the window opens on, or None for the primary one
+    pub monitor: Option<i32>,
+    /// how far the screen is turned as it's drawn
+    pub rotation: Rotation,
+    /// a GLSL fragment shader file to draw the screen through, if any
+    pub shader: Option<String>,
+    /// whether faint lines are drawn between the screen's pixels to start with
+    pub grid: bool,
+}
+
+impl Default for WindowOptions {
+    fn default() -> WindowOptions {
+        WindowOptions::new()
+    }
+}
+
+impl WindowOptions {
+    /// Construct the options for an ordinary window at the default scale
+    pub fn new() -> WindowOptions {
+        WindowOptions {
+            scale: DEFAULT_DISPLAY_SCALE,
+            borderless: false,
+            always_on_top: false,
+            fullscreen: false,
+            monitor: None,
+            rotation: Rotation::None,
+            shader: None,
+            grid: false,
+        }
+    }
+}
+
+/// What the HUD shows: the timers and how fast the program is running
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimerStatus {
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// how many instructions ran in the last frame
+    pub instructions_per_frame: u32,
+}
 
 /// A structure to manage displaying the screen based on the system's VRAM
 pub struct Display<'a> {
     renderer: Renderer<'a>,
     texture: Texture,
+    mega_texture: Texture,
+    /// the RGB24 pixels for every byte a VRAM row can hold, in the palette's colors
+    byte_colors: Vec<[u8; BYTE_RGB_LENGTH]>,
+    /// the height of the virtual display the window is sized for
+    display_height: usize,
+    /// how many screens are shown side by side
+    panes: usize,
+    /// how many window pixels across each pixel of the virtual display is
+    scale: u32,
+    /// whether the window fills its monitor, with the screen kept in proportion in the middle of it
+    fullscreen: bool,
+    /// the color around the screen when it doesn't fill the window
+    background: Color,
+    /// how far the screen is turned as it's drawn
+    rotation: Rotation,
+    /// whether faint lines are drawn between the screen's pixels
+    grid: bool,
+    /// the shader the screen is drawn through, if there is one and the renderer can run it
+    #[cfg(feature = "shaders")]
+    shader: Option<PostShader>,
+    /// whether the Mega-Chip screen is showing rather than the monochrome one
+    showing_mega: bool,
+    /// the messages shown over the screen and when each was posted, oldest first
+    messages: Vec<(String, Instant)>,
+    /// what the HUD in the top right corner shows, if it's switched on
+    hud: Option<TimerStatus>,
 }
 
 impl<'a> Display<'a> {
-    /// Construct a new Display object
-    pub fn new(sdl_context: &Sdl) -> Display<'a> {
+    /// Construct a new Display object for a virtual display of the given height
+    pub fn new(sdl_context: &Sdl, display_height: usize, palette: Palette, options: WindowOptions) -> Display<'a> {
+        Display::with_panes(sdl_context, display_height, palette, 1, options)
+    }
+
+    /// Construct a Display object that shows several virtual displays of the given height side by side
+    pub fn with_panes(sdl_context: &Sdl, display_height: usize, palette: Palette, panes: usize, options: WindowOptions) -> Display<'a> {
         let video_subsystem = sdl_context.video().unwrap();
 
-        let window = video_subsystem.window("CHIP-8: This Time In Rust", 
-            DISPLAY_SCALE * cpu::VIRTUAL_DISPLAY_WIDTH as u32, 
-            DISPLAY_SCALE * cpu::VIRTUAL_DISPLAY_HEIGHT as u32)
-            .position_centered()
-            .opengl()
-            .build()
-            .unwrap();
+        let (width, height) = window_size(options.scale, options.rotation, cpu::VIRTUAL_DISPLAY_WIDTH * panes, display_height);
+        let mut builder = video_subsystem.window(WINDOW_TITLE, width, height);
+        builder.opengl();
+
+        let monitors = video_subsystem.num_video_displays().unwrap_or(1);
+        match options.monitor {
+            Some(m) if m >= 0 && m < monitors => {
+                builder.position(SDL_WINDOWPOS_CENTERED_DISPLAY | m, SDL_WINDOWPOS_CENTERED_DISPLAY | m);
+            },
+            Some(m) => {
+                warn!(target: "display", "There's no monitor {} (there are {}), so using the primary one.", m, monitors);
+                builder.position_centered();
+            },
+            None => {
+                builder.position_centered();
+            },
+        }
+
+        if options.fullscreen {
+            builder.fullscreen_desktop();
+        }
+
+        if options.borderless {
+            builder.borderless();
+        }
+        if options.always_on_top {
+            let flags = builder.window_flags() | SDL_WINDOW_ALWAYS_ON_TOP;
+            builder.set_window_flags(flags);
+        }
+
+        let window = builder.build().unwrap();
 
         let mut renderer = window.renderer().build().unwrap(); 
 
-        renderer.set_draw_color(Color::RGB(16, 113, 145));
+        let (r, g, b) = Palette::rgb(palette.background);
+        let background = Color::RGB(r, g, b);
+        renderer.set_draw_color(background);
         renderer.clear();
         renderer.present();
 
+        // drawing goes on without the shader if it can't be used, rather than not at all
+        #[cfg(feature = "shaders")]
+        let shader = options.shader.and_then(|path| match PostShader::load(&video_subsystem, &renderer, Path::new(&path)) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                warn!(target: "display", "Couldn't use the shader, so drawing without it. Error message: {}", e);
+                None
+            },
+        });
+
         let texture = renderer.create_texture_streaming(
-            PixelFormatEnum::RGB24, cpu::VIRTUAL_DISPLAY_WIDTH as u32, cpu::VIRTUAL_DISPLAY_HEIGHT as u32).unwrap();
+            PixelFormatEnum::RGB24, (cpu::VIRTUAL_DISPLAY_WIDTH * panes) as u32, display_height as u32).unwrap();
+
+        let mega_texture = renderer.create_texture_streaming(
+            PixelFormatEnum::RGB24, megachip::MEGA_DISPLAY_WIDTH as u32, megachip::MEGA_DISPLAY_HEIGHT as u32).unwrap();
 
         Display {
             renderer: renderer,
             texture: texture,
+            mega_texture: mega_texture,
+            byte_colors: byte_color_table(&palette),
+            display_height: display_height,
+            panes: panes,
+            scale: options.scale,
+            fullscreen: options.fullscreen,
+            background: background,
+            rotation: options.rotation,
+            grid: options.grid,
+            #[cfg(feature = "shaders")]
+            shader: shader,
+            showing_mega: false,
+            messages: Vec::new(),
+            hud: None,
+        }
+    }
+
+    /// Resizes the window for a virtual display of the given height, if it isn't already
+    pub fn resize(&mut self, display_height: usize) {
+        if display_height == self.display_height {
+            return;
         }
+
+        self.display_height = display_height;
+        self.fit_window();
+
+        self.texture = self.renderer.create_texture_streaming(
+            PixelFormatEnum::RGB24, (cpu::VIRTUAL_DISPLAY_WIDTH * self.panes) as u32, display_height as u32).unwrap();
     }
 
-    /// Draws the screen given a CPU object whose VRAM we can read
-    pub fn draw_screen(&mut self, cpu: &Cpu) {
-        // update our texture with the system's VRAM
+    /// Turns the screen a further quarter turn clockwise, resizing the window to
+    /// match, and returns how far it's turned now
+    pub fn rotate(&mut self) -> Rotation {
+        self.rotation = self.rotation.next();
+        self.fit_window();
+        self.present();
+
+        self.rotation
+    }
+
+    /// Shows or hides the lines between the screen's pixels, and returns whether they're showing now
+    pub fn toggle_grid(&mut self) -> bool {
+        self.grid = !self.grid;
+        self.present();
+
+        self.grid
+    }
+
+    /// Sizes the window for the screen as it's turned, unless it's fullscreen,
+    /// when it stays the size of its monitor
+    fn fit_window(&mut self) {
+        let (width, height) = window_size(self.scale, self.rotation, cpu::VIRTUAL_DISPLAY_WIDTH * self.panes, self.display_height);
+
+        match self.renderer.window_mut() {
+            Some(window) if !self.fullscreen => window.set_size(width, height).unwrap(),
+            _ => (),
+        }
+    }
+
+    /// Draws a snapshot of the system's screen
+    pub fn draw_frame(&mut self, frame: &Frame) {
+        if let Some(ref mega_screen) = frame.mega_screen {
+            self.draw_mega_screen(mega_screen);
+            return;
+        }
+
+        self.draw_frames(&[frame]);
+    }
+
+    /// Draws snapshots of several monochrome screens side by side, left to right
+    pub fn draw_frames(&mut self, frames: &[&Frame]) {
+        let byte_colors = &self.byte_colors;
+
+        // update our texture with the system's VRAM, eight pixels at a time
         self.texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for y in 0..cpu::VIRTUAL_DISPLAY_HEIGHT {
-                for x in 0..cpu::VIRTUAL_DISPLAY_WIDTH {
-                    let bit = cpu.vram[y][x];
+            for (pane, frame) in frames.iter().enumerate() {
+                let left = pane * cpu::VIRTUAL_DISPLAY_WIDTH * 3;
+
+                for (y, &row) in frame.vram.iter().enumerate() {
+                    let start = (y * pitch) + left;
+                    let line = &mut buffer[start..start + cpu::VIRTUAL_DISPLAY_WIDTH * 3];
+
+                    for (pixels, &byte) in line.chunks_mut(BYTE_RGB_LENGTH).zip(row.to_be_bytes().iter()) {
+                        pixels.copy_from_slice(&byte_colors[byte as usize]);
+                    }
+                }
+            }
+        }).unwrap();
+
+        self.showing_mega = false;
+        self.present();
+    }
+
+    /// Draws the Mega-Chip screen, given as 0xRRGGBB pixels
+    fn draw_mega_screen(&mut self, mega_screen: &[u32]) {
+        // update our texture with the last finished Mega-Chip frame
+        self.mega_texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for y in 0..megachip::MEGA_DISPLAY_HEIGHT {
+                for x in 0..megachip::MEGA_DISPLAY_WIDTH {
+                    let color = mega_screen[y * megachip::MEGA_DISPLAY_WIDTH + x];
                     let offset = (y * pitch) + (x * 3);
 
-                    buffer[offset] = if bit { 255 } else { 16 };
-                    buffer[offset + 1] = if bit { 255 } else { 113 };
-                    buffer[offset + 2] = if bit { 255 } else { 145 };
+                    buffer[offset] = (color >> 16) as u8;
+                    buffer[offset + 1] = (color >> 8) as u8;
+                    buffer[offset + 2] = color as u8;
                 }
             }
         }).unwrap();
 
-        // draw the texture
-        self.renderer.copy(&self.texture, None, None);
+        self.showing_mega = true;
+        self.present();
+    }
+
+    /// Names the program that's running in the window's title
+    pub fn set_program_title(&mut self, program: &str) {
+        if let Some(window) = self.renderer.window_mut() {
+            let _ = window.set_title(&format!("{} - {}", WINDOW_TITLE, program));
+        }
+    }
+
+    /// Shows a message over the screen for a few seconds
+    pub fn show_message(&mut self, text: &str) {
+        self.messages.push((text.to_owned(), Instant::now()));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+
+        self.present();
+    }
+
+    /// Takes down any messages that have been up long enough
+    pub fn expire_messages(&mut self) {
+        let count = self.messages.len();
+        self.messages.retain(|&(_, posted)| posted.elapsed() < MESSAGE_DURATION);
+
+        if self.messages.len() != count {
+            self.present();
+        }
+    }
+
+    /// Shows the timers in the top right corner, or takes them down if given None
+    pub fn set_hud(&mut self, hud: Option<TimerStatus>) {
+        if hud != self.hud {
+            self.hud = hud;
+            self.present();
+        }
+    }
+
+    /// Draws the last screen we were given with the messages over it
+    fn present(&mut self) {
+        let _span = tracing::debug_span!("display").entered();
+        let (width, height) = if self.showing_mega {
+            (megachip::MEGA_DISPLAY_WIDTH as u32, megachip::MEGA_DISPLAY_HEIGHT as u32)
+        } else {
+            ((cpu::VIRTUAL_DISPLAY_WIDTH * self.panes) as u32, self.display_height as u32)
+        };
+        let screen = self.screen_rect(width, height);
+
+        if screen.is_some() {
+            self.renderer.set_draw_color(self.background);
+            self.renderer.clear();
+        }
+
+        #[cfg(feature = "shaders")]
+        let shaded = self.draw_shaded((width, height), screen);
+        #[cfg(not(feature = "shaders"))]
+        let shaded = false;
+
+        if !shaded {
+            let texture = if self.showing_mega { &self.mega_texture } else { &self.texture };
+            if self.rotation == Rotation::None {
+                self.renderer.copy(texture, None, screen);
+            } else {
+                let _ = self.renderer.copy_ex(texture, None, screen, self.rotation.degrees() as f64, None, false, false);
+            }
+        }
+
+        if self.grid {
+            self.draw_grid((width, height), screen);
+        }
+
+        self.draw_messages();
+        self.draw_hud();
         self.renderer.present();
     }
-}
\ No newline at end of file
+
+    /// Draws the screen through the shader, if there is one, into where screen_rect
+    /// said it goes. Returns whether it was drawn.
+    #[cfg(feature = "shaders")]
+    fn draw_shaded(&mut self, size: (u32, u32), screen: Option<Rect>) -> bool {
+        let window_size = match self.renderer.output_size() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+
+        // the shader draws the screen already turned, so it wants the outline it turns into
+        let outline = self.screen_outline(screen, window_size);
+
+        let texture = if self.showing_mega { &mut self.mega_texture } else { &mut self.texture };
+        match self.shader {
+            Some(ref shader) => {
+                shader.draw(texture, size, outline, window_size, self.rotation);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The part of a window of the given size the screen covers once it's turned,
+    /// given where screen_rect said it's copied to
+    fn screen_outline(&self, screen: Option<Rect>, window_size: (u32, u32)) -> Rect {
+        match screen {
+            None => Rect::new(0, 0, window_size.0, window_size.1),
+            Some(r) if self.rotation.is_sideways() => {
+                let (center_x, center_y) = (r.x() + r.width() as i32 / 2, r.y() + r.height() as i32 / 2);
+                Rect::new(center_x - r.height() as i32 / 2, center_y - r.width() as i32 / 2, r.height(), r.width())
+            },
+            Some(r) => r,
+        }
+    }
+
+    /// Draws faint lines between the screen's pixels, as long as they're big enough
+    /// for the lines not to swamp them
+    fn draw_grid(&mut self, size: (u32, u32), screen: Option<Rect>) {
+        let window_size = match self.renderer.output_size() {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+
+        let outline = self.screen_outline(screen, window_size);
+        let (columns, rows) = if self.rotation.is_sideways() { (size.1, size.0) } else { size };
+        if outline.width() < columns * MIN_GRID_CELL || outline.height() < rows * MIN_GRID_CELL {
+            return;
+        }
+
+        self.renderer.set_blend_mode(BlendMode::Blend);
+        self.renderer.set_draw_color(Color::RGBA(0x80, 0x80, 0x80, 0x60));
+
+        let (left, top) = (outline.x(), outline.y());
+        let (right, bottom) = (left + outline.width() as i32, top + outline.height() as i32);
+        for column in 1..columns {
+            let x = left + (column * outline.width() / columns) as i32;
+            let _ = self.renderer.draw_line(Point::new(x, top), Point::new(x, bottom - 1));
+        }
+        for row in 1..rows {
+            let y = top + (row * outline.height() / rows) as i32;
+            let _ = self.renderer.draw_line(Point::new(left, y), Point::new(right - 1, y));
+        }
+
+        self.renderer.set_blend_mode(BlendMode::None);
+    }
+
+    /// Where a screen of the given size is copied to in the window, before it's
+    /// turned: None for the whole window, when it fills the window unturned. A
+    /// fullscreen window gets the largest area in proportion to the screen that
+    /// fits, centered.
+    fn screen_rect(&self, width: u32, height: u32) -> Option<Rect> {
+        if !self.fullscreen && self.rotation == Rotation::None {
+            return None;
+        }
+
+        let (window_width, window_height) = match self.renderer.output_size() {
+            Ok(size) => size,
+            Err(_) => return None,
+        };
+
+        // the outline the screen takes up in the window, which is on its side if the screen is
+        let (across, down) = if self.rotation.is_sideways() { (height, width) } else { (width, height) };
+        let (fit_across, fit_down) = if !self.fullscreen {
+            (window_width, window_height)
+        } else if window_width * down > window_height * across {
+            (window_height * across / down, window_height)
+        } else {
+            (window_width, window_width * down / across)
+        };
+
+        // the screen is turned about the middle of where it's copied to, so a screen
+        // on its side is copied into an outline the other way round
+        let (fit_width, fit_height) = if self.rotation.is_sideways() { (fit_down, fit_across) } else { (fit_across, fit_down) };
+
+        Some(Rect::new((window_width as i32 - fit_width as i32) / 2, (window_height as i32 - fit_height as i32) / 2, fit_width, fit_height))
+    }
+
+    /// Draws the messages in the bottom left corner, each on a dark band so it can be read over anything
+    fn draw_messages(&mut self) {
+        let (_, window_height) = match self.renderer.output_size() {
+            Ok(size) => size,
+            Err(_) => return,
+        };
+
+        let line_height = osd::GLYPH_SIZE * MESSAGE_SCALE + MESSAGE_MARGIN;
+        let mut y = window_height as i32 - (line_height * self.messages.len() as u32) as i32;
+
+        self.renderer.set_blend_mode(BlendMode::Blend);
+
+        for (text, _) in self.messages.iter() {
+            let width = osd::text_width(text, MESSAGE_SCALE) + MESSAGE_MARGIN;
+
+            self.renderer.set_draw_color(Color::RGBA(0, 0, 0, 0xA0));
+            let _ = self.renderer.fill_rect(Rect::new(0, y, width + MESSAGE_MARGIN / 2, line_height));
+
+            self.renderer.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+            osd::draw_text(&mut self.renderer, MESSAGE_MARGIN as i32, y + (MESSAGE_MARGIN / 2) as i32, MESSAGE_SCALE, text);
+
+            y += line_height as i32;
+        }
+
+        self.renderer.set_blend_mode(BlendMode::None);
+    }
+
+    /// Draws the HUD in the top right corner on a dark band, like the messages
+    fn draw_hud(&mut self) {
+        let status = match self.hud {
+            Some(s) => s,
+            None => return,
+        };
+
+        let (window_width, _) = match self.renderer.output_size() {
+            Ok(size) => size,
+            Err(_) => return,
+        };
+
+        let text = format!("DT {:3} ST {:3} IPF {}", status.delay_timer, status.sound_timer, status.instructions_per_frame);
+        let width = osd::text_width(&text, MESSAGE_SCALE) + MESSAGE_MARGIN * 2;
+        let height = osd::GLYPH_SIZE * MESSAGE_SCALE + MESSAGE_MARGIN;
+        let x = window_width as i32 - width as i32;
+
+        self.renderer.set_blend_mode(BlendMode::Blend);
+
+        self.renderer.set_draw_color(Color::RGBA(0, 0, 0, 0xA0));
+        let _ = self.renderer.fill_rect(Rect::new(x, 0, width, height));
+
+        self.renderer.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+        osd::draw_text(&mut self.renderer, x + MESSAGE_MARGIN as i32, (MESSAGE_MARGIN / 2) as i32, MESSAGE_SCALE, &text);
+
+        self.renderer.set_blend_mode(BlendMode::None);
+    }
+}
+
+/// Works out the RGB24 pixels for each of the 256 bytes a VRAM row can hold, with
+/// the leftmost pixel in the high bit, so whole bytes can be copied into a texture
+fn byte_color_table(palette: &Palette) -> Vec<[u8; BYTE_RGB_LENGTH]> {
+    let on = Palette::rgb(palette.foreground);
+    let off = Palette::rgb(palette.background);
+
+    (0..256usize).map(|byte| {
+        let mut pixels = [0u8; BYTE_RGB_LENGTH];
+        for (bit, pixel) in pixels.chunks_mut(3).enumerate() {
+            let (r, g, b) = if byte & (0x80 >> bit) != 0 { on } else { off };
+            pixel.copy_from_slice(&[r, g, b]);
+        }
+        pixels
+    }).collect()
+}
+
+/// The size of a window that shows a screen of the given size, scale times over,
+/// turned as given
+fn window_size(scale: u32, rotation: Rotation, width: usize, height: usize) -> (u32, u32) {
+    let (width, height) = (scale * width as u32, scale * height as u32);
+
+    if rotation.is_sideways() { (height, width) } else { (width, height) }
+}
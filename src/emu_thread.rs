@@ -0,0 +1,1030 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate sdl2;
+
+#[cfg(feature = "scripting")]
+use rhai::EvalAltResult;
+#[cfg(feature = "scripting")]
+use std::path::Path;
+use std::cmp;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing;
+
+use chip8::cheats::Cheats;
+use chip8::cpu::{Cpu, EmulationError};
+use chip8::debugger::{Debugger, DebuggerAction};
+#[cfg(feature = "debug-gui")]
+use chip8::debugger::set_register;
+use chip8::emulator::{Emulator, EmulatorBuilder};
+use chip8::font::Font;
+use chip8::frame::Frame;
+use chip8::frontend::Frontend;
+use chip8::movie::{Movie, MovieHeader};
+use chip8::netplay::Netplay;
+use chip8::observer::Observer;
+use chip8::playlist::Playlist;
+use chip8::replay::Recorder;
+use chip8::rewind::RewindBuffer;
+use chip8::romconfig::RomConfig;
+use chip8::savestate;
+#[cfg(feature = "scripting")]
+use chip8::scripting::Script;
+use chip8::trace::Tracer;
+use chip8::watch::{self, SourceWatcher};
+use chip8::watchdog::{RunLimits, Watchdog};
+use chip8::webdebugger::WebDebugger;
+use chip8::xochip;
+use audio;
+#[cfg(feature = "debug-gui")]
+use debug_gui::{DebugCommand, DebugSnapshot};
+use debug_window::{self, MemorySnapshot};
+use display::TimerStatus;
+use sdl2::keyboard::Keycode;
+
+/// How long the emulator thread sleeps between looking for input while paused
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How many times faster or slower than the speed it started at the emulator may be made to run, as a power of two
+const MAX_SPEED_SHIFT: i32 = 4;
+
+/// What the emulator thread sends to the UI thread
+pub enum UiMessage {
+    /// the screen changed and should be redrawn
+    Frame(Frame),
+    /// start playing unsigned 8-bit sample data at the given rate, and whether it loops
+    PlaySound(Vec<u8>, u32, bool),
+    /// stop the sound that's playing
+    StopSound,
+    /// start or stop the buzzer
+    Beep(bool),
+    /// change the pitch of the buzzer, in Hz
+    BeepPitch(f64),
+    /// have the buzzer loop an XO-CHIP pattern at the given rate instead, or go back to its own sound
+    BeepPattern(Option<[u8; xochip::PATTERN_LENGTH]>, f64),
+    /// the state of memory at the end of a frame, for the debug window
+    Memory(MemorySnapshot),
+    /// the machine at the end of a frame, or wherever it's stopped, for the debugger GUI
+    #[cfg(feature = "debug-gui")]
+    Debug(DebugSnapshot),
+    /// something to tell the user, shown over the screen for a moment
+    Notify(String),
+    /// the timers at the end of a frame, for the HUD
+    Timers(TimerStatus),
+    /// a program started, described by its title, author and release, or its file name
+    ProgramInfo(String),
+}
+
+/// What the UI thread sends to the emulator thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMessage {
+    /// a host key was pressed or released, at the given time in milliseconds
+    Key(Keycode, bool, u32),
+    /// break into the debugger before the next instruction
+    Break,
+    /// switch the cheats on or off
+    ToggleCheats,
+    /// save the state next to the program
+    SaveState,
+    /// load the state saved next to the program
+    LoadState,
+    /// move on to the next program on the playlist
+    NextProgram,
+    /// start or stop sending the state of memory every frame
+    WatchMemory(bool),
+    /// start or stop sending the timers every frame
+    WatchTimers(bool),
+    /// pause emulation, or resume it if it's paused
+    TogglePause,
+    /// start stepping back a frame at a time, or stop
+    Rewind(bool),
+    /// run twice as fast
+    SpeedUp,
+    /// run half as fast
+    SlowDown,
+    /// start or stop tracing
+    ToggleTrace,
+    /// something the debugger GUI asked for
+    #[cfg(feature = "debug-gui")]
+    Debug(DebugCommand),
+    /// stop emulation
+    Quit,
+}
+
+/// What the emulator thread hands back once it stops
+pub struct Outcome {
+    /// the emulator, as it was when it stopped
+    pub emulator: Emulator,
+    /// the error that stopped it, if any
+    pub result: Result<(), EmulationError>,
+    /// whether the program stopped by itself, by finishing or faulting, rather than being quit
+    pub halted: bool,
+    /// the path of the program that was running, which may have changed if there was a playlist
+    pub filename: String,
+    /// the checksum of the program that was running
+    pub program_checksum: u32,
+    /// whether the run was stopped for going on longer than its limits allow
+    pub watchdog_expired: bool,
+    /// the movie recorded during the run, if one was
+    pub movie: Option<Movie>,
+}
+
+/// Everything the emulator thread needs besides the emulator itself
+pub struct Settings {
+    pub cheats: Cheats,
+    /// whether to break into the debugger before the first instruction
+    pub break_requested: bool,
+    /// whether to start paused, before the first instruction
+    pub paused: bool,
+    /// the debugger to serve to browsers, if any
+    pub web_debugger: Option<WebDebugger>,
+    /// the link to the other player, if playing over the network
+    pub netplay: Option<Netplay>,
+    /// the programs to cycle through, if there's more than one
+    pub playlist: Option<PlaylistSettings>,
+    /// the source to assemble the program from again whenever it's saved, if watching one
+    pub watch: Option<WatchSettings>,
+    /// the path of the running program, which save-states are kept next to
+    pub filename: String,
+    /// the checksum of the running program, recorded in save-states
+    pub program_checksum: u32,
+    /// the settings kept next to the running program
+    pub rom_config: RomConfig,
+    /// the pitch of the buzzer given on the command line, which overrides every program's own
+    pub beep_pitch: Option<f64>,
+    /// how long the run may go on for
+    pub limits: RunLimits,
+    /// what to record a movie of the run with, if one's being recorded
+    pub movie: Option<MovieHeader>,
+    /// where executed instructions are written while tracing, if anywhere
+    pub tracer: Option<Tracer>,
+    /// the terminal debugger, with the program's symbols, source lines and any breakpoints set up front
+    pub debugger: Debugger,
+    /// the path of the script to run alongside the program, if any
+    #[cfg(feature = "scripting")]
+    pub script_path: Option<String>,
+}
+
+/// What the emulator thread needs to move from one program to the next
+pub struct PlaylistSettings {
+    pub playlist: Playlist,
+    /// how every program's emulator is set up
+    pub builder: EmulatorBuilder,
+    /// how long each program runs before moving on by itself, if it does
+    pub interval: Option<Duration>,
+}
+
+/// What the emulator thread needs to reload a program whenever its source is saved
+pub struct WatchSettings {
+    pub watcher: SourceWatcher,
+    /// how the program's emulator is set up
+    pub builder: EmulatorBuilder,
+}
+
+/// Loads the cheats kept next to a program, if there are any
+pub fn load_cheats(filename: &str) -> Cheats {
+    let cheats_path = Cheats::path_for(filename);
+    if !cheats_path.exists() {
+        return Cheats::new();
+    }
+
+    match Cheats::load_file(&cheats_path) {
+        Ok(c) => {
+            info!(target: "config", "Loaded {} cheat(s) from {}. Press F2 to toggle them.", c.cheats.len(), cheats_path.display());
+            for cheat in c.cheats.iter() {
+                info!(target: "config", "  {:?} 0x{:03X} = {:02X}  {}", cheat.kind, cheat.address, cheat.value, cheat.description);
+            }
+            c
+        },
+        Err(e) => {
+            warn!(target: "config", "Couldn't load cheats, continuing without them. Error message: {}", e);
+            Cheats::new()
+        },
+    }
+}
+
+/// Loads the settings kept next to a program, if there are any
+pub fn load_rom_config(filename: &str) -> RomConfig {
+    let config_path = RomConfig::path_for(filename);
+    if !config_path.exists() {
+        return RomConfig::new();
+    }
+
+    match RomConfig::load_file(&config_path) {
+        Ok(c) => {
+            info!(target: "config", "Loaded the program's settings from {}.", config_path.display());
+            c
+        },
+        Err(e) => {
+            warn!(target: "config", "Couldn't load the program's settings, continuing without them. Error message: {}", e);
+            RomConfig::new()
+        },
+    }
+}
+
+/// Sets up the builder for one program, giving it the program's own settings
+/// where the command line hasn't set them for every program
+pub fn program_builder(builder: &EmulatorBuilder, rom_config: &RomConfig) -> EmulatorBuilder {
+    let mut builder = builder.clone();
+
+    match rom_config.variant {
+        Some(variant) if !builder.has_variant() => builder = builder.variant(variant),
+        _ => (),
+    }
+
+    match rom_config.speed {
+        Some(speed) if !builder.has_speed() => builder = builder.speed(speed),
+        _ => (),
+    }
+
+    match rom_config.font {
+        Some(ref name) if !builder.has_font() => match Font::from_name_or_file(name) {
+            Ok(font) => builder.font(font),
+            Err(e) => {
+                warn!(target: "config", "Couldn't load the font {}, using the built-in one. Error message: {}", name, e);
+                builder
+            },
+        },
+        _ => builder,
+    }
+}
+
+/// Starts running the emulator on its own thread. Returns the thread, the channel
+/// input goes in through, and the channel frames and sounds come out of.
+pub fn spawn(mut emulator: Emulator, settings: Settings) -> (JoinHandle<Outcome>, Sender<InputMessage>, Receiver<UiMessage>) {
+    let (input_tx, input_rx) = mpsc::channel();
+    let (ui_tx, ui_rx) = mpsc::channel();
+
+    let handle = thread::spawn(move || {
+        let limits = settings.limits;
+        let movie = settings.movie.clone();
+        let thread_frontend = ThreadFrontend::new(settings, ui_tx, input_rx, &emulator);
+        let mut frontend = Watchdog::new(Recorder::new(thread_frontend, movie), limits);
+        let result = emulator.run(&mut frontend);
+
+        Outcome {
+            emulator: emulator,
+            result: result,
+            halted: frontend.inner.inner.halted,
+            filename: frontend.inner.inner.filename,
+            program_checksum: frontend.inner.inner.program_checksum,
+            watchdog_expired: frontend.expired,
+            movie: frontend.inner.movie,
+        }
+    });
+
+    (handle, input_tx, ui_rx)
+}
+
+/// The frontend on the emulator thread: it runs the terminal debugger, cheats,
+/// scripts and save-states, and leaves the window and speakers to the UI thread
+struct ThreadFrontend {
+    cheats: Cheats,
+    debugger: Debugger,
+    web_debugger: Option<WebDebugger>,
+    netplay: Option<Netplay>,
+    playlist: Option<PlaylistSettings>,
+    watch: Option<WatchSettings>,
+    /// when the program playing now was started
+    program_started: Instant,
+    /// whether the UI thread wants the state of memory every frame
+    watching_memory: bool,
+    /// whether the UI thread wants the timers every frame
+    watching_timers: bool,
+    /// whether the debugger GUI is open, in which case breakpoints pause emulation for it rather than the terminal
+    debug_gui_open: bool,
+    /// whether the debugger GUI asked for one instruction to run while paused
+    debug_gui_step: bool,
+    /// how many instructions have run so far this frame
+    frame_cycles: u32,
+    /// whether the user has paused emulation
+    paused: bool,
+    /// the machine as it was over the last while
+    rewind: RewindBuffer,
+    /// whether the user is holding the rewind key
+    rewinding: bool,
+    /// whether the last frame ended by stepping back, rather than by being kept for rewinding to
+    rewound: bool,
+    /// how long each instruction took at the speed we started at
+    base_instruction_delay: Duration,
+    /// how many times the speed has been doubled, or halved if negative
+    speed_shift: i32,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    /// the flag that says we should break into the debugger before the next instruction
+    break_requested: bool,
+    /// how many more instructions to run before the debugger prompt comes back, when stepping several
+    steps_left: usize,
+    /// the address to break into the debugger at, when running to one
+    run_to: Option<usize>,
+    /// where executed instructions are written while tracing, if anywhere
+    tracer: Option<Tracer>,
+    /// the path of the running program, which save-states are kept next to
+    filename: String,
+    /// the checksum of the running program, recorded in save-states
+    program_checksum: u32,
+    /// whether a movie is being recorded, in which case nothing but the keypad may change the machine
+    recording: bool,
+    /// whether the program stopped by itself, by finishing or faulting, rather than being quit
+    halted: bool,
+    /// the pitch of the buzzer given on the command line, if any
+    beep_pitch: Option<f64>,
+    /// where frames and sounds go
+    ui: Sender<UiMessage>,
+    /// where key presses and hotkeys come from
+    input: Receiver<InputMessage>,
+    /// the address of the instruction being executed, for the script hooks
+    #[cfg(feature = "scripting")]
+    pc: usize,
+}
+
+impl ThreadFrontend {
+    fn new(settings: Settings, ui: Sender<UiMessage>, input: Receiver<InputMessage>, emulator: &Emulator) -> ThreadFrontend {
+        // scripts can't be sent between threads, so this one is loaded here
+        #[cfg(feature = "scripting")]
+        let script = settings.script_path.map(|path| {
+            let spare = match Cpu::init_from_buffer(Vec::new(), Some(emulator.cpu.config)) {
+                Err(e) => panic!("Failed to set up the script's machine. Error message: {:?}", e),
+                Ok(v) => v,
+            };
+
+            match Script::load_file(Path::new(&path), spare) {
+                Err(e) => panic!("Failed to load script. Error message: {}", e),
+                Ok(s) => s,
+            }
+        });
+
+        // a movie only holds the keys pressed, so anything else that changes the machine is kept out of it
+        let recording = settings.movie.is_some();
+        let mut cheats = settings.cheats;
+        let mut debugger = settings.debugger;
+        let mut web_debugger = settings.web_debugger;
+        if recording {
+            if cheats.enabled && !cheats.cheats.is_empty() {
+                info!(target: "config", "Cheats are off while recording a movie.");
+            }
+            cheats.enabled = false;
+        }
+
+        // nor may the debuggers change one player's machine and not the other's
+        let read_only = if settings.netplay.is_some() {
+            Some("during netplay")
+        } else if recording {
+            Some("while recording a movie")
+        } else {
+            None
+        };
+        debugger.set_read_only(read_only);
+        if let Some(ref mut w) = web_debugger {
+            w.set_read_only(read_only);
+        }
+
+        let frontend = ThreadFrontend {
+            cheats: cheats,
+            debugger: debugger,
+            web_debugger: web_debugger,
+            netplay: settings.netplay,
+            playlist: settings.playlist,
+            watch: settings.watch,
+            program_started: Instant::now(),
+            watching_memory: false,
+            watching_timers: false,
+            debug_gui_open: false,
+            debug_gui_step: false,
+            frame_cycles: 0,
+            paused: settings.paused,
+            rewind: RewindBuffer::default(),
+            rewinding: false,
+            rewound: false,
+            base_instruction_delay: emulator.instruction_delay,
+            speed_shift: 0,
+            #[cfg(feature = "scripting")]
+            script: script,
+            break_requested: settings.break_requested,
+            steps_left: 0,
+            run_to: None,
+            tracer: settings.tracer,
+            filename: settings.filename,
+            program_checksum: settings.program_checksum,
+            recording: recording,
+            halted: false,
+            beep_pitch: settings.beep_pitch,
+            ui: ui,
+            input: input,
+            #[cfg(feature = "scripting")]
+            pc: 0,
+        };
+
+        frontend.set_beep_pitch(&settings.rom_config);
+        frontend.announce_program(&settings.rom_config);
+        if frontend.paused {
+            frontend.notify(format!("Paused at 0x{:03X}. Press F3 to run, or F12 for the debugger", emulator.cpu.program_counter));
+        }
+        frontend
+    }
+
+    /// Tunes the buzzer for a program, unless the command line gave a pitch for every program
+    fn set_beep_pitch(&self, rom_config: &RomConfig) {
+        let pitch = self.beep_pitch.or(rom_config.beep_pitch).unwrap_or(audio::DEFAULT_BEEP_PITCH);
+        let _ = self.ui.send(UiMessage::BeepPitch(pitch));
+    }
+
+    /// Tells the UI thread what the program that's starting is
+    fn announce_program(&self, rom_config: &RomConfig) {
+        let _ = self.ui.send(UiMessage::ProgramInfo(rom_config.describe(&self.filename)));
+    }
+
+    /// Acts on a message from the UI thread. Returns false if it should stop.
+    fn handle_input(&mut self, emulator: &mut Emulator, message: InputMessage) -> bool {
+        match message {
+            InputMessage::Key(key, state, timestamp) => match self.netplay {
+                // the other player's keys are added in at the end of the frame
+                Some(ref mut netplay) => if let Some(k) = emulator.keymap.key_for(key) {
+                    netplay.set_local_key(k, state);
+                },
+                None => emulator.queue_key(key, state, timestamp),
+            },
+            InputMessage::Break => {
+                // the debugger keeps us paused itself, between its commands
+                self.paused = false;
+                self.break_requested = true;
+                self.notify("Paused in the debugger, see the terminal".to_owned());
+            },
+            InputMessage::ToggleCheats => {
+                // the handshake checked both ends have the same cheats, on or off
+                if self.netplay.is_some() {
+                    self.notify("Can't toggle cheats during netplay".to_owned());
+                } else if self.recording {
+                    self.notify("Can't toggle cheats while recording a movie".to_owned());
+                } else {
+                    self.cheats.toggle();
+                    let message = format!("Cheats {}", if self.cheats.enabled { "on" } else { "off" });
+                    self.notify(message);
+                }
+            },
+            InputMessage::SaveState => {
+                let path = savestate::state_path(&self.filename);
+                match savestate::save_state_file(&emulator.cpu, Some(self.program_checksum), &path) {
+                    Ok(()) => self.notify(format!("Saved state to {}", path.display())),
+                    Err(e) => self.notify(format!("Couldn't save state. Error message: {}", e)),
+                }
+            },
+            // the other player's machine would carry on from where it was
+            InputMessage::LoadState if self.netplay.is_some() => self.notify("Can't load states during netplay".to_owned()),
+            InputMessage::LoadState if self.recording => self.notify("Can't load states while recording a movie".to_owned()),
+            InputMessage::LoadState => {
+                let path = savestate::state_path(&self.filename);
+                match savestate::load_state_file(&path, Some(emulator.cpu.config.variant), Some(self.program_checksum)) {
+                    Ok(state) => {
+                        emulator.restore(state);
+                        self.notify(format!("Loaded state from {}", path.display()));
+                    },
+                    Err(e) => self.notify(format!("Couldn't load state. Error message: {}", e)),
+                }
+            },
+            InputMessage::NextProgram => self.next_program(emulator),
+            InputMessage::WatchMemory(watching) => self.watching_memory = watching,
+            InputMessage::WatchTimers(watching) => self.watching_timers = watching,
+            InputMessage::TogglePause => {
+                self.paused = !self.paused;
+                self.notify(if self.paused { "Paused" } else { "Resumed" }.to_owned());
+            },
+            InputMessage::Rewind(rewinding) => {
+                // the other player's machine would carry on without us
+                if rewinding && self.netplay.is_some() {
+                    self.notify("Can't rewind during netplay".to_owned());
+                } else if rewinding && self.recording {
+                    self.notify("Can't rewind while recording a movie".to_owned());
+                } else if rewinding != self.rewinding {
+                    self.rewinding = rewinding;
+                    if rewinding {
+                        self.notify("Rewinding".to_owned());
+                    }
+                }
+            },
+            InputMessage::SpeedUp => self.change_speed(emulator, 1),
+            InputMessage::SlowDown => self.change_speed(emulator, -1),
+            InputMessage::ToggleTrace => {
+                let enabled = self.tracer.as_ref().is_some_and(|t| !t.is_enabled());
+                self.set_tracing(enabled);
+            },
+            #[cfg(feature = "debug-gui")]
+            InputMessage::Debug(command) => self.debug_command(emulator, command),
+            InputMessage::Quit => return false,
+        }
+
+        true
+    }
+
+    /// Acts on something the debugger GUI asked for, and shows it the result
+    #[cfg(feature = "debug-gui")]
+    fn debug_command(&mut self, emulator: &mut Emulator, command: DebugCommand) {
+        match command {
+            DebugCommand::Watch(watching) => self.debug_gui_open = watching,
+            DebugCommand::Pause => {
+                self.paused = true;
+                self.notify("Paused".to_owned());
+            },
+            DebugCommand::Run => {
+                self.paused = false;
+                self.notify("Resumed".to_owned());
+            },
+            // stepping while running just stops, at the instruction that's next
+            DebugCommand::Step => if self.paused {
+                self.debug_gui_step = true;
+            } else {
+                self.paused = true;
+            },
+            DebugCommand::SetBreakpoint(spec) => if let Err(e) = self.debugger.set_breakpoint(&spec) {
+                self.notify(e);
+            },
+            DebugCommand::RemoveBreakpoint(n) => self.debugger.remove_breakpoint(n),
+            DebugCommand::Poke(..) | DebugCommand::SetRegister(..) if self.netplay.is_some() => {
+                self.notify("Can't change the machine during netplay".to_owned());
+            },
+            DebugCommand::Poke(..) | DebugCommand::SetRegister(..) if self.recording => {
+                self.notify("Can't change the machine while recording a movie".to_owned());
+            },
+            DebugCommand::Poke(addr, value) => {
+                if !self.paused {
+                    self.notify("Pause emulation before editing memory".to_owned());
+                } else if addr < emulator.cpu.memory.len() {
+                    emulator.cpu.memory[addr] = value;
+                }
+            },
+            DebugCommand::SetRegister(name, value) => {
+                if !self.paused {
+                    self.notify("Pause emulation before setting registers".to_owned());
+                } else if let Err(e) = set_register(&mut emulator.cpu, &name, value) {
+                    self.notify(e);
+                }
+            },
+        }
+
+        self.send_debug_snapshot(emulator);
+    }
+
+    /// Sends the debugger GUI the machine as it is now, if it's open
+    #[cfg(feature = "debug-gui")]
+    fn send_debug_snapshot(&self, emulator: &Emulator) {
+        if self.debug_gui_open {
+            let _ = self.ui.send(UiMessage::Debug(DebugSnapshot::capture(&emulator.cpu, &self.debugger, self.paused)));
+        }
+    }
+
+    /// Doubles or halves the speed, within limits
+    fn change_speed(&mut self, emulator: &mut Emulator, shift: i32) {
+        // both ends of a netplay session have to run the same number of instructions every frame
+        if self.netplay.is_some() {
+            self.notify("Can't change speed during netplay".to_owned());
+            return;
+        }
+        // a movie plays back at the speed it started at
+        if self.recording {
+            self.notify("Can't change speed while recording a movie".to_owned());
+            return;
+        }
+
+        self.speed_shift = (self.speed_shift + shift).clamp(-MAX_SPEED_SHIFT, MAX_SPEED_SHIFT);
+        self.apply_speed(emulator);
+
+        let message = if self.speed_shift >= 0 {
+            format!("Speed x{}", 1 << self.speed_shift)
+        } else {
+            format!("Speed x1/{}", 1 << -self.speed_shift)
+        };
+        self.notify(message);
+    }
+
+    /// Sets the emulator's speed from the speed we started at and the changes made since
+    fn apply_speed(&self, emulator: &mut Emulator) {
+        emulator.instruction_delay = if self.speed_shift >= 0 {
+            self.base_instruction_delay / (1 << self.speed_shift)
+        } else {
+            self.base_instruction_delay * (1 << -self.speed_shift)
+        };
+    }
+
+    /// Swaps the running program for the next one on the playlist, skipping any that won't load
+    fn next_program(&mut self, emulator: &mut Emulator) {
+        let settings = match self.playlist {
+            Some(ref mut s) => s,
+            None => return,
+        };
+
+        for _ in 0..settings.playlist.len() {
+            let path = settings.playlist.advance().to_string_lossy().into_owned();
+            let rom_config = load_rom_config(&path);
+
+            match program_builder(&settings.builder, &rom_config).build_from_file(&path) {
+                Ok(next) => {
+                    self.swap_program(emulator, next);
+                    self.cheats = load_cheats(&path);
+                    self.set_beep_pitch(&rom_config);
+                    self.filename = path;
+                    self.announce_program(&rom_config);
+                    return;
+                },
+                Err(e) => warn!(target: "cpu", "Couldn't load {}, skipping it. Error message: {}", path, e),
+            }
+        }
+    }
+
+    /// Assembles the program again if its source has been saved since it was last
+    /// assembled, and starts it over, running with the same variant and quirks as
+    /// before. If it doesn't assemble, the program that's running carries on.
+    fn reload_source(&mut self, emulator: &mut Emulator) {
+        if !self.watch.as_mut().is_some_and(|s| s.watcher.changed()) {
+            return;
+        }
+        let settings = match self.watch {
+            Some(ref s) => s,
+            None => return,
+        };
+
+        let config = emulator.cpu.config;
+        let rom_config = load_rom_config(&self.filename);
+        let reloaded = watch::assemble_file(settings.watcher.path(), config.variant.load_addr()).and_then(|program| {
+            program_builder(&settings.builder, &rom_config)
+                .variant(config.variant)
+                .quirks(config.quirks)
+                .build_from_buffer(program)
+                .map_err(|e| format!("Couldn't load the assembled program. Error message: {}", e))
+        });
+
+        match reloaded {
+            Ok(next) => {
+                self.swap_program(emulator, next);
+                self.notify(format!("Reloaded {}", self.filename));
+            },
+            Err(e) => {
+                error!(target: "config", "{}", e);
+                self.notify("The source didn't assemble, so the last version carries on".to_owned());
+            },
+        }
+    }
+
+    /// Starts another program in place of the running one, at the speed the user
+    /// has set and with the same devices hooked up, with nothing from the old one
+    /// to rewind to
+    fn swap_program(&mut self, emulator: &mut Emulator, mut next: Emulator) {
+        next.cpu.devices = mem::take(&mut emulator.cpu.devices);
+        *emulator = next;
+        emulator.cpu.keyboard.cancel_wait();
+        emulator.cpu.draw_flag = true;
+        emulator.cpu.xo.sound_flag = true;
+        self.base_instruction_delay = emulator.instruction_delay;
+        self.apply_speed(emulator);
+
+        self.program_checksum = savestate::program_checksum(emulator.cpu.program());
+        self.rewind.clear();
+        self.program_started = Instant::now();
+    }
+
+    /// Swaps the machine for how it was at the last snapshot kept, if we've got that far back
+    fn step_back(&mut self, emulator: &mut Emulator) {
+        match self.rewind.rewind() {
+            Ok(Some(cpu)) => {
+                emulator.restore(cpu);
+                emulator.present(self);
+            },
+            Ok(None) => (),
+            Err(e) => {
+                self.notify(format!("Couldn't rewind. Error message: {}", e));
+                self.rewind.clear();
+            },
+        }
+    }
+
+    /// Tells the user something, both in the log and over the screen
+    fn notify(&self, message: String) {
+        info!(target: "ui", "{}", message);
+        let _ = self.ui.send(UiMessage::Notify(message));
+    }
+
+    /// Acts on every message the UI thread has sent since last time. Returns false if it should stop.
+    fn poll_input(&mut self, emulator: &mut Emulator) -> bool {
+        loop {
+            match self.input.try_recv() {
+                Ok(message) => if !self.handle_input(emulator, message) {
+                    return false;
+                },
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Switches tracing on or off, telling the user which
+    fn set_tracing(&mut self, enabled: bool) {
+        let result = match self.tracer {
+            Some(ref mut t) => t.set_enabled(enabled),
+            None => {
+                self.notify("Nowhere to trace to, start with --trace <file>".to_owned());
+                return;
+            },
+        };
+
+        match result {
+            Ok(()) => self.notify(format!("Tracing {}", if enabled { "on" } else { "off" })),
+            Err(e) => {
+                self.notify(format!("Couldn't write the trace, so tracing has stopped. Error message: {}", e));
+                self.tracer = None;
+            },
+        }
+    }
+
+    /// Answers the web debugger's requests, staying here while either the user or
+    /// the web debugger has us paused. Returns false if it should stop.
+    fn wait_while_paused(&mut self, emulator: &mut Emulator) -> bool {
+        let mut web_debugger = self.web_debugger.take();
+        if let Some(ref mut w) = web_debugger {
+            w.poll(&mut emulator.cpu);
+        }
+
+        #[cfg(feature = "debug-gui")]
+        {
+            if self.paused {
+                self.send_debug_snapshot(emulator);
+            }
+        }
+
+        let mut keep_running = true;
+        while keep_running && ((self.paused && !self.debug_gui_step) || web_debugger.as_mut().is_some_and(|w| w.should_wait())) {
+            thread::sleep(PAUSED_POLL_INTERVAL);
+
+            keep_running = self.poll_input(emulator);
+            if let Some(ref mut w) = web_debugger {
+                w.poll(&mut emulator.cpu);
+            }
+        }
+
+        self.web_debugger = web_debugger;
+        keep_running
+    }
+
+    /// Runs one of the script's hooks, disabling the script if it fails
+    #[cfg(feature = "scripting")]
+    fn run_script<H>(&mut self, hook: H)
+        where H: FnOnce(&mut Script) -> Result<(), Box<EvalAltResult>>
+    {
+        let result = match self.script {
+            Some(ref mut s) => hook(s),
+            None => Ok(()),
+        };
+
+        if let Err(e) = result {
+            self.notify(format!("Script error, disabling the script. Error message: {}", e));
+            self.script = None;
+        }
+
+        if let Some(enabled) = self.script.as_ref().and_then(|s| s.take_trace_request()) {
+            self.set_tracing(enabled);
+        }
+    }
+}
+
+impl Observer for ThreadFrontend {
+    fn on_draw(&mut self, cpu: &Cpu) {
+        // if the UI thread has gone away, end_frame will find out and stop
+        let _ = self.ui.send(UiMessage::Frame(Frame::capture(cpu)));
+    }
+
+    fn on_sound_start(&mut self) {
+        let _ = self.ui.send(UiMessage::Beep(true));
+    }
+
+    fn on_sound_stop(&mut self) {
+        let _ = self.ui.send(UiMessage::Beep(false));
+    }
+
+    fn on_halt(&mut self, _error: Option<&EmulationError>) {
+        self.halted = true;
+    }
+}
+
+impl Frontend for ThreadFrontend {
+    fn before_cycle(&mut self, emulator: &mut Emulator) -> bool {
+        #[cfg(feature = "scripting")]
+        {
+            self.pc = emulator.cpu.program_counter;
+        }
+
+        // once the user lets a paused machine go, the instruction it stopped at runs
+        // without stopping at it again
+        let was_paused = self.paused;
+        if !self.wait_while_paused(emulator) {
+            return false;
+        }
+
+        // breakpoints cut short a run of steps, as well as stopping a running program
+        if !was_paused && (!self.break_requested || self.steps_left > 0) {
+            let pc = emulator.cpu.program_counter;
+            let message = match (self.debugger.breakpoint_hit(&emulator.cpu), self.debugger.catch_hit(&emulator.cpu)) {
+                (Some(_), _) => Some(format!("Breakpoint at {}", self.debugger.symbols().describe(pc))),
+                (None, Some(pattern)) => Some(format!("Caught {} at {}", pattern.text, self.debugger.symbols().describe(pc))),
+                (None, None) => None,
+            };
+
+            if let Some(message) = message {
+                self.steps_left = 0;
+
+                // with the debugger GUI open, it's where the program stops
+                if self.debug_gui_open {
+                    self.paused = true;
+                    self.notify(message);
+                    if !self.wait_while_paused(emulator) {
+                        return false;
+                    }
+                } else {
+                    self.break_requested = true;
+                    self.notify(format!("{}, see the terminal", message));
+                }
+            } else if self.debugger.source_step_done(&emulator.cpu) {
+                self.break_requested = true;
+            }
+        }
+
+        if self.run_to == Some(emulator.cpu.program_counter) {
+            self.break_requested = true;
+        }
+
+        // break into the debugger if asked, staying there between steps
+        if self.break_requested {
+            if self.steps_left > 0 {
+                self.steps_left -= 1;
+                return true;
+            }
+
+            // whatever stopped us, any run to an address is over
+            self.run_to = None;
+            let action = self.debugger.repl(&mut emulator.cpu);
+            if let Some(enabled) = self.debugger.take_trace_request() {
+                self.set_tracing(enabled);
+            }
+
+            match action {
+                DebuggerAction::Continue => self.break_requested = false,
+                DebuggerAction::Step(n) => self.steps_left = n - 1,
+                DebuggerAction::RunTo(addr) => {
+                    self.break_requested = false;
+                    self.run_to = Some(addr);
+                },
+                DebuggerAction::Quit => return false,
+            }
+        }
+
+        true
+    }
+
+    fn after_cycle(&mut self, emulator: &mut Emulator) -> bool {
+        if let (Some(tracer), Some((pc, instruction))) = (self.tracer.as_mut(), emulator.cpu.history.last()) {
+            if let Err(e) = tracer.record(&emulator.cpu, pc, instruction) {
+                self.notify(format!("Couldn't write the trace, so tracing has stopped. Error message: {}", e));
+                self.tracer = None;
+            }
+        }
+
+        #[cfg(feature = "scripting")]
+        {
+            let pc = self.pc;
+            let instruction = emulator.cpu.current_instruction;
+            self.run_script(|s| s.on_instruction(&mut emulator.cpu, pc, instruction));
+
+            if emulator.drawn_flag {
+                self.run_script(|s| s.on_draw(&mut emulator.cpu));
+            }
+        }
+
+        self.frame_cycles += 1;
+
+        if (!self.break_requested || self.steps_left > 0) && self.debugger.screen_watch_hit(&emulator.cpu).is_some() {
+            let pc = emulator.cpu.history.last().map(|(pc, _)| pc).unwrap_or(0);
+            let message = format!("The DRW at {} drew in a watched part of the screen", self.debugger.symbols().describe(pc));
+            self.steps_left = 0;
+
+            if self.debug_gui_open {
+                self.paused = true;
+                self.notify(message);
+            } else {
+                self.break_requested = true;
+                self.notify(format!("{}, see the terminal", message));
+            }
+        }
+
+        // a step the debugger GUI asked for is done, and it's shown where we've got to before the next instruction
+        if self.debug_gui_step {
+            self.debug_gui_step = false;
+            emulator.present(self);
+        }
+
+        // stepping through the debugger, every draw is worth seeing straight away
+        if self.break_requested {
+            emulator.present(self);
+        }
+
+        for warning in emulator.cpu.warnings.drain(..) {
+            warn!(target: "cpu", "{}", warning);
+        }
+
+        // start or stop any digitized sound the program asked for, copying the
+        // samples out since the program is free to overwrite them
+        if emulator.cpu.mega.sound_flag {
+            let message = match emulator.cpu.mega.sound {
+                Some(sound) => {
+                    let samples = emulator.cpu.memory[sound.start..sound.start + sound.length].to_vec();
+                    UiMessage::PlaySound(samples, sound.sample_rate, sound.looping)
+                },
+                None => UiMessage::StopSound,
+            };
+
+            let _ = self.ui.send(message);
+            emulator.cpu.mega.sound_flag = false;
+        }
+
+        if emulator.cpu.xo.sound_flag {
+            let _ = self.ui.send(UiMessage::BeepPattern(emulator.cpu.xo.pattern, emulator.cpu.xo.playback_rate()));
+            emulator.cpu.xo.sound_flag = false;
+        }
+
+        true
+    }
+
+    fn end_frame(&mut self, emulator: &mut Emulator) -> bool {
+        // re-apply frozen memory once the frame's instructions have run
+        self.cheats.apply(&mut emulator.cpu);
+
+        #[cfg(feature = "scripting")]
+        tracing::debug_span!("script").in_scope(|| self.run_script(|s| s.on_frame(&mut emulator.cpu)));
+
+        if !tracing::debug_span!("input").in_scope(|| self.poll_input(emulator)) {
+            return false;
+        }
+
+        // while rewinding, every frame steps back to the one before instead. The
+        // frame that rewinding starts on is kept first, since it's the one on screen
+        // and stepping back from anything older would skip the frame before it.
+        // Nothing's kept during netplay or recording, where rewinding is refused
+        tracing::debug_span!("rewind").in_scope(|| {
+            let kept = if self.netplay.is_some() || self.recording {
+                Ok(())
+            } else if !self.rewinding {
+                self.rewind.end_frame(&emulator.cpu)
+            } else if !self.rewound {
+                self.rewind.push(&emulator.cpu)
+            } else {
+                Ok(())
+            };
+            if let Err(e) = kept {
+                warn!(target: "cpu", "Couldn't keep the state for rewinding. Error message: {}", e);
+            }
+            if self.rewinding {
+                self.step_back(emulator);
+            }
+            self.rewound = self.rewinding;
+        });
+
+        if self.watching_memory {
+            let length = cmp::min(emulator.cpu.memory.len(), debug_window::MAP_LENGTH);
+            let _ = self.ui.send(UiMessage::Memory(MemorySnapshot {
+                memory: emulator.cpu.memory[..length].to_vec(),
+                program_counter: emulator.cpu.program_counter,
+                i_register: emulator.cpu.i_register,
+            }));
+        }
+
+        #[cfg(feature = "debug-gui")]
+        self.send_debug_snapshot(emulator);
+
+        if self.watching_timers {
+            let _ = self.ui.send(UiMessage::Timers(TimerStatus {
+                delay_timer: emulator.cpu.delay_timer,
+                sound_timer: emulator.cpu.sound_timer,
+                instructions_per_frame: self.frame_cycles,
+            }));
+        }
+        self.frame_cycles = 0;
+
+        // in attract mode, move on once the program has had its turn
+        let interval = self.playlist.as_ref().and_then(|p| p.interval);
+        if let Some(interval) = interval {
+            if self.program_started.elapsed() >= interval {
+                self.next_program(emulator);
+            }
+        }
+
+        self.reload_source(emulator);
+
+        if let Some(ref mut netplay) = self.netplay {
+            if let Err(e) = tracing::debug_span!("netplay").in_scope(|| netplay.exchange(&mut emulator.cpu.keyboard)) {
+                error!(target: "netplay", "Lost the other player. Error message: {}", e);
+                return false;
+            }
+        }
+
+        true
+    }
+}
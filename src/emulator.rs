@@ -0,0 +1,585 @@
+//
+// Author: Joshua Holmes
+//
+
+use sdl2::keyboard::Keycode;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing;
+
+use cpu::{Cpu, EmulationError, ProgramLoadError};
+use device::Device;
+use font::Font;
+use frontend::Frontend;
+use input::{InputQueue, KeyEvent};
+use keyboard::Keymap;
+use machine::{MachineConfig, Quirks};
+use observer::{NullObserver, Observer};
+use palette::Palette;
+use variant::Variant;
+use xochip;
+
+/// How long one tick of the 60Hz timers lasts
+pub const TIMER_PERIOD: Duration = Duration::from_nanos(16_666_666);
+/// How many instructions are executed per second by default
+pub const DEFAULT_SPEED: u32 = 500;
+
+/// What happened during a frame run by Emulator::run_frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameReport {
+    /// whether the screen changed and should be redrawn
+    pub drew: bool,
+    /// whether the buzzer should be sounding at the end of the frame
+    pub beeping: bool,
+    /// whether the program finished during the frame, which ends it early
+    pub halted: bool,
+}
+
+/// Drives a CPU: runs its instructions, ticks its timers at 60Hz and decides when
+/// the screen needs drawing, reporting all of it to an observer.
+///
+/// Time is split into 60Hz frames. Each frame runs however many instructions the
+/// speed calls for and then ticks the timers once, so the timers keep counting
+/// down at the right rate no matter what the CPU is doing, including sitting in
+/// LD Vx, K waiting for a key.
+pub struct Emulator {
+    /// the machine being run
+    pub cpu: Cpu,
+    /// the flag that says the timers have just ticked, cleared by run_frame_with once the frontend has ended the frame
+    pub frame_flag: bool,
+    /// the flag that says the last instruction drew on the screen
+    pub drawn_flag: bool,
+    /// which host keys press which CHIP-8 keys
+    pub keymap: Keymap,
+    /// the key events waiting for the next frame to start
+    pub input: InputQueue,
+    /// the key events applied as the frame under way started, oldest first
+    pub frame_input: Vec<KeyEvent>,
+    /// the colors the monochrome screen should be drawn in
+    pub palette: Palette,
+    /// how much emulated time each instruction takes, which sets the emulation speed
+    pub instruction_delay: Duration,
+    /// whether to check the machine's invariants after every instruction, stopping
+    /// with an error at the first one broken; see Cpu::invariant_violation
+    pub paranoid: bool,
+    /// whether the last instruction left the CPU waiting for a key press
+    waiting_for_key: bool,
+    /// whether the sound timer was running the last time we looked
+    sounding: bool,
+    /// the XO-CHIP pitch register the last time we looked
+    pitch: u8,
+    /// whether the screen has been drawn on since the observer was last told
+    pending_draw: bool,
+    /// the emulated time left over from previous frames that wasn't enough for a whole instruction
+    cycle_budget: Duration,
+    /// whether a frame has started and not yet had its timers ticked
+    frame_open: bool,
+    /// when the next frame is due to start
+    next_frame: Instant,
+}
+
+impl Emulator {
+    /// Construct an emulator that runs the given CPU with the default settings.
+    /// Use an EmulatorBuilder to change them.
+    pub fn new(cpu: Cpu) -> Emulator {
+        Emulator {
+            cpu: cpu,
+            frame_flag: false,
+            drawn_flag: false,
+            pending_draw: false,
+            keymap: Keymap::default(),
+            input: InputQueue::new(),
+            frame_input: Vec::new(),
+            palette: Palette::default(),
+            instruction_delay: speed_to_delay(DEFAULT_SPEED),
+            paranoid: false,
+            waiting_for_key: false,
+            sounding: false,
+            pitch: xochip::DEFAULT_PITCH,
+            cycle_budget: Duration::from_secs(0),
+            frame_open: false,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Executes one instruction and redraws the screen if needed, telling the
+    /// observer about anything it should react to. Returns false once the program
+    /// has finished. The timers are left alone; see tick_timers.
+    pub fn cycle<O: Observer>(&mut self, observer: &mut O) -> Result<bool, EmulationError> {
+        let result = self.execute_cycle(observer);
+
+        // the screen as the program left it is still worth showing
+        match result {
+            Ok(true) => (),
+            Ok(false) => {
+                self.present(observer);
+                observer.on_halt(None);
+            },
+            Err(ref e) => {
+                self.present(observer);
+                observer.on_halt(Some(e));
+            },
+        }
+
+        result
+    }
+
+    fn execute_cycle<O: Observer>(&mut self, observer: &mut O) -> Result<bool, EmulationError> {
+        let pc = self.cpu.program_counter;
+
+        if !self.cpu.step()? {
+            return Ok(false);
+        }
+
+        // catch an interpreter bug at the instruction responsible, rather than wherever it leads to a crash
+        if self.paranoid {
+            let problem = self.cpu.invariant_violation().or_else(|| self.cpu.step_violation(pc, self.cpu.current_instruction));
+            if let Some(problem) = problem {
+                return Err(EmulationError::InvariantViolation(pc, problem, Box::new(self.cpu.state())));
+            }
+        }
+
+        // LD Vx, K leaves the PC where it is until a key is pressed
+        let waiting_for_key = self.cpu.current_instruction & 0xF0FF == 0xF00A && self.cpu.program_counter == pc;
+        if waiting_for_key && !self.waiting_for_key {
+            observer.on_key_wait();
+        }
+        self.waiting_for_key = waiting_for_key;
+
+        self.update_sound(observer);
+
+        // the screen is only redrawn once the frame is over
+        if self.cpu.draw_flag {
+            self.cpu.draw_flag = false;
+            self.drawn_flag = true;
+            self.pending_draw = true;
+        }
+
+        Ok(true)
+    }
+
+    /// Tells the observer about the screen if it has been drawn on since it was last
+    /// told, so it's redrawn at most once a frame however fast the program is running
+    pub fn present<O: Observer>(&mut self, observer: &mut O) {
+        if self.pending_draw || self.cpu.draw_flag {
+            observer.on_draw(&self.cpu);
+            self.cpu.draw_flag = false;
+            self.pending_draw = false;
+        }
+    }
+
+    /// Puts the machine back the way it was in a saved state, keeping the devices
+    /// hooked up to its memory, and has the screen redrawn and the sound restarted
+    pub fn restore(&mut self, cpu: Cpu) {
+        let devices = mem::take(&mut self.cpu.devices);
+        self.cpu = cpu;
+        self.cpu.devices = devices;
+        self.cpu.keyboard.cancel_wait();
+        self.cpu.draw_flag = true;
+        self.cpu.xo.sound_flag = true;
+    }
+
+    /// Ticks the delay and sound timers once and lets go of any latched key taps, as
+    /// happens at the end of every 60Hz frame
+    pub fn tick_timers<O: Observer>(&mut self, observer: &mut O) {
+        self.cpu.tick_timers();
+        self.cpu.keyboard.end_frame();
+        self.frame_flag = true;
+
+        self.update_sound(observer);
+    }
+
+    /// Tells the observer if the sound timer has started or stopped, or the pitch
+    /// has changed, since we last looked
+    fn update_sound<O: Observer>(&mut self, observer: &mut O) {
+        if self.cpu.xo.pitch != self.pitch {
+            debug!(target: "audio", "Buzzer pitch {}", self.cpu.xo.pitch);
+            observer.on_pitch_change(self.cpu.xo.playback_rate());
+            self.pitch = self.cpu.xo.pitch;
+        }
+
+        let sounding = self.cpu.sound_timer > 0;
+
+        if sounding && !self.sounding {
+            debug!(target: "audio", "Buzzer on for {} ticks", self.cpu.sound_timer);
+            observer.on_sound_start();
+        } else if self.sounding && !sounding {
+            debug!(target: "audio", "Buzzer off");
+            observer.on_sound_stop();
+        }
+
+        self.sounding = sounding;
+    }
+
+    /// Runs the program until it finishes, faults, or the frontend stops it
+    pub fn run<F: Frontend>(&mut self, frontend: &mut F) -> Result<(), EmulationError> {
+        while self.run_frame_with(frontend)? {}
+
+        Ok(())
+    }
+
+    /// Runs a 60Hz frame's worth of instructions, ticks the timers and lets the
+    /// frontend end the frame, then waits until the next frame is due. Returns
+    /// false once the program has finished or the frontend has asked to stop.
+    pub fn run_frame_with<F: Frontend>(&mut self, frontend: &mut F) -> Result<bool, EmulationError> {
+        let frame = tracing::info_span!("frame").entered();
+        self.open_frame();
+
+        let instructions = tracing::debug_span!("instructions").entered();
+        while self.cycle_budget >= self.instruction_delay {
+            self.cycle_budget -= self.instruction_delay;
+
+            if !frontend.before_cycle(self) || !self.cycle(frontend)? {
+                return Ok(false);
+            }
+
+            let keep_running = frontend.after_cycle(self);
+            self.drawn_flag = false;
+
+            if !keep_running {
+                return Ok(false);
+            }
+        }
+        instructions.exit();
+
+        tracing::debug_span!("present").in_scope(|| self.present(frontend));
+        self.tick_timers(frontend);
+        self.frame_open = false;
+
+        let keep_running = tracing::debug_span!("end_frame").in_scope(|| frontend.end_frame(self));
+        self.frame_flag = false;
+        frame.exit();
+
+        // outside the frame, so time spent waiting isn't mistaken for time spent working
+        tracing::debug_span!("wait").in_scope(|| self.wait_for_next_frame());
+
+        Ok(keep_running)
+    }
+
+    /// Runs the given number of 60Hz frames as fast as possible, with no frontend,
+    /// counting one left partway through by run_until_* as the first. Returns false
+    /// if the program finished first.
+    pub fn run_frames(&mut self, frames: u32) -> Result<bool, EmulationError> {
+        for _ in 0..frames {
+            if self.run_frame()?.halted {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Runs one 60Hz frame as fast as possible, with no frontend: the frame's share
+    /// of instructions, then a single tick of the timers. This is the unit of work
+    /// for frontends that keep their own time, like a libretro core or a browser.
+    /// A frame left partway through by run_until_* is finished instead.
+    pub fn run_frame(&mut self) -> Result<FrameReport, EmulationError> {
+        self.run_frame_observed(&mut NullObserver)
+    }
+
+    /// Runs one 60Hz frame as fast as possible like run_frame, telling the observer
+    /// about anything it should react to
+    pub fn run_frame_observed<O: Observer>(&mut self, observer: &mut O) -> Result<FrameReport, EmulationError> {
+        let _frame = tracing::info_span!("frame").entered();
+        let mut report = FrameReport { drew: false, beeping: false, halted: false };
+
+        self.open_frame();
+
+        while self.cycle_budget >= self.instruction_delay {
+            self.cycle_budget -= self.instruction_delay;
+
+            if !self.cycle(observer)? {
+                report.halted = true;
+                return Ok(report);
+            }
+
+            report.drew |= self.drawn_flag;
+            self.drawn_flag = false;
+        }
+
+        self.present(observer);
+        self.tick_timers(observer);
+        self.frame_open = false;
+        self.frame_flag = false;
+        report.beeping = self.sounding;
+
+        Ok(report)
+    }
+
+    /// Runs instructions as fast as possible, with no frontend, until one draws on
+    /// the screen. Returns false if the program finished first, and never returns
+    /// if it runs forever without drawing.
+    pub fn run_until_draw(&mut self) -> Result<bool, EmulationError> {
+        self.run_until(|emulator| emulator.drawn_flag)
+    }
+
+    /// Runs instructions as fast as possible, with no frontend, until the program
+    /// counter next reaches the given address, so the instruction there is about to
+    /// run. Returns false if the program finished first, and never returns if it
+    /// runs forever without getting there.
+    pub fn run_until_pc(&mut self, addr: usize) -> Result<bool, EmulationError> {
+        self.run_until(|emulator| emulator.cpu.program_counter == addr)
+    }
+
+    /// Runs instructions until `done` says to stop after one, ticking the timers in
+    /// between frames just as run_frame_with does. This never returns if the program
+    /// neither finishes nor gets to where `done` is waiting for.
+    fn run_until<P: FnMut(&Emulator) -> bool>(&mut self, mut done: P) -> Result<bool, EmulationError> {
+        loop {
+            self.open_frame();
+
+            while self.cycle_budget >= self.instruction_delay {
+                self.cycle_budget -= self.instruction_delay;
+
+                if !self.cycle(&mut NullObserver)? {
+                    return Ok(false);
+                }
+
+                let stop = done(self);
+                self.drawn_flag = false;
+
+                if stop {
+                    return Ok(true);
+                }
+            }
+
+            self.close_frame();
+        }
+    }
+
+    /// Gives the frame that's starting its share of emulated time, unless a frame
+    /// is already under way because it was left partway through. The keys queued
+    /// since the last frame are pressed and released as the frame starts.
+    fn open_frame(&mut self) {
+        if !self.frame_open {
+            self.frame_input = self.input.take_events();
+            for event in self.frame_input.iter() {
+                self.cpu.keyboard.set_key(event.key, event.pressed);
+            }
+
+            self.cycle_budget += TIMER_PERIOD;
+            self.frame_open = true;
+        }
+    }
+
+    /// Ends a frame run with no frontend by ticking the timers
+    fn close_frame(&mut self) {
+        self.present(&mut NullObserver);
+        self.tick_timers(&mut NullObserver);
+        self.frame_open = false;
+        self.frame_flag = false;
+    }
+
+    /// Sleeps until the next frame is due. If we've fallen more than a frame
+    /// behind, say because the debugger had us paused, we start counting afresh
+    /// rather than rushing to catch up.
+    fn wait_for_next_frame(&mut self) {
+        let now = Instant::now();
+
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+            self.next_frame += TIMER_PERIOD;
+        } else if now - self.next_frame > TIMER_PERIOD {
+            self.next_frame = now + TIMER_PERIOD;
+        } else {
+            self.next_frame += TIMER_PERIOD;
+        }
+    }
+
+    /// How many instructions run each second, going by instruction_delay
+    pub fn speed(&self) -> u32 {
+        (1_000_000_000 / cmp::max(1, self.instruction_delay.as_nanos())) as u32
+    }
+
+    /// Presses or releases whichever CHIP-8 key the host key is mapped to
+    pub fn update_key(&mut self, key: Keycode, state: bool) {
+        self.cpu.keyboard.update_key(&self.keymap, key, state);
+    }
+
+    /// Queues a press or release of whichever CHIP-8 key the host key is mapped to,
+    /// seen by the host at the given time in milliseconds, for when the next frame starts
+    pub fn queue_key(&mut self, key: Keycode, state: bool, timestamp: u32) {
+        if let Some(k) = self.keymap.key_for(key) {
+            self.input.push(KeyEvent { timestamp: timestamp, key: k, pressed: state });
+        }
+    }
+}
+
+/// Configures and constructs an Emulator. Anything not set is left at its default.
+#[derive(Clone)]
+pub struct EmulatorBuilder {
+    variant: Option<Variant>,
+    quirks: Quirks,
+    speed: Option<u32>,
+    palette: Palette,
+    keymap: Keymap,
+    seed: Option<u64>,
+    font: Option<Font>,
+    paranoid: bool,
+    /// the devices to hook up, each made afresh for every emulator built
+    devices: Vec<(Range<usize>, DeviceFactory)>,
+}
+
+/// Makes a device for an EmulatorBuilder to hook up to an emulator it builds
+type DeviceFactory = Arc<dyn Fn() -> Box<dyn Device> + Send + Sync>;
+
+impl fmt::Debug for EmulatorBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EmulatorBuilder")
+            .field("variant", &self.variant)
+            .field("quirks", &self.quirks)
+            .field("speed", &self.speed)
+            .field("palette", &self.palette)
+            .field("keymap", &self.keymap)
+            .field("seed", &self.seed)
+            .field("font", &self.font)
+            .field("paranoid", &self.paranoid)
+            .field("devices", &self.devices.iter().map(|d| d.0.clone()).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> EmulatorBuilder {
+        EmulatorBuilder::new()
+    }
+}
+
+impl EmulatorBuilder {
+    /// Start from the default settings, detecting the variant from the program
+    pub fn new() -> EmulatorBuilder {
+        EmulatorBuilder {
+            variant: None,
+            quirks: Quirks::default(),
+            speed: None,
+            palette: Palette::default(),
+            keymap: Keymap::default(),
+            seed: None,
+            font: None,
+            paranoid: false,
+            devices: Vec::new(),
+        }
+    }
+
+    /// Emulate the given variant instead of detecting it from the program
+    pub fn variant(mut self, variant: Variant) -> EmulatorBuilder {
+        self.variant = Some(variant);
+        self
+    }
+
+    /// Set how the interpreter behaves in corner cases
+    pub fn quirks(mut self, quirks: Quirks) -> EmulatorBuilder {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Set how many instructions are executed per second
+    pub fn speed(mut self, instructions_per_second: u32) -> EmulatorBuilder {
+        self.speed = Some(instructions_per_second);
+        self
+    }
+
+    /// Set the colors the monochrome screen is drawn in
+    pub fn palette(mut self, palette: Palette) -> EmulatorBuilder {
+        self.palette = palette;
+        self
+    }
+
+    /// Set which host keys press which CHIP-8 keys
+    pub fn keymap(mut self, keymap: Keymap) -> EmulatorBuilder {
+        self.keymap = keymap;
+        self
+    }
+
+    /// Seed the random number generator, so RND gives the same sequence every run
+    pub fn seed(mut self, seed: u64) -> EmulatorBuilder {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Replace the built-in font
+    pub fn font(mut self, font: Font) -> EmulatorBuilder {
+        self.font = Some(font);
+        self
+    }
+
+    /// Check the machine's invariants after every instruction, which is slow but
+    /// stops at the first instruction the interpreter gets wrong
+    pub fn paranoid(mut self, paranoid: bool) -> EmulatorBuilder {
+        self.paranoid = paranoid;
+        self
+    }
+
+    /// Hook up a device to answer for a range of addresses. Every emulator built gets
+    /// its own, made by calling `make`. The frontend, observer and audio sink aren't
+    /// set here, as they're handed to the emulator each time it runs; see Emulator::run.
+    pub fn device<F>(mut self, addrs: Range<usize>, make: F) -> EmulatorBuilder
+        where F: Fn() -> Box<dyn Device> + Send + Sync + 'static
+    {
+        self.devices.push((addrs, Arc::new(make)));
+        self
+    }
+
+    /// Whether a variant has been set, rather than detected from the program
+    pub fn has_variant(&self) -> bool {
+        self.variant.is_some()
+    }
+
+    /// Whether a speed has been set, rather than left at the default
+    pub fn has_speed(&self) -> bool {
+        self.speed.is_some()
+    }
+
+    /// Whether a font has been set to replace the built-in one
+    pub fn has_font(&self) -> bool {
+        self.font.is_some()
+    }
+
+    /// Loads the program at the given path and constructs the emulator
+    pub fn build_from_file(self, filepath: &str) -> Result<Emulator, ProgramLoadError> {
+        let cpu = Cpu::init_from_file_path(filepath, self.variant.map(MachineConfig::new))?;
+        Ok(self.build(cpu))
+    }
+
+    /// Loads the program from a buffer and constructs the emulator
+    pub fn build_from_buffer(self, buf: Vec<u8>) -> Result<Emulator, ProgramLoadError> {
+        let cpu = Cpu::init_from_buffer(buf, self.variant.map(MachineConfig::new))?;
+        Ok(self.build(cpu))
+    }
+
+    fn build(self, mut cpu: Cpu) -> Emulator {
+        cpu.config.quirks = self.quirks;
+        if let Some(seed) = self.seed {
+            cpu.seed_rng(seed);
+        }
+        if let Some(ref font) = self.font {
+            cpu.load_font(font);
+        }
+        for (addrs, make) in self.devices.iter() {
+            cpu.devices.attach(addrs.clone(), make());
+        }
+
+        let mut emulator = Emulator::new(cpu);
+        emulator.keymap = self.keymap;
+        emulator.palette = self.palette;
+        emulator.instruction_delay = speed_to_delay(self.speed.unwrap_or(DEFAULT_SPEED));
+        emulator.paranoid = self.paranoid;
+
+        emulator
+    }
+}
+
+/// How much emulated time each instruction takes to run at the given number of instructions per second
+fn speed_to_delay(instructions_per_second: u32) -> Duration {
+    if instructions_per_second == 0 {
+        Duration::from_secs(1)
+    } else {
+        // never zero, or a frame would never run out of time for instructions
+        Duration::from_nanos(cmp::max(1, 1_000_000_000 / instructions_per_second as u64))
+    }
+}
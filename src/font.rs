@@ -0,0 +1,187 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use cpu;
+
+/// How many bytes the small font takes: 5 for each of the 16 hex digits
+pub const SMALL_FONT_LENGTH: usize = 80;
+/// How many bytes a big font of the 10 decimal digits takes, at 10 bytes each
+pub const BIG_DECIMAL_FONT_LENGTH: usize = 100;
+/// How many bytes a big font of all 16 hex digits takes, at 10 bytes each
+pub const BIG_HEX_FONT_LENGTH: usize = 160;
+/// How many bytes of memory, from the start of the font, a font can take up
+pub const MAX_FONT_LENGTH: usize = SMALL_FONT_LENGTH + BIG_HEX_FONT_LENGTH;
+
+/// The font of the original COSMAC VIP interpreter
+pub const VIP_FONT: [u8; 80] = [ 0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0x0
+                                 0x60, 0x20, 0x20, 0x20, 0x70,   // 0x1
+                                 0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 0x2
+                                 0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 0x3
+                                 0xA0, 0xA0, 0xF0, 0x20, 0x20,   // 0x4
+                                 0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 0x5
+                                 0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 0x6
+                                 0xF0, 0x10, 0x10, 0x10, 0x10,   // 0x7
+                                 0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 0x8
+                                 0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 0x9
+                                 0xF0, 0x90, 0xF0, 0x90, 0x90,   // 0xA
+                                 0xF0, 0x50, 0x70, 0x50, 0xF0,   // 0xB
+                                 0xF0, 0x80, 0x80, 0x80, 0xF0,   // 0xC
+                                 0xF0, 0x50, 0x50, 0x50, 0xF0,   // 0xD
+                                 0xF0, 0x80, 0xF0, 0x80, 0xF0,   // 0xE
+                                 0xF0, 0x80, 0xF0, 0x80, 0x80 ]; // 0xF
+
+/// The font of the DREAM 6800's CHIPOS
+pub const DREAM6800_FONT: [u8; 80] = [ 0xE0, 0xA0, 0xA0, 0xA0, 0xE0,   // 0x0
+                                       0x40, 0x40, 0x40, 0x40, 0x40,   // 0x1
+                                       0xE0, 0x20, 0xE0, 0x80, 0xE0,   // 0x2
+                                       0xE0, 0x20, 0xE0, 0x20, 0xE0,   // 0x3
+                                       0x80, 0xA0, 0xE0, 0x20, 0x20,   // 0x4
+                                       0xE0, 0x80, 0xE0, 0x20, 0xE0,   // 0x5
+                                       0xE0, 0x80, 0xE0, 0xA0, 0xE0,   // 0x6
+                                       0xE0, 0x20, 0x20, 0x20, 0x20,   // 0x7
+                                       0xE0, 0xA0, 0xE0, 0xA0, 0xE0,   // 0x8
+                                       0xE0, 0xA0, 0xE0, 0x20, 0xE0,   // 0x9
+                                       0xE0, 0xA0, 0xE0, 0xA0, 0xA0,   // 0xA
+                                       0xC0, 0xA0, 0xE0, 0xA0, 0xC0,   // 0xB
+                                       0xE0, 0x80, 0x80, 0x80, 0xE0,   // 0xC
+                                       0xC0, 0xA0, 0xA0, 0xA0, 0xC0,   // 0xD
+                                       0xE0, 0x80, 0xE0, 0x80, 0xE0,   // 0xE
+                                       0xE0, 0x80, 0xC0, 0x80, 0x80 ]; // 0xF
+
+/// The font of the ETI-660's interpreter
+pub const ETI660_FONT: [u8; 80] = [ 0xE0, 0xA0, 0xA0, 0xA0, 0xE0,   // 0x0
+                                    0x20, 0x20, 0x20, 0x20, 0x20,   // 0x1
+                                    0xE0, 0x20, 0xE0, 0x80, 0xE0,   // 0x2
+                                    0xE0, 0x20, 0xE0, 0x20, 0xE0,   // 0x3
+                                    0xA0, 0xA0, 0xE0, 0x20, 0x20,   // 0x4
+                                    0xE0, 0x80, 0xE0, 0x20, 0xE0,   // 0x5
+                                    0xE0, 0x80, 0xE0, 0xA0, 0xE0,   // 0x6
+                                    0xE0, 0x20, 0x20, 0x20, 0x20,   // 0x7
+                                    0xE0, 0xA0, 0xE0, 0xA0, 0xE0,   // 0x8
+                                    0xE0, 0xA0, 0xE0, 0x20, 0xE0,   // 0x9
+                                    0xE0, 0xA0, 0xE0, 0xA0, 0xA0,   // 0xA
+                                    0x80, 0x80, 0xE0, 0xA0, 0xE0,   // 0xB
+                                    0xE0, 0x80, 0x80, 0x80, 0xE0,   // 0xC
+                                    0x20, 0x20, 0xE0, 0xA0, 0xE0,   // 0xD
+                                    0xE0, 0x80, 0xE0, 0x80, 0xE0,   // 0xE
+                                    0xE0, 0x80, 0xC0, 0x80, 0x80 ]; // 0xF
+
+/// The big decimal digits of SUPER-CHIP 1.1, which follow the small font
+pub const SCHIP_BIG_FONT: [u8; 100] = [ 0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C,   // 0x0
+                                        0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C,   // 0x1
+                                        0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,   // 0x2
+                                        0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C,   // 0x3
+                                        0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06,   // 0x4
+                                        0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,   // 0x5
+                                        0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C,   // 0x6
+                                        0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,   // 0x7
+                                        0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,   // 0x8
+                                        0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C ]; // 0x9
+
+/// Reasons a font couldn't be loaded
+#[derive(Debug)]
+pub enum FontError {
+    IoError(io::Error),
+    /// the font is a size that doesn't match a small font, with or without a big one
+    BadLength(usize),
+}
+
+impl From<io::Error> for FontError {
+    fn from(err: io::Error) -> Self {
+        FontError::IoError(err)
+    }
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FontError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            FontError::BadLength(length) => write!(f, "a font is {}, {} or {} bytes long, not {}", SMALL_FONT_LENGTH,
+                SMALL_FONT_LENGTH + BIG_DECIMAL_FONT_LENGTH, MAX_FONT_LENGTH, length),
+        }
+    }
+}
+
+/// The digit shapes loaded into memory for LD F, Vx. The 80-byte small font can
+/// be followed by a big font with 10 bytes per digit, which is loaded right after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    pub data: Vec<u8>,
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font::new()
+    }
+}
+
+impl Font {
+    /// The font built into the interpreter
+    pub fn new() -> Font {
+        Font {
+            data: cpu::FONT_SET.to_vec(),
+        }
+    }
+
+    /// The font as it is in the machine's memory, with room for big digits whether
+    /// or not there are any
+    pub fn in_memory(cpu: &cpu::Cpu) -> Font {
+        let start = cpu.config.font_start_addr;
+        Font {
+            data: cpu.memory[start..start + MAX_FONT_LENGTH].to_vec(),
+        }
+    }
+
+    /// Makes a font from raw bytes, checking they're a size a font can be
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font, FontError> {
+        match data.len() {
+            SMALL_FONT_LENGTH | MAX_FONT_LENGTH => Ok(Font { data: data }),
+            l if l == SMALL_FONT_LENGTH + BIG_DECIMAL_FONT_LENGTH => Ok(Font { data: data }),
+            l => Err(FontError::BadLength(l)),
+        }
+    }
+
+    /// Looks up one of the fonts of the historical interpreters by name:
+    ///
+    /// ```text
+    /// chip48     the built-in font
+    /// vip        COSMAC VIP
+    /// dream6800  DREAM 6800
+    /// eti660     ETI-660
+    /// schip      SUPER-CHIP 1.1, with its big decimal digits
+    /// ```
+    pub fn from_name(name: &str) -> Option<Font> {
+        let data = match name {
+            "chip48" => cpu::FONT_SET.to_vec(),
+            "vip" | "cosmac-vip" => VIP_FONT.to_vec(),
+            "dream6800" | "dream-6800" => DREAM6800_FONT.to_vec(),
+            "eti660" | "eti-660" => ETI660_FONT.to_vec(),
+            "schip" | "superchip" => cpu::FONT_SET.iter().chain(SCHIP_BIG_FONT.iter()).cloned().collect(),
+            _ => return None,
+        };
+
+        Some(Font { data: data })
+    }
+
+    /// Loads the font with the given name, or failing that, from the file at the given path
+    pub fn from_name_or_file(name_or_path: &str) -> Result<Font, FontError> {
+        match Font::from_name(name_or_path) {
+            Some(font) => Ok(font),
+            None => Font::load_file(Path::new(name_or_path)),
+        }
+    }
+
+    /// Loads a font from a file of raw bytes
+    pub fn load_file(path: &Path) -> Result<Font, FontError> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        Font::from_bytes(data)
+    }
+}
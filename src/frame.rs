@@ -0,0 +1,43 @@
+//
+// Author: Joshua Holmes
+//
+
+use cpu::Cpu;
+
+/// A snapshot of what's on screen, which can be handed to another thread
+/// while the CPU carries on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// the monochrome screen, one 64-bit row per line on display with the leftmost
+    /// pixel in the high bit
+    pub vram: Vec<u64>,
+    /// the Mega-Chip screen as 0xRRGGBB, if Mega-Chip mode is on
+    pub mega_screen: Option<Vec<u32>>,
+}
+
+impl Frame {
+    /// Takes a snapshot of the CPU's screen
+    pub fn capture(cpu: &Cpu) -> Frame {
+        Frame {
+            vram: cpu.vram[..cpu.display_height].to_vec(),
+            mega_screen: if cpu.mega.enabled { Some(cpu.mega.screen_buffer.clone()) } else { None },
+        }
+    }
+
+    /// The monochrome screen packed into one 64-bit word per row, with the leftmost
+    /// pixel in the high bit
+    pub fn packed_rows(&self) -> Vec<u64> {
+        self.vram.clone()
+    }
+
+    /// The monochrome screen packed eight pixels to a byte; see pack_bytes
+    pub fn packed_bytes(&self) -> Vec<u8> {
+        pack_bytes(&self.vram)
+    }
+}
+
+/// Packs rows of the monochrome screen into a bitmap, eight pixels to a byte with
+/// the leftmost in the high bit, row after row. A 64x32 screen is 256 bytes.
+pub fn pack_bytes(vram: &[u64]) -> Vec<u8> {
+    vram.iter().flat_map(|row| row.to_be_bytes().to_vec()).collect()
+}
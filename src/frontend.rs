@@ -0,0 +1,31 @@
+//
+// Author: Joshua Holmes
+//
+
+use emulator::Emulator;
+use observer::{NullObserver, Observer};
+
+/// Something that presents a running emulator to the user and feeds it input:
+/// an SDL window, a headless test harness, a browser canvas. Besides the observer
+/// notifications, a frontend gets a chance to act around every instruction and at
+/// the end of every 60Hz frame. Returning false from any of these stops the emulator.
+pub trait Frontend: Observer {
+    /// Called before each instruction is executed
+    fn before_cycle(&mut self, _emulator: &mut Emulator) -> bool {
+        true
+    }
+
+    /// Called after each instruction has executed and the screen has been drawn
+    fn after_cycle(&mut self, _emulator: &mut Emulator) -> bool {
+        true
+    }
+
+    /// Called once the instructions for a 60Hz frame have run and the timers have
+    /// ticked. This is the place to poll for input and do any per-frame work.
+    fn end_frame(&mut self, _emulator: &mut Emulator) -> bool {
+        true
+    }
+}
+
+/// A frontend with no display or input, for running programs headless
+impl Frontend for NullObserver {}
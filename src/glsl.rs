@@ -0,0 +1,40 @@
+//
+// Author: Joshua Holmes
+//
+
+use gl::types::{GLchar, GLenum, GLuint};
+use std::ffi::CString;
+use std::ptr;
+
+/// Compiles one stage of a shader, returning the compiler's complaints if it won't
+pub unsafe fn compile(stage: GLenum, source: &str) -> Result<GLuint, String> {
+    let source = CString::new(source).map_err(|_| "the shader has a NUL byte in it".to_owned())?;
+    let shader = gl::CreateShader(stage);
+    gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null());
+    gl::CompileShader(shader);
+
+    let mut compiled = 0;
+    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut compiled);
+    if compiled != 0 {
+        return Ok(shader);
+    }
+
+    let mut length = 0;
+    gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0u8; length.max(1) as usize];
+    gl::GetShaderInfoLog(shader, length, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+    gl::DeleteShader(shader);
+
+    let stage_name = if stage == gl::VERTEX_SHADER { "vertex" } else { "fragment" };
+    Err(format!("the {} shader didn't compile: {}", stage_name, String::from_utf8_lossy(&log).trim_end_matches('\0').trim()))
+}
+
+/// What the linker had to say about a program
+pub unsafe fn program_log(program: GLuint) -> String {
+    let mut length = 0;
+    gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+    let mut log = vec![0u8; length.max(1) as usize];
+    gl::GetProgramInfoLog(program, length, ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+
+    String::from_utf8_lossy(&log).trim_end_matches('\0').trim().to_owned()
+}
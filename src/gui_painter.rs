@@ -0,0 +1,246 @@
+//
+// Author: Joshua Holmes
+//
+
+use egui::epaint::{ClippedPrimitive, ImageDelta, Primitive, Vertex};
+use egui::{ImageData, TextureFilter, TextureId, TextureWrapMode, TexturesDelta};
+use gl::types::{GLint, GLsizei, GLsizeiptr, GLuint};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+
+use glsl::{compile, program_log};
+
+/// The vertex shader, which takes egui's positions in pixels from the top left
+const VERTEX_SHADER: &str = "#version 120
+uniform vec2 screen_size;
+attribute vec2 position;
+attribute vec2 texcoord;
+attribute vec4 color;
+varying vec2 uv;
+varying vec4 rgba;
+
+void main() {
+    uv = texcoord;
+    rgba = color;
+    gl_Position = vec4(2.0 * position.x / screen_size.x - 1.0, 1.0 - 2.0 * position.y / screen_size.y, 0.0, 1.0);
+}
+";
+
+/// The fragment shader. egui's colors and textures are both premultiplied, so
+/// they just multiply together.
+const FRAGMENT_SHADER: &str = "#version 120
+uniform sampler2D image;
+varying vec2 uv;
+varying vec4 rgba;
+
+void main() {
+    gl_FragColor = rgba * texture2D(image, uv);
+}
+";
+
+/// Draws what egui tessellates into the current OpenGL context. It only needs
+/// OpenGL 2.1, like the post-processing shaders, so it runs anywhere SDL's
+/// OpenGL renderer does.
+pub struct GuiPainter {
+    program: GLuint,
+    screen_size: GLint,
+    image: GLint,
+    /// the position, texcoord and color attributes
+    attributes: [GLuint; 3],
+    vertex_buffer: GLuint,
+    index_buffer: GLuint,
+    /// the OpenGL texture for each of egui's
+    textures: HashMap<TextureId, GLuint>,
+}
+
+impl GuiPainter {
+    /// Compiles the shaders and sets up the buffers, in the current context
+    pub fn new() -> Result<GuiPainter, String> {
+        if !gl::CreateProgram::is_loaded() || !gl::GenBuffers::is_loaded() {
+            return Err("the OpenGL driver doesn't support shaders".to_owned());
+        }
+
+        unsafe {
+            let vertex = compile(gl::VERTEX_SHADER, VERTEX_SHADER)?;
+            let fragment = match compile(gl::FRAGMENT_SHADER, FRAGMENT_SHADER) {
+                Ok(f) => f,
+                Err(e) => {
+                    gl::DeleteShader(vertex);
+                    return Err(e);
+                },
+            };
+
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, vertex);
+            gl::AttachShader(program, fragment);
+
+            // every attribute is used, so they can be put where we like before linking
+            let attributes = [0, 1, 2];
+            for (&attribute, name) in attributes.iter().zip(["position", "texcoord", "color"].iter()) {
+                gl::BindAttribLocation(program, attribute, CString::new(*name).unwrap().as_ptr());
+            }
+
+            gl::LinkProgram(program);
+            gl::DeleteShader(vertex);
+            gl::DeleteShader(fragment);
+
+            let mut linked = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut linked);
+            if linked == 0 {
+                let log = program_log(program);
+                gl::DeleteProgram(program);
+                return Err(format!("the GUI shader didn't link: {}", log));
+            }
+
+            let uniform = |name: &str| gl::GetUniformLocation(program, CString::new(name).unwrap().as_ptr());
+
+            let mut buffers = [0; 2];
+            gl::GenBuffers(2, buffers.as_mut_ptr());
+
+            Ok(GuiPainter {
+                program: program,
+                screen_size: uniform("screen_size"),
+                image: uniform("image"),
+                attributes: attributes,
+                vertex_buffer: buffers[0],
+                index_buffer: buffers[1],
+                textures: HashMap::new(),
+            })
+        }
+    }
+
+    /// Clears a window of the given size in pixels and draws the primitives in it,
+    /// making and freeing the textures egui asked for on the way
+    pub fn paint(&mut self, size: (u32, u32), pixels_per_point: f32, primitives: &[ClippedPrimitive], textures: &TexturesDelta) {
+        for &(id, ref delta) in textures.set.iter() {
+            self.set_texture(id, delta);
+        }
+
+        unsafe {
+            gl::Viewport(0, 0, size.0 as GLsizei, size.1 as GLsizei);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+
+            gl::Enable(gl::BLEND);
+            gl::BlendFuncSeparate(gl::ONE, gl::ONE_MINUS_SRC_ALPHA, gl::ONE_MINUS_DST_ALPHA, gl::ONE);
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Enable(gl::SCISSOR_TEST);
+
+            gl::UseProgram(self.program);
+            gl::Uniform2f(self.screen_size, size.0 as f32 / pixels_per_point, size.1 as f32 / pixels_per_point);
+            gl::Uniform1i(self.image, 0);
+            gl::ActiveTexture(gl::TEXTURE0);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_buffer);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.index_buffer);
+
+            let stride = mem::size_of::<Vertex>() as GLsizei;
+            for &attribute in self.attributes.iter() {
+                gl::EnableVertexAttribArray(attribute);
+            }
+            gl::VertexAttribPointer(self.attributes[0], 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::VertexAttribPointer(self.attributes[1], 2, gl::FLOAT, gl::FALSE, stride, (2 * mem::size_of::<f32>()) as *const _);
+            gl::VertexAttribPointer(self.attributes[2], 4, gl::UNSIGNED_BYTE, gl::TRUE, stride, (4 * mem::size_of::<f32>()) as *const _);
+
+            for primitive in primitives {
+                let mesh = match primitive.primitive {
+                    Primitive::Mesh(ref mesh) => mesh,
+                    // nothing we draw paints with callbacks
+                    Primitive::Callback(_) => continue,
+                };
+                let texture = match self.textures.get(&mesh.texture_id) {
+                    Some(&t) => t,
+                    None => continue,
+                };
+
+                // the clip rect is in points from the top left, and the scissor box in pixels from the bottom left
+                let clip = primitive.clip_rect;
+                let left = (clip.min.x * pixels_per_point).round().clamp(0.0, size.0 as f32) as GLint;
+                let right = (clip.max.x * pixels_per_point).round().clamp(0.0, size.0 as f32) as GLint;
+                let top = (clip.min.y * pixels_per_point).round().clamp(0.0, size.1 as f32) as GLint;
+                let bottom = (clip.max.y * pixels_per_point).round().clamp(0.0, size.1 as f32) as GLint;
+                if right <= left || bottom <= top {
+                    continue;
+                }
+                gl::Scissor(left, size.1 as GLint - bottom, right - left, bottom - top);
+
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                gl::BufferData(gl::ARRAY_BUFFER, (mesh.vertices.len() * mem::size_of::<Vertex>()) as GLsizeiptr,
+                    mesh.vertices.as_ptr() as *const _, gl::STREAM_DRAW);
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (mesh.indices.len() * mem::size_of::<u32>()) as GLsizeiptr,
+                    mesh.indices.as_ptr() as *const _, gl::STREAM_DRAW);
+                gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as GLsizei, gl::UNSIGNED_INT, ptr::null());
+            }
+
+            for &attribute in self.attributes.iter() {
+                gl::DisableVertexAttribArray(attribute);
+            }
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+
+        for id in textures.free.iter() {
+            if let Some(texture) = self.textures.remove(id) {
+                unsafe {
+                    gl::DeleteTextures(1, &texture);
+                }
+            }
+        }
+    }
+
+    /// Makes a texture, or changes part of one
+    fn set_texture(&mut self, id: TextureId, delta: &ImageDelta) {
+        let (width, height) = (delta.image.width() as GLsizei, delta.image.height() as GLsizei);
+        let pixels: Vec<u8> = match delta.image {
+            ImageData::Color(ref image) => image.pixels.iter().flat_map(|c| c.to_array()).collect(),
+            ImageData::Font(ref image) => image.srgba_pixels(None).flat_map(|c| c.to_array()).collect(),
+        };
+
+        let filter = |f: TextureFilter| match f {
+            TextureFilter::Nearest => gl::NEAREST as GLint,
+            TextureFilter::Linear => gl::LINEAR as GLint,
+        };
+        let wrap = match delta.options.wrap_mode {
+            TextureWrapMode::ClampToEdge => gl::CLAMP_TO_EDGE as GLint,
+            TextureWrapMode::Repeat => gl::REPEAT as GLint,
+            TextureWrapMode::MirroredRepeat => gl::MIRRORED_REPEAT as GLint,
+        };
+
+        unsafe {
+            let texture = *self.textures.entry(id).or_insert_with(|| {
+                let mut texture = 0;
+                gl::GenTextures(1, &mut texture);
+                texture
+            });
+
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter(delta.options.magnification));
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter(delta.options.minification));
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            match delta.pos {
+                Some([x, y]) => gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as GLint, y as GLint, width, height,
+                    gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const _),
+                None => gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as GLint, width, height, 0,
+                    gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const _),
+            }
+        }
+    }
+}
+
+impl Drop for GuiPainter {
+    fn drop(&mut self) {
+        unsafe {
+            for texture in self.textures.values() {
+                gl::DeleteTextures(1, texture);
+            }
+            let buffers = [self.vertex_buffer, self.index_buffer];
+            gl::DeleteBuffers(2, buffers.as_ptr());
+            gl::DeleteProgram(self.program);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::iter::Chain;
+use std::slice::Iter;
+
+/// Iterator over remembered (PC, instruction) pairs, oldest first
+pub type HistoryIter<'a> = Chain<Iter<'a, (usize, u16)>, Iter<'a, (usize, u16)>>;
+
+/// A fixed-size circular buffer of the most recently executed (PC, instruction)
+/// pairs. Recording an instruction never allocates, so it can stay on all the time.
+#[derive(Serialize, Deserialize)]
+pub struct InstructionHistory {
+    /// the recorded entries; once full, the oldest entry is at `next`
+    entries: Vec<(usize, u16)>,
+    /// how many entries the buffer holds before it starts overwriting
+    capacity: usize,
+    /// the index the next entry will be written to
+    next: usize,
+}
+
+impl InstructionHistory {
+    /// Construct a new, empty history that remembers the last `capacity` instructions
+    pub fn new(capacity: usize) -> InstructionHistory {
+        InstructionHistory {
+            entries: Vec::with_capacity(capacity),
+            capacity: capacity,
+            next: 0,
+        }
+    }
+
+    /// Records an executed instruction, forgetting the oldest one if the buffer is full
+    pub fn push(&mut self, pc: usize, instruction: u16) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() < self.capacity {
+            self.entries.push((pc, instruction));
+        } else {
+            self.entries[self.next] = (pc, instruction);
+        }
+
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    /// The most recently recorded instruction, if any
+    pub fn last(&self) -> Option<(usize, u16)> {
+        if self.entries.is_empty() {
+            None
+        } else {
+            Some(self.entries[(self.next + self.capacity - 1) % self.capacity])
+        }
+    }
+
+    /// Iterates over the remembered instructions, oldest first
+    pub fn iter(&self) -> HistoryIter<'_> {
+        let split = if self.entries.len() < self.capacity { 0 } else { self.next };
+        let (newer, older) = self.entries.split_at(split);
+        older.iter().chain(newer.iter())
+    }
+}
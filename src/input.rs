@@ -0,0 +1,52 @@
+//
+// Author: Joshua Holmes
+//
+
+/// A CHIP-8 key being pressed or let go, stamped with when the host saw it happen
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// when the host saw the event, in milliseconds from whenever its clock started
+    pub timestamp: u32,
+    /// the CHIP-8 key, 0 through F
+    pub key: u8,
+    /// whether the key went down rather than up
+    pub pressed: bool,
+}
+
+/// Key events waiting for the next frame to start. They're handed over all at once
+/// in the order they happened, however they happened to arrive, so every frame sees
+/// the same input no matter how the host delivered it.
+#[derive(Debug, Clone, Default)]
+pub struct InputQueue {
+    /// the events waiting, in the order they arrived
+    events: Vec<KeyEvent>,
+}
+
+impl InputQueue {
+    /// Construct an empty queue
+    pub fn new() -> InputQueue {
+        InputQueue {
+            events: Vec::new(),
+        }
+    }
+
+    /// Adds an event to be applied when the next frame starts
+    pub fn push(&mut self, event: KeyEvent) {
+        self.events.push(event);
+    }
+
+    /// Says whether there's nothing waiting
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Takes every waiting event, oldest first. Events stamped with the same time
+    /// stay in the order they arrived.
+    pub fn take_events(&mut self) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        events.append(&mut self.events);
+        events.sort_by_key(|e| e.timestamp);
+
+        events
+    }
+}
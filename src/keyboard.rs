@@ -7,10 +7,30 @@ extern crate sdl2;
 use sdl2::keyboard::Keycode;
 use sdl2::keyboard::Keycode::*;
 
+use machine::KeyOrder;
+
 /// Structure to abstract away the keyboard
+#[derive(Serialize, Deserialize)]
 pub struct Keyboard {
     /// says whether or not the given key is pressed
     pub keys: [bool; 16],
+    /// the keys pressed down since the last frame ended, which still count as pressed
+    /// until the next one does, so a tap shorter than a frame isn't missed. Not saved.
+    #[serde(skip)]
+    latched: [bool; 16],
+    /// the keys pressed down since LD Vx, K started waiting, oldest first. Not saved,
+    /// so a wait in progress when a state is loaded starts over.
+    #[serde(skip)]
+    presses: Vec<u8>,
+    /// whether LD Vx, K is waiting for a key
+    #[serde(skip)]
+    waiting: bool,
+}
+
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
 }
 
 impl Keyboard {
@@ -18,34 +38,180 @@ impl Keyboard {
     pub fn new() -> Keyboard {
         Keyboard {
             keys: [false; 16],
+            latched: [false; 16],
+            presses: Vec::new(),
+            waiting: false,
         }
     }
 
-    /// Says whether or not the given key is pressed
+    /// Says whether or not the given key is pressed, or was tapped since the last
+    /// frame ended. Only the low nibble counts, as there are only 16 keys.
     pub fn is_pressed(&self, key: u8) -> bool {
-        self.keys[key as usize]
-    }
-
-    /// Presses the given key and sets the appropriate flag
-    pub fn update_key(&mut self, key: Keycode, state: bool) {
-        match key {
-            Num1 => self.keys[0x1] = state,
-            Num2 => self.keys[0x2] = state,
-            Num3 => self.keys[0x3] = state,
-            Num4 => self.keys[0xC] = state,
-            Q => self.keys[0x4] = state,
-            W => self.keys[0x5] = state,
-            E => self.keys[0x6] = state,
-            R => self.keys[0xD] = state,
-            A => self.keys[0x7] = state,
-            S => self.keys[0x8] = state,
-            D => self.keys[0x9] = state,
-            F => self.keys[0xE] = state,
-            Z => self.keys[0xA] = state,
-            X => self.keys[0x0] = state,
-            C => self.keys[0xB] = state,
-            V => self.keys[0xF] = state,
-            _ => {},
-        }
-    }
-}
\ No newline at end of file
+        let key = (key & 0xF) as usize;
+        self.keys[key] || self.latched[key]
+    }
+
+    /// The keys that count as pressed, held down or tapped since the last frame ended,
+    /// with bit n for key n
+    pub fn keypad_state(&self) -> u16 {
+        (0..16u8).fold(0u16, |acc, key| if self.is_pressed(key) { acc | (1 << key) } else { acc })
+    }
+
+    /// Presses the keys whose bits are set, with bit n for key n, and releases the rest
+    pub fn set_keypad_state(&mut self, state: u16) {
+        for key in 0..16u8 {
+            self.set_key(key, state & (1 << key) != 0);
+        }
+    }
+
+    /// Lets go of the taps latched during the frame that has just ended
+    pub fn end_frame(&mut self) {
+        self.latched = [false; 16];
+    }
+
+    /// Presses or releases the given CHIP-8 key
+    pub fn set_key(&mut self, key: u8, state: bool) {
+        let key = key & 0xF;
+        if state != self.keys[key as usize] {
+            debug!(target: "input", "Key {:X} {}", key, if state { "pressed" } else { "released" });
+        }
+        if state && !self.keys[key as usize] && self.waiting {
+            self.presses.push(key);
+        }
+        if state {
+            self.latched[key as usize] = true;
+        }
+
+        self.keys[key as usize] = state;
+    }
+
+    /// For LD Vx, K: picks one of the keys pressed down since the wait started, in
+    /// the given order, ending the wait. Keys that were already held when the wait
+    /// started don't count until they are pressed again. Returns None, and keeps
+    /// waiting, if no key has been pressed yet.
+    pub fn wait_for_press(&mut self, order: KeyOrder) -> Option<u8> {
+        if !self.waiting {
+            self.waiting = true;
+            self.presses.clear();
+        }
+
+        let key = match order {
+            KeyOrder::Lowest => self.presses.iter().min().cloned(),
+            KeyOrder::Highest => self.presses.iter().max().cloned(),
+            KeyOrder::First => self.presses.first().cloned(),
+            KeyOrder::Last => self.presses.last().cloned(),
+        };
+
+        if key.is_some() {
+            self.waiting = false;
+            self.presses.clear();
+        }
+
+        key
+    }
+
+    /// Stops waiting for a key, for when the machine leaves LD Vx, K some other way,
+    /// like a state being loaded or the program being swapped
+    pub fn cancel_wait(&mut self) {
+        self.waiting = false;
+        self.presses.clear();
+    }
+
+    /// Presses or releases whichever CHIP-8 key the host key is mapped to, if any
+    pub fn update_key(&mut self, keymap: &Keymap, key: Keycode, state: bool) {
+        if let Some(k) = keymap.key_for(key) {
+            self.set_key(k, state);
+        }
+    }
+}
+
+/// Which host key presses each of the 16 CHIP-8 keys
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// the host key for each CHIP-8 key, indexed by the CHIP-8 key
+    pub keys: [Keycode; 16],
+}
+
+impl Default for Keymap {
+    /// The usual layout, with the left side of a QWERTY keyboard standing in for the hex keypad:
+    ///
+    /// ```text
+    /// 1 2 3 4        1 2 3 C
+    /// Q W E R   ->   4 5 6 D
+    /// A S D F        7 8 9 E
+    /// Z X C V        A 0 B F
+    /// ```
+    fn default() -> Keymap {
+        Keymap {
+            keys: [X, Num1, Num2, Num3, Q, W, E, A, S, D, Z, C, Num4, R, F, V],
+        }
+    }
+}
+
+impl Keymap {
+    /// Returns the CHIP-8 key the host key is mapped to, if any
+    pub fn key_for(&self, key: Keycode) -> Option<u8> {
+        self.keys.iter().position(|&k| k == key).map(|k| k as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_only_counts_keys_pressed_after_it_started() {
+        let mut keyboard = Keyboard::new();
+        keyboard.set_key(0x5, true);
+
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), None);
+        keyboard.set_key(0x5, true);
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), None);
+
+        keyboard.set_key(0x5, false);
+        keyboard.set_key(0x5, true);
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), Some(0x5));
+    }
+
+    #[test]
+    fn the_key_order_picks_between_presses() {
+        let orders = [(KeyOrder::Lowest, 0x2), (KeyOrder::Highest, 0xC), (KeyOrder::First, 0x7), (KeyOrder::Last, 0x2)];
+
+        for &(order, expected) in orders.iter() {
+            let mut keyboard = Keyboard::new();
+            assert_eq!(keyboard.wait_for_press(order), None);
+            for &key in [0x7, 0xC, 0x2].iter() {
+                keyboard.set_key(key, true);
+            }
+            assert_eq!(keyboard.wait_for_press(order), Some(expected));
+        }
+    }
+
+    #[test]
+    fn a_tap_within_a_frame_still_counts() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), None);
+
+        keyboard.set_key(0xA, true);
+        keyboard.set_key(0xA, false);
+        assert!(keyboard.is_pressed(0xA));
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), Some(0xA));
+
+        keyboard.end_frame();
+        assert!(!keyboard.is_pressed(0xA));
+    }
+
+    #[test]
+    fn cancelling_a_wait_forgets_its_presses() {
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), None);
+        keyboard.set_key(0x3, true);
+        keyboard.cancel_wait();
+
+        keyboard.set_key(0x3, false);
+        keyboard.set_key(0x4, true);
+        keyboard.set_key(0x4, false);
+        assert!(keyboard.presses.is_empty());
+        assert_eq!(keyboard.wait_for_press(KeyOrder::Lowest), None);
+    }
+}
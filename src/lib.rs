@@ -0,0 +1,90 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate bincode;
+#[cfg(feature = "compress")]
+extern crate flate2;
+#[macro_use]
+extern crate log;
+extern crate rand;
+#[cfg(feature = "scripting")]
+extern crate rhai;
+extern crate sdl2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "archive")]
+extern crate serde_json;
+extern crate tracing;
+#[cfg(feature = "profiling")]
+extern crate tracing_chrome;
+#[cfg(feature = "profiling")]
+extern crate tracing_subscriber;
+#[cfg(feature = "remote")]
+extern crate tungstenite;
+#[cfg(feature = "archive")]
+extern crate ureq;
+
+pub mod cpu;
+pub mod opcode;
+pub mod keyboard;
+pub mod audiosink;
+pub mod analysis;
+pub mod assembler;
+pub mod batch;
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod cheats;
+pub mod compat;
+pub mod condition;
+pub mod crash;
+pub mod debugger;
+pub mod debugport;
+pub mod device;
+pub mod determinism;
+pub mod disassembler;
+pub mod demo;
+pub mod emulator;
+pub mod font;
+pub mod frame;
+pub mod frontend;
+pub mod history;
+pub mod input;
+pub mod linemap;
+pub mod logging;
+pub mod lint;
+pub mod machine;
+pub mod megachip;
+pub mod movie;
+pub mod netplay;
+pub mod observer;
+pub mod octo;
+pub mod palette;
+pub mod playlist;
+pub mod quirkcheck;
+pub mod png;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod replay;
+pub mod rewind;
+pub mod romconfig;
+pub mod rotation;
+pub mod savestate;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod serialization;
+pub mod spritesheet;
+pub mod stats;
+pub mod stress;
+pub mod symbols;
+pub mod trace;
+pub mod variant;
+pub mod watch;
+pub mod watchdog;
+pub mod wav;
+pub mod webdebugger;
+pub mod xochip;
+pub mod xorshift;
@@ -0,0 +1,158 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use cheats::parse_hex;
+
+/// Reasons a line map couldn't be loaded
+#[derive(Debug)]
+pub enum LineMapError {
+    IoError(io::Error),
+    /// a line of the line map couldn't be understood
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl From<io::Error> for LineMapError {
+    fn from(err: io::Error) -> Self {
+        LineMapError::IoError(err)
+    }
+}
+
+impl fmt::Display for LineMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LineMapError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            LineMapError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for LineMapError {}
+
+/// Which line of a program's source, like an Octo program's, each of its
+/// instructions was assembled from, along with the source itself
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    /// the source line, counting from 1, of each address that starts one
+    lines: BTreeMap<usize, usize>,
+    /// the source, line by line
+    source: Vec<String>,
+}
+
+impl LineMap {
+    /// Construct a map with no lines in it
+    pub fn new() -> LineMap {
+        LineMap::default()
+    }
+
+    /// The path a program's line map is saved to: next to the program, with a .lines extension
+    pub fn path_for(program_path: &str) -> PathBuf {
+        Path::new(program_path).with_extension("lines")
+    }
+
+    /// Loads a line map. Its first line names the source file, relative to the map,
+    /// and each line after pairs a hex address with the decimal source line the
+    /// instruction there came from:
+    ///
+    /// ```text
+    /// source game.8o
+    /// 200 12
+    /// 202 12
+    /// 204 14
+    /// ```
+    ///
+    /// Blank lines and lines starting with # are ignored.
+    pub fn load_file(path: &Path) -> Result<LineMap, LineMapError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut map = LineMap::new();
+        let mut has_source = false;
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_error = |message: &str| LineMapError::Parse { line: i + 1, message: message.to_owned() };
+
+            if !has_source {
+                let source_path = match line.strip_prefix("source ") {
+                    Some(p) => path.parent().unwrap_or(Path::new("")).join(p.trim()),
+                    None => return Err(parse_error("expected source <file>")),
+                };
+                map.source = fs::read_to_string(source_path)?.lines().map(|l| l.to_owned()).collect();
+                has_source = true;
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let addr = match words.first().and_then(|w| parse_hex(w)) {
+                Some(a) => a,
+                None => return Err(parse_error("expected a hex address")),
+            };
+            let source_line = match words.get(1).and_then(|w| w.parse::<usize>().ok()) {
+                Some(l) if l > 0 && words.len() == 2 => l,
+                _ => return Err(parse_error("expected a line number after the address")),
+            };
+
+            map.lines.insert(addr, source_line);
+        }
+
+        Ok(map)
+    }
+
+    /// Saves the map in the form load_file reads, naming the source it came from.
+    /// The source is named relative to the map if they're in the same directory,
+    /// or else by its full path.
+    pub fn save_file(&self, path: &Path, source_path: &Path) -> io::Result<()> {
+        let directory = |p: &Path| fs::canonicalize(p.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new(".")));
+        let source_name = match (directory(path), directory(source_path), source_path.file_name()) {
+            (Ok(a), Ok(b), Some(name)) if a == b => PathBuf::from(name),
+            _ => fs::canonicalize(source_path)?,
+        };
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "source {}", source_name.display())?;
+        for (addr, line) in self.lines.iter() {
+            writeln!(writer, "{:03X} {}", addr, line)?;
+        }
+        writer.flush()
+    }
+
+    /// Maps the instruction at an address to the source line it came from
+    pub fn insert(&mut self, addr: usize, line: usize) {
+        self.lines.insert(addr, line);
+    }
+
+    /// Whether no instructions are mapped to lines at all
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The source line the instruction at an address came from, if it came from one
+    pub fn line_at(&self, addr: usize) -> Option<usize> {
+        self.lines.get(&addr).cloned()
+    }
+
+    /// The first address assembled from a source line, if any was
+    pub fn addr_of_line(&self, line: usize) -> Option<usize> {
+        self.lines.iter().find(|&(_, &l)| l == line).map(|(&a, _)| a)
+    }
+
+    /// The text of a source line, counting from 1
+    pub fn source_line(&self, line: usize) -> Option<&str> {
+        line.checked_sub(1).and_then(|i| self.source.get(i)).map(|l| l.as_str())
+    }
+}
@@ -0,0 +1,130 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::{BTreeMap, HashSet};
+
+use analysis::{self, Call, IValue};
+use cpu::STACK_LENGTH;
+use opcode::OpCode;
+use variant::Variant;
+
+const WRITE_BELOW_PROGRAM: &str = "Memory below the program holds the font, and on the original machines the interpreter itself. Writing there changes the digits LD F, Vx points at, or crashes the interpreter on real hardware. I was probably meant to point at a buffer in the program.";
+const WRITE_PAST_END: &str = "LD B, Vx writes three bytes, at I, I+1 and I+2, and LD [I], Vx writes one for each register up to Vx, so I has to be far enough from the end of memory for all of them. Past the end, the write faults or wraps round to the start of memory, depending on the interpreter.";
+const I_NEVER_SET: &str = "Interpreters don't agree on where I starts, so the sprite drawn depends on which one runs the program. Set I with LD I, addr before drawing.";
+const STACK_TOO_DEEP: &str = "The stack has room for 16 return addresses. Following the calls from the start of the program, this CALL can be reached with all of them in use, so it overflows the stack.";
+const RECURSION: &str = "A subroutine that calls itself, directly or through others, overflows the 16-entry stack unless something stops it in time, and the analysis can't tell whether anything does.";
+
+/// Something suspicious about a program, found without running it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// where the instruction responsible is
+    pub addr: usize,
+    /// what's wrong, like "LD B, V3 writes 1000-1002, past the end of memory"
+    pub message: String,
+    /// why it's worth fixing, and how
+    pub explanation: &'static str,
+}
+
+/// Looks for common homebrew bugs along every path analysis::visit_paths can
+/// follow through a program loaded at the given address:
+///
+/// - LD B, Vx and LD [I], Vx writing below the program, or past the end of memory
+/// - DRW with I never set
+/// - CALLs nested deeper than the stack, or calling back into themselves
+///
+/// The memory given is the machine's, so the end of it is the end of memory.
+/// The lints found are in address order.
+pub fn check(memory: &[u8], load_addr: usize, variant: Variant) -> Vec<Lint> {
+    let start = variant.start_addr();
+    let mut lints = BTreeMap::new();
+
+    analysis::visit_paths(memory, start, variant, |pc, instruction, i_register| {
+        let x = ((instruction >> 8) & 0xF) as usize;
+        let disasm = OpCode::from_u16(instruction, variant).map(|o| o.disasm_str).unwrap_or_default();
+
+        let written = match instruction & 0xF0FF {
+            0xF033 => 3,
+            0xF055 => x + 1,
+            _ => 0,
+        };
+
+        match (written, i_register) {
+            (0, _) => (),
+            (_, IValue::At(addr)) if addr < load_addr => {
+                let message = format!("{} writes {:03X}, below the program at {:03X}", disasm, addr, load_addr);
+                lints.insert((pc, 0), Lint { addr: pc, message: message, explanation: WRITE_BELOW_PROGRAM });
+            },
+            (_, IValue::At(addr)) if addr + written > memory.len() => {
+                let message = format!("{} writes {:03X}-{:03X}, past the end of memory", disasm, addr, addr + written - 1);
+                lints.insert((pc, 1), Lint { addr: pc, message: message, explanation: WRITE_PAST_END });
+            },
+            _ => (),
+        }
+
+        if instruction & 0xF000 == 0xD000 && i_register == IValue::Unset {
+            let message = format!("{} draws from I before anything sets it", disasm);
+            lints.insert((pc, 2), Lint { addr: pc, message: message, explanation: I_NEVER_SET });
+        }
+    });
+
+    for lint in check_calls(&analysis::call_graph(memory, start, variant), start) {
+        lints.insert((lint.addr, 3), lint);
+    }
+
+    lints.into_values().collect()
+}
+
+/// Looks for calls nested too deep for the stack, and for recursion, in a call graph
+fn check_calls(graph: &BTreeMap<usize, Vec<Call>>, start: usize) -> Vec<Lint> {
+    let mut depths = BTreeMap::new();
+    let mut lints = Vec::new();
+    depth(graph, start, &mut Vec::new(), &mut depths, &mut lints);
+
+    // follow the deepest calls down from the start to the one the stack has no room for
+    let mut entry = start;
+    for level in 1.. {
+        let deepest = graph.get(&entry).and_then(|calls| {
+            calls.iter().max_by_key(|c| depths.get(&c.target).cloned().unwrap_or(0))
+        });
+        let call = match deepest {
+            Some(c) => c,
+            None => break,
+        };
+
+        if level > STACK_LENGTH {
+            let message = format!("CALL {:03X} can be made with {} return addresses already on the stack", call.target, level - 1);
+            lints.push(Lint { addr: call.site, message: message, explanation: STACK_TOO_DEEP });
+            break;
+        }
+        entry = call.target;
+    }
+
+    lints
+}
+
+/// How many calls deep a subroutine can go, not counting calls back into a
+/// subroutine on the way to it, which are noted as recursion instead
+fn depth(graph: &BTreeMap<usize, Vec<Call>>, entry: usize, path: &mut Vec<usize>, depths: &mut BTreeMap<usize, usize>, lints: &mut Vec<Lint>) -> usize {
+    if let Some(&d) = depths.get(&entry) {
+        return d;
+    }
+
+    path.push(entry);
+    let mut deepest = 0;
+    let mut reported = HashSet::new();
+    for call in graph.get(&entry).map(|c| c.as_slice()).unwrap_or(&[]) {
+        if path.contains(&call.target) {
+            if reported.insert(call.site) {
+                let message = format!("CALL {:03X} calls back into a subroutine it was called from", call.target);
+                lints.push(Lint { addr: call.site, message: message, explanation: RECURSION });
+            }
+            continue;
+        }
+        deepest = deepest.max(1 + depth(graph, call.target, path, depths, lints));
+    }
+    path.pop();
+
+    depths.insert(entry, deepest);
+    deepest
+}
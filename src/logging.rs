@@ -0,0 +1,107 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::io::{self, Write};
+
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+
+/// The targets messages are logged under:
+///
+/// ```text
+/// cpu        the program starting, stopping and running into trouble
+/// decode     every instruction as it's executed, at trace level
+/// display    the window, monitors and shaders
+/// audio      the buzzer, sound output and audio recording
+/// input      keys pressed and released
+/// config     settings, cheats and fonts loaded for a program
+/// netplay    the connection to the other player
+/// remote     remote viewers and the web debugger
+/// ui         messages shown over the screen, like "Saved state"
+/// debugport  the lines a program prints through the debug port
+/// ```
+pub const TARGETS: [&str; 10] = ["cpu", "decode", "display", "audio", "input", "config", "netplay", "remote", "ui", "debugport"];
+
+/// A logger that writes every message at or above the level set for its target
+/// to stderr, like "WARN  cpu: Sprite overrun: ..."
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Logger {
+    /// the level for targets not given one of their own
+    default: LevelFilter,
+    /// the levels given for particular targets
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Logger {
+    /// Construct a logger from a comma-separated list of levels, each either a
+    /// level for every target or target=level for one, like "warn,decode=trace".
+    /// The levels are off, error, warn, info, debug and trace, and anything not
+    /// given a level logs at info.
+    pub fn from_spec(spec: &str) -> Result<Logger, String> {
+        let mut logger = Logger { default: LevelFilter::Info, targets: Vec::new() };
+
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (target, level) = match part.find('=') {
+                Some(n) => (Some(&part[..n]), &part[n + 1..]),
+                None => (None, part),
+            };
+
+            let level = match level.parse::<LevelFilter>() {
+                Ok(l) => l,
+                Err(_) => return Err(format!("Unknown log level \"{}\". Expected one of: off, error, warn, info, debug, trace", level)),
+            };
+
+            match target {
+                Some(t) if !TARGETS.contains(&t) => return Err(format!("Unknown log target \"{}\". Expected one of: {}", t, TARGETS.join(", "))),
+                Some(t) => logger.targets.push((t.to_owned(), level)),
+                None => logger.default = level,
+            }
+        }
+
+        Ok(logger)
+    }
+
+    /// The level messages for a target have to be at or above to be logged
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        // a later level for the same target overrides an earlier one
+        self.targets.iter().rev().find(|&(t, _)| t == target).map(|&(_, l)| l).unwrap_or(self.default)
+    }
+
+    /// The most verbose level any target logs at
+    pub fn max_level(&self) -> LevelFilter {
+        self.targets.iter().map(|&(_, l)| l).fold(self.default, |a, b| a.max(b))
+    }
+
+    /// Makes this the logger every log message goes to, which can only be done once
+    pub fn install(self) -> Result<(), log::SetLoggerError> {
+        let max_level = self.max_level();
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let level = match record.level() {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        let _ = writeln!(io::stderr(), "{:<5} {}: {}", level, record.target(), record.args());
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
@@ -0,0 +1,212 @@
+//
+// Author: Joshua Holmes
+//
+
+use cpu;
+use variant::Variant;
+
+/// What DRW does when a sprite runs past the end of memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpriteOverrun {
+    /// bytes past the end of memory read as zero
+    Clamp,
+    /// bytes past the end of memory are read from the start of memory
+    Wrap,
+}
+
+impl SpriteOverrun {
+    /// Looks up a sprite overrun behavior by the name used on the command line
+    pub fn from_name(name: &str) -> Option<SpriteOverrun> {
+        match name {
+            "clamp" => Some(SpriteOverrun::Clamp),
+            "wrap" => Some(SpriteOverrun::Wrap),
+            _ => None,
+        }
+    }
+}
+
+/// What ADD I, Vx does to VF
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IOverflow {
+    /// VF is left alone, as on the COSMAC VIP
+    Never,
+    /// VF is set if I ends up past 0xFFF and cleared otherwise, as on the Amiga interpreter
+    SetVf,
+    /// VF is always cleared, which is what this interpreter has always done
+    Clear,
+}
+
+impl IOverflow {
+    /// Looks up an ADD I, Vx behavior by the name used on the command line
+    pub fn from_name(name: &str) -> Option<IOverflow> {
+        match name {
+            "never" => Some(IOverflow::Never),
+            "set-vf" => Some(IOverflow::SetVf),
+            "clear" => Some(IOverflow::Clear),
+            _ => None,
+        }
+    }
+}
+
+/// What SYS does, since no interpreter can run the machine code it calls
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SysBehavior {
+    /// SYS does nothing
+    Ignore,
+    /// SYS does nothing, but raises a warning with the address it calls
+    Warn,
+    /// SYS stops emulation, since it usually means data is being executed
+    Fault,
+}
+
+impl SysBehavior {
+    /// Looks up a SYS behavior by the name used on the command line
+    pub fn from_name(name: &str) -> Option<SysBehavior> {
+        match name {
+            "ignore" => Some(SysBehavior::Ignore),
+            "warn" => Some(SysBehavior::Warn),
+            "fault" => Some(SysBehavior::Fault),
+            _ => None,
+        }
+    }
+}
+
+/// What happens when an instruction is fetched from an odd address, or from the
+/// last byte of memory so that it runs past the end
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FetchCheck {
+    /// the instruction runs anyway, with a fetch past the end of memory wrapping to the start
+    Ignore,
+    /// the instruction runs anyway, but raises a warning with the instruction that led there
+    Warn,
+    /// emulation stops, since it almost always means a bad jump
+    Fault,
+}
+
+impl FetchCheck {
+    /// Looks up a misaligned fetch behavior by the name used on the command line
+    pub fn from_name(name: &str) -> Option<FetchCheck> {
+        match name {
+            "ignore" => Some(FetchCheck::Ignore),
+            "warn" => Some(FetchCheck::Warn),
+            "fault" => Some(FetchCheck::Fault),
+            _ => None,
+        }
+    }
+}
+
+/// Which key LD Vx, K picks when more than one was pressed while it waited
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyOrder {
+    /// the key with the lowest number
+    Lowest,
+    /// the key with the highest number
+    Highest,
+    /// the key that was pressed first
+    First,
+    /// the key that was pressed last
+    Last,
+}
+
+impl KeyOrder {
+    /// Looks up a key order by the name used on the command line
+    pub fn from_name(name: &str) -> Option<KeyOrder> {
+        match name {
+            "lowest" => Some(KeyOrder::Lowest),
+            "highest" => Some(KeyOrder::Highest),
+            "first" => Some(KeyOrder::First),
+            "last" => Some(KeyOrder::Last),
+            _ => None,
+        }
+    }
+}
+
+/// How the interpreter behaves in corner cases where interpreters disagree or
+/// where programs are usually doing something wrong
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quirks {
+    /// whether instructions may touch memory below the user program, other than reading the font
+    pub memory_protection: bool,
+    /// what DRW does when a sprite runs past the end of memory
+    pub sprite_overrun: SpriteOverrun,
+    /// what ADD I, Vx does to VF
+    pub i_overflow: IOverflow,
+    /// what SYS does
+    pub sys: SysBehavior,
+    /// which key LD Vx, K picks when more than one was pressed while it waited
+    pub key_order: KeyOrder,
+    /// what fetching an instruction from an odd address or past the end of memory does
+    pub misaligned_fetch: FetchCheck,
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            memory_protection: false,
+            sprite_overrun: SpriteOverrun::Clamp,
+            i_overflow: IOverflow::Clear,
+            sys: SysBehavior::Ignore,
+            key_order: KeyOrder::Lowest,
+            misaligned_fetch: FetchCheck::Ignore,
+        }
+    }
+}
+
+impl Quirks {
+    /// Returns these quirks with the changes in a comma-separated list applied,
+    /// as given on the command line, like "protect-memory,sprite-overrun=wrap".
+    /// Returns None if any change isn't understood.
+    pub fn with_changes(mut self, changes: &str) -> Option<Quirks> {
+        for change in changes.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+            let mut parts = change.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some("protect-memory"), None) => self.memory_protection = true,
+                (Some("no-protect-memory"), None) => self.memory_protection = false,
+                (Some("sprite-overrun"), Some(name)) => self.sprite_overrun = SpriteOverrun::from_name(name)?,
+                (Some("i-overflow"), Some(name)) => self.i_overflow = IOverflow::from_name(name)?,
+                (Some("sys"), Some(name)) => self.sys = SysBehavior::from_name(name)?,
+                (Some("key-order"), Some(name)) => self.key_order = KeyOrder::from_name(name)?,
+                (Some("misaligned-fetch"), Some(name)) => self.misaligned_fetch = FetchCheck::from_name(name)?,
+                _ => return None,
+            }
+        }
+
+        Some(self)
+    }
+}
+
+/// The layout of the machine being emulated. Most of this follows from the
+/// variant, but it can be changed for interpreters or test setups that need
+/// non-default values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineConfig {
+    /// the kind of machine being emulated
+    pub variant: Variant,
+    /// how many bytes of system memory there are
+    pub memory_length: usize,
+    /// the address at which the user program is loaded into memory
+    pub program_start_addr: usize,
+    /// the address at which execution begins
+    pub execution_start_addr: usize,
+    /// the address at which the system's font data starts in memory
+    pub font_start_addr: usize,
+    /// how the interpreter behaves in corner cases
+    pub quirks: Quirks,
+    /// how many recently executed instructions to remember for debugging
+    pub history_length: usize,
+}
+
+impl MachineConfig {
+    /// Construct the default machine configuration for the given variant
+    pub fn new(variant: Variant) -> MachineConfig {
+        MachineConfig {
+            variant: variant,
+            memory_length: variant.memory_length(),
+            program_start_addr: variant.load_addr(),
+            execution_start_addr: variant.start_addr(),
+            font_start_addr: cpu::FONT_SET_START_ADDR,
+            quirks: Quirks::default(),
+            history_length: cpu::HISTORY_LENGTH,
+        }
+    }
+}
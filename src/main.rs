@@ -2,55 +2,1557 @@
 // Author: Joshua Holmes
 //
 
-extern crate rand;
+extern crate chip8_this_time_in_rust as chip8;
+#[cfg(feature = "debug-gui")]
+extern crate egui;
+#[cfg(feature = "debug-gui")]
+extern crate egui_dock;
+#[cfg(any(feature = "shaders", feature = "debug-gui"))]
+extern crate gl;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "scripting")]
+extern crate rhai;
 extern crate sdl2;
+extern crate tracing;
 
 use std::str;
+use std::cmp;
 use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-mod cpu;
-mod opcode;
 mod display;
-mod keyboard;
+mod osd;
+mod audio;
+mod compare;
+#[cfg(feature = "debug-gui")]
+mod debug_gui;
+mod debug_window;
+mod emu_thread;
+#[cfg(any(feature = "shaders", feature = "debug-gui"))]
+mod glsl;
+#[cfg(feature = "debug-gui")]
+mod gui_painter;
+#[cfg(feature = "shaders")]
+mod shader;
 
-use cpu::Cpu;
-use display::Display;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use audio::Audio;
+use chip8::analysis::{self, ByteUse};
+use chip8::assembler;
+use chip8::audiosink::AudioSink;
+#[cfg(feature = "archive")]
+use chip8::archive;
+use chip8::batch;
+use chip8::cheats;
+use chip8::compat;
+use chip8::crash;
+use chip8::debugger::Debugger;
+use chip8::debugport::DebugPort;
+use chip8::demo;
+use chip8::determinism;
+use chip8::disassembler::{self, ColorChoice, ListingStyle};
+use chip8::emulator::{self, Emulator, EmulatorBuilder};
+use chip8::font::Font;
+use chip8::linemap::LineMap;
+use chip8::logging::Logger;
+use chip8::lint;
+use chip8::machine::{FetchCheck, IOverflow, KeyOrder, Quirks, SpriteOverrun, SysBehavior};
+use chip8::movie::{self, Movie, MovieHeader};
+use chip8::netplay::Netplay;
+use chip8::observer::NullObserver;
+use chip8::playlist::Playlist;
+#[cfg(feature = "profiling")]
+use chip8::profiling::{self, ProfileOutput};
+use chip8::quirkcheck;
+use chip8::replay;
+use chip8::romconfig::RomConfig;
+use chip8::rotation::Rotation;
+#[cfg(feature = "remote")]
+use chip8::remote::{RemoteFrontend, RemoteServer};
+use chip8::savestate;
+use chip8::spritesheet;
+use chip8::stats::OpcodeStats;
+use chip8::stress;
+use chip8::symbols::SymbolTable;
+use chip8::trace::Tracer;
+use chip8::variant::Variant;
+use chip8::watch::{self, SourceWatcher};
+use chip8::watchdog::{RunLimits, Watchdog};
+use chip8::webdebugger::WebDebugger;
+#[cfg(feature = "debug-gui")]
+use debug_gui::{DebugCommand, DebugGui};
+use debug_window::DebugWindow;
+use display::{Display, WindowOptions};
+use emu_thread::{InputMessage, Outcome, UiMessage};
+use sdl2::event::{Event, WindowEventId};
+use sdl2::keyboard::{self, Keycode};
+
+/// How long the UI thread sleeps between looking for frames and input
+const UI_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// How many frames each program runs for in a compatibility report, unless --max-frames says otherwise
+const COMPAT_REPORT_FRAMES: u64 = 600;
+/// How many frames each run of --determinism lasts, without a movie or --max-frames to say
+const DETERMINISM_FRAMES: u64 = 600;
+/// The exit code when a run is stopped by --max-instructions or --max-frames, as timeout(1) uses
+const WATCHDOG_EXIT_CODE: i32 = 124;
 
 fn main() {
-    // get the program filename from the commandline and load it up
+    // get the program filename and options from the commandline and load it up
     let args: Vec<_> = env::args().collect();
-    let filename = &args[1];
+    let mut programs = Vec::new();
+    let mut variant = None;
+    let mut memory_protection = false;
+    let mut sprite_overrun = SpriteOverrun::Clamp;
+    let mut i_overflow = IOverflow::Clear;
+    let mut sys = SysBehavior::Ignore;
+    let mut key_order = KeyOrder::Lowest;
+    let mut misaligned_fetch = FetchCheck::Ignore;
+    let mut autosave = false;
+    let mut debug = false;
+    let mut start_paused = false;
+    let mut break_at: Vec<String> = Vec::new();
+    let mut script_path = None;
+    let mut speed = None;
+    let mut seed = None;
+    let mut serve_addr = None;
+    let mut debug_http_addr = None;
+    let mut debug_port = None;
+    let mut host_addr = None;
+    let mut join_addr = None;
+    let mut attract_seconds = None;
+    let mut min_beep = emulator::TIMER_PERIOD;
+    let mut beep_pitch = None;
+    let mut scale = None;
+    let mut borderless = false;
+    let mut always_on_top = false;
+    let mut fullscreen = false;
+    let mut monitor = None;
+    let mut rotation = None;
+    let mut shader = None;
+    let mut grid = false;
+    let mut rip_sprites = None;
+    let mut assemble_to = None;
+    let mut roundtrip = false;
+    let mut disasm = false;
+    let mut watch_source = false;
+    let mut listing = false;
+    let mut color = ColorChoice::Auto;
+    let mut show_bytes = false;
+    let mut show_ascii = false;
+    let mut dead_code = false;
+    let mut stats = false;
+    let mut check = false;
+    let mut quirk_check_frames = None;
+    let mut determinism_runs = None;
+    let mut stress_programs = None;
+    let mut compat_report = None;
+    let mut jobs = None;
+    let mut stats_frames = None;
+    let mut font = None;
+    let mut compare_changes = None;
+    let mut from_archive = false;
+    let mut headless = false;
+    let mut paranoid = false;
+    let mut log_level = None;
+    let mut profile = None;
+    let mut record_movie = None;
+    let mut play_movie = None;
+    let mut record_audio = None;
+    let mut trace_path = None;
+    let mut trace_off = false;
+    let mut symbols_path = None;
+    let mut line_map_path = None;
+    let mut limits = RunLimits::new();
+    let mut i = 1;
+
+    while i < args.len() {
+        match args[i].as_str() {
+            "--variant" => {
+                i += 1;
+                variant = match args.get(i).and_then(|name| Variant::from_name(name)) {
+                    Some(v) => Some(v),
+                    None => panic!("Unknown variant. Expected one of: chip8, hires, eti660, megachip, xochip"),
+                };
+            },
+            "--protect-memory" => memory_protection = true,
+            "--autosave" => autosave = true,
+            "--archive" => from_archive = true,
+            "--headless" => headless = true,
+            "--paranoid" => paranoid = true,
+            "--profile" => {
+                i += 1;
+                profile = match args.get(i) {
+                    Some(output) => Some(output.to_owned()),
+                    None => panic!("Expected console, or a file to write a Chrome trace to, after --profile"),
+                };
+            },
+            "--log-level" => {
+                i += 1;
+                log_level = match args.get(i) {
+                    Some(spec) => Some(spec.to_owned()),
+                    None => panic!("Expected a log level, or a list of target=level, after --log-level"),
+                };
+            },
+            "--roundtrip" => roundtrip = true,
+            "--disasm" => disasm = true,
+            "--watch" => watch_source = true,
+            "--listing" => listing = true,
+            "--bytes" => show_bytes = true,
+            "--ascii" => show_ascii = true,
+            "--color" => {
+                i += 1;
+                color = match args.get(i).and_then(|name| ColorChoice::from_name(name)) {
+                    Some(c) => c,
+                    None => panic!("Expected one of: auto, always, never after --color"),
+                };
+            },
+            "--dead-code" => dead_code = true,
+            "--stats" => stats = true,
+            "--check" => check = true,
+            "--compat-report" => {
+                i += 1;
+                compat_report = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a directory to write the report to after --compat-report"),
+                };
+            },
+            "--jobs" => {
+                i += 1;
+                jobs = match args.get(i).and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if n > 0 => Some(n),
+                    _ => panic!("Expected a number of threads after --jobs"),
+                };
+            },
+            "--stress" => {
+                i += 1;
+                stress_programs = match args.get(i).and_then(|n| n.parse::<u64>().ok()) {
+                    Some(n) => Some(n),
+                    None => panic!("Expected a number of random programs to run after --stress"),
+                };
+            },
+            "--determinism" => {
+                i += 1;
+                determinism_runs = match args.get(i).and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if n > 1 => Some(n),
+                    _ => panic!("Expected a number of runs, at least 2, after --determinism"),
+                };
+            },
+            "--quirk-check" => {
+                i += 1;
+                quirk_check_frames = match args.get(i).and_then(|n| n.parse::<u32>().ok()) {
+                    Some(n) => Some(n),
+                    None => panic!("Expected a number of frames to run after --quirk-check"),
+                };
+            },
+            "--stats-frames" => {
+                i += 1;
+                stats = true;
+                stats_frames = match args.get(i).and_then(|n| n.parse::<u32>().ok()) {
+                    Some(n) => Some(n),
+                    None => panic!("Expected a number of frames to run after --stats-frames"),
+                };
+            },
+            "--borderless" => borderless = true,
+            "--always-on-top" => always_on_top = true,
+            "--fullscreen" => fullscreen = true,
+            "--grid" => grid = true,
+            "--monitor" => {
+                i += 1;
+                monitor = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(n) if n >= 0 => Some(n),
+                    _ => panic!("Expected a monitor number, counting from 0, after --monitor"),
+                };
+            },
+            "--max-instructions" => {
+                i += 1;
+                limits.max_instructions = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(n) => Some(n),
+                    None => panic!("Expected a number of instructions after --max-instructions"),
+                };
+            },
+            "--max-frames" => {
+                i += 1;
+                limits.max_frames = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(n) => Some(n),
+                    None => panic!("Expected a number of frames after --max-frames"),
+                };
+            },
+            "--record-movie" => {
+                i += 1;
+                record_movie = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a file to record the movie to after --record-movie"),
+                };
+            },
+            "--play-movie" => {
+                i += 1;
+                play_movie = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a movie file after --play-movie"),
+                };
+            },
+            "--record-audio" => {
+                i += 1;
+                record_audio = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a WAV file to record the audio to after --record-audio"),
+                };
+            },
+            "--trace" => {
+                i += 1;
+                trace_path = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a file to trace instructions to after --trace"),
+                };
+            },
+            "--trace-off" => trace_off = true,
+            "--line-map" => {
+                i += 1;
+                line_map_path = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a line map after --line-map"),
+                };
+            },
+            "--symbols" => {
+                i += 1;
+                symbols_path = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a symbol file after --symbols"),
+                };
+            },
+            "--assemble" => {
+                i += 1;
+                assemble_to = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a file to save the assembled program to after --assemble"),
+                };
+            },
+            "--rip-sprites" => {
+                i += 1;
+                rip_sprites = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a PNG file to save the sprites to after --rip-sprites"),
+                };
+            },
+            "--debug" => debug = true,
+            "--paused" => start_paused = true,
+            "--break-at" => {
+                i += 1;
+                match args.get(i) {
+                    Some(spec) => break_at.push(spec.to_owned()),
+                    None => panic!("Expected an address, optionally followed by if <condition>, after --break-at"),
+                }
+            },
+            "--script" => {
+                i += 1;
+                script_path = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a script file after --script"),
+                };
+            },
+            "--speed" => {
+                i += 1;
+                speed = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(s) => Some(s),
+                    None => panic!("Expected a number of instructions per second after --speed"),
+                };
+            },
+            "--seed" => {
+                i += 1;
+                seed = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(s) => Some(s),
+                    None => panic!("Expected a number after --seed"),
+                };
+            },
+            "--debug-port" => {
+                i += 1;
+                debug_port = match args.get(i).and_then(|a| cheats::parse_hex(a)) {
+                    Some(addr) => Some(addr),
+                    None => panic!("Expected an address for the program to print through after --debug-port, like 0xFC0"),
+                };
+            },
+            "--debug-http" => {
+                i += 1;
+                debug_http_addr = match args.get(i) {
+                    Some(addr) => Some(addr.to_owned()),
+                    None => panic!("Expected an address to listen on after --debug-http, like 127.0.0.1:8081"),
+                };
+            },
+            "--host" => {
+                i += 1;
+                host_addr = match args.get(i) {
+                    Some(addr) => Some(addr.to_owned()),
+                    None => panic!("Expected an address to wait for the other player on after --host, like 0.0.0.0:8082"),
+                };
+            },
+            "--join" => {
+                i += 1;
+                join_addr = match args.get(i) {
+                    Some(addr) => Some(addr.to_owned()),
+                    None => panic!("Expected the other player's address after --join"),
+                };
+            },
+            "--serve" => {
+                i += 1;
+                serve_addr = match args.get(i) {
+                    Some(addr) => Some(addr.to_owned()),
+                    None => panic!("Expected an address to listen on after --serve, like 0.0.0.0:8080"),
+                };
+            },
+            "--sprite-overrun" => {
+                i += 1;
+                sprite_overrun = match args.get(i).and_then(|name| SpriteOverrun::from_name(name)) {
+                    Some(s) => s,
+                    None => panic!("Unknown sprite overrun behavior. Expected one of: clamp, wrap"),
+                };
+            },
+            "--i-overflow" => {
+                i += 1;
+                i_overflow = match args.get(i).and_then(|name| IOverflow::from_name(name)) {
+                    Some(o) => o,
+                    None => panic!("Unknown ADD I, Vx behavior. Expected one of: clear, never, set-vf"),
+                };
+            },
+            "--sys" => {
+                i += 1;
+                sys = match args.get(i).and_then(|name| SysBehavior::from_name(name)) {
+                    Some(s) => s,
+                    None => panic!("Unknown SYS behavior. Expected one of: ignore, warn, fault"),
+                };
+            },
+            "--key-order" => {
+                i += 1;
+                key_order = match args.get(i).and_then(|name| KeyOrder::from_name(name)) {
+                    Some(k) => k,
+                    None => panic!("Unknown key order. Expected one of: lowest, highest, first, last"),
+                };
+            },
+            "--misaligned-fetch" => {
+                i += 1;
+                misaligned_fetch = match args.get(i).and_then(|name| FetchCheck::from_name(name)) {
+                    Some(m) => m,
+                    None => panic!("Unknown misaligned fetch behavior. Expected one of: ignore, warn, fault"),
+                };
+            },
+            "--compare" => {
+                i += 1;
+                compare_changes = match args.get(i) {
+                    Some(changes) => Some(changes.to_owned()),
+                    None => panic!("Expected the quirks to change after --compare, like sprite-overrun=wrap,protect-memory"),
+                };
+            },
+            "--attract" => {
+                i += 1;
+                attract_seconds = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(s) => Some(s),
+                    None => panic!("Expected a number of seconds after --attract"),
+                };
+            },
+            "--min-beep" => {
+                i += 1;
+                min_beep = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(ms) => Duration::from_millis(ms),
+                    None => panic!("Expected a number of milliseconds after --min-beep"),
+                };
+            },
+            "--beep-pitch" => {
+                i += 1;
+                beep_pitch = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(pitch) if pitch > 0.0 => Some(pitch),
+                    _ => panic!("Expected a pitch in Hz after --beep-pitch"),
+                };
+            },
+            "--scale" => {
+                i += 1;
+                scale = match args.get(i).and_then(|s| s.parse().ok()) {
+                    Some(n) if n > 0 => Some(n),
+                    _ => panic!("Expected a whole number of window pixels per display pixel after --scale"),
+                };
+            },
+            "--rotate" => {
+                i += 1;
+                rotation = match args.get(i).and_then(|s| s.parse().ok()).and_then(Rotation::from_degrees) {
+                    Some(r) => Some(r),
+                    None => panic!("Expected one of 0, 90, 180, 270 degrees after --rotate"),
+                };
+            },
+            "--shader" => {
+                i += 1;
+                shader = match args.get(i) {
+                    Some(path) => Some(path.to_owned()),
+                    None => panic!("Expected a GLSL fragment shader file after --shader"),
+                };
+            },
+            "--font" => {
+                i += 1;
+                font = match args.get(i).map(|name| Font::from_name_or_file(name)) {
+                    Some(Ok(f)) => Some(f),
+                    Some(Err(e)) => panic!("Couldn't load the font. Error message: {}", e),
+                    None => panic!("Expected a font name or file after --font"),
+                };
+            },
+            arg => programs.push(arg.to_owned()),
+        }
+
+        i += 1;
+    }
+
+    #[cfg(not(feature = "archive"))]
+    {
+        if from_archive {
+            panic!("This build can't browse the CHIP-8 Archive. Rebuild with --features archive.");
+        }
+    }
+
+    #[cfg(feature = "archive")]
+    {
+        if from_archive {
+            programs.push(pick_from_archive());
+        }
+    }
+
+    // assemble the program given into a ROM, rather than running it
+    if let Some(path) = assemble_to {
+        let source_path = match programs.pop() {
+            Some(p) => p,
+            None => panic!("Expected a source file to assemble with --assemble"),
+        };
+        let source = match fs::read_to_string(&source_path) {
+            Ok(s) => s,
+            Err(e) => panic!("Couldn't read the source to assemble. Error message: {}", e),
+        };
+
+        match assembler::assemble_with_symbols(&source, variant.unwrap_or(Variant::Chip8).load_addr()) {
+            Err(errors) => {
+                for e in errors.iter() {
+                    println!("{}: {}", source_path, e);
+                    println!("{}", e.excerpt(&source));
+                }
+                println!("{} problem(s) found, so nothing was assembled.", errors.len());
+                process::exit(1);
+            },
+            Ok(assembled) => {
+                if let Err(e) = fs::write(&path, &assembled.bytes) {
+                    panic!("Couldn't save the assembled program. Error message: {}", e);
+                }
+
+                // the labels and source lines go next to the ROM, for --symbols and --line-map
+                let symbols_path = SymbolTable::path_for(&path);
+                if let Err(e) = assembled.symbols.save_file(&symbols_path) {
+                    panic!("Couldn't save the program's symbols. Error message: {}", e);
+                }
+                let line_map_path = LineMap::path_for(&path);
+                if let Err(e) = assembled.line_map.save_file(&line_map_path, Path::new(&source_path)) {
+                    panic!("Couldn't save the program's line map. Error message: {}", e);
+                }
+
+                println!("Assembled {} bytes to {}, with its symbols in {} and line map in {}",
+                    assembled.bytes.len(), path, symbols_path.display(), line_map_path.display());
+            },
+        }
+        return;
+    }
+
+    // several programs, or a directory of them, make a playlist
+    let playlist = if programs.len() > 1 || programs.iter().any(|p| Path::new(p).is_dir()) {
+        match Playlist::new(&programs) {
+            Err(e) => panic!("Couldn't read the programs to play. Error message: {}", e),
+            Ok(ref p) if p.is_empty() => panic!("No programs found to play."),
+            Ok(p) => Some(p),
+        }
+    } else {
+        None
+    };
+
+    let filename = match playlist {
+        Some(ref p) => {
+            println!("Playing {} programs. Press F6 to move on to the next one.", p.len());
+            Some(p.current().to_string_lossy().into_owned())
+        },
+        None => programs.pop(),
+    };
+
+    if filename.is_none() {
+        println!("Usage: {} [--variant chip8|hires|eti660|megachip|xochip] [--protect-memory] [--sprite-overrun clamp|wrap] [--i-overflow clear|never|set-vf] [--sys ignore|warn|fault] [--key-order lowest|highest|first|last] [--misaligned-fetch ignore|warn|fault] [--speed <instructions per second>] [--seed <n>] [--autosave] [--debug] [--paused] [--break-at <address> [if <condition>]]... [--debug-http <address>] [--debug-port <address>] [--script <file>] [--serve <address>] [--headless] [--paranoid] [--log-level <level>|<target>=<level>,...] [--profile console|<trace file>] [--max-instructions <n>] [--max-frames <n>] [--record-movie <file>] [--play-movie <file>] [--record-audio <file>] [--trace <file> [--trace-off]] [--symbols <file>] [--line-map <file>] [--rip-sprites <file>] [--assemble <rom file>] [--watch] [--roundtrip] [--disasm [--color auto|always|never] [--bytes] [--ascii]] [--listing] [--dead-code] [--stats [--stats-frames <n>]] [--check] [--quirk-check <frames>] [--determinism <runs> [--play-movie <file>] [--max-frames <n>]] [--compat-report <directory> [--max-frames <n>] [--jobs <n>]] [--stress <programs> [--jobs <n>]] [--host <address>|--join <address>] [--attract <seconds>] [--min-beep <milliseconds>] [--beep-pitch <Hz>] [--scale <n>] [--borderless] [--always-on-top] [--fullscreen] [--monitor <n>] [--rotate 0|90|180|270] [--shader <file>] [--grid] [--font chip48|vip|dream6800|eti660|schip|<file>] [--compare <quirk changes>] [--archive] [<program file or directory>...]", args[0]);
+        println!("No program given, so running the built-in demo.");
+    }
+
+    let logger = match Logger::from_spec(log_level.as_deref().unwrap_or("info")) {
+        Ok(l) => l,
+        Err(e) => panic!("{}", e),
+    };
+    if let Err(e) = logger.install() {
+        panic!("Couldn't start logging. Error message: {}", e);
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    {
+        if profile.is_some() {
+            panic!("This build can't profile. Rebuild with --features profiling.");
+        }
+    }
+
+    // kept until main returns, which is when a Chrome trace is finished off
+    #[cfg(feature = "profiling")]
+    let _profiler = profile.map(|output| match profiling::start(ProfileOutput::from_name(&output)) {
+        Ok(p) => p,
+        Err(e) => panic!("Couldn't start profiling. Error message: {}", e),
+    });
+
+    crash::install_panic_hook();
+
+    let quirks = Quirks { memory_protection: memory_protection, sprite_overrun: sprite_overrun, i_overflow: i_overflow, sys: sys, key_order: key_order, misaligned_fetch: misaligned_fetch };
+
+    // both sides of a comparison need the same random numbers to have any hope of
+    // matching, and so does a movie when it's played back
+    if (compare_changes.is_some() || record_movie.is_some() || quirk_check_frames.is_some() || determinism_runs.is_some() || stress_programs.is_some()) && seed.is_none() {
+        seed = Some(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0));
+    }
+
+    if record_movie.is_some() && (playlist.is_some() || host_addr.is_some() || join_addr.is_some() || serve_addr.is_some() || headless || script_path.is_some() || watch_source) {
+        panic!("Movies can only be recorded of a single program played in the window, without netplay, scripts or --watch.");
+    }
 
-    let mut cpu = match Cpu::init_from_file_path(filename) {
-        Err(e) => panic!("Failed to load user program. Error message: {:?}", e),
+    // the other player starts from power-on, so resuming an autosave would desync from the first frame
+    if autosave && (host_addr.is_some() || join_addr.is_some()) {
+        panic!("--autosave doesn't work with netplay, where both players start the program afresh.");
+    }
+
+    if record_audio.is_some() && (serve_addr.is_some() || headless) {
+        panic!("Audio can only be recorded while playing in the window.");
+    }
+
+    if trace_path.is_some() && (serve_addr.is_some() || headless) {
+        panic!("Instructions can only be traced while playing in the window.");
+    }
+
+    if trace_off && trace_path.is_none() {
+        panic!("--trace-off needs a file to trace to, given with --trace <file>.");
+    }
+
+    let mut builder = EmulatorBuilder::new()
+        .quirks(quirks);
+    if let Some(v) = variant {
+        builder = builder.variant(v);
+    }
+    if let Some(s) = speed {
+        builder = builder.speed(s);
+    }
+    if let Some(s) = seed {
+        builder = builder.seed(s);
+    }
+    if let Some(f) = font {
+        builder = builder.font(f);
+    }
+    builder = builder.paranoid(paranoid);
+
+    // run random programs looking for bugs in the interpreter, rather than playing anything
+    if let Some(programs) = stress_programs {
+        let first_seed = seed.unwrap_or(0);
+        let stress_variant = variant.unwrap_or(Variant::Chip8);
+
+        // the panics caught are reported with the programs that caused them, so they needn't be printed as well
+        panic::set_hook(Box::new(|_| ()));
+        let failures = stress::run_many(&builder, stress_variant, first_seed, programs, jobs.unwrap_or_else(batch::default_jobs));
+        drop(panic::take_hook());
+
+        for f in failures.iter() {
+            println!("Seed {}: after {} instructions, {} at 0x{:03X}: {}", f.seed, f.steps, f.disasm, f.addr, f.problem);
+        }
+
+        if failures.is_empty() {
+            println!("Ran {} random {:?} programs from seed {} without finding anything wrong.", programs, stress_variant, first_seed);
+        } else {
+            println!("{} of {} random programs found something wrong. Run with --stress 1 --seed <n> to run one again.", failures.len(), programs);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // run every program given for a while and report how each got on, rather than playing them
+    if let Some(dir) = compat_report {
+        let paths = match playlist {
+            Some(ref p) => p.paths.clone(),
+            None => filename.iter().map(PathBuf::from).collect(),
+        };
+        let frames = limits.max_frames.unwrap_or(COMPAT_REPORT_FRAMES);
+
+        // every program gets its own emulator, so they can all run at once
+        let results: Vec<_> = batch::run(paths, jobs.unwrap_or_else(batch::default_jobs), |path| {
+            let name = path.to_string_lossy().into_owned();
+            let loaded = emu_thread::program_builder(&builder, &emu_thread::load_rom_config(&name)).build_from_file(&name);
+            let mut emulator = match loaded {
+                Ok(e) => e,
+                Err(e) => {
+                    println!("{}: couldn't load it. Error message: {}", name, e);
+                    return None;
+                },
+            };
+
+            let result = compat::run(&mut emulator, &path, frames);
+            println!("{}: {} {}", name, result.verdict.name(), result.verdict.detail());
+            Some(result)
+        }).into_iter().flatten().collect();
+
+        match compat::write_report(Path::new(&dir), &results) {
+            Ok(()) => println!("Wrote the report for {} programs to {}", results.len(), dir),
+            Err(e) => panic!("Couldn't write the report. Error message: {}", e),
+        }
+        return;
+    }
+
+    // give the program somewhere to print to, for debugging it
+    if let Some(addr) = debug_port {
+        builder = builder.device(DebugPort::new(addr).addrs(), move || Box::new(DebugPort::new(addr)));
+    }
+
+    let rom_config = match filename {
+        Some(ref f) => emu_thread::load_rom_config(f),
+        None => RomConfig::new(),
+    };
+
+    // with --watch, the program given is source, assembled now and again whenever it's saved
+    let loaded = match filename {
+        Some(ref f) if watch_source => {
+            let load_addr = variant.or(rom_config.variant).unwrap_or(Variant::Chip8).load_addr();
+            match watch::assemble_file(Path::new(f), load_addr) {
+                Ok(program) => emu_thread::program_builder(&builder, &rom_config).build_from_buffer(program),
+                Err(e) => {
+                    println!("{}", e);
+                    process::exit(1);
+                },
+            }
+        },
+        Some(ref f) => emu_thread::program_builder(&builder, &rom_config).build_from_file(f),
+        None => builder.clone().build_from_buffer(demo::DEMO_PROGRAM.to_vec()),
+    };
+
+    let mut emulator = match loaded {
+        Err(e) => panic!("Failed to load user program. Error message: {}", e),
         Ok(v) => v
     };
 
+    let filename = filename.unwrap_or_else(|| demo::DEMO_NAME.to_owned());
+
+    if let Some(addr) = debug_port {
+        let addrs = DebugPort::new(addr).addrs();
+        if addrs.end > emulator.cpu.memory.len() {
+            panic!("The debug port takes up 0x{:03X} to 0x{:03X}, which doesn't fit in memory.", addr, addrs.end - 1);
+        }
+    }
+
+    // the command line wins over the program's config, as it does for everything else
+    let window = WindowOptions {
+        scale: scale.or(rom_config.scale).unwrap_or(display::DEFAULT_DISPLAY_SCALE),
+        borderless: borderless,
+        always_on_top: always_on_top,
+        fullscreen: fullscreen,
+        monitor: monitor.or(rom_config.monitor),
+        rotation: rotation.or(rom_config.rotation).unwrap_or_default(),
+        shader: shader,
+        grid: grid,
+    };
+
+    // find the sprites the program draws and save them, rather than running it
+    if let Some(path) = rip_sprites {
+        let sprites = analysis::find_sprites(&emulator.cpu.memory, emulator.cpu.config.variant.start_addr(), emulator.cpu.config.variant);
+
+        for sprite in sprites.iter() {
+            let drawn_at: Vec<String> = sprite.drawn_at.iter().map(|addr| format!("{:03X}", addr)).collect();
+            println!("{:03X}: {}x{:<2} drawn at {}", sprite.addr, sprite.width, sprite.height, drawn_at.join(", "));
+        }
+
+        match spritesheet::save_sprite_sheet(&emulator.cpu.memory, &sprites, Path::new(&path)) {
+            Ok(()) => println!("Saved {} sprites to {}", sprites.len(), path),
+            Err(e) => println!("Couldn't save the sprites. Error message: {}", e),
+        }
+        return;
+    }
+
+    // show which parts of the program are code, which are sprites and which are neither, rather than running it
+    if dead_code {
+        let cpu = &emulator.cpu;
+        let end = cpu.config.program_start_addr + cpu.program().len();
+        let regions = analysis::classify(&cpu.memory[..end], cpu.config.program_start_addr, cpu.config.variant.start_addr(), cpu.config.variant);
+
+        let mut totals = [0; 4];
+        for region in regions.iter() {
+            let (description, total) = match region.kind {
+                ByteUse::Code => ("code", &mut totals[0]),
+                ByteUse::SpriteData => ("sprite data only", &mut totals[1]),
+                ByteUse::CodeAndSprite => ("code, also drawn as a sprite", &mut totals[2]),
+                ByteUse::Unreached => ("unreached", &mut totals[3]),
+            };
+            *total += region.end - region.start;
+            println!("{:03X}-{:03X}  {:>4} bytes  {}", region.start, region.end - 1, region.end - region.start, description);
+        }
+
+        println!("{} bytes of code, {} of sprite data, {} of both and {} unreached.", totals[0], totals[1], totals[2], totals[3]);
+        if totals[3] > 0 {
+            println!("Unreached bytes may still be used through JP V0, or as data the program reads with LD Vx, [I].");
+        }
+        return;
+    }
+
+    // look for common bugs in the program, rather than running it
+    if check {
+        let lints = lint::check(&emulator.cpu.memory, emulator.cpu.config.program_start_addr, emulator.cpu.config.variant);
+
+        for l in lints.iter() {
+            println!("0x{:03X}: {}", l.addr, l.message);
+            println!("    {}", l.explanation);
+        }
+
+        if lints.is_empty() {
+            println!("No problems found.");
+        } else {
+            println!("{} problem(s) found.", lints.len());
+            process::exit(1);
+        }
+        return;
+    }
+
+    // count the program's instructions by family, and those it executes in a short run, rather than playing it
+    if stats {
+        let static_stats = OpcodeStats::of_program(emulator.cpu.program(), emulator.cpu.config.program_start_addr, emulator.cpu.config.variant);
+        let mut run_stats = OpcodeStats::new();
+        if let Some(frames) = stats_frames {
+            if let Err(e) = run_stats.record_run(&mut emulator, frames) {
+                println!("The run stopped early. Error message: {}", e);
+            }
+        }
+
+        let mut families: Vec<&str> = static_stats.families().chain(run_stats.families()).map(|(f, _)| f).collect();
+        families.sort_by_key(|f| (cmp::Reverse(static_stats.count(f)), cmp::Reverse(run_stats.count(f)), *f));
+        families.dedup();
+
+        println!("{:<20}  {:>8}  {:>10}", "Instruction", "In code", "Executed");
+        let executed = |n: usize| if stats_frames.is_some() { n.to_string() } else { "-".to_owned() };
+        for family in families {
+            println!("{:<20}  {:>8}  {:>10}", family, static_stats.count(family), executed(run_stats.count(family)));
+        }
+        println!("{:<20}  {:>8}  {:>10}", "Total", static_stats.total(), executed(run_stats.total()));
+        return;
+    }
+
+    // list the program's disassembly, rather than running it
+    if disasm {
+        let cpu = &emulator.cpu;
+        let style = ListingStyle { color: color.enabled(), bytes: show_bytes, ascii: show_ascii };
+
+        let (program, origin, variant) = (cpu.program(), cpu.config.program_start_addr, cpu.config.variant);
+        let comments = disassembler::xref_comments(program, origin, variant);
+
+        for line in disassembler::disassemble(program, origin, variant) {
+            println!("{}", disassembler::format_line(&line, style, comments.get(&line.addr).map(String::as_str)));
+        }
+        return;
+    }
+
+    // print a listing of the program to diff against other versions of it, rather than running it
+    if listing {
+        let cpu = &emulator.cpu;
+        print!("{}", disassembler::listing(&disassembler::disassemble(cpu.program(), cpu.config.program_start_addr, cpu.config.variant)));
+        return;
+    }
+
+    // check the disassembler and assembler agree about the program, rather than running it
+    if roundtrip {
+        let cpu = &emulator.cpu;
+        let mismatches = disassembler::roundtrip(cpu.program(), cpu.config.program_start_addr, cpu.config.variant);
+
+        for m in mismatches.iter() {
+            let bytes: Vec<String> = m.line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            println!("0x{:03X}: {:<11}  {:<24}  {}", m.line.addr, bytes.join(" "), m.line.text, m.problem);
+        }
+
+        if mismatches.is_empty() {
+            println!("All {} bytes of the program came back the same after disassembling and assembling it again.", cpu.program().len());
+        } else {
+            println!("{} line(s) of the disassembly didn't assemble back to the same bytes.", mismatches.len());
+            process::exit(1);
+        }
+        return;
+    }
+
+    // find which quirks make a difference to the program, rather than playing it
+    if let Some(frames) = quirk_check_frames {
+        let program = emulator.cpu.program().to_vec();
+        let build = |q: Quirks| match emu_thread::program_builder(&builder, &rom_config).quirks(q).build_from_buffer(program.clone()) {
+            Err(e) => panic!("Failed to load user program. Error message: {}", e),
+            Ok(v) => v
+        };
+
+        println!("Running {} frames with no input under {:?}, and again with each quirk changed.", frames, quirks);
+        for dependence in quirkcheck::analyze(build, quirks, frames) {
+            match dependence.divergence {
+                None => println!("{:<24}  no difference", dependence.change),
+                Some(d) => {
+                    let effect = match (d.error, d.control_flow) {
+                        (Some(e), _) => format!("one run stopped: {}", e),
+                        (None, true) => "this changes which instructions run next".to_owned(),
+                        (None, false) => "this changes values, but not which instructions run".to_owned(),
+                    };
+                    println!("{:<24}  frame {}, the result of {} at 0x{:03X} differs, and {}", dependence.change, d.frame, d.disasm, d.addr, effect);
+                },
+            }
+        }
+        println!("Run with --compare <change> to see the difference side by side.");
+        return;
+    }
+
+    // run the program again with other quirks alongside this one
+    if let Some(changes) = compare_changes {
+        let other_quirks = match quirks.with_changes(&changes) {
+            Some(q) => q,
+            None => panic!("Unknown quirk change. Expected a comma-separated list of: protect-memory, no-protect-memory, sprite-overrun=clamp|wrap, i-overflow=clear|never|set-vf, sys=ignore|warn|fault, key-order=lowest|highest|first|last, misaligned-fetch=ignore|warn|fault"),
+        };
+
+        let other = match emu_thread::program_builder(&builder, &rom_config).quirks(other_quirks).build_from_buffer(emulator.cpu.program().to_vec()) {
+            Err(e) => panic!("Failed to load user program. Error message: {}", e),
+            Ok(v) => v
+        };
+
+        println!("Comparing {:?} on the left with {:?} on the right.", quirks, other_quirks);
+
+        let sdl_context = sdl2::init().unwrap();
+        compare::run(&sdl_context, emulator, other, window);
+        return;
+    }
+
+    let program_checksum = savestate::program_checksum(emulator.cpu.program());
+
+    // run the program several times over, with the movie's input if there is one,
+    // checking every run goes exactly the same
+    if let Some(runs) = determinism_runs {
+        let movie = play_movie.map(|path| match Movie::load_file(Path::new(&path)) {
+            Err(e) => panic!("Couldn't load the movie. Error message: {}", e),
+            Ok(m) => m,
+        });
+
+        let mut run_builder = emu_thread::program_builder(&builder, &rom_config);
+        let (input, frames) = match movie {
+            Some(ref m) => {
+                if m.header.program_checksum != program_checksum {
+                    panic!("The movie was recorded with a different program.");
+                }
+                run_builder = run_builder.variant(m.header.variant).quirks(m.header.quirks).seed(m.header.seed).speed(m.header.speed);
+                match m.header.recorded_font() {
+                    Ok(Some(f)) => run_builder = run_builder.font(f),
+                    Ok(None) => (),
+                    Err(e) => panic!("Couldn't load the movie's font. Error message: {}", e),
+                }
+                (m.frames.iter().map(|f| f.events.clone()).collect(), limits.max_frames.unwrap_or(m.frames.len() as u64))
+            },
+            None => (Vec::new(), limits.max_frames.unwrap_or(DETERMINISM_FRAMES)),
+        };
+
+        let program = emulator.cpu.program().to_vec();
+        let build = || match run_builder.clone().build_from_buffer(program.clone()) {
+            Err(e) => panic!("Failed to load user program. Error message: {}", e),
+            Ok(v) => v
+        };
+
+        match determinism::check(build, &input, frames, runs, jobs.unwrap_or_else(batch::default_jobs)) {
+            Ok(checksum) => println!("All {} runs of {} frames went the same, ending on state checksum {:08X}.", runs, frames, checksum),
+            Err(e) => {
+                println!("The runs didn't all go the same: {}", e);
+                process::exit(1);
+            },
+        }
+        return;
+    }
+
+    // play a movie back with no window, checking it still goes the way it was recorded
+    if let Some(path) = play_movie {
+        let movie = match Movie::load_file(Path::new(&path)) {
+            Err(e) => panic!("Couldn't load the movie. Error message: {}", e),
+            Ok(m) => m,
+        };
+
+        if movie.header.program_checksum != program_checksum {
+            panic!("The movie was recorded with a different program.");
+        }
+
+        let header = &movie.header;
+        let mut movie_builder = emu_thread::program_builder(&builder, &rom_config)
+            .variant(header.variant)
+            .quirks(header.quirks)
+            .seed(header.seed)
+            .speed(header.speed);
+        match header.recorded_font() {
+            Ok(Some(f)) => movie_builder = movie_builder.font(f),
+            Ok(None) => (),
+            Err(e) => panic!("Couldn't load the movie's font. Error message: {}", e),
+        }
+
+        let mut emulator = match movie_builder.build_from_buffer(emulator.cpu.program().to_vec()) {
+            Err(e) => panic!("Failed to load user program. Error message: {}", e),
+            Ok(v) => v
+        };
+
+        match replay::play(&movie, &mut emulator) {
+            Ok(frames) => println!("Played all {} frames of the movie as recorded.", frames),
+            Err(e) => {
+                println!("The movie didn't play back as recorded: {}", e);
+                process::exit(1);
+            },
+        }
+        return;
+    }
+
+    // a movie starts from power on, so there's no resuming while recording one
+    let autosave_path = savestate::autosave_path(&filename);
+    if autosave && record_movie.is_none() && autosave_path.exists() && ask("Resume from the last autosave? [Y/n] ") {
+        match savestate::load_state_file(&autosave_path, Some(emulator.cpu.config.variant), Some(program_checksum)) {
+            Ok(state) => emulator.restore(state),
+            Err(e) => warn!(target: "cpu", "Couldn't resume, starting over. Error message: {}", e),
+        }
+    }
+
+    // pick up any cheats kept next to the program
+    let cheats = emu_thread::load_cheats(&filename);
+
+    #[cfg(not(feature = "scripting"))]
+    {
+        if script_path.is_some() {
+            panic!("This build doesn't support scripts. Rebuild with --features scripting.");
+        }
+    }
+
+    #[cfg(not(feature = "shaders"))]
+    {
+        if window.shader.is_some() {
+            panic!("This build doesn't support shaders. Rebuild with --features shaders.");
+        }
+    }
+
+    #[cfg(not(feature = "remote"))]
+    {
+        if serve_addr.is_some() {
+            panic!("This build doesn't support remote viewers. Rebuild with --features remote.");
+        }
+    }
+
+    let symbols = match symbols_path {
+        Some(path) => match SymbolTable::load_file(Path::new(&path)) {
+            Err(e) => panic!("Failed to load the symbol file. Error message: {}", e),
+            Ok(s) => s,
+        },
+        None => SymbolTable::new(),
+    };
+
+    let line_map = match line_map_path {
+        Some(path) => match LineMap::load_file(Path::new(&path)) {
+            Err(e) => panic!("Failed to load the line map. Error message: {}", e),
+            Ok(m) => m,
+        },
+        None => LineMap::new(),
+    };
+
+    // breakpoints can name addresses and source lines, so they're set once the debugger knows them
+    let mut debugger = Debugger::new();
+    debugger.set_symbols(symbols.clone());
+    debugger.set_line_map(line_map);
+    for spec in break_at.iter() {
+        if let Err(e) = debugger.set_breakpoint(spec) {
+            panic!("Couldn't set the breakpoint \"{}\". {}", spec, e);
+        }
+    }
+
+    let web_debugger = debug_http_addr.map(|addr| {
+        match WebDebugger::bind(&addr) {
+            Err(e) => panic!("Couldn't start the web debugger. Error message: {}", e),
+            Ok(mut w) => {
+                match w.local_addr() {
+                    Ok(a) => info!(target: "remote", "Web debugger listening on http://{}/", a),
+                    Err(_) => info!(target: "remote", "Web debugger listening on http://{}/", addr),
+                }
+                w.set_symbols(symbols.clone());
+                w
+            },
+        }
+    });
+
+    if playlist.is_some() && (host_addr.is_some() || join_addr.is_some()) {
+        panic!("Netplay only works with a single program.");
+    }
+
+    if start_paused && (serve_addr.is_some() || headless) {
+        panic!("--paused only works when playing in the window, where F3 resumes.");
+    }
+
+    if !break_at.is_empty() && (serve_addr.is_some() || headless) {
+        panic!("--break-at only works when playing in the window, with the debugger on the terminal.");
+    }
+
+    if watch_source && (playlist.is_some() || host_addr.is_some() || join_addr.is_some() || serve_addr.is_some() || headless) {
+        panic!("--watch only works with a single program, played in the window.");
+    }
+
+    // link up with the other player, whose machine has to start out the same as ours
+    let netplay = if let Some(addr) = host_addr {
+        info!(target: "netplay", "Waiting for the other player on {}", addr);
+        match Netplay::host(&addr, &mut emulator, program_checksum, &cheats, seed) {
+            Err(e) => panic!("Couldn't start netplay. Error message: {}", e),
+            Ok(n) => Some(n),
+        }
+    } else if let Some(addr) = join_addr {
+        match Netplay::join(&addr, &mut emulator, program_checksum, &cheats) {
+            Err(e) => panic!("Couldn't join netplay. Error message: {}", e),
+            Ok(n) => Some(n),
+        }
+    } else {
+        None
+    };
+
+    if let Some(ref n) = netplay {
+        if let Ok(a) = n.peer_addr() {
+            info!(target: "netplay", "Playing with {}", a);
+        }
+    }
+
+    info!(target: "cpu", "Done loading user program. Beginning execution.");
+
+    let settings = emu_thread::Settings {
+        cheats: cheats,
+        break_requested: debug,
+        paused: start_paused,
+        web_debugger: web_debugger,
+        netplay: netplay,
+        watch: if watch_source {
+            Some(emu_thread::WatchSettings { watcher: SourceWatcher::new(Path::new(&filename)), builder: builder.clone() })
+        } else {
+            None
+        },
+        playlist: playlist.map(|p| emu_thread::PlaylistSettings {
+            playlist: p,
+            builder: builder,
+            interval: attract_seconds.map(Duration::from_secs),
+        }),
+        filename: filename.clone(),
+        program_checksum: program_checksum,
+        rom_config: rom_config,
+        beep_pitch: beep_pitch,
+        limits: limits,
+        movie: record_movie.as_ref().map(|_| MovieHeader {
+            program_checksum: program_checksum,
+            variant: emulator.cpu.config.variant,
+            quirks: emulator.cpu.config.quirks,
+            seed: seed.unwrap_or(0),
+            speed: emulator.speed(),
+            checksum_interval: movie::DEFAULT_CHECKSUM_INTERVAL,
+            font: Some(Font::in_memory(&emulator.cpu).data),
+        }),
+        tracer: trace_path.map(|path| match Tracer::create(Path::new(&path), !trace_off) {
+            Err(e) => panic!("Couldn't create the trace file. Error message: {}", e),
+            Ok(mut t) => {
+                t.set_symbols(symbols.clone());
+                t
+            },
+        }),
+        debugger: debugger,
+        #[cfg(feature = "scripting")]
+        script_path: script_path,
+    };
+
+    #[cfg(feature = "remote")]
+    let outcome = match serve_addr {
+        Some(addr) => run_remote(emulator, &addr, &filename, program_checksum, limits),
+        None if headless => run_headless(emulator, &filename, program_checksum, limits),
+        None => run_sdl(emulator, settings, min_beep, window, record_audio),
+    };
+    #[cfg(not(feature = "remote"))]
+    let outcome = if headless {
+        run_headless(emulator, &filename, program_checksum, limits)
+    } else {
+        run_sdl(emulator, settings, min_beep, window, record_audio)
+    };
+
+    if let Err(e) = outcome.result {
+        error!(target: "cpu", "Emulation stopped. {}", e);
+
+        if let Some(state) = e.state() {
+            print!("{}", state);
+        }
+
+        match crash::write_crash_report(&outcome.emulator.cpu, &e, &outcome.filename) {
+            Ok(path) => println!("Crash report written to {}", path.display()),
+            Err(e) => println!("Couldn't write crash report. Error message: {}", e),
+        }
+    }
+
+    // only save if the user quit; a finished, crashed or timed out program isn't worth resuming
+    if autosave && !outcome.halted && !outcome.watchdog_expired {
+        let autosave_path = savestate::autosave_path(&outcome.filename);
+        match savestate::save_state_file(&outcome.emulator.cpu, Some(outcome.program_checksum), &autosave_path) {
+            Ok(()) => println!("Autosaved to {}", autosave_path.display()),
+            Err(e) => println!("Couldn't autosave. Error message: {}", e),
+        }
+    }
+
+    if let (Some(path), Some(ref movie)) = (record_movie, outcome.movie) {
+        match movie.save_file(Path::new(&path)) {
+            Ok(()) => println!("Recorded {} frames to {}", movie.frames.len(), path),
+            Err(e) => println!("Couldn't save the movie. Error message: {}", e),
+        }
+    }
+
+    if outcome.watchdog_expired {
+        println!("Stopped after reaching the limit set by --max-instructions or --max-frames.");
+        process::exit(WATCHDOG_EXIT_CODE);
+    }
+
+    info!(target: "cpu", "Program execution complete.");
+}
+
+/// Runs the emulator in an SDL window until the program finishes or the user presses
+/// escape, sounding the buzzer for at least min_beep each time, and writing the
+/// sound to a WAV file at record_audio if given
+fn run_sdl(emulator: Emulator, settings: emu_thread::Settings, min_beep: Duration, window: WindowOptions, record_audio: Option<String>) -> Outcome {
     // initialize SDL
     let sdl_context = sdl2::init().unwrap();
-    let mut display = Display::new(&sdl_context);
+    let mut display = Display::new(&sdl_context, emulator.cpu.display_height, emulator.palette, window);
+    let mut audio = match Audio::new(&sdl_context, min_beep) {
+        Err(e) => {
+            warn!(target: "audio", "Couldn't open audio device, continuing without sound. Error message: {}", e);
+            None
+        },
+        Ok(a) => Some(a),
+    };
+
+    if let Some(ref path) = record_audio {
+        match audio {
+            Some(ref mut audio) => {
+                if let Err(e) = audio.start_recording(Path::new(path)) {
+                    error!(target: "audio", "Couldn't start recording the audio. Error message: {}", e);
+                }
+            },
+            None => warn!(target: "audio", "There's no audio device, so no audio will be recorded."),
+        }
+    }
 
-    // execute the program until the user presses escape
-    println!("Done loading user program. Beginning execution.");
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    'running: while cpu.fetch_and_execute(&mut display) {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => break 'running,
-                Event::KeyDown { keycode: Some(key), .. } => {
-                    match key {
-                        Keycode::Escape => break 'running,
-                        _ => cpu.keyboard.update_key(key, true),
+    // execute the program on its own thread, so nothing the window does can hold up emulation
+    let (handle, input, output) = emu_thread::spawn(emulator, settings);
+
+    // the memory map opens in a window of its own, so it never covers the game
+    let mut debug_window: Option<DebugWindow> = None;
+    // and so does the debugger GUI
+    #[cfg(feature = "debug-gui")]
+    let mut debug_gui: Option<DebugGui> = None;
+    let mut showing_hud = false;
+    let mut program_info = String::new();
+
+    'running: loop {
+        // only the newest frame is worth drawing if several arrived since we last looked
+        let mut frame = None;
+        let mut memory = None;
+        loop {
+            match output.try_recv() {
+                Ok(UiMessage::Frame(f)) => frame = Some(f),
+                Ok(UiMessage::Memory(m)) => memory = Some(m),
+                #[cfg(feature = "debug-gui")]
+                Ok(UiMessage::Debug(snapshot)) => {
+                    if let Some(ref mut gui) = debug_gui {
+                        gui.set_snapshot(snapshot);
+                    }
+                },
+                Ok(UiMessage::Timers(t)) => {
+                    if showing_hud {
+                        display.set_hud(Some(t));
+                    }
+                },
+                Ok(UiMessage::Notify(message)) => display.show_message(&message),
+                Ok(UiMessage::ProgramInfo(info)) => {
+                    info!(target: "cpu", "Now playing {}", info);
+                    display.set_program_title(&info);
+                    display.show_message(&info);
+                    program_info = info;
+                },
+                Ok(UiMessage::PlaySound(samples, sample_rate, looping)) => {
+                    if let Some(ref mut audio) = audio {
+                        audio.play_samples(samples, sample_rate, looping);
+                    }
+                },
+                Ok(UiMessage::StopSound) => {
+                    if let Some(ref mut audio) = audio {
+                        audio.stop();
+                    }
+                },
+                Ok(UiMessage::Beep(beeping)) => {
+                    if let Some(ref mut audio) = audio {
+                        if beeping {
+                            audio.start_beep();
+                        } else {
+                            audio.stop_beep();
+                        }
+                    }
+                },
+                Ok(UiMessage::BeepPitch(pitch)) => {
+                    if let Some(ref mut audio) = audio {
+                        audio.set_pitch(pitch);
+                    }
+                },
+                Ok(UiMessage::BeepPattern(pattern, sample_rate)) => {
+                    if let Some(ref mut audio) = audio {
+                        audio.set_beep_pattern(pattern, sample_rate);
                     }
                 },
-                Event::KeyUp { keycode: Some(key), .. } => cpu.keyboard.update_key(key, false),
-                _ => {}
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break 'running,
+            }
+        }
+
+        if let Some(ref f) = frame {
+            // the next program on a playlist may have a taller or shorter screen
+            display.resize(f.vram.len());
+            display.draw_frame(f);
+        }
+
+        display.expire_messages();
+
+        if let Some(ref mut audio) = audio {
+            audio.update();
+        }
+
+        if let (Some(w), Some(m)) = (debug_window.as_mut(), memory) {
+            w.draw(m);
+        }
+
+        #[cfg(feature = "debug-gui")]
+        {
+            if let Some(ref mut gui) = debug_gui {
+                for message in gui.update() {
+                    let _ = input.send(message);
+                }
+            }
+        }
+
+        // the emulator thread stops once it sees Quit, which ends this loop
+        for event in event_pump.poll_iter() {
+            #[cfg(feature = "debug-gui")]
+            {
+                let in_gui = debug_gui.as_ref().is_some_and(|g| g.owns(&event));
+                let toggle = match event {
+                    Event::KeyDown { keycode: Some(Keycode::D), keymod, .. } => keymod.intersects(keyboard::LCTRLMOD | keyboard::RCTRLMOD),
+                    _ => false,
+                };
+
+                // ctrl+D opens the debugger GUI from either window
+                if toggle && debug_gui.is_none() {
+                    match DebugGui::new(&sdl_context) {
+                        Ok(gui) => {
+                            debug_gui = Some(gui);
+                            display.show_message("Debugger opened");
+                            let _ = input.send(InputMessage::Debug(DebugCommand::Watch(true)));
+                        },
+                        Err(e) => {
+                            error!(target: "display", "Couldn't open the debugger. Error message: {}", e);
+                            display.show_message("Couldn't open the debugger, see the terminal");
+                        },
+                    }
+                    continue;
+                }
+
+                // and closes it again, as does closing its window
+                if toggle || (in_gui && !debug_gui.as_mut().is_some_and(|g| g.handle_event(&event))) {
+                    debug_gui = None;
+                    display.show_message("Debugger closed");
+                    let _ = input.send(InputMessage::Debug(DebugCommand::Watch(false)));
+                    continue;
+                }
+
+                // everything else that happens in the debugger's window is the debugger's
+                if in_gui {
+                    continue;
+                }
             }
+
+            let message = match event {
+                Event::Quit { .. } => InputMessage::Quit,
+                Event::Window { window_id, win_event_id: WindowEventId::Close, .. } => {
+                    if debug_window.as_ref().and_then(|w| w.window_id()) == Some(window_id) {
+                        debug_window = None;
+                        InputMessage::WatchMemory(false)
+                    } else {
+                        InputMessage::Quit
+                    }
+                },
+                Event::MouseButtonDown { window_id, mouse_btn, x, y, .. } => {
+                    if let Some(w) = debug_window.as_mut().filter(|w| w.window_id() == Some(window_id)) {
+                        w.click(mouse_btn, x, y);
+                    }
+                    continue;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                    debug_window = match debug_window {
+                        Some(_) => None,
+                        None => Some(DebugWindow::new(&sdl_context)),
+                    };
+                    display.show_message(if debug_window.is_some() { "Memory window opened" } else { "Memory window closed" });
+                    InputMessage::WatchMemory(debug_window.is_some())
+                },
+                Event::KeyDown { keycode: Some(Keycode::F10), .. } => {
+                    showing_hud = !showing_hud;
+                    if !showing_hud {
+                        display.set_hud(None);
+                    }
+                    InputMessage::WatchTimers(showing_hud)
+                },
+                Event::KeyDown { keycode: Some(Keycode::R), keymod, .. } if keymod.intersects(keyboard::LCTRLMOD | keyboard::RCTRLMOD) => {
+                    let rotation = display.rotate();
+                    display.show_message(&format!("Rotated {} degrees", rotation.degrees()));
+                    continue;
+                },
+                Event::KeyDown { keycode: Some(Keycode::G), keymod, .. } if keymod.intersects(keyboard::LCTRLMOD | keyboard::RCTRLMOD) => {
+                    let showing = display.toggle_grid();
+                    display.show_message(if showing { "Pixel grid on" } else { "Pixel grid off" });
+                    continue;
+                },
+                Event::KeyDown { keycode: Some(Keycode::T), keymod, .. } if keymod.intersects(keyboard::LCTRLMOD | keyboard::RCTRLMOD) => InputMessage::ToggleTrace,
+                Event::KeyDown { keycode: Some(Keycode::F1), .. } => {
+                    display.show_message(&program_info);
+                    continue;
+                },
+                Event::KeyDown { keycode: Some(key), timestamp, .. } => match key {
+                    Keycode::Escape => InputMessage::Quit,
+                    Keycode::F12 => InputMessage::Break,
+                    Keycode::F2 => InputMessage::ToggleCheats,
+                    Keycode::F5 => InputMessage::SaveState,
+                    Keycode::F9 => InputMessage::LoadState,
+                    Keycode::F6 => InputMessage::NextProgram,
+                    Keycode::F3 => InputMessage::TogglePause,
+                    Keycode::F4 => InputMessage::Rewind(true),
+                    Keycode::F7 => InputMessage::SlowDown,
+                    Keycode::F8 => InputMessage::SpeedUp,
+                    _ => InputMessage::Key(key, true, timestamp),
+                },
+                Event::KeyUp { keycode: Some(Keycode::F4), .. } => InputMessage::Rewind(false),
+                Event::KeyUp { keycode: Some(key), timestamp, .. } => InputMessage::Key(key, false, timestamp),
+                _ => continue,
+            };
+
+            let _ = input.send(message);
         }
+
+        thread::sleep(UI_POLL_INTERVAL);
+    }
+
+    if let (Some(audio), Some(path)) = (audio.as_mut(), record_audio) {
+        match audio.finish_recording() {
+            Ok(Some(seconds)) => println!("Recorded {:.1} seconds of audio to {}", seconds, path),
+            Ok(None) => (),
+            Err(e) => println!("Couldn't finish the audio recording. Error message: {}", e),
+        }
+    }
+
+    match handle.join() {
+        Err(_) => panic!("The emulator thread panicked."),
+        Ok(o) => o,
+    }
+}
+
+/// Runs the emulator headless, showing the screen to whoever connects over WebSocket
+#[cfg(feature = "remote")]
+fn run_remote(mut emulator: Emulator, addr: &str, filename: &str, program_checksum: u32, limits: RunLimits) -> Outcome {
+    let server = match RemoteServer::bind(addr) {
+        Err(e) => panic!("Couldn't listen for remote viewers. Error message: {}", e),
+        Ok(s) => s,
+    };
+
+    match server.local_addr() {
+        Ok(a) => info!(target: "remote", "Waiting for remote viewers on ws://{}", a),
+        Err(_) => info!(target: "remote", "Waiting for remote viewers on ws://{}", addr),
+    }
+
+    let mut frontend = Watchdog::new(RemoteFrontend::new(server), limits);
+    let result = emulator.run(&mut frontend);
+
+    Outcome {
+        emulator: emulator,
+        result: result,
+        halted: frontend.inner.halted,
+        filename: filename.to_owned(),
+        program_checksum: program_checksum,
+        watchdog_expired: frontend.expired,
+        movie: None,
+    }
+}
+
+/// Runs the emulator with no window, sound or input, for automated runs
+fn run_headless(mut emulator: Emulator, filename: &str, program_checksum: u32, limits: RunLimits) -> Outcome {
+    let mut frontend = Watchdog::new(NullObserver, limits);
+    let result = emulator.run(&mut frontend);
+
+    Outcome {
+        emulator: emulator,
+        result: result,
+        // with no way to quit, only the watchdog stops a run before the program does
+        halted: !frontend.expired,
+        filename: filename.to_owned(),
+        program_checksum: program_checksum,
+        watchdog_expired: frontend.expired,
+        movie: None,
     }
+}
+
+/// Lists the programs in the CHIP-8 Archive on the terminal, and downloads the one
+/// the user picks along with its recommended settings. Returns where it was saved.
+#[cfg(feature = "archive")]
+fn pick_from_archive() -> String {
+    println!("Fetching the list of programs from the CHIP-8 Archive...");
+    let programs = match archive::fetch_index() {
+        Err(e) => panic!("Couldn't browse the archive. Error message: {}", e),
+        Ok(p) => p,
+    };
+
+    for (n, program) in programs.iter().enumerate() {
+        println!("{:4}. {}", n + 1, program);
+    }
+
+    let program = loop {
+        print!("Pick a program by number: ");
+        let _ = io::stdout().flush();
+
+        let mut answer = String::new();
+        match io::stdin().lock().read_line(&mut answer) {
+            Ok(0) | Err(_) => panic!("No program picked."),
+            Ok(_) => (),
+        }
+
+        match answer.trim().parse::<usize>() {
+            Ok(n) if (1..=programs.len()).contains(&n) => break &programs[n - 1],
+            _ => println!("Expected a number from 1 to {}.", programs.len()),
+        }
+    };
 
-    println!("Program execution complete.");
+    if program.variant().is_none() {
+        println!("{} was written for {}, which isn't emulated here, so it may not run properly.", program.title, program.platform);
+    }
+
+    match program.download(&archive::cache_dir()) {
+        Err(e) => panic!("Couldn't download {}. Error message: {}", program.title, e),
+        Ok(path) => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// Asks the user a yes/no question on the terminal, defaulting to yes
+fn ask(question: &str) -> bool {
+    print!("{}", question);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(_) => !answer.trim().to_lowercase().starts_with('n'),
+        Err(_) => false,
+    }
 }
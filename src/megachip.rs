@@ -0,0 +1,251 @@
+//
+// Author: Joshua Holmes
+//
+
+use serialization;
+
+/// How many bytes of system memory a Mega-Chip machine has (addressable through LDHI)
+pub const MEGACHIP_MEMORY_LENGTH: usize = 0x1000000;
+/// The width of the Mega-Chip screen in pixels
+pub const MEGA_DISPLAY_WIDTH: usize = 256;
+/// The height of the Mega-Chip screen in pixels
+pub const MEGA_DISPLAY_HEIGHT: usize = 192;
+/// The number of entries in the Mega-Chip color palette
+pub const PALETTE_LENGTH: usize = 256;
+/// How many bytes of header precede the sample data of a digitized sound
+pub const DIGITIZED_SOUND_HEADER_LENGTH: usize = 6;
+
+/// How sprite pixels are combined with the pixels already on screen
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    Normal,
+    Percent25,
+    Percent50,
+    Percent75,
+    Additive,
+    Multiply,
+}
+
+impl BlendMode {
+    /// Returns the blend mode selected by the BMODE opcode's argument
+    pub fn from_u8(mode: u8) -> BlendMode {
+        match mode {
+            1 => BlendMode::Percent25,
+            2 => BlendMode::Percent50,
+            3 => BlendMode::Percent75,
+            4 => BlendMode::Additive,
+            5 => BlendMode::Multiply,
+            _ => BlendMode::Normal,
+        }
+    }
+}
+
+/// A digitized sound started by the DIGISND opcode
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigitizedSound {
+    /// the playback rate of the sample data in Hz
+    pub sample_rate: u32,
+    /// the address in memory where the unsigned 8-bit sample data begins
+    pub start: usize,
+    /// the number of bytes of sample data
+    pub length: usize,
+    /// whether the sound starts over once it reaches the end
+    pub looping: bool,
+}
+
+/// The extra machine state introduced by the Mega-Chip extensions
+#[derive(Serialize, Deserialize)]
+pub struct MegaChip {
+    /// whether Mega-Chip mode has been switched on with MEGAON
+    pub enabled: bool,
+    /// the color palette, stored as ARGB
+    #[serde(with = "serialization::palette")]
+    pub palette: [u32; PALETTE_LENGTH],
+    /// the width of sprites drawn by DRW, set by SPRW
+    pub sprite_width: usize,
+    /// the height of sprites drawn by DRW, set by SPRH
+    pub sprite_height: usize,
+    /// the opacity applied to sprites, set by ALPHA
+    pub alpha: u8,
+    /// the blend mode applied to sprites, set by BMODE
+    pub blend_mode: BlendMode,
+    /// the palette index that DRW reports collisions against, set by CCOL
+    pub collision_color: u8,
+    /// the palette index of every pixel on screen, used for collision detection
+    pub index_buffer: Vec<u8>,
+    /// the RGB color of every pixel in the frame being built, after blending
+    pub frame_buffer: Vec<u32>,
+    /// the RGB color of every pixel in the last finished frame, which is what gets displayed
+    pub screen_buffer: Vec<u32>,
+    /// the digitized sound that should currently be playing, if any
+    pub sound: Option<DigitizedSound>,
+    /// the flag that says whether the sound has been started or stopped
+    pub sound_flag: bool,
+}
+
+impl Default for MegaChip {
+    fn default() -> MegaChip {
+        MegaChip::new()
+    }
+}
+
+impl MegaChip {
+    /// Construct the Mega-Chip state as it is at power on
+    pub fn new() -> MegaChip {
+        MegaChip {
+            enabled: false,
+            palette: [0u32; PALETTE_LENGTH],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend_mode: BlendMode::Normal,
+            collision_color: 0,
+            index_buffer: vec![0u8; MEGA_DISPLAY_WIDTH * MEGA_DISPLAY_HEIGHT],
+            frame_buffer: vec![0u32; MEGA_DISPLAY_WIDTH * MEGA_DISPLAY_HEIGHT],
+            screen_buffer: vec![0u32; MEGA_DISPLAY_WIDTH * MEGA_DISPLAY_HEIGHT],
+            sound: None,
+            sound_flag: false,
+        }
+    }
+
+    /// Clears the screen back to the transparent color
+    pub fn clear(&mut self) {
+        for x in self.index_buffer.iter_mut() {
+            *x = 0;
+        }
+
+        for x in self.frame_buffer.iter_mut() {
+            *x = 0;
+        }
+    }
+
+    /// Makes the frame built up so far the one on display and starts a fresh frame
+    pub fn present(&mut self) {
+        self.screen_buffer.copy_from_slice(&self.frame_buffer);
+        self.clear();
+    }
+
+    /// Loads `count` ARGB colors from memory into the palette, starting at index 1
+    /// since index 0 is always transparent
+    pub fn load_palette(&mut self, memory: &[u8], addr: usize, count: usize) {
+        for i in 0..count {
+            let offset = addr + i * 4;
+
+            if i + 1 >= PALETTE_LENGTH || offset + 3 >= memory.len() {
+                break;
+            }
+
+            self.palette[i + 1] = ((memory[offset] as u32) << 24) | ((memory[offset + 1] as u32) << 16)
+                | ((memory[offset + 2] as u32) << 8) | (memory[offset + 3] as u32);
+        }
+    }
+
+    /// Blits a sprite of palette indices from memory onto the screen at (x, y).
+    /// Index 0 is transparent. Returns true if any pixel drawn over was the collision color.
+    pub fn draw_sprite(&mut self, memory: &[u8], addr: usize, x: usize, y: usize) -> bool {
+        let mut collision = false;
+
+        for j in 0..self.sprite_height {
+            let screen_y = y + j;
+            if screen_y >= MEGA_DISPLAY_HEIGHT {
+                break;
+            }
+
+            for i in 0..self.sprite_width {
+                let screen_x = x + i;
+                if screen_x >= MEGA_DISPLAY_WIDTH {
+                    break;
+                }
+
+                let index = match memory.get(addr + j * self.sprite_width + i) {
+                    Some(index) => *index,
+                    None => 0,
+                };
+
+                if index == 0 {
+                    continue;
+                }
+
+                let offset = screen_y * MEGA_DISPLAY_WIDTH + screen_x;
+
+                if self.index_buffer[offset] == self.collision_color {
+                    collision = true;
+                }
+
+                self.index_buffer[offset] = index;
+                self.frame_buffer[offset] = self.blend(self.palette[index as usize], self.frame_buffer[offset]);
+            }
+        }
+
+        collision
+    }
+
+    /// Scrolls the screen by the given number of pixels, filling the gap with the transparent color
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let old_index = self.index_buffer.clone();
+        let old_frame = self.frame_buffer.clone();
+
+        for y in 0..MEGA_DISPLAY_HEIGHT {
+            for x in 0..MEGA_DISPLAY_WIDTH {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let offset = y * MEGA_DISPLAY_WIDTH + x;
+
+                if src_x < 0 || src_y < 0 || src_x >= MEGA_DISPLAY_WIDTH as isize || src_y >= MEGA_DISPLAY_HEIGHT as isize {
+                    self.index_buffer[offset] = 0;
+                    self.frame_buffer[offset] = 0;
+                } else {
+                    let src_offset = src_y as usize * MEGA_DISPLAY_WIDTH + src_x as usize;
+                    self.index_buffer[offset] = old_index[src_offset];
+                    self.frame_buffer[offset] = old_frame[src_offset];
+                }
+            }
+        }
+    }
+
+    /// Reads the header of a digitized sound stored at the given address
+    pub fn read_sound(memory: &[u8], addr: usize, looping: bool) -> Option<DigitizedSound> {
+        if addr + DIGITIZED_SOUND_HEADER_LENGTH > memory.len() {
+            return None;
+        }
+
+        let sample_rate = ((memory[addr] as u32) << 8) | (memory[addr + 1] as u32);
+        let length = ((memory[addr + 2] as usize) << 16) | ((memory[addr + 3] as usize) << 8) | (memory[addr + 4] as usize);
+        let start = addr + DIGITIZED_SOUND_HEADER_LENGTH;
+
+        Some(DigitizedSound {
+            sample_rate: sample_rate,
+            start: start,
+            length: if start + length > memory.len() { memory.len() - start } else { length },
+            looping: looping,
+        })
+    }
+
+    /// Combines a sprite color with the color already on screen using the current blend mode and alpha
+    fn blend(&self, src: u32, dst: u32) -> u32 {
+        let mut result = 0u32;
+
+        for shift in [0u32, 8, 16].iter() {
+            let s = (src >> *shift) & 0xFF;
+            let d = (dst >> *shift) & 0xFF;
+
+            let channel = match self.blend_mode {
+                BlendMode::Normal => mix(s, d, 255),
+                BlendMode::Percent25 => mix(s, d, 64),
+                BlendMode::Percent50 => mix(s, d, 128),
+                BlendMode::Percent75 => mix(s, d, 192),
+                BlendMode::Additive => if s + d > 255 { 255 } else { s + d },
+                BlendMode::Multiply => (s * d) / 255,
+            };
+
+            result |= mix(channel, d, self.alpha as u32) << *shift;
+        }
+
+        result
+    }
+}
+
+/// Linearly interpolates between two color channels given an opacity out of 255
+fn mix(src: u32, dst: u32, opacity: u32) -> u32 {
+    (src * opacity + dst * (255 - opacity)) / 255
+}
@@ -0,0 +1,548 @@
+//
+// Author: Joshua Holmes
+//
+
+use bincode;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use font::{Font, FontError};
+use input::KeyEvent;
+use machine::Quirks;
+use variant::Variant;
+
+/// The bytes every movie file starts with
+pub const MOVIE_MAGIC: [u8; 4] = *b"C8MV";
+/// The version of the movie format written by this build. Bump this whenever the
+/// header changes shape, including when a quirk is added, and teach MovieReader to
+/// read the old one.
+/// Also bump it when Cpu::checksum changes what it hashes.
+pub const MOVIE_VERSION: u16 = 4;
+/// How many frames apart recordings check the machine state by default, once a second
+pub const DEFAULT_CHECKSUM_INTERVAL: u32 = 60;
+
+/// Everything needed to play a movie back exactly as it was recorded, bar the
+/// program itself, which is only identified by its checksum.
+///
+/// A movie file is laid out as:
+///
+/// ```text
+/// "C8MV"                  magic
+/// u16, big-endian         format version
+/// MovieHeader             bincode
+/// u16, big-endian         how many key events the first frame started with
+/// u32, big-endian         the first event's timestamp
+/// u8                      its key, with the top bit set if it was pressed
+/// ...                     and so on, for every event and then every frame, to the end of the file
+/// ```
+///
+/// Every checksum_interval frames, the frame's events are followed by a big-endian
+/// u32: Cpu::checksum as the frame ended, so playback can tell exactly where it
+/// stopped matching the recording.
+///
+/// Versions 1 and 2 held a big-endian u16 per frame instead of its events: the
+/// keypad during the frame, with bit n for key n. Checksums in Mega-Chip and
+/// XO-CHIP movies from before version 4 aren't checked, since they were taken
+/// without those machines' own state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MovieHeader {
+    /// the checksum of the program the movie was recorded with
+    pub program_checksum: u32,
+    /// the kind of machine the movie was recorded on
+    pub variant: Variant,
+    /// the behavior switches in effect while recording
+    pub quirks: Quirks,
+    /// the seed the random number generator started from
+    pub seed: u64,
+    /// how many instructions ran each second, which sets how many run each frame
+    pub speed: u32,
+    /// how many frames apart the machine state is checked, or 0 for never
+    pub checksum_interval: u32,
+    /// the font in memory as the recording started, or None for movies from before
+    /// fonts were recorded, which play back with whatever font is set up
+    pub font: Option<Vec<u8>>,
+}
+
+impl MovieHeader {
+    /// Whether the frame with the given index, counting from 0, ends with a checksum
+    pub fn checksum_due(&self, frame: u64) -> bool {
+        self.checksum_interval != 0 && (frame + 1).is_multiple_of(self.checksum_interval as u64)
+    }
+
+    /// The font the movie was recorded with, if it says
+    pub fn recorded_font(&self) -> Result<Option<Font>, FontError> {
+        match self.font {
+            Some(ref data) => Ok(Some(Font::from_bytes(data.clone())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The header written by version 1, before movies held checksums
+#[derive(Deserialize)]
+struct MovieHeaderV1 {
+    program_checksum: u32,
+    variant: Variant,
+    quirks: Quirks,
+    seed: u64,
+    speed: u32,
+}
+
+impl From<MovieHeaderV1> for MovieHeader {
+    fn from(header: MovieHeaderV1) -> Self {
+        MovieHeader {
+            program_checksum: header.program_checksum,
+            variant: header.variant,
+            quirks: header.quirks,
+            seed: header.seed,
+            speed: header.speed,
+            checksum_interval: 0,
+            font: None,
+        }
+    }
+}
+
+/// The header written by version 2, before movies held the font
+#[derive(Deserialize)]
+struct MovieHeaderV2 {
+    program_checksum: u32,
+    variant: Variant,
+    quirks: Quirks,
+    seed: u64,
+    speed: u32,
+    checksum_interval: u32,
+}
+
+impl From<MovieHeaderV2> for MovieHeader {
+    fn from(header: MovieHeaderV2) -> Self {
+        MovieHeader {
+            program_checksum: header.program_checksum,
+            variant: header.variant,
+            quirks: header.quirks,
+            seed: header.seed,
+            speed: header.speed,
+            checksum_interval: header.checksum_interval,
+            font: None,
+        }
+    }
+}
+
+/// One frame of a movie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovieFrame {
+    /// the key events applied as the frame started, oldest first
+    pub events: Vec<KeyEvent>,
+    /// Cpu::checksum as the frame ended, if the frame is one that's checked
+    pub checksum: Option<u32>,
+}
+
+/// Reasons a movie couldn't be read or written
+#[derive(Debug)]
+pub enum MovieError {
+    IoError(io::Error),
+    /// the file doesn't start with the movie magic bytes
+    NotAMovie,
+    /// the file was written by a newer build, or an older format we can no longer read
+    UnsupportedVersion(u16),
+    /// the header couldn't be decoded, or the file ends partway through a frame
+    Corrupt(String),
+}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> Self {
+        MovieError::IoError(err)
+    }
+}
+
+impl From<bincode::Error> for MovieError {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(e) => MovieError::IoError(e),
+            e => MovieError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            MovieError::NotAMovie => write!(f, "not a movie file"),
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {} (this build reads version {})",
+                v, MOVIE_VERSION),
+            MovieError::Corrupt(ref msg) => write!(f, "movie is corrupt: {}", msg),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+/// Writes a movie a frame at a time, so a recording can go straight to disk
+pub struct MovieWriter<W: Write> {
+    writer: W,
+    header: MovieHeader,
+    /// how many frames have been written so far
+    frames: u64,
+}
+
+impl<W: Write> MovieWriter<W> {
+    /// Starts a movie by writing the magic bytes, format version and header
+    pub fn new(mut writer: W, header: &MovieHeader) -> Result<MovieWriter<W>, MovieError> {
+        writer.write_all(&MOVIE_MAGIC)?;
+        writer.write_all(&MOVIE_VERSION.to_be_bytes())?;
+        bincode::serialize_into(&mut writer, header)?;
+
+        Ok(MovieWriter {
+            writer: writer,
+            header: header.clone(),
+            frames: 0,
+        })
+    }
+
+    /// Adds a frame. Frames that are checked have to come with a checksum, and any
+    /// other frame's checksum is left out.
+    pub fn write_frame(&mut self, frame: &MovieFrame) -> Result<(), MovieError> {
+        if frame.events.len() > u16::MAX as usize {
+            return Err(MovieError::Corrupt(format!("frame {} has too many key events", self.frames + 1)));
+        }
+        self.writer.write_all(&(frame.events.len() as u16).to_be_bytes())?;
+        for event in frame.events.iter() {
+            self.writer.write_all(&event.timestamp.to_be_bytes())?;
+            self.writer.write_all(&[(event.key & 0xF) | if event.pressed { 0x80 } else { 0 }])?;
+        }
+
+        if self.header.checksum_due(self.frames) {
+            match frame.checksum {
+                Some(checksum) => self.writer.write_all(&checksum.to_be_bytes())?,
+                None => return Err(MovieError::Corrupt(format!("frame {} is missing its checksum", self.frames + 1))),
+            }
+        }
+
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    /// Flushes the movie and hands back the writer
+    pub fn finish(mut self) -> Result<W, MovieError> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Reads a movie a frame at a time
+pub struct MovieReader<R: Read> {
+    reader: R,
+    header: MovieHeader,
+    /// the format version being read
+    version: u16,
+    /// how many frames have been read so far
+    frames: u64,
+    /// the keypad during the last frame read, for turning the keypads of versions
+    /// 1 and 2 into key events
+    keys: u16,
+}
+
+impl<R: Read> MovieReader<R> {
+    /// Opens a movie by reading and checking everything before the first frame
+    pub fn new(mut reader: R) -> Result<MovieReader<R>, MovieError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MOVIE_MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+
+        let mut version = [0u8; 2];
+        reader.read_exact(&mut version)?;
+
+        // older versions get migrated here as the format changes
+        let version = u16::from_be_bytes(version);
+        let header = match version {
+            1 => bincode::deserialize_from::<_, MovieHeaderV1>(&mut reader)?.into(),
+            2 => bincode::deserialize_from::<_, MovieHeaderV2>(&mut reader)?.into(),
+            3 | MOVIE_VERSION => bincode::deserialize_from(&mut reader)?,
+            v => return Err(MovieError::UnsupportedVersion(v)),
+        };
+
+        Ok(MovieReader {
+            reader: reader,
+            header: header,
+            version: version,
+            frames: 0,
+            keys: 0,
+        })
+    }
+
+    /// What the movie was recorded with
+    pub fn header(&self) -> &MovieHeader {
+        &self.header
+    }
+
+    /// Reads the next frame, or None once the movie is over
+    pub fn read_frame(&mut self) -> Result<Option<MovieFrame>, MovieError> {
+        let mut buf = [0u8; 2];
+        if !self.read_or_end(&mut buf)? {
+            return Ok(None);
+        }
+
+        let events = if self.version < 3 {
+            // the keys that changed since the last frame, as if they changed as it ended
+            let keys = u16::from_be_bytes(buf);
+            let timestamp = (self.frames * 1000 / 60) as u32;
+            let events = (0..16u8).filter(|&k| (keys ^ self.keys) & (1 << k) != 0)
+                .map(|k| KeyEvent { timestamp: timestamp, key: k, pressed: keys & (1 << k) != 0 })
+                .collect();
+            self.keys = keys;
+            events
+        } else {
+            let mut events = Vec::new();
+            for _ in 0..u16::from_be_bytes(buf) {
+                let mut event = [0u8; 5];
+                self.read_rest(&mut event)?;
+                events.push(KeyEvent {
+                    timestamp: u32::from_be_bytes([event[0], event[1], event[2], event[3]]),
+                    key: event[4] & 0xF,
+                    pressed: event[4] & 0x80 != 0,
+                });
+            }
+            events
+        };
+
+        let checksum = if self.header.checksum_due(self.frames) {
+            let mut checksum = [0u8; 4];
+            self.read_rest(&mut checksum)?;
+            // before version 4 the checksum left out the Mega-Chip and XO-CHIP state
+            let stale = self.version < 4
+                && (self.header.variant == Variant::MegaChip || self.header.variant == Variant::XoChip);
+            if stale { None } else { Some(u32::from_be_bytes(checksum)) }
+        } else {
+            None
+        };
+
+        self.frames += 1;
+
+        Ok(Some(MovieFrame {
+            events: events,
+            checksum: checksum,
+        }))
+    }
+
+    /// Fills the buffer from the start of a frame. Returns false if the movie ended instead.
+    fn read_or_end(&mut self, buf: &mut [u8]) -> Result<bool, MovieError> {
+        let mut read = 0;
+
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(MovieError::IoError(e)),
+            }
+        }
+
+        if read == 0 {
+            Ok(false)
+        } else if read < buf.len() {
+            Err(MovieError::Corrupt("the last frame is cut short".to_owned()))
+        } else {
+            Ok(true)
+        }
+    }
+
+    /// Fills the buffer from the rest of a frame
+    fn read_rest(&mut self, buf: &mut [u8]) -> Result<(), MovieError> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(MovieError::Corrupt("the last frame is cut short".to_owned())),
+            Err(e) => Err(MovieError::IoError(e)),
+        }
+    }
+}
+
+/// A whole movie, held in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub header: MovieHeader,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Construct a movie with no frames yet
+    pub fn new(header: MovieHeader) -> Movie {
+        Movie {
+            header: header,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Reads a whole movie
+    pub fn read<R: Read>(reader: R) -> Result<Movie, MovieError> {
+        let mut reader = MovieReader::new(reader)?;
+        let mut movie = Movie::new(reader.header().clone());
+
+        while let Some(frame) = reader.read_frame()? {
+            movie.frames.push(frame);
+        }
+
+        Ok(movie)
+    }
+
+    /// Writes the whole movie
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), MovieError> {
+        let mut writer = MovieWriter::new(writer, &self.header)?;
+        for frame in self.frames.iter() {
+            writer.write_frame(frame)?;
+        }
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Loads a movie from the given file
+    pub fn load_file(path: &Path) -> Result<Movie, MovieError> {
+        Movie::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Saves the movie to the given file
+    pub fn save_file(&self, path: &Path) -> Result<(), MovieError> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use font::SMALL_FONT_LENGTH;
+
+    fn header(checksum_interval: u32) -> MovieHeader {
+        MovieHeader {
+            program_checksum: 0x1234_5678,
+            variant: Variant::Chip8,
+            quirks: Quirks::default(),
+            seed: 42,
+            speed: 700,
+            checksum_interval: checksum_interval,
+            font: Some(vec![0xF0; SMALL_FONT_LENGTH]),
+        }
+    }
+
+    fn movie(checksum_interval: u32, frames: u32) -> Movie {
+        let mut movie = Movie::new(header(checksum_interval));
+        for frame in 0..frames {
+            // a tap, then a press and release within the same frame
+            let events = (0..frame % 3).map(|n| KeyEvent { timestamp: frame * 17 + n, key: (frame % 16) as u8, pressed: n == 0 }).collect();
+            movie.frames.push(MovieFrame {
+                events: events,
+                checksum: if movie.header.checksum_due(frame as u64) { Some(frame * 1000) } else { None },
+            });
+        }
+        movie
+    }
+
+    #[test]
+    fn movies_round_trip() {
+        for &interval in [0, 1, 3].iter() {
+            let movie = movie(interval, 10);
+            let mut data = Vec::new();
+            movie.write(&mut data).unwrap();
+
+            assert_eq!(Movie::read(data.as_slice()).unwrap(), movie);
+        }
+    }
+
+    #[test]
+    fn an_empty_movie_round_trips() {
+        let movie = movie(3, 0);
+        let mut data = Vec::new();
+        movie.write(&mut data).unwrap();
+
+        assert_eq!(Movie::read(data.as_slice()).unwrap(), movie);
+    }
+
+    #[test]
+    fn checked_frames_need_a_checksum() {
+        let mut movie = movie(2, 4);
+        movie.frames[1].checksum = None;
+
+        match movie.write(Vec::new()) {
+            Err(MovieError::Corrupt(_)) => (),
+            r => panic!("expected a missing checksum, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn a_cut_short_frame_is_corrupt() {
+        let mut data = Vec::new();
+        movie(2, 4).write(&mut data).unwrap();
+        data.pop();
+
+        match Movie::read(data.as_slice()) {
+            Err(MovieError::Corrupt(_)) => (),
+            r => panic!("expected a cut short frame, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn version_1_movies_have_no_checksums() {
+        let header = MovieHeader { font: None, ..header(0) };
+        let mut data = MOVIE_MAGIC.to_vec();
+        data.extend(&1u16.to_be_bytes());
+        data.extend(bincode::serialize(&(header.program_checksum, header.variant, header.quirks, header.seed, header.speed)).unwrap());
+        data.extend(&[0x00, 0x01, 0x00, 0x01]);
+
+        let movie = Movie::read(data.as_slice()).unwrap();
+        assert_eq!(movie.header, header);
+        assert_eq!(movie.frames.len(), 2);
+        assert!(movie.frames.iter().all(|f| f.checksum.is_none()));
+    }
+
+    #[test]
+    fn version_2_keypads_become_key_events() {
+        let header = MovieHeader { font: None, ..header(2) };
+        let mut data = MOVIE_MAGIC.to_vec();
+        data.extend(&2u16.to_be_bytes());
+        data.extend(bincode::serialize(&(header.program_checksum, header.variant, header.quirks, header.seed, header.speed, header.checksum_interval)).unwrap());
+        data.extend(&[0x00, 0x03]);
+        data.extend(&[0x00, 0x03, 0x12, 0x34, 0x56, 0x78]);
+        data.extend(&[0x80, 0x02]);
+
+        let movie = Movie::read(data.as_slice()).unwrap();
+        assert_eq!(movie.header, header);
+
+        let events: Vec<Vec<(u8, bool)>> = movie.frames.iter().map(|f| f.events.iter().map(|e| (e.key, e.pressed)).collect()).collect();
+        assert_eq!(events, vec![vec![(0x0, true), (0x1, true)], vec![], vec![(0x0, false), (0xF, true)]]);
+        assert_eq!(movie.frames[1].checksum, Some(0x1234_5678));
+    }
+
+    #[test]
+    fn version_3_mega_chip_checksums_are_dropped() {
+        let header = MovieHeader { variant: Variant::MegaChip, ..header(1) };
+        let mut data = MOVIE_MAGIC.to_vec();
+        data.extend(&3u16.to_be_bytes());
+        data.extend(bincode::serialize(&header).unwrap());
+        data.extend(&[0x00, 0x00, 0x12, 0x34, 0x56, 0x78]);
+
+        let movie = Movie::read(data.as_slice()).unwrap();
+        assert_eq!(movie.frames.len(), 1);
+        assert_eq!(movie.frames[0].checksum, None);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        match Movie::read(&b"C8SS\x00\x01"[..]) {
+            Err(MovieError::NotAMovie) => (),
+            r => panic!("expected not a movie, got {:?}", r),
+        }
+
+        let mut data = MOVIE_MAGIC.to_vec();
+        data.extend(&(MOVIE_VERSION + 1).to_be_bytes());
+        match Movie::read(data.as_slice()) {
+            Err(MovieError::UnsupportedVersion(v)) if v == MOVIE_VERSION + 1 => (),
+            r => panic!("expected an unsupported version, got {:?}", r),
+        }
+    }
+}
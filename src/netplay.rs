@@ -0,0 +1,233 @@
+//
+// Author: Joshua Holmes
+//
+
+use bincode;
+use rand;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use cheats::{CheatKind, Cheats};
+use cpu::Cpu;
+use emulator::Emulator;
+use font;
+use keyboard::Keyboard;
+use savestate::program_checksum;
+
+/// The bytes every netplay handshake starts with
+pub const NETPLAY_MAGIC: [u8; 4] = *b"C8NP";
+/// The netplay protocol version, bumped whenever the messages change
+pub const NETPLAY_VERSION: u16 = 2;
+
+/// Reasons a netplay session couldn't be set up or carried on
+#[derive(Debug)]
+pub enum NetplayError {
+    IoError(io::Error),
+    /// the other end didn't answer with a netplay handshake
+    NotAPeer,
+    /// the other end speaks a different version of the protocol
+    UnsupportedVersion(u16),
+    /// the other end is running a different program
+    ProgramMismatch,
+    /// the other end's machine is set up differently, in the way named
+    SetupMismatch(&'static str),
+}
+
+impl From<io::Error> for NetplayError {
+    fn from(err: io::Error) -> Self {
+        NetplayError::IoError(err)
+    }
+}
+
+impl fmt::Display for NetplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NetplayError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            NetplayError::NotAPeer => write!(f, "the other end isn't a netplay peer"),
+            NetplayError::UnsupportedVersion(v) => write!(f, "the other end speaks netplay version {} (this build speaks version {})",
+                v, NETPLAY_VERSION),
+            NetplayError::ProgramMismatch => write!(f, "the other end is running a different program"),
+            NetplayError::SetupMismatch(what) => write!(f, "the other end has different {}", what),
+        }
+    }
+}
+
+impl Error for NetplayError {}
+
+/// A lockstep link between two instances running the same program, so two
+/// players on different machines can share the keypad.
+///
+/// Emulation is deterministic once both ends start from the same state with the
+/// same random seed and speed, which the handshake takes care of. Both ends also
+/// send hashes of their variant and quirks, font and cheats, and refuse to play
+/// if any of them differ. At the end of
+/// every frame each end sends the keys its player is holding and waits for the
+/// other's, and both press the union of the two. Keys only ever change between
+/// frames, so both machines see exactly the same input at the same moment.
+///
+/// ```text
+/// handshake, host to guest:  "C8NP" version:u16 checksum:u32 setup:[u32; 3] seed:u64 instruction delay in ns:u64
+/// handshake, guest to host:  "C8NP" version:u16 checksum:u32 setup:[u32; 3]
+/// setup:                     hashes of the machine config, the font and the cheats
+/// every frame, both ways:    held keys:u16, bit n for key n
+/// ```
+///
+/// Everything is big-endian.
+pub struct Netplay {
+    stream: TcpStream,
+    /// the keys the local player is holding
+    local_keys: [bool; 16],
+}
+
+impl Netplay {
+    /// Waits for the other player to connect, then tells them how to set up their
+    /// emulator to match ours
+    pub fn host<A: ToSocketAddrs>(addr: A, emulator: &mut Emulator, program_checksum: u32, cheats: &Cheats, seed: Option<u64>) -> Result<Netplay, NetplayError> {
+        let listener = TcpListener::bind(addr)?;
+        let (mut stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        let seed = seed.unwrap_or_else(rand::random);
+        emulator.cpu.seed_rng(seed);
+
+        let setup = setup_hashes(&emulator.cpu, cheats);
+        let mut handshake = handshake(program_checksum, &setup);
+        handshake.extend_from_slice(&seed.to_be_bytes());
+        handshake.extend_from_slice(&(emulator.instruction_delay.as_nanos() as u64).to_be_bytes());
+        stream.write_all(&handshake)?;
+
+        read_handshake(&mut stream, program_checksum, &setup)?;
+
+        Ok(Netplay::new(stream))
+    }
+
+    /// Connects to the other player and sets up our emulator to match theirs
+    pub fn join<A: ToSocketAddrs>(addr: A, emulator: &mut Emulator, program_checksum: u32, cheats: &Cheats) -> Result<Netplay, NetplayError> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        // sent before checking theirs, so the host finds out about any mismatch too
+        let setup = setup_hashes(&emulator.cpu, cheats);
+        stream.write_all(&handshake(program_checksum, &setup))?;
+        read_handshake(&mut stream, program_checksum, &setup)?;
+
+        let mut settings = [0u8; 16];
+        stream.read_exact(&mut settings)?;
+        emulator.cpu.seed_rng(read_u64(&settings[..8]));
+        emulator.instruction_delay = Duration::from_nanos(read_u64(&settings[8..]));
+
+        Ok(Netplay::new(stream))
+    }
+
+    fn new(stream: TcpStream) -> Netplay {
+        Netplay {
+            stream: stream,
+            local_keys: [false; 16],
+        }
+    }
+
+    /// The address of the other player
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Presses or releases one of the local player's keys. It takes effect at the end of the frame.
+    pub fn set_local_key(&mut self, key: u8, state: bool) {
+        self.local_keys[key as usize & 0xF] = state;
+    }
+
+    /// Swaps held keys with the other player, waiting for theirs, and presses
+    /// the keys either of them is holding
+    pub fn exchange(&mut self, keyboard: &mut Keyboard) -> Result<(), NetplayError> {
+        let local = self.local_keys.iter().enumerate()
+            .fold(0u16, |acc, (i, &held)| if held { acc | (1 << i) } else { acc });
+        self.stream.write_all(&local.to_be_bytes())?;
+
+        let mut buf = [0u8; 2];
+        self.stream.read_exact(&mut buf)?;
+        let remote = u16::from_be_bytes(buf);
+
+        keyboard.set_keypad_state(local | remote);
+
+        Ok(())
+    }
+}
+
+/// What the setup hashes are of, in the order they're sent
+const SETUP_NAMES: [&str; 3] = ["variants or quirks", "fonts", "cheats"];
+
+/// Hashes of everything besides the program that has to match for both ends to
+/// stay in step: the machine config, the font in memory, and the cheats and
+/// whether they're on
+fn setup_hashes(cpu: &Cpu, cheats: &Cheats) -> [u32; 3] {
+    // how much history the debugger keeps doesn't change what the program does
+    let mut config = cpu.config;
+    config.history_length = 0;
+    let config = bincode::serialize(&config).unwrap_or_default();
+
+    let font_start = cpu.config.font_start_addr.min(cpu.memory.len());
+    let font_end = font_start.saturating_add(font::MAX_FONT_LENGTH).min(cpu.memory.len());
+
+    let mut cheat_bytes = vec![cheats.enabled as u8];
+    for cheat in cheats.cheats.iter() {
+        cheat_bytes.push(if cheat.kind == CheatKind::Freeze { 0 } else { 1 });
+        cheat_bytes.extend_from_slice(&(cheat.address as u32).to_be_bytes());
+        cheat_bytes.push(cheat.value);
+    }
+
+    [program_checksum(&config), program_checksum(&cpu.memory[font_start..font_end]), program_checksum(&cheat_bytes)]
+}
+
+/// The part of the handshake both ends send
+fn handshake(program_checksum: u32, setup: &[u32; 3]) -> Vec<u8> {
+    let mut handshake = Vec::new();
+    handshake.extend_from_slice(&NETPLAY_MAGIC);
+    handshake.extend_from_slice(&NETPLAY_VERSION.to_be_bytes());
+    handshake.extend_from_slice(&program_checksum.to_be_bytes());
+    for hash in setup.iter() {
+        handshake.extend_from_slice(&hash.to_be_bytes());
+    }
+    handshake
+}
+
+/// Reads the part of the handshake both ends send, checking it matches us
+fn read_handshake(stream: &mut TcpStream, program_checksum: u32, setup: &[u32; 3]) -> Result<(), NetplayError> {
+    let mut handshake = [0u8; 6];
+    stream.read_exact(&mut handshake)?;
+
+    if handshake[..4] != NETPLAY_MAGIC {
+        return Err(NetplayError::NotAPeer);
+    }
+
+    let version = u16::from_be_bytes([handshake[4], handshake[5]]);
+    if version != NETPLAY_VERSION {
+        return Err(NetplayError::UnsupportedVersion(version));
+    }
+
+    // only read the rest once we know it's the same version, and so the same length
+    let mut checksums = [0u8; 16];
+    stream.read_exact(&mut checksums)?;
+
+    if read_u32(&checksums[..4]) != program_checksum {
+        return Err(NetplayError::ProgramMismatch);
+    }
+
+    for (i, hash) in setup.iter().enumerate() {
+        if read_u32(&checksums[4 + i * 4..]) != *hash {
+            return Err(NetplayError::SetupMismatch(SETUP_NAMES[i]));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    bytes[..8].iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
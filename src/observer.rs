@@ -0,0 +1,34 @@
+//
+// Author: Joshua Holmes
+//
+
+use cpu::{Cpu, EmulationError};
+
+/// Receives notifications about what the running program is doing, so a frontend
+/// doesn't have to poll the CPU for them. Every method does nothing by default.
+pub trait Observer {
+    /// The screen has changed. The monochrome screen is in `cpu.vram`, or the
+    /// Mega-Chip screen in `cpu.mega` when Mega-Chip mode is on. This comes at the
+    /// end of a frame, however many times the screen was drawn on during it.
+    fn on_draw(&mut self, _cpu: &Cpu) {}
+
+    /// The sound timer has been set, so the buzzer should start
+    fn on_sound_start(&mut self) {}
+
+    /// The sound timer has run down, so the buzzer should stop
+    fn on_sound_stop(&mut self) {}
+
+    /// The XO-CHIP pitch register has changed, so the buzzer should play at the given rate, in Hz
+    fn on_pitch_change(&mut self, _rate: f64) {}
+
+    /// The program has started waiting for a key press
+    fn on_key_wait(&mut self) {}
+
+    /// The program has stopped, either by running off its end or with the given error
+    fn on_halt(&mut self, _error: Option<&EmulationError>) {}
+}
+
+/// An observer that ignores every notification
+pub struct NullObserver;
+
+impl Observer for NullObserver {}
@@ -0,0 +1,711 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::HashMap;
+
+use assembler::{self, AssembleError};
+use opcode::INSTR_SIZE;
+
+/// The words that mean something to Octo, offered as suggestions for near misses
+const KEYWORDS: [&str; 33] = [
+    ":", ":=", ":const", ":alias", ";", "return", "clear", "bcd", "save", "load", "sprite", "jump", "jump0",
+    "loop", "again", "while", "if", "then", "begin", "else", "end", "key", "-key", "random", "delay",
+    "buzzer", "pitch", "audio", "hex", "bighex", "scroll-down", "scroll-left", "scroll-right",
+];
+
+/// A word of the source, with where it is
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    /// the line it's on, counting from 1
+    line: usize,
+    /// the column it starts at, counting from 1
+    column: usize,
+}
+
+/// A control structure that's been opened and not yet closed
+enum Block {
+    /// an if ... begin, with where the jump past its body is, to fill in at its else or end
+    If { token: Token, jump: usize },
+    /// a loop, with where it starts and where each of its whiles jumps out of it
+    Loop { token: Token, start: usize, exits: Vec<usize> },
+}
+
+/// What a condition compiles to: the instruction that skips the next when it
+/// holds, and the one that skips the next when it doesn't
+struct Condition {
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+/// Everything the source defines so far, and the program assembled so far
+struct Octo {
+    tokens: Vec<Token>,
+    /// the next token to read
+    position: usize,
+    origin: usize,
+    bytes: Vec<u8>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    /// the names given to registers by :alias
+    aliases: HashMap<String, u16>,
+    /// where each instruction naming a label not yet defined is, and the name
+    fixups: Vec<(usize, Token)>,
+    blocks: Vec<Block>,
+}
+
+/// Assembles a program written in Octo, to be loaded at the given address. This
+/// covers the core of the language:
+///
+/// ```text
+/// # comments, to the end of the line
+/// : main                        a label, naming the address of what follows
+/// :const SPEED 3                a constant, and :alias x v4 to name a register
+/// v0 := 5  v0 += v1  v2 -= 1    register operations: := += -= =- |= &= ^= >>= <<=
+/// v1 := random 0x1F             and v := delay, v := key, delay := v, buzzer := v
+/// i := sprite_data  i += v2     and i := hex v, i := bighex v
+/// sprite v0 v1 5  clear         drawing
+/// bcd v3  save v2  load v2      memory
+/// jump main  jump0 table        jumps, and a label on its own calls it
+/// return  ;                     returning from a call
+/// if v0 == 3 then v1 += 1       conditions are == and != against a register
+/// if v0 key begin ... else ... end     or a byte, and key and -key
+/// loop ... while v0 != 0 ... again
+/// 0xF0 0x90 0b11110000          bytes, in decimal, hex or binary
+/// ```
+///
+/// The program starts at main, with a jump to it first unless it's at the start.
+/// As in Octo, constants have to be defined before they're used, though labels
+/// can be jumped to and called from anywhere. Every problem found comes back,
+/// each with the rest of its line skipped.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, Vec<AssembleError>> {
+    let tokens = tokenize(source);
+
+    // main only turns out to be first once everything before it is known to be empty
+    match assemble_tokens(tokens.clone(), origin, true) {
+        Ok(ref octo) if octo.labels.get("main") == Some(&(origin + INSTR_SIZE)) => assemble_tokens(tokens, origin, false),
+        result => result,
+    }.map(|octo| octo.bytes)
+}
+
+/// Assembles a program from its words, first with a jump to main if asked for
+fn assemble_tokens(tokens: Vec<Token>, origin: usize, jump_to_main: bool) -> Result<Octo, Vec<AssembleError>> {
+    let mut octo = Octo {
+        tokens: tokens,
+        position: 0,
+        origin: origin,
+        bytes: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+    };
+    let mut errors = Vec::new();
+
+    if jump_to_main {
+        octo.emit(0x1000);
+    }
+
+    while octo.position < octo.tokens.len() {
+        if let Err(e) = octo.statement() {
+            // carry on from the next line, so one problem doesn't hide the rest
+            let line = e.line;
+            while octo.peek().is_some_and(|t| t.line == line) {
+                octo.position += 1;
+            }
+            errors.push(e);
+        }
+    }
+
+    match octo.labels.get("main") {
+        Some(&main) if jump_to_main => {
+            let token = Token { text: "main".to_owned(), line: 1, column: 1 };
+            if let Err(e) = octo.patch(0, main, &token) {
+                errors.push(e);
+            }
+        },
+        Some(_) => (),
+        None => errors.push(AssembleError {
+            line: 1,
+            column: 1,
+            token: String::new(),
+            message: "there's no : main for the program to start at".to_owned(),
+            suggestion: None,
+        }),
+    }
+
+    for block in octo.blocks.drain(..) {
+        errors.push(match block {
+            Block::If { token, .. } => error(&token, "this if has no end".to_owned()),
+            Block::Loop { token, .. } => error(&token, "this loop has no again".to_owned()),
+        });
+    }
+
+    for (position, token) in octo.fixups.clone() {
+        let resolved = match octo.labels.get(&token.text) {
+            Some(&addr) => octo.patch(position, addr, &token),
+            None => Err(octo.unknown_name(&token)),
+        };
+        if let Err(e) = resolved {
+            errors.push(e);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(octo)
+    } else {
+        errors.sort_by_key(|e| (e.line, e.column));
+        Err(errors)
+    }
+}
+
+/// Splits the source into words, leaving out comments
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let chars: Vec<char> = line.chars().take_while(|&c| c != '#').collect();
+
+        let mut n = 0;
+        while n < chars.len() {
+            if chars[n].is_whitespace() {
+                n += 1;
+                continue;
+            }
+
+            let start = n;
+            while n < chars.len() && !chars[n].is_whitespace() {
+                n += 1;
+            }
+            tokens.push(Token { text: chars[start..n].iter().collect(), line: i + 1, column: start + 1 });
+        }
+    }
+
+    tokens
+}
+
+/// A problem with a token
+fn error(token: &Token, message: String) -> AssembleError {
+    AssembleError {
+        line: token.line,
+        column: token.column,
+        token: token.text.clone(),
+        message: message,
+        suggestion: None,
+    }
+}
+
+/// The number of a V register, like v3 or VA
+fn register(text: &str) -> Option<u16> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(n), None) | (Some('V'), Some(n), None) => n.to_digit(16).map(|n| n as u16),
+        _ => None,
+    }
+}
+
+/// The value of a number written in decimal, in hex after 0x or in binary after 0b
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(t) => (true, t),
+        None => (false, text),
+    };
+
+    let value = if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        i64::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        i64::from_str_radix(digits, 2).ok()
+    } else {
+        text.parse::<i64>().ok()
+    };
+
+    value.map(|v| if negative { -v } else { v })
+}
+
+impl Octo {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// The next token, which the statement being read needs, after the one given
+    fn next(&mut self, after: &Token) -> Result<Token, AssembleError> {
+        match self.tokens.get(self.position) {
+            Some(t) => {
+                self.position += 1;
+                Ok(t.clone())
+            },
+            None => Err(error(after, format!("expected something after {}", after.text))),
+        }
+    }
+
+    /// The next token, which has to be the one given
+    fn expect(&mut self, after: &Token, text: &str) -> Result<Token, AssembleError> {
+        let token = self.next(after)?;
+        if token.text != text {
+            return Err(error(&token, format!("expected {} but found {}", text, token.text)));
+        }
+        Ok(token)
+    }
+
+    /// The address the next byte goes at
+    fn here(&self) -> usize {
+        self.origin + self.bytes.len()
+    }
+
+    fn emit(&mut self, instruction: u16) {
+        self.bytes.push((instruction >> 8) as u8);
+        self.bytes.push(instruction as u8);
+    }
+
+    /// Fills in the address of the instruction at a position in the program
+    fn patch(&mut self, position: usize, addr: usize, token: &Token) -> Result<(), AssembleError> {
+        if addr > 0xFFF {
+            return Err(error(token, format!("{} is at {:X}, which is too far to reach", token.text, addr)));
+        }
+        self.bytes[position] |= (addr >> 8) as u8;
+        self.bytes[position + 1] = addr as u8;
+        Ok(())
+    }
+
+    /// Whether a name is taken by a label, constant or alias
+    fn is_defined(&self, name: &str) -> bool {
+        self.labels.contains_key(name) || self.constants.contains_key(name) || self.aliases.contains_key(name)
+    }
+
+    /// The error for a name that isn't defined, suggesting one that is or a keyword
+    fn unknown_name(&self, token: &Token) -> AssembleError {
+        let names = self.labels.keys().chain(self.constants.keys()).chain(self.aliases.keys())
+            .map(|n| n.as_str())
+            .chain(KEYWORDS.iter().cloned());
+        AssembleError {
+            suggestion: assembler::closest(&token.text, names),
+            ..error(token, format!("{} isn't a label, constant or keyword", token.text))
+        }
+    }
+
+    /// Reads a name for something being defined, which mustn't already be taken
+    fn new_name(&mut self, after: &Token) -> Result<Token, AssembleError> {
+        let name = self.next(after)?;
+        if parse_number(&name.text).is_some() || register(&name.text).is_some() || KEYWORDS.contains(&name.text.as_str()) {
+            return Err(error(&name, format!("{} can't be used as a name", name.text)));
+        }
+        if self.is_defined(&name.text) {
+            return Err(error(&name, format!("{} is already defined", name.text)));
+        }
+        Ok(name)
+    }
+
+    /// The value of a number or constant
+    fn number(&self, token: &Token) -> Result<i64, AssembleError> {
+        if let Some(&value) = self.constants.get(&token.text) {
+            return Ok(value);
+        }
+        parse_number(&token.text).ok_or_else(|| {
+            let names = self.constants.keys().map(|n| n.as_str());
+            AssembleError {
+                suggestion: assembler::closest(&token.text, names),
+                ..error(token, format!("{} isn't a number or a constant", token.text))
+            }
+        })
+    }
+
+    /// A number that fits in a byte, with negative ones wrapping round
+    fn byte(&self, token: &Token) -> Result<u16, AssembleError> {
+        match self.number(token)? {
+            v if (-0x80..=0xFF).contains(&v) => Ok((v & 0xFF) as u16),
+            v => Err(error(token, format!("{} is {}, which doesn't fit in a byte", token.text, v))),
+        }
+    }
+
+    fn nibble(&self, token: &Token) -> Result<u16, AssembleError> {
+        match self.number(token)? {
+            v if (0..=0xF).contains(&v) => Ok(v as u16),
+            v => Err(error(token, format!("{} is {}, which doesn't fit between 0 and 15", token.text, v))),
+        }
+    }
+
+    /// The number of a register, named directly or by an alias
+    fn register(&self, token: &Token) -> Option<u16> {
+        register(&token.text).or_else(|| self.aliases.get(&token.text).cloned())
+    }
+
+    fn expect_register(&self, token: &Token) -> Result<u16, AssembleError> {
+        self.register(token).ok_or_else(|| self.register_error(token))
+    }
+
+    /// The error for a token that should have been a register
+    fn register_error(&self, token: &Token) -> AssembleError {
+        let v_registers: Vec<String> = (0..16).map(|n| format!("v{:x}", n)).collect();
+        let names = v_registers.iter().map(|r| r.as_str()).chain(self.aliases.keys().map(|n| n.as_str()));
+        AssembleError {
+            suggestion: assembler::closest(&token.text, names),
+            ..error(token, format!("expected a register but found {}", token.text))
+        }
+    }
+
+    /// Emits an instruction taking an address, which may be a number, a constant,
+    /// or a label to fill in once it's defined
+    fn emit_address(&mut self, instruction: u16, token: &Token) -> Result<(), AssembleError> {
+        if let Some(&addr) = self.labels.get(&token.text) {
+            let position = self.bytes.len();
+            self.emit(instruction);
+            return self.patch(position, addr, token);
+        }
+
+        if self.constants.contains_key(&token.text) || parse_number(&token.text).is_some() {
+            return match self.number(token)? {
+                v if (0..=0xFFF).contains(&v) => {
+                    self.emit(instruction | v as u16);
+                    Ok(())
+                },
+                v => Err(error(token, format!("{} is {}, which isn't an address", token.text, v))),
+            };
+        }
+
+        self.fixups.push((self.bytes.len(), token.clone()));
+        self.emit(instruction);
+        Ok(())
+    }
+
+    /// Reads a condition, as if, while and begin take: a register compared with
+    /// == or != to another or a byte, or followed by key or -key
+    fn condition(&mut self, after: &Token) -> Result<Condition, AssembleError> {
+        let left = self.next(after)?;
+        let x = self.expect_register(&left)? << 8;
+        let op = self.next(&left)?;
+
+        let (skip_if_true, skip_if_false) = match op.text.as_str() {
+            "key" => (0xE09E | x, 0xE0A1 | x),
+            "-key" => (0xE0A1 | x, 0xE09E | x),
+            "==" | "!=" => {
+                let right = self.next(&op)?;
+                let (equal, not_equal) = match self.register(&right) {
+                    Some(y) => (0x5000 | x | y << 4, 0x9000 | x | y << 4),
+                    None => {
+                        let kk = self.byte(&right)?;
+                        (0x3000 | x | kk, 0x4000 | x | kk)
+                    },
+                };
+                if op.text == "==" { (equal, not_equal) } else { (not_equal, equal) }
+            },
+            _ => return Err(error(&op, format!("expected ==, !=, key or -key but found {}", op.text))),
+        };
+
+        Ok(Condition { skip_if_true: skip_if_true, skip_if_false: skip_if_false })
+    }
+
+    /// Reads and assembles one statement
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.tokens[self.position].clone();
+        self.position += 1;
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.new_name(&token)?;
+                let here = self.here();
+                self.labels.insert(name.text, here);
+            },
+            ":const" => {
+                let name = self.new_name(&token)?;
+                let value = self.next(&name)?;
+                let value = self.number(&value)?;
+                self.constants.insert(name.text, value);
+            },
+            ":alias" => {
+                let name = self.new_name(&token)?;
+                let target = self.next(&name)?;
+                let x = self.expect_register(&target)?;
+                self.aliases.insert(name.text, x);
+            },
+            ";" | "return" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "audio" => self.emit(0xF002),
+            "scroll-left" => self.emit(0x00FC),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-down" => {
+                let n = self.next(&token)?;
+                let n = self.nibble(&n)?;
+                self.emit(0x00C0 | n);
+            },
+            "bcd" | "save" | "load" => {
+                let operand = self.next(&token)?;
+                let x = self.expect_register(&operand)?;
+                let low = match token.text.as_str() {
+                    "bcd" => 0x33,
+                    "save" => 0x55,
+                    _ => 0x65,
+                };
+                self.emit(0xF000 | x << 8 | low);
+            },
+            "sprite" => {
+                let vx = self.next(&token)?;
+                let vy = self.next(&vx)?;
+                let n = self.next(&vy)?;
+                let instruction = 0xD000 | self.expect_register(&vx)? << 8 | self.expect_register(&vy)? << 4 | self.nibble(&n)?;
+                self.emit(instruction);
+            },
+            "jump" | "jump0" => {
+                let target = self.next(&token)?;
+                self.emit_address(if token.text == "jump" { 0x1000 } else { 0xB000 }, &target)?;
+            },
+            "delay" | "buzzer" | "pitch" => {
+                let assign = self.expect(&token, ":=")?;
+                let operand = self.next(&assign)?;
+                let x = self.expect_register(&operand)?;
+                let low = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.emit(0xF000 | x << 8 | low);
+            },
+            "i" => self.i_statement(&token)?,
+            "if" => {
+                let condition = self.condition(&token)?;
+                let form = self.next(&token)?;
+                match form.text.as_str() {
+                    // the statement after then is skipped unless the condition holds
+                    "then" => self.emit(condition.skip_if_false),
+                    // the body is jumped past unless the condition holds
+                    "begin" => {
+                        self.emit(condition.skip_if_true);
+                        self.blocks.push(Block::If { token: token.clone(), jump: self.bytes.len() });
+                        self.emit(0x1000);
+                    },
+                    _ => return Err(error(&form, format!("expected then or begin but found {}", form.text))),
+                }
+            },
+            "else" => {
+                let jump = match self.blocks.last() {
+                    Some(&Block::If { jump, .. }) => jump,
+                    _ => return Err(error(&token, "else has no if ... begin to go with".to_owned())),
+                };
+                let else_jump = self.bytes.len();
+                self.emit(0x1000);
+                let here = self.here();
+                self.patch(jump, here, &token)?;
+                if let Some(&mut Block::If { ref mut jump, .. }) = self.blocks.last_mut() {
+                    *jump = else_jump;
+                }
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) => {
+                    let here = self.here();
+                    self.patch(jump, here, &token)?;
+                },
+                other => {
+                    self.blocks.extend(other);
+                    return Err(error(&token, "end has no if ... begin to go with".to_owned()));
+                },
+            },
+            "loop" => {
+                let start = self.here();
+                self.blocks.push(Block::Loop { token: token.clone(), start: start, exits: Vec::new() });
+            },
+            "while" => {
+                let condition = self.condition(&token)?;
+                self.emit(condition.skip_if_true);
+                let exit = self.bytes.len();
+                match self.blocks.iter_mut().rev().find(|b| matches!(b, Block::Loop { .. })) {
+                    Some(&mut Block::Loop { ref mut exits, .. }) => exits.push(exit),
+                    _ => return Err(error(&token, "while has no loop to go with".to_owned())),
+                }
+                self.emit(0x1000);
+            },
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    let position = self.bytes.len();
+                    self.emit(0x1000);
+                    self.patch(position, start, &token)?;
+                    let here = self.here();
+                    for exit in exits {
+                        self.patch(exit, here, &token)?;
+                    }
+                },
+                other => {
+                    self.blocks.extend(other);
+                    return Err(error(&token, "again has no loop to go with".to_owned()));
+                },
+            },
+            _ => {
+                if let Some(x) = self.register(&token) {
+                    return self.register_statement(&token, x);
+                }
+
+                // a number on its own is a byte of data, and a name on its own a call
+                if self.constants.contains_key(&token.text) || parse_number(&token.text).is_some() {
+                    let value = self.byte(&token)?;
+                    self.bytes.push(value as u8);
+                } else {
+                    self.emit_address(0x2000, &token)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Reads the rest of a statement starting with i
+    fn i_statement(&mut self, i: &Token) -> Result<(), AssembleError> {
+        let op = self.next(i)?;
+        let operand = self.next(&op)?;
+
+        match op.text.as_str() {
+            ":=" if operand.text == "hex" || operand.text == "bighex" => {
+                let register = self.next(&operand)?;
+                let x = self.expect_register(&register)?;
+                self.emit(0xF000 | x << 8 | if operand.text == "hex" { 0x29 } else { 0x30 });
+            },
+            ":=" => self.emit_address(0xA000, &operand)?,
+            "+=" => {
+                let x = self.expect_register(&operand)?;
+                self.emit(0xF01E | x << 8);
+            },
+            _ => return Err(error(&op, format!("expected := or += but found {}", op.text))),
+        }
+
+        Ok(())
+    }
+
+    /// Reads the rest of a statement starting with a register
+    fn register_statement(&mut self, left: &Token, x: u16) -> Result<(), AssembleError> {
+        let op = self.next(left)?;
+        let operand = self.next(&op)?;
+        let x = x << 8;
+        let y = self.register(&operand).map(|y| y << 4);
+
+        let instruction = match (op.text.as_str(), operand.text.as_str(), y) {
+            (":=", _, Some(y)) => 0x8000 | x | y,
+            (":=", "random", _) => {
+                let mask = self.next(&operand)?;
+                0xC000 | x | self.byte(&mask)?
+            },
+            (":=", "delay", _) => 0xF007 | x,
+            (":=", "key", _) => 0xF00A | x,
+            (":=", _, None) => 0x6000 | x | self.byte(&operand)?,
+            ("+=", _, Some(y)) => 0x8004 | x | y,
+            ("+=", _, None) => 0x7000 | x | self.byte(&operand)?,
+            ("-=", _, Some(y)) => 0x8005 | x | y,
+            ("-=", _, None) => 0x7000 | x | (0x100 - self.byte(&operand)?) & 0xFF,
+            ("=-", _, Some(y)) => 0x8007 | x | y,
+            ("|=", _, Some(y)) => 0x8001 | x | y,
+            ("&=", _, Some(y)) => 0x8002 | x | y,
+            ("^=", _, Some(y)) => 0x8003 | x | y,
+            (">>=", _, Some(y)) => 0x8006 | x | y,
+            ("<<=", _, Some(y)) => 0x800E | x | y,
+            ("=-", _, None) | ("|=", _, None) | ("&=", _, None) | ("^=", _, None) | (">>=", _, None) | ("<<=", _, None) => {
+                return Err(self.register_error(&operand));
+            },
+            _ => return Err(error(&op, format!("expected :=, +=, -=, =-, |=, &=, ^=, >>= or <<= but found {}", op.text))),
+        };
+
+        self.emit(instruction);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A program's instructions as words, after the jump to main
+    fn words(source: &str) -> Vec<u16> {
+        let bytes = assemble(source, 0x200).unwrap();
+        bytes.chunks(2).map(|w| (w[0] as u16) << 8 | w[1] as u16).collect()
+    }
+
+    #[test]
+    fn assembles_the_basics() {
+        let source = "
+            :const SPEED 3
+            :alias x v4
+            : main
+                clear
+                x := 10  v1 := 0x1F  v2 := 0b101
+                x += SPEED  x -= 1  x += v1  x -= v1  x =- v1
+                x |= v2  x &= v2  x ^= v2  x >>= v2  x <<= v2  x := v2
+                v3 := random 0xFF  v3 := delay  v3 := key
+                delay := v3  buzzer := v3
+                i := digits  i += v3  i := hex v3
+                sprite x v1 5
+                bcd v3  save v3  load v3
+                draw
+                jump main
+            : draw
+                return
+            : digits
+                0xF0 0x90 240 -1
+        ";
+
+        assert_eq!(words(source), vec![
+            0x00E0,
+            0x640A, 0x611F, 0x6205,
+            0x7403, 0x74FF, 0x8414, 0x8415, 0x8417,
+            0x8421, 0x8422, 0x8423, 0x8426, 0x842E, 0x8420,
+            0xC3FF, 0xF307, 0xF30A,
+            0xF315, 0xF318,
+            0xA23C, 0xF31E, 0xF329,
+            0xD415,
+            0xF333, 0xF355, 0xF365,
+            0x223A,
+            0x1200,
+            0x00EE,
+            0xF090, 0xF0FF,
+        ]);
+    }
+
+    #[test]
+    fn jumps_to_main_unless_it_comes_first() {
+        assert_eq!(words(": helper return\n: main helper"), vec![0x1204, 0x00EE, 0x2202]);
+        assert_eq!(words(": main jump main"), vec![0x1200]);
+    }
+
+    #[test]
+    fn conditions_skip_the_statement_after_then() {
+        let source = ": main
+            if v0 == 3 then v1 += 1
+            if v0 != v2 then v1 += 1
+            if v0 key then v1 += 1
+            if v0 -key then v1 += 1";
+        assert_eq!(words(source), vec![0x4003, 0x7101, 0x5020, 0x7101, 0xE0A1, 0x7101, 0xE09E, 0x7101]);
+    }
+
+    #[test]
+    fn blocks_jump_past_their_bodies() {
+        let source = ": main
+            if v0 == 1 begin
+                v1 := 1
+            else
+                v1 := 2
+            end
+            loop
+                v0 += 1
+                while v0 != 10
+                v2 := 0
+            again";
+        assert_eq!(words(source), vec![
+            0x3001, 0x1208, 0x6101, 0x120A, 0x6102,
+            0x7001, 0x400A, 0x1214, 0x6200, 0x120A,
+        ]);
+    }
+
+    #[test]
+    fn problems_are_all_reported_with_where_they_are() {
+        let source = ": main\n  v0 := 300\n  sprite v0 v1 16\n  jump nowhere\n  clera\n  loop";
+        let errors = assemble(source, 0x200).unwrap_err();
+        let found: Vec<(usize, usize, &str)> = errors.iter().map(|e| (e.line, e.column, e.token.as_str())).collect();
+
+        assert_eq!(found, vec![(2, 9, "300"), (3, 16, "16"), (4, 8, "nowhere"), (5, 3, "clera"), (6, 3, "loop")]);
+        assert_eq!(errors[3].suggestion.as_deref(), Some("clear"));
+        assert!(errors[4].message.contains("no again"), "{}", errors[4].message);
+    }
+
+    #[test]
+    fn a_program_needs_a_main() {
+        let errors = assemble("clear", 0x200).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("main"), "{}", errors[0].message);
+    }
+}
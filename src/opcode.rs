@@ -2,8 +2,15 @@
 // Author: Joshua Holmes
 //
 
+use std::cmp;
+
 use cpu;
-use cpu::Cpu;
+use cpu::{Cpu, EmulationError, EmulationWarning, MemoryAccess};
+use font;
+use machine::{IOverflow, SysBehavior};
+use megachip::{BlendMode, MegaChip};
+use xochip::PATTERN_LENGTH;
+use variant::Variant;
 
 // how many bytes are present in an instruction
 pub const INSTR_SIZE: usize = 2;
@@ -43,12 +50,12 @@ pub struct OpCode {
     pub opcode: u16,
     pub args: OpCodeArgs,
     pub disasm_str: String,
-    pub operation: fn(&OpCodeArgs, &mut Cpu),
+    pub operation: fn(&OpCodeArgs, &mut Cpu) -> Result<(), EmulationError>,
 }
 
 impl OpCode {
     /// Contruct a new OpCode given its u16 opcode, its dissassembly string, and its operation delegate
-    pub fn new(opcode: u16, args: OpCodeArgs, disasm_str: String, operation: fn(&OpCodeArgs, &mut Cpu)) -> OpCode {
+    pub fn new(opcode: u16, args: OpCodeArgs, disasm_str: String, operation: fn(&OpCodeArgs, &mut Cpu) -> Result<(), EmulationError>) -> OpCode {
         OpCode {
             opcode: opcode,
             args: args,
@@ -57,8 +64,8 @@ impl OpCode {
         }
     }
 
-    /// Constructs a new OpCode object given a u16 opcode value
-    pub fn from_u16(opcode: u16) -> Option<OpCode> {
+    /// Constructs a new OpCode object given a u16 opcode value and the variant being emulated
+    pub fn from_u16(opcode: u16, variant: Variant) -> Option<OpCode> {
         // get the opcode arguments and the first nibble then go 
         // down our lookups to determine which opcode this is
         let opcode_category = opcode & 0xF000;
@@ -69,6 +76,8 @@ impl OpCode {
                 match opcode {
                     0x00E0 => Some(OpCode::new(opcode, args, "CLS".to_owned(), OpCode::opcode_cls)),
                     0x00EE => Some(OpCode::new(opcode, args, "RET".to_owned(), OpCode::opcode_ret)),
+                    0x0230 if variant == Variant::HiresChip8 => Some(OpCode::new(opcode, args, "CLS".to_owned(), OpCode::opcode_cls)),
+                    _ if variant == Variant::MegaChip => OpCode::from_u16_megachip(opcode, args),
                     _ => Some(OpCode::new(opcode, args, format!("SYS {:03X}", args.nnn), OpCode::opcode_sys))
                 }
             },
@@ -134,13 +143,16 @@ impl OpCode {
             },
             0xF000 => {
                 match args.kk {
+                    0x02 if variant == Variant::XoChip && args.x == 0 => Some(OpCode::new(opcode, args, "AUDIO".to_owned(), OpCode::opcode_audio)),
                     0x07 => Some(OpCode::new(opcode, args, format!("LD V{:X}, DT", args.x), OpCode::opcode_ld_vx_dt)),
                     0x0A => Some(OpCode::new(opcode, args, format!("LD V{:X}, K", args.x), OpCode::opcode_ld_vx_k)),
                     0x15 => Some(OpCode::new(opcode, args, format!("LD DT, V{:X}", args.x), OpCode::opcode_ld_dt_vx)),
                     0x18 => Some(OpCode::new(opcode, args, format!("LD ST, V{:X}", args.x), OpCode::opcode_ld_st_vx)),
                     0x1E => Some(OpCode::new(opcode, args, format!("ADD I, V{:X}", args.x), OpCode::opcode_add_i_vx)),
                     0x29 => Some(OpCode::new(opcode, args, format!("LD F, V{:X}", args.x), OpCode::opcode_ld_f_vx)),
+                    0x30 if variant.has_big_font() => Some(OpCode::new(opcode, args, format!("LD HF, V{:X}", args.x), OpCode::opcode_ld_hf_vx)),
                     0x33 => Some(OpCode::new(opcode, args, format!("LD B, V{:X}", args.x), OpCode::opcode_ld_b_vx)),
+                    0x3A if variant == Variant::XoChip => Some(OpCode::new(opcode, args, format!("PITCH V{:X}", args.x), OpCode::opcode_pitch_vx)),
                     0x55 => Some(OpCode::new(opcode, args, format!("LD [I], V{:X}", args.x), OpCode::opcode_ld_i_vx)),
                     0x65 => Some(OpCode::new(opcode, args, format!("LD V{:X}, [I]", args.x), OpCode::opcode_ld_vx_i)),
                     _ => None
@@ -150,6 +162,38 @@ impl OpCode {
         }
     }
 
+    /// Decodes the 0x0nnn opcodes added by the Mega-Chip extensions. Anything
+    /// that isn't a Mega-Chip opcode falls back to SYS.
+    fn from_u16_megachip(opcode: u16, args: OpCodeArgs) -> Option<OpCode> {
+        match opcode & 0xFF00 {
+            0x0000 => {
+                match opcode {
+                    0x0010 => Some(OpCode::new(opcode, args, "MEGAOFF".to_owned(), OpCode::opcode_megaoff)),
+                    0x0011 => Some(OpCode::new(opcode, args, "MEGAON".to_owned(), OpCode::opcode_megaon)),
+                    0x00FB => Some(OpCode::new(opcode, args, "SCR".to_owned(), OpCode::opcode_scr)),
+                    0x00FC => Some(OpCode::new(opcode, args, "SCL".to_owned(), OpCode::opcode_scl)),
+                    _ => {
+                        match opcode & 0xFFF0 {
+                            0x00B0 => Some(OpCode::new(opcode, args, format!("SCU {:X}", args.n), OpCode::opcode_scu_nibble)),
+                            0x00C0 => Some(OpCode::new(opcode, args, format!("SCD {:X}", args.n), OpCode::opcode_scd_nibble)),
+                            _ => Some(OpCode::new(opcode, args, format!("SYS {:03X}", args.nnn), OpCode::opcode_sys))
+                        }
+                    }
+                }
+            },
+            0x0100 => Some(OpCode::new(opcode, args, format!("LDHI I, {:02X}xxxx", args.kk), OpCode::opcode_ldhi_i_addr)),
+            0x0200 => Some(OpCode::new(opcode, args, format!("LDPAL {:02X}", args.kk), OpCode::opcode_ldpal_byte)),
+            0x0300 => Some(OpCode::new(opcode, args, format!("SPRW {:02X}", args.kk), OpCode::opcode_sprw_byte)),
+            0x0400 => Some(OpCode::new(opcode, args, format!("SPRH {:02X}", args.kk), OpCode::opcode_sprh_byte)),
+            0x0500 => Some(OpCode::new(opcode, args, format!("ALPHA {:02X}", args.kk), OpCode::opcode_alpha_byte)),
+            0x0600 => Some(OpCode::new(opcode, args, format!("DIGISND {:X}", args.n), OpCode::opcode_digisnd_nibble)),
+            0x0700 => Some(OpCode::new(opcode, args, "STOPSND".to_owned(), OpCode::opcode_stopsnd)),
+            0x0800 => Some(OpCode::new(opcode, args, format!("BMODE {:X}", args.n), OpCode::opcode_bmode_nibble)),
+            0x0900 => Some(OpCode::new(opcode, args, format!("CCOL {:02X}", args.kk), OpCode::opcode_ccol_byte)),
+            _ => Some(OpCode::new(opcode, args, format!("SYS {:03X}", args.nnn), OpCode::opcode_sys))
+        }
+    }
+
     // -------------------------------------------------------------
     // Below are the implementations for each of the opcodes. These
     // functions are the subjects of the function pointers in each
@@ -158,339 +202,784 @@ impl OpCode {
 
     /// 0x0nnn
     /// "SYS addr" opcode. We don't *really* support this, nor does anyone else.
-    fn opcode_sys(args: &OpCodeArgs, cpu: &mut Cpu) {
+    /// Depending on the quirks it's ignored, warned about, or a fault.
+    fn opcode_sys(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        match cpu.config.quirks.sys {
+            SysBehavior::Ignore => (),
+            SysBehavior::Warn => cpu.warnings.push(EmulationWarning::SysCall {
+                address: args.nnn,
+                program_counter: cpu.program_counter,
+            }),
+            SysBehavior::Fault => return Err(EmulationError::SysCall(args.nnn, Box::new(cpu.state()))),
+        }
+
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
-    /// 0x00E0
+    /// 0x00E0 (0x0230 on hi-res CHIP-8)
     /// "CLS" opcode. Clears the display.
-    fn opcode_cls(args: &OpCodeArgs, cpu: &mut Cpu) {
-        cpu.vram = [[false; cpu::VIRTUAL_DISPLAY_WIDTH]; cpu::VIRTUAL_DISPLAY_HEIGHT];
+    /// In Mega-Chip mode this is also when the finished frame gets shown.
+    fn opcode_cls(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.pixel_changes.clear();
+
+        if cpu.mega.enabled {
+            // show the frame that was just built up, then start a new one
+            cpu.mega.present();
+            cpu.draw_flag = true;
+            cpu.program_counter += INSTR_SIZE;
+            return Ok(());
+        }
+
+        for (y, &row) in cpu.vram.iter().enumerate() {
+            cpu.pixel_changes.extend(cpu::lit_pixels(row).map(|x| (x, y)));
+        }
+
+        cpu.clear_vram();
         cpu.draw_flag = true;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x00EE
     /// "RET" opcode. Returns from a subroutine.
-    fn opcode_ret(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ret(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         // check the stack bounds
         if cpu.stack_pointer == 0 {
-            panic!("No address on the stack to return to");
+            return Err(EmulationError::StackUnderflow(Box::new(cpu.state())));
         }
 
         cpu.stack_pointer -= 1;
         cpu.program_counter = cpu.stack[cpu.stack_pointer] + INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x1nnn
     /// "JP addr" opcode. Jumps to a specified address.
-    fn opcode_jp_addr(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_jp_addr(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.program_counter = args.nnn;
+
+        Ok(())
     }
 
     /// 0x2nnn
     /// "CALL addr" opcode. Calls the subroutine at the given address.
-    fn opcode_call_addr(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_call_addr(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if cpu.stack_pointer >= cpu::STACK_LENGTH {
-            panic!("Stack full, can't call another subroutine");
+            return Err(EmulationError::StackOverflow(Box::new(cpu.state())));
         }
 
         cpu.stack[cpu.stack_pointer] = cpu.program_counter;
         cpu.stack_pointer += 1;
         cpu.program_counter = args.nnn;
+
+        Ok(())
     }
 
     /// 0x3xkk
     /// "SE Vx, byte" opcode. Skip next instruction if Vx = kk.
-    fn opcode_se_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_se_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if cpu.data_registers[args.x] == args.kk {
             cpu.program_counter += INSTR_SIZE;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x4xkk
     /// "SNE Vx, byte" opcode. Skip next instruction if Vx != kk.
-    fn opcode_sne_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_sne_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if cpu.data_registers[args.x] != args.kk {
             cpu.program_counter += INSTR_SIZE;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x5xy0
     /// "SE Vx, Vy" opcode. Skip next instruction if Vx = Vy.
-    fn opcode_se_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_se_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if cpu.data_registers[args.x] == cpu.data_registers[args.y] {
             cpu.program_counter += INSTR_SIZE;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x6xkk
     /// "LD Vx, byte" opcode. Set Vx = kk.
-    fn opcode_ld_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] = args.kk;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x7xkk
     /// "ADD Vx, byte" opcode. Set Vx = Vx + kk.
-    fn opcode_add_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_add_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         let (value, _) = cpu.data_registers[args.x].overflowing_add(args.kk);
         cpu.data_registers[args.x] = value;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy0
     /// "LD Vx, Vy" opcode. Set Vx = Vy.
-    fn opcode_ld_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] = cpu.data_registers[args.y];
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy1
     /// "OR Vx, Vy" opcode. Set Vx = Vx OR Vy.
-    fn opcode_or_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_or_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] |= cpu.data_registers[args.y];
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy2
     /// "AND Vx, Vy" opcode. Set Vx = Vx AND Vy.
-    fn opcode_and_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_and_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] &= cpu.data_registers[args.y];
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy3
     /// "XOR Vx, Vy" opcode. Set Vx = Vx XOR Vy.
-    fn opcode_xor_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_xor_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] ^= cpu.data_registers[args.y];
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy4
     /// "ADD Vx, Vy" opcode. Set Vx = Vx + Vy, set VF = carry.
-    fn opcode_add_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_add_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         let (value, flag) = cpu.data_registers[args.x].overflowing_add(cpu.data_registers[args.y]);
         cpu.data_registers[args.x] = value;
         cpu.data_registers[0xF] = if flag { 1 } else { 0 };
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy5
     /// "SUB Vx, Vy" opcode. Set Vx = Vx - Vy, set VF = NOT borrow.
-    fn opcode_sub_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_sub_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         let (value, flag) = cpu.data_registers[args.x].overflowing_sub(cpu.data_registers[args.y]);
         cpu.data_registers[args.x] = value;
         cpu.data_registers[0xF] = if flag { 0 } else { 1 };
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy6
     /// "SHR Vx {, Vy}" opcode. Set Vx = Vx SHR 1.
-    fn opcode_shr_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_shr_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[0xF] = cpu.data_registers[args.x] & 0x1;
         cpu.data_registers[args.x] >>= 1;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xy7
     /// "SUBN Vx, Vy" opcode. Set Vx = Vy - Vx, set VF = NOT borrow.
-    fn opcode_subn_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_subn_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         let (value, flag) = cpu.data_registers[args.y].overflowing_sub(cpu.data_registers[args.x]);
         cpu.data_registers[args.x] = value;
         cpu.data_registers[0xF] = if flag { 0 } else { 1 };
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x8xyE
     /// "SHL Vx {, Vy}" opcode. Set Vx = Vx SHL 1.
-    fn opcode_shl_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_shl_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[0xF] = cpu.data_registers[args.x] >> 7;
         cpu.data_registers[args.x] = cpu.data_registers[args.x] << 1;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0x9xy0
     /// "SNE Vx, Vy" opcode. Skip next instruction if Vx != Vy.
-    fn opcode_sne_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_sne_vx_vy(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if cpu.data_registers[args.x] != cpu.data_registers[args.y] {
             cpu.program_counter += INSTR_SIZE;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xAnnn
     /// "LD I, addr" opcode. Set I = nnn.
-    fn opcode_ld_i_addr(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_i_addr(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.i_register = args.nnn;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xBnnn
     /// "JP V0, addr" opcode. Jump to location nnn + V0.
-    fn opcode_jp_v0_addr(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_jp_v0_addr(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.program_counter = args.nnn + (cpu.data_registers[0x0] as usize);
+
+        Ok(())
     }
 
     /// 0xCxkk
     /// "RND Vx, byte" opcode. Set Vx = random byte AND kk.
-    fn opcode_rnd_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_rnd_vx_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] = cpu.get_random_byte() & args.kk;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xDxyn
     /// "DRW Vx, Vy, nibble" opcode. Display n-byte sprite starting at memory 
     /// location I at (Vx, Vy), set VF = collision.
-    fn opcode_drw_vx_vy_nibble(args: &OpCodeArgs, cpu: &mut Cpu) {
-        let sprite = &cpu.memory[cpu.i_register..cpu.i_register + args.n as usize];
-        let mut collision = 0u8;
-
-        for j in 0..args.n as usize {
-            for i in 0..8_usize {
-                let bit = (sprite[j] & (0x80 >> (i as u8))) != 0;
-                let x = (cpu.data_registers[args.x] as usize + i) % cpu::VIRTUAL_DISPLAY_WIDTH;
-                let y = (cpu.data_registers[args.y]as usize + j) % cpu::VIRTUAL_DISPLAY_HEIGHT;
-
-                if cpu.vram[y][x] && bit {
-                    collision = 1u8;
-                }
+    /// In Mega-Chip mode the sprite is SPRW x SPRH palette indices instead.
+    /// In hi-res, DRW Vx, Vy, 0 draws a 16x16 sprite of 32 bytes.
+    fn opcode_drw_vx_vy_nibble(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.pixel_changes.clear();
+
+        if cpu.mega.enabled {
+            let x = cpu.data_registers[args.x] as usize;
+            let y = cpu.data_registers[args.y] as usize;
+            let collision = cpu.mega.draw_sprite(&cpu.memory, cpu.i_register, x, y);
+            cpu.data_registers[0xF] = if collision { 1 } else { 0 };
+
+            cpu.program_counter += INSTR_SIZE;
+            return Ok(());
+        }
 
-                cpu.vram[y][x] ^= bit;
+        let addr = cpu.i_register;
+
+        // in hi-res, DXY0 draws a 16x16 sprite from two bytes per row, as on the SCHIP,
+        // and VF counts the rows that collided rather than just being set
+        let large = args.n == 0 && cpu.display_height == cpu::HIRES_DISPLAY_HEIGHT;
+        let (width, height) = if large { (16, 16) } else { (8, args.n as usize) };
+        let sprite = cpu.read_sprite(addr, height * width / 8)?;
+        let x = cpu.data_registers[args.x] as u32 % cpu::VIRTUAL_DISPLAY_WIDTH as u32;
+        let mut collided_rows = 0u8;
+
+        for j in 0..height {
+            let row = if large {
+                ((sprite[j * 2] as u16) << 8) | (sprite[j * 2 + 1] as u16)
+            } else {
+                (sprite[j] as u16) << 8
+            };
+            // line the sprite row up with the left of the VRAM row, then rotate it into
+            // place so whatever goes off the right edge wraps around to the left
+            let mask = ((row as u64) << 48).rotate_right(x);
+            let y = (cpu.data_registers[args.y] as usize + j) % cpu.display_height;
+
+            if (cpu.vram[y] & mask).count_ones() > 0 {
+                collided_rows += 1;
             }
+
+            cpu.vram[y] ^= mask;
+            cpu.pixel_changes.extend(cpu::lit_pixels(mask).map(|x| (x, y)));
         }
 
-        cpu.data_registers[0xF] = collision;
+        cpu.data_registers[0xF] = if large { collided_rows } else { cmp::min(collided_rows, 1) };
         cpu.draw_flag = true;
         
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xEx9E
     /// "SKP Vx" opcode. Skip next instruction if key with the value of Vx is pressed.
-    fn opcode_skp_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_skp_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if cpu.keyboard.is_pressed(cpu.data_registers[args.x]) {
             cpu.program_counter += INSTR_SIZE;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xExA1
     /// "SKNP Vx" opcode. Skip next instruction if key with the value of Vx is not pressed.
-    fn opcode_sknp_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_sknp_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         if !cpu.keyboard.is_pressed(cpu.data_registers[args.x]) {
             cpu.program_counter += INSTR_SIZE;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx07
     /// "LD Vx, DT" opcode. Set Vx = delay timer value.
-    fn opcode_ld_vx_dt(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_vx_dt(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.data_registers[args.x] = cpu.delay_timer;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx0A
     /// "LD Vx, K" opcode. Wait for a key press, store the value of the key in Vx.
-    fn opcode_ld_vx_k(args: &OpCodeArgs, cpu: &mut Cpu) {
-        // check for the first pressed key. if no keys are pressed, simply
-        // don't increase the program counter
-        for i in 0u8..16 {
-            if cpu.keyboard.is_pressed(i) {
-                cpu.data_registers[args.x] = i;
-                cpu.program_counter += INSTR_SIZE;
-                break;
-            }
+    fn opcode_ld_vx_k(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        // if no key has been pressed yet, simply don't increase the program counter
+        if let Some(key) = cpu.keyboard.wait_for_press(cpu.config.quirks.key_order) {
+            cpu.data_registers[args.x] = key;
+            cpu.program_counter += INSTR_SIZE;
         }
+
+        Ok(())
     }
 
     /// 0xFx15
     /// "LD DT, Vx" opcode. Set delay timer = Vx.
-    fn opcode_ld_dt_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_dt_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.delay_timer = cpu.data_registers[args.x];
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx18
     /// "LD ST, Vx" opcode. Set sound timer = Vx.
-    fn opcode_ld_st_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_st_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         cpu.sound_timer = cpu.data_registers[args.x];
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx1E
     /// "ADD I, Vx" opcode. Set I = I + Vx.
-    fn opcode_add_i_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
-        let (value, flag) = cpu.i_register.overflowing_add(cpu.data_registers[args.x] as usize);
+    fn opcode_add_i_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        let value = cpu.i_register + cpu.data_registers[args.x] as usize;
+        cpu.check_memory_access(value, MemoryAccess::Pointer)?;
         cpu.i_register = value;
-        cpu.data_registers[0xF] = if flag { 1 } else { 0 };
+
+        match cpu.config.quirks.i_overflow {
+            IOverflow::Never => (),
+            IOverflow::SetVf => cpu.data_registers[0xF] = if value > 0xFFF { 1 } else { 0 },
+            IOverflow::Clear => cpu.data_registers[0xF] = 0,
+        }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx29
     /// "LD F, Vx" opcode. Set I = location of sprite for digit Vx.
-    fn opcode_ld_f_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
-        cpu.i_register = (cpu.data_registers[args.x] as usize) * 5;
+    fn opcode_ld_f_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.i_register = cpu.config.font_start_addr + (cpu.data_registers[args.x] as usize) * 5;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0xFx30
+    /// "LD HF, Vx" opcode. Set I = location of big sprite for digit Vx.
+    fn opcode_ld_hf_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.i_register = cpu.config.font_start_addr + font::SMALL_FONT_LENGTH + (cpu.data_registers[args.x] as usize) * 10;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx33
     /// "LD B, Vx" opcode. Store BCD representation of Vx in memory locations I, I+1, and I+2.
-    fn opcode_ld_b_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_b_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         let val = cpu.data_registers[args.x];
-        cpu.memory[cpu.i_register] = val / 100;
-        cpu.memory[cpu.i_register + 1] = (val / 10) % 10;
-        cpu.memory[cpu.i_register + 2] = (val % 100) % 10;
+        let addr = cpu.i_register;
+        cpu.write_memory(addr, val / 100)?;
+        cpu.write_memory(addr + 1, (val / 10) % 10)?;
+        cpu.write_memory(addr + 2, (val % 100) % 10)?;
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx55
     /// "LD [I], Vx" opcode. Store registers V0 through Vx in memory starting at location I.
-    fn opcode_ld_i_vx(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_i_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         for i in 0..args.x + 1 {
-            cpu.memory[cpu.i_register + i] = cpu.data_registers[i];
+            let addr = cpu.i_register + i;
+            let val = cpu.data_registers[i];
+            cpu.write_memory(addr, val)?;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
     }
 
     /// 0xFx65
     /// "LD Vx, [I]" opcode. Read registers V0 through Vx from memory starting at location I.
-    fn opcode_ld_vx_i(args: &OpCodeArgs, cpu: &mut Cpu) {
+    fn opcode_ld_vx_i(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
         for i in 0..args.x + 1 {
-            cpu.data_registers[i] = cpu.memory[cpu.i_register + i];
+            cpu.data_registers[i] = cpu.read_memory(cpu.i_register + i)?;
+        }
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    // -------------------------------------------------------------
+    // Below are the Mega-Chip extension opcodes. They are only ever
+    // decoded when emulating the Mega-Chip variant.
+    // -------------------------------------------------------------
+
+    /// 0x0010
+    /// "MEGAOFF" opcode. Switches back to the regular CHIP-8 screen.
+    fn opcode_megaoff(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.enabled = false;
+        cpu.draw_flag = true;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x0011
+    /// "MEGAON" opcode. Switches to the 256x192 indexed-color screen.
+    fn opcode_megaon(_args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.enabled = true;
+        cpu.mega.clear();
+        cpu.draw_flag = true;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x00Bn
+    /// "SCU nibble" opcode. Scroll the screen up n lines.
+    fn opcode_scu_nibble(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        if cpu.mega.enabled {
+            cpu.mega.scroll(0, -(args.n as isize));
+        } else {
+            cpu.scroll_vram(0, -(args.n as isize));
+            cpu.draw_flag = true;
+        }
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x00Cn
+    /// "SCD nibble" opcode. Scroll the screen down n lines.
+    fn opcode_scd_nibble(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        if cpu.mega.enabled {
+            cpu.mega.scroll(0, args.n as isize);
+        } else {
+            cpu.scroll_vram(0, args.n as isize);
+            cpu.draw_flag = true;
         }
 
         cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x00FB
+    /// "SCR" opcode. Scroll the screen right 4 pixels.
+    fn opcode_scr(_args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        if cpu.mega.enabled {
+            cpu.mega.scroll(4, 0);
+        } else {
+            cpu.scroll_vram(4, 0);
+            cpu.draw_flag = true;
+        }
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x00FC
+    /// "SCL" opcode. Scroll the screen left 4 pixels.
+    fn opcode_scl(_args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        if cpu.mega.enabled {
+            cpu.mega.scroll(-4, 0);
+        } else {
+            cpu.scroll_vram(-4, 0);
+            cpu.draw_flag = true;
+        }
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x01kk nnnn
+    /// "LDHI I, addr" opcode. Set I = kknnnn, where nnnn is the following word.
+    fn opcode_ldhi_i_addr(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        // the following word is fetched like an instruction, so it can't run off the end of memory
+        let operand = cpu.program_counter + INSTR_SIZE;
+        if operand + 1 >= cpu.memory.len() {
+            return Err(EmulationError::FetchPastEnd(None, Box::new(cpu.state())));
+        }
+
+        let low = ((cpu.memory[operand] as usize) << 8) | (cpu.memory[operand + 1] as usize);
+        cpu.i_register = ((args.kk as usize) << 16) | low;
+
+        cpu.program_counter += INSTR_SIZE * 2;
+
+        Ok(())
+    }
+
+    /// 0x02kk
+    /// "LDPAL byte" opcode. Load kk ARGB colors starting at location I into the palette.
+    fn opcode_ldpal_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.load_palette(&cpu.memory, cpu.i_register, args.kk as usize);
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x03kk
+    /// "SPRW byte" opcode. Set the sprite width to kk (0 means 256).
+    fn opcode_sprw_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.sprite_width = if args.kk == 0 { 256 } else { args.kk as usize };
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x04kk
+    /// "SPRH byte" opcode. Set the sprite height to kk (0 means 256).
+    fn opcode_sprh_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.sprite_height = if args.kk == 0 { 256 } else { args.kk as usize };
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x05kk
+    /// "ALPHA byte" opcode. Set the sprite opacity to kk.
+    fn opcode_alpha_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.alpha = args.kk;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x060n
+    /// "DIGISND nibble" opcode. Play the digitized sound at location I, looping unless n = 1.
+    fn opcode_digisnd_nibble(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.sound = MegaChip::read_sound(&cpu.memory, cpu.i_register, args.n == 0);
+        cpu.mega.sound_flag = true;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x0700
+    /// "STOPSND" opcode. Stop any digitized sound that is playing.
+    fn opcode_stopsnd(_args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.sound = None;
+        cpu.mega.sound_flag = true;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x080n
+    /// "BMODE nibble" opcode. Set the sprite blend mode to n.
+    fn opcode_bmode_nibble(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.blend_mode = BlendMode::from_u8(args.n);
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0x09kk
+    /// "CCOL byte" opcode. Set the collision color to palette index kk.
+    fn opcode_ccol_byte(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.mega.collision_color = args.kk;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    // -------------------------------------------------------------
+    // Below are the XO-CHIP extension opcodes. They are only ever
+    // decoded when emulating the XO-CHIP variant.
+    // -------------------------------------------------------------
+
+    /// 0xF002
+    /// "AUDIO" opcode. Load the 16-byte audio pattern at location I.
+    fn opcode_audio(_args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        let mut pattern = [0u8; PATTERN_LENGTH];
+        pattern.copy_from_slice(&cpu.read_memory_range(cpu.i_register, PATTERN_LENGTH)?);
+        cpu.xo.pattern = Some(pattern);
+        cpu.xo.sound_flag = true;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+
+    /// 0xFx3A
+    /// "PITCH Vx" opcode. Set the pitch register, and so the pattern's playback rate, to Vx.
+    fn opcode_pitch_vx(args: &OpCodeArgs, cpu: &mut Cpu) -> Result<(), EmulationError> {
+        cpu.xo.pitch = cpu.data_registers[args.x];
+        cpu.xo.sound_flag = true;
+
+        cpu.program_counter += INSTR_SIZE;
+
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use cpu::Cpu;
+    use machine::MachineConfig;
+    use variant::Variant;
+
+    /// Draws the sprite at (x, y) with DRW V0, V1, n, returning VF
+    fn draw(cpu: &mut Cpu, x: u8, y: u8, n: u8, sprite: &[u8]) -> u8 {
+        cpu.memory[0x300..0x300 + sprite.len()].copy_from_slice(sprite);
+        cpu.memory[0x200] = 0xD0;
+        cpu.memory[0x201] = 0x10 | n;
+        cpu.i_register = 0x300;
+        cpu.data_registers[0] = x;
+        cpu.data_registers[1] = y;
+        cpu.program_counter = 0x200;
+
+        cpu.step().unwrap();
+        cpu.data_registers[0xF]
+    }
+
+    fn machine(variant: Variant) -> Cpu {
+        Cpu::init_from_buffer(vec![0xD0, 0x11], Some(MachineConfig::new(variant))).unwrap()
+    }
+
+    #[test]
+    fn drw_sets_vf_only_when_a_lit_pixel_is_erased() {
+        let mut cpu = machine(Variant::Chip8);
+
+        assert_eq!(draw(&mut cpu, 0, 0, 1, &[0x80]), 0);
+        assert_eq!(draw(&mut cpu, 1, 0, 1, &[0x80]), 0);
+        assert_eq!(cpu.vram[0], 0xC000_0000_0000_0000);
+
+        assert_eq!(draw(&mut cpu, 0, 0, 1, &[0xC0]), 1);
+        assert_eq!(cpu.vram[0], 0);
+    }
+
+    #[test]
+    fn drw_sets_vf_to_1_however_many_rows_collide() {
+        let mut cpu = machine(Variant::Chip8);
+
+        draw(&mut cpu, 0, 0, 3, &[0xFF, 0xFF, 0xFF]);
+        assert_eq!(draw(&mut cpu, 0, 0, 3, &[0xFF, 0xFF, 0xFF]), 1);
+    }
+
+    #[test]
+    fn drw_wraps_sprites_off_the_right_and_bottom_edges() {
+        let mut cpu = machine(Variant::Chip8);
+
+        assert_eq!(draw(&mut cpu, 60, 31, 2, &[0xFF, 0x81]), 0);
+        assert_eq!(cpu.vram[31], 0xF000_0000_0000_000F);
+        assert_eq!(cpu.vram[0], 0x1000_0000_0000_0008);
+        assert!(cpu.vram[1..31].iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn drw_wraps_coordinates_past_the_edge_of_the_screen() {
+        let mut cpu = machine(Variant::Chip8);
+
+        draw(&mut cpu, 64 + 2, 32 + 1, 1, &[0x80]);
+        assert_eq!(cpu.vram[1], 0x2000_0000_0000_0000);
+    }
+
+    #[test]
+    fn drw_records_the_pixels_it_changed() {
+        let mut cpu = machine(Variant::Chip8);
+
+        draw(&mut cpu, 62, 3, 1, &[0xE0]);
+        assert_eq!(cpu.pixel_changes, vec![(0, 3), (62, 3), (63, 3)]);
+    }
+
+    #[test]
+    fn drw_0_in_hires_draws_16x16_and_counts_collided_rows() {
+        let mut cpu = machine(Variant::HiresChip8);
+        let sprite = [0xFF; 32];
+
+        assert_eq!(draw(&mut cpu, 0, 56, 0, &sprite), 0);
+        assert_eq!(cpu.vram[56], 0xFFFF_0000_0000_0000);
+        assert_eq!(cpu.vram[7], 0xFFFF_0000_0000_0000);
+        assert_eq!(cpu.vram[8], 0);
+
+        // only the bottom 4 rows overlap the second sprite
+        assert_eq!(draw(&mut cpu, 8, 4, 0, &sprite), 4);
+        assert_eq!(cpu.vram[4], 0xFF00_FF00_0000_0000);
+    }
+
+    #[test]
+    fn drw_0_in_low_res_draws_nothing() {
+        let mut cpu = machine(Variant::Chip8);
+
+        assert_eq!(draw(&mut cpu, 0, 0, 0, &[0xFF; 32]), 0);
+        assert!(cpu.vram.iter().all(|&row| row == 0));
     }
 }
@@ -0,0 +1,41 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate font8x8;
+extern crate sdl2;
+
+use self::font8x8::legacy::BASIC_LEGACY;
+use sdl2::rect::Rect;
+use sdl2::render::Renderer;
+
+/// The width and height of a character in font pixels
+pub const GLYPH_SIZE: u32 = 8;
+
+/// How wide a line of text is on screen
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    text.chars().count() as u32 * GLYPH_SIZE * scale
+}
+
+/// Draws a line of text in the renderer's current draw color, with its top left
+/// corner at (x, y) and each font pixel drawn `scale` screen pixels across.
+/// Characters outside ASCII are drawn as question marks.
+pub fn draw_text(renderer: &mut Renderer, x: i32, y: i32, scale: u32, text: &str) {
+    let mut rects = Vec::new();
+
+    for (i, c) in text.chars().enumerate() {
+        let glyph = BASIC_LEGACY[if c.is_ascii() { c as usize } else { '?' as usize }];
+        let left = x + (i as u32 * GLYPH_SIZE * scale) as i32;
+
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..GLYPH_SIZE {
+                // the leftmost pixel of each row is the low bit
+                if bits & (1 << col) != 0 {
+                    rects.push(Rect::new(left + (col * scale) as i32, y + (row as u32 * scale) as i32, scale, scale));
+                }
+            }
+        }
+    }
+
+    let _ = renderer.fill_rects(&rects);
+}
@@ -0,0 +1,28 @@
+//
+// Author: Joshua Holmes
+//
+
+/// The colors the monochrome screen is drawn in, as 0xRRGGBB
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Palette {
+    /// the color of pixels that are off
+    pub background: u32,
+    /// the color of pixels that are on
+    pub foreground: u32,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            background: 0x107191,
+            foreground: 0xFFFFFF,
+        }
+    }
+}
+
+impl Palette {
+    /// Splits a color into its red, green and blue parts
+    pub fn rgb(color: u32) -> (u8, u8, u8) {
+        ((color >> 16) as u8, (color >> 8) as u8, color as u8)
+    }
+}
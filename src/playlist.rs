@@ -0,0 +1,75 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A list of programs to cycle through, for demo kiosks or for looking through
+/// a collection
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    /// the programs, in the order they're played
+    pub paths: Vec<PathBuf>,
+    /// the index of the program playing now
+    current: usize,
+}
+
+impl Playlist {
+    /// Builds a playlist from program paths. A directory stands for every file
+    /// in it, in name order.
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> io::Result<Playlist> {
+        let mut programs = Vec::new();
+
+        for path in paths.iter() {
+            let path = path.as_ref();
+
+            if path.is_dir() {
+                let mut entries = Vec::new();
+                for entry in fs::read_dir(path)? {
+                    let entry_path = entry?.path();
+                    if entry_path.is_file() && !is_companion_file(&entry_path) {
+                        entries.push(entry_path);
+                    }
+                }
+
+                entries.sort();
+                programs.extend(entries);
+            } else {
+                programs.push(path.to_path_buf());
+            }
+        }
+
+        Ok(Playlist {
+            paths: programs,
+            current: 0,
+        })
+    }
+
+    /// How many programs are on the playlist
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Whether the playlist has no programs
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// The program playing now
+    pub fn current(&self) -> &Path {
+        &self.paths[self.current]
+    }
+
+    /// Moves on to the next program, going back to the first after the last
+    pub fn advance(&mut self) -> &Path {
+        self.current = (self.current + 1) % self.paths.len();
+        self.current()
+    }
+}
+
+/// Whether the file is one we keep next to programs, rather than a program
+fn is_companion_file(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("state") | Some("cheats") | Some("cfg") | Some("txt") | Some("rhai"))
+}
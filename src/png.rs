@@ -0,0 +1,96 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::io::{self, Write};
+
+/// The bytes every PNG file starts with
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// The most a stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Writes an image of 8-bit grey pixels, given row by row, as a PNG. The image data
+/// is stored rather than compressed, which keeps this free of dependencies and is
+/// small enough for the sprite sheets it's used for.
+pub fn write_grayscale<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    if pixels.len() != width as usize * height as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the pixels don't fill the image"));
+    }
+
+    writer.write_all(&PNG_SIGNATURE)?;
+
+    // IHDR: the size, then 8 bits per sample, greyscale, deflate, no filtering, no interlacing
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // every row starts with the byte that says it isn't filtered
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for row in pixels.chunks(width.max(1) as usize).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(writer, b"IDAT", &zlib_stored(&raw))?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+/// Writes a chunk: its length, type, data and the CRC of the type and data
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data.iter()));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps data in a zlib stream made of stored, uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+
+    // an empty stream still needs its one, final, block
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let length = block.len() as u16;
+        out.push(if blocks.peek().is_none() { 0x01 } else { 0x00 });
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// The CRC-32 that PNG chunks end with
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+/// The Adler-32 checksum that zlib streams end with
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
@@ -0,0 +1,68 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::io;
+
+use tracing::{self, Level};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+
+/// Where the timings of the frame loop's spans go. Frames, and the work within
+/// them, are spans:
+///
+/// ```text
+/// frame           one 60Hz frame, up to the wait for the next
+///   instructions  running the frame's instructions
+///     instruction one instruction, at trace level
+///   present       telling the frontend about the screen
+///   end_frame     the frontend's work between frames: input, scripts, rewind, netplay
+/// wait            sleeping until the next frame is due
+/// display         drawing the window, on the UI thread
+/// audio           filling the sound buffer, on SDL's audio thread
+/// ```
+pub enum ProfileOutput {
+    /// how long every span took, on stderr as each one closes, leaving out single instructions
+    Console,
+    /// every span, into a file chrome://tracing or Perfetto can show
+    Chrome(String),
+}
+
+impl ProfileOutput {
+    /// The output named: "console", or the file to write a Chrome trace to
+    pub fn from_name(name: &str) -> ProfileOutput {
+        match name {
+            "console" => ProfileOutput::Console,
+            path => ProfileOutput::Chrome(path.to_owned()),
+        }
+    }
+}
+
+/// Keeps a profile going. A Chrome trace is only complete once this is dropped.
+pub struct Profiler {
+    _chrome: Option<FlushGuard>,
+}
+
+/// Starts recording the spans to the output given. This can only be done once.
+/// Log messages are left to the logger, rather than recorded as well.
+pub fn start(output: ProfileOutput) -> Result<Profiler, String> {
+    match output {
+        ProfileOutput::Console => {
+            let subscriber = tracing_subscriber::fmt()
+                .with_writer(io::stderr)
+                .with_max_level(Level::DEBUG)
+                .with_span_events(FmtSpan::CLOSE)
+                .finish();
+            tracing::subscriber::set_global_default(subscriber).map_err(|e| e.to_string())?;
+
+            Ok(Profiler { _chrome: None })
+        },
+        ProfileOutput::Chrome(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new().file(path).include_args(true).build();
+            tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer)).map_err(|e| e.to_string())?;
+
+            Ok(Profiler { _chrome: Some(guard) })
+        },
+    }
+}
@@ -0,0 +1,144 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::cmp;
+
+use cpu::Cpu;
+use emulator::Emulator;
+use machine::{FetchCheck, IOverflow, Quirks, SpriteOverrun, SysBehavior};
+use observer::NullObserver;
+use opcode::OpCode;
+
+/// Where a run with a quirk changed first went differently from one without
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// the 60Hz frame it happened in, counting from 0
+    pub frame: u32,
+    /// the address of the instruction whose result differed
+    pub addr: usize,
+    pub disasm: String,
+    /// whether the runs went on to execute different instructions, rather than
+    /// only holding different values
+    pub control_flow: bool,
+    /// the error one run stopped with where the other carried on, if that's how they differed
+    pub error: Option<String>,
+}
+
+/// A change to the quirks, and what it did to a run of the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuirkDependence {
+    /// the change, as --compare takes it, like "sprite-overrun=wrap"
+    pub change: String,
+    /// where the run with the change first went differently, if it did
+    pub divergence: Option<Divergence>,
+}
+
+/// Every change to one quirk that could make a difference to how a program runs
+/// without input. Settings that only warn are left out, as they run the same as
+/// ignoring, and so is the key order, as only a player pressing keys can show it up.
+pub fn alternatives(quirks: &Quirks) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    changes.push(if quirks.memory_protection { "no-protect-memory" } else { "protect-memory" }.to_owned());
+    changes.push(match quirks.sprite_overrun {
+        SpriteOverrun::Clamp => "sprite-overrun=wrap",
+        SpriteOverrun::Wrap => "sprite-overrun=clamp",
+    }.to_owned());
+    changes.push(match quirks.i_overflow {
+        IOverflow::SetVf => "i-overflow=clear",
+        _ => "i-overflow=set-vf",
+    }.to_owned());
+    changes.push(match quirks.sys {
+        SysBehavior::Fault => "sys=ignore",
+        _ => "sys=fault",
+    }.to_owned());
+    changes.push(match quirks.misaligned_fetch {
+        FetchCheck::Fault => "misaligned-fetch=ignore",
+        _ => "misaligned-fetch=fault",
+    }.to_owned());
+
+    changes
+}
+
+/// Runs a program for some 60Hz frames with no input alongside a run with each
+/// of the alternatives to its quirks in turn, instruction by instruction, and
+/// reports the first instruction whose result each change made a difference to.
+/// The build function makes an emulator for the program with the quirks given,
+/// and has to give every emulator the same seed, or RND alone sets them apart.
+pub fn analyze<F: Fn(Quirks) -> Emulator>(build: F, quirks: Quirks, frames: u32) -> Vec<QuirkDependence> {
+    alternatives(&quirks).into_iter().map(|change| {
+        // every change alternatives gives is one with_changes understands
+        let other_quirks = quirks.with_changes(&change).unwrap_or(quirks);
+        let divergence = diverge(&mut build(quirks), &mut build(other_quirks), frames);
+
+        QuirkDependence { change: change, divergence: divergence }
+    }).collect()
+}
+
+/// Runs two emulators in step, returning where they first went differently
+fn diverge(a: &mut Emulator, b: &mut Emulator, frames: u32) -> Option<Divergence> {
+    let per_frame = cmp::max(1, a.speed() / 60);
+    let mut found: Option<Divergence> = None;
+
+    for frame in 0..frames {
+        for _ in 0..per_frame {
+            let pc = a.cpu.program_counter;
+            let instruction = a.cpu.memory.get(pc..pc + 2).map(|w| ((w[0] as u16) << 8) | (w[1] as u16)).unwrap_or(0);
+
+            let results = (a.cycle(&mut NullObserver), b.cycle(&mut NullObserver));
+            a.cpu.warnings.clear();
+            b.cpu.warnings.clear();
+
+            let error = match results {
+                (Err(ref e), Ok(_)) | (Ok(_), Err(ref e)) => Some(e.to_string()),
+                _ => None,
+            };
+
+            match found {
+                // once they've gone their separate ways, all that's left to see is whether they run different code
+                Some(ref mut d) if a.cpu.program_counter != b.cpu.program_counter => {
+                    d.control_flow = true;
+                    return found;
+                },
+                Some(_) => (),
+                None if error.is_some() || !same_state(&a.cpu, &b.cpu) => {
+                    let disasm = OpCode::from_u16(instruction, a.cpu.config.variant).map(|o| o.disasm_str).unwrap_or_else(|| "???".to_owned());
+                    let divergence = Divergence {
+                        frame: frame,
+                        addr: pc,
+                        disasm: disasm,
+                        control_flow: a.cpu.program_counter != b.cpu.program_counter || error.is_some(),
+                        error: error,
+                    };
+                    if divergence.control_flow {
+                        return Some(divergence);
+                    }
+                    found = Some(divergence);
+                },
+                None => (),
+            }
+
+            match results {
+                (Ok(true), Ok(true)) => (),
+                _ => return found,
+            }
+        }
+
+        a.tick_timers(&mut NullObserver);
+        b.tick_timers(&mut NullObserver);
+    }
+
+    found
+}
+
+/// Whether two machines are in the same state, as far as the program can tell
+fn same_state(a: &Cpu, b: &Cpu) -> bool {
+    a.program_counter == b.program_counter &&
+        a.data_registers == b.data_registers &&
+        a.i_register == b.i_register &&
+        a.stack_pointer == b.stack_pointer &&
+        a.stack == b.stack &&
+        a.memory == b.memory &&
+        a.vram == b.vram
+}
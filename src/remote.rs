@@ -0,0 +1,184 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use tungstenite::{self, Message, WebSocket};
+
+use cpu::{Cpu, EmulationError};
+use emulator::Emulator;
+use frame::Frame;
+use frontend::Frontend;
+use observer::Observer;
+
+/// How long a viewer has to finish connecting before it's given up on
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A WebSocket server that streams the screen to remote viewers and takes key
+/// presses back, so the emulator can run on a machine with no display.
+///
+/// Every time the screen changes, each viewer is sent a binary message holding
+/// the monochrome screen as a bitmap: one bit per pixel, eight pixels to a byte
+/// with the leftmost in the high bit, row after row. A 64x32 screen is 256 bytes.
+/// Viewers press and release keys by sending two-byte binary messages: the
+/// CHIP-8 key (0x0-0xF), then 1 for pressed or 0 for released.
+pub struct RemoteServer {
+    listener: TcpListener,
+    clients: Vec<WebSocket<TcpStream>>,
+    /// where viewers that have finished connecting come in from the threads connecting them
+    connected: Receiver<(WebSocket<TcpStream>, SocketAddr)>,
+    connected_tx: Sender<(WebSocket<TcpStream>, SocketAddr)>,
+    /// the last bitmap sent, so viewers who connect later see the screen straight away
+    last_bitmap: Option<Vec<u8>>,
+}
+
+impl RemoteServer {
+    /// Starts listening for viewers on the given address
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<RemoteServer> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let (connected_tx, connected) = mpsc::channel();
+
+        Ok(RemoteServer {
+            listener: listener,
+            clients: Vec::new(),
+            connected: connected,
+            connected_tx: connected_tx,
+            last_bitmap: None,
+        })
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// How many viewers are connected
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Takes on any viewers waiting to connect. Each one's handshake is done on a
+    /// thread of its own, so a viewer that connects and then says nothing can't
+    /// hold up emulation, and it's taken on here once it's finished.
+    pub fn accept_clients(&mut self) {
+        while let Ok((stream, addr)) = self.listener.accept() {
+            let connected = self.connected_tx.clone();
+            thread::spawn(move || match handshake(stream) {
+                Ok(client) => {
+                    let _ = connected.send((client, addr));
+                },
+                Err(e) => warn!(target: "remote", "Remote viewer {} failed to connect. Error message: {}", addr, e),
+            });
+        }
+
+        while let Ok((mut client, addr)) = self.connected.try_recv() {
+            info!(target: "remote", "Remote viewer {} connected", addr);
+
+            if let Some(ref bitmap) = self.last_bitmap {
+                if !send(&mut client, bitmap.clone()) {
+                    continue;
+                }
+            }
+
+            self.clients.push(client);
+        }
+    }
+
+    /// Sends the screen to every viewer, dropping any that have gone away
+    pub fn broadcast_frame(&mut self, frame: &Frame) {
+        let bitmap = frame.packed_bytes();
+
+        self.clients.retain_mut(|client| send(client, bitmap.clone()));
+        self.last_bitmap = Some(bitmap);
+    }
+
+    /// Reads the key presses and releases viewers have sent since last time,
+    /// dropping any viewers that have gone away
+    pub fn poll_keys(&mut self) -> Vec<(u8, bool)> {
+        let mut keys = Vec::new();
+
+        self.clients.retain_mut(|client| loop {
+            match client.read() {
+                Ok(Message::Binary(data)) => {
+                    if data.len() == 2 && data[0] < 16 {
+                        keys.push((data[0], data[1] != 0));
+                    }
+                },
+                Ok(Message::Close(_)) => return false,
+                Ok(_) => (),
+                Err(tungstenite::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        });
+
+        keys
+    }
+}
+
+/// Does the WebSocket handshake with a viewer, blocking for at most
+/// HANDSHAKE_TIMEOUT at a time, and leaves the connection non-blocking
+fn handshake(stream: TcpStream) -> Result<WebSocket<TcpStream>, String> {
+    stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut client = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+    client.get_mut().set_nonblocking(true).map_err(|e| e.to_string())?;
+    Ok(client)
+}
+
+/// Sends a bitmap to a viewer. Returns false if the viewer has gone away.
+fn send(client: &mut WebSocket<TcpStream>, bitmap: Vec<u8>) -> bool {
+    match client.send(Message::Binary(bitmap)) {
+        Ok(()) => true,
+        // whatever didn't fit stays queued and goes out with the next send
+        Err(tungstenite::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => true,
+        Err(_) => false,
+    }
+}
+
+/// A frontend with no window of its own that shows the screen and takes input
+/// through a RemoteServer
+pub struct RemoteFrontend {
+    pub server: RemoteServer,
+    /// whether the program stopped by itself, by finishing or faulting, rather than being quit
+    pub halted: bool,
+}
+
+impl RemoteFrontend {
+    /// Construct a frontend around a listening server
+    pub fn new(server: RemoteServer) -> RemoteFrontend {
+        RemoteFrontend {
+            server: server,
+            halted: false,
+        }
+    }
+}
+
+impl Observer for RemoteFrontend {
+    fn on_draw(&mut self, cpu: &Cpu) {
+        self.server.broadcast_frame(&Frame::capture(cpu));
+    }
+
+    fn on_halt(&mut self, _error: Option<&EmulationError>) {
+        self.halted = true;
+    }
+}
+
+impl Frontend for RemoteFrontend {
+    fn end_frame(&mut self, emulator: &mut Emulator) -> bool {
+        self.server.accept_clients();
+
+        for (key, state) in self.server.poll_keys() {
+            emulator.cpu.keyboard.set_key(key, state);
+        }
+
+        true
+    }
+}
@@ -0,0 +1,210 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::error::Error;
+use std::fmt;
+
+use cpu::{Cpu, EmulationError};
+use emulator::Emulator;
+use frontend::Frontend;
+use movie::{Movie, MovieFrame, MovieHeader};
+use observer::Observer;
+
+/// Reasons a movie didn't play back the way it was recorded
+#[derive(Debug)]
+pub enum ReplayError {
+    /// the program stopped with an error partway through the given frame, counting from 1
+    Emulation(u64, EmulationError),
+    /// the program finished in the given frame, before the movie did
+    Halted(u64),
+    /// the machine state at the end of the given frame isn't what was recorded
+    Desync {
+        frame: u64,
+        expected: u32,
+        found: u32,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReplayError::Emulation(frame, ref e) => write!(f, "emulation stopped in frame {}: {}", frame, e),
+            ReplayError::Halted(frame) => write!(f, "the program finished in frame {}, before the movie did", frame),
+            ReplayError::Desync { frame, expected, found } => write!(f, "desync in frame {}: the state checksum is {:08X} but {:08X} was recorded",
+                frame, found, expected),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+/// A frontend that passes everything through to another one, recording the key
+/// events every frame started with, and every so often a checksum of the machine
+/// state, into a movie
+pub struct Recorder<F> {
+    /// the frontend being recorded
+    pub inner: F,
+    /// the recording so far, or None if nothing's being recorded
+    pub movie: Option<Movie>,
+}
+
+impl<F: Frontend> Recorder<F> {
+    /// Construct a recorder over the given frontend, recording a movie with the
+    /// given header if there is one
+    pub fn new(inner: F, header: Option<MovieHeader>) -> Recorder<F> {
+        Recorder {
+            inner: inner,
+            movie: header.map(Movie::new),
+        }
+    }
+}
+
+impl<F: Frontend> Observer for Recorder<F> {
+    fn on_draw(&mut self, cpu: &Cpu) {
+        self.inner.on_draw(cpu);
+    }
+
+    fn on_sound_start(&mut self) {
+        self.inner.on_sound_start();
+    }
+
+    fn on_sound_stop(&mut self) {
+        self.inner.on_sound_stop();
+    }
+
+    fn on_pitch_change(&mut self, rate: f64) {
+        self.inner.on_pitch_change(rate);
+    }
+
+    fn on_key_wait(&mut self) {
+        self.inner.on_key_wait();
+    }
+
+    fn on_halt(&mut self, error: Option<&EmulationError>) {
+        self.inner.on_halt(error);
+    }
+}
+
+impl<F: Frontend> Frontend for Recorder<F> {
+    fn before_cycle(&mut self, emulator: &mut Emulator) -> bool {
+        self.inner.before_cycle(emulator)
+    }
+
+    fn after_cycle(&mut self, emulator: &mut Emulator) -> bool {
+        self.inner.after_cycle(emulator)
+    }
+
+    fn end_frame(&mut self, emulator: &mut Emulator) -> bool {
+        // recorded before the inner frontend gets to change anything between frames
+        if let Some(ref mut movie) = self.movie {
+            let index = movie.frames.len() as u64;
+            movie.frames.push(MovieFrame {
+                events: emulator.frame_input.clone(),
+                checksum: if movie.header.checksum_due(index) { Some(emulator.cpu.checksum()) } else { None },
+            });
+        }
+
+        self.inner.end_frame(emulator)
+    }
+}
+
+/// Plays a movie back as fast as possible on an emulator set up as its header says,
+/// checking the machine state wherever the recording did. Returns how many frames
+/// were played, or where playback first went differently.
+pub fn play(movie: &Movie, emulator: &mut Emulator) -> Result<u64, ReplayError> {
+    let mut played = 0;
+
+    for frame in movie.frames.iter() {
+        played += 1;
+        for &event in frame.events.iter() {
+            emulator.input.push(event);
+        }
+
+        let report = match emulator.run_frame() {
+            Ok(r) => r,
+            Err(e) => return Err(ReplayError::Emulation(played, e)),
+        };
+
+        // a program that finished in the last frame has played the whole movie
+        if report.halted && played < movie.frames.len() as u64 {
+            return Err(ReplayError::Halted(played));
+        }
+
+        if let Some(expected) = frame.checksum {
+            let found = emulator.cpu.checksum();
+            if found != expected {
+                return Err(ReplayError::Desync { frame: played, expected: expected, found: found });
+            }
+        }
+    }
+
+    Ok(played)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audiosink::{RecordingSink, SinkObserver};
+    use cpu::Cpu;
+    use input::KeyEvent;
+    use machine::{MachineConfig, Quirks};
+    use variant::Variant;
+
+    // loop: LD V0, K; ADD V1, V0; JP loop
+    const PROGRAM: [u8; 6] = [0xF0, 0x0A, 0x81, 0x04, 0x12, 0x00];
+
+    fn machine() -> Emulator {
+        Emulator::new(Cpu::init_from_buffer(PROGRAM.to_vec(), Some(MachineConfig::new(Variant::Chip8))).unwrap())
+    }
+
+    fn header() -> MovieHeader {
+        MovieHeader {
+            program_checksum: 0,
+            variant: Variant::Chip8,
+            quirks: Quirks::default(),
+            seed: 0,
+            speed: 500,
+            checksum_interval: 1,
+            font: None,
+        }
+    }
+
+    #[test]
+    fn taps_within_a_frame_play_back_as_recorded() {
+        let mut emulator = machine();
+        let mut recorder = Recorder::new(SinkObserver::new(RecordingSink::new()), Some(header()));
+
+        for frame in 0..10 {
+            if frame == 3 || frame == 6 {
+                for &(key, pressed) in [(0x5, true), (0x5, false), (0x2, true)].iter() {
+                    emulator.input.push(KeyEvent { timestamp: frame * 16, key: key, pressed: pressed });
+                }
+            }
+            if frame == 4 {
+                emulator.input.push(KeyEvent { timestamp: frame * 16, key: 0x2, pressed: false });
+            }
+            emulator.run_frame_with(&mut recorder).unwrap();
+        }
+        assert_eq!(emulator.cpu.data_registers[1], 0x4);
+
+        let movie = recorder.movie.unwrap();
+        assert_eq!(movie.frames[3].events.len(), 3);
+        match play(&movie, &mut machine()) {
+            Ok(10) => (),
+            r => panic!("expected all 10 frames to play, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn playback_stops_where_the_state_differs() {
+        let mut movie = Movie::new(header());
+        movie.frames.push(MovieFrame { events: Vec::new(), checksum: None });
+        movie.frames.push(MovieFrame { events: vec![KeyEvent { timestamp: 0, key: 1, pressed: true }], checksum: Some(0) });
+
+        match play(&movie, &mut machine()) {
+            Err(ReplayError::Desync { frame: 2, expected: 0, .. }) => (),
+            r => panic!("expected a desync in frame 2, got {:?}", r),
+        }
+    }
+}
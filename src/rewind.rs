@@ -0,0 +1,319 @@
+//
+// Author: Joshua Holmes
+//
+
+use bincode;
+use std::collections::VecDeque;
+
+use cpu::Cpu;
+use savestate::SaveStateError;
+
+/// How much memory the rewind history may take up by default
+pub const DEFAULT_REWIND_CAPACITY: usize = 4 * 1024 * 1024;
+/// About how many bytes of machine state may be serialized each frame. Machines
+/// bigger than this, like Mega-Chip's 16 MB, are only kept every so many frames.
+pub const SNAPSHOT_BUDGET: usize = 256 * 1024;
+
+/// The machine as it was over the last while, for stepping back through.
+///
+/// Only the newest snapshot is kept whole. Every older one is kept as the XOR of
+/// itself with the snapshot after it, which is almost all zeroes since little
+/// changes from one frame to the next, and the runs of zeroes are squeezed out.
+/// Undoing the newest delta on the newest snapshot gives back the one before.
+///
+/// Snapshots of small machines are taken every frame, and those of big ones only
+/// as often as SNAPSHOT_BUDGET allows, so each step back goes further on them.
+pub struct RewindBuffer {
+    /// the newest snapshot, serialized
+    latest: Option<Vec<u8>>,
+    /// the deltas that each turn a snapshot into the one before it, oldest first
+    deltas: VecDeque<Vec<u8>>,
+    /// how many bytes the deltas may take up before the oldest are dropped
+    capacity: usize,
+    /// how many bytes the deltas take up
+    size: usize,
+    /// how many frames apart snapshots are taken, going by the size of the last one
+    interval: usize,
+    /// how many frames have passed since the last snapshot
+    frames: usize,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> RewindBuffer {
+        RewindBuffer::new(DEFAULT_REWIND_CAPACITY)
+    }
+}
+
+impl RewindBuffer {
+    /// Construct an empty buffer whose history may take up about the given number of bytes
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            latest: None,
+            deltas: VecDeque::new(),
+            capacity: capacity,
+            size: 0,
+            interval: 1,
+            frames: 0,
+        }
+    }
+
+    /// Counts a frame, taking a snapshot of the machine as it ends if enough have
+    /// passed since the last one
+    pub fn end_frame(&mut self, cpu: &Cpu) -> Result<(), SaveStateError> {
+        self.frames += 1;
+        if self.frames < self.interval {
+            return Ok(());
+        }
+
+        self.push(cpu)
+    }
+
+    /// Takes a snapshot of the machine, forgetting the oldest ones if there's no room
+    pub fn push(&mut self, cpu: &Cpu) -> Result<(), SaveStateError> {
+        let snapshot = bincode::serialize(cpu)?;
+        self.interval = (snapshot.len() / SNAPSHOT_BUDGET).max(1);
+        self.frames = 0;
+
+        if let Some(previous) = self.latest.take() {
+            let delta = encode_delta(&snapshot, &previous);
+            self.size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(snapshot);
+
+        while self.size > self.capacity {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Steps back to the snapshot before the newest, forgetting the newest, and
+    /// returns the machine as it was then. Returns None once there's nothing older.
+    pub fn rewind(&mut self) -> Result<Option<Cpu>, SaveStateError> {
+        let delta = match self.deltas.pop_back() {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        self.size -= delta.len();
+
+        let latest = self.latest.take().unwrap_or_default();
+        let previous = decode_delta(&latest, &delta);
+        let cpu = bincode::deserialize(&previous)?;
+        self.latest = Some(previous);
+
+        Ok(Some(cpu))
+    }
+
+    /// How many snapshots there are to step back through
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Says whether there's nothing to step back through
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// How many bytes the history takes up
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Forgets every snapshot
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.size = 0;
+        self.interval = 1;
+        self.frames = 0;
+    }
+}
+
+/// Encodes what turns `from` into `to`: the length of `to`, then the XOR of the two,
+/// with the shorter padded with zeroes, as runs of zeroes each followed by the bytes
+/// up to the next run. Every number is written as a LEB128 varint.
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let length = from.len().max(to.len());
+    let xor = |i: usize| from.get(i).unwrap_or(&0) ^ to.get(i).unwrap_or(&0);
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, to.len());
+
+    let mut i = 0;
+    while i < length {
+        let zeroes_start = i;
+        while i < length && xor(i) == 0 {
+            i += 1;
+        }
+
+        let literal_start = i;
+        while i < length && xor(i) != 0 {
+            i += 1;
+        }
+
+        write_varint(&mut delta, literal_start - zeroes_start);
+        write_varint(&mut delta, i - literal_start);
+        delta.extend((literal_start..i).map(xor));
+    }
+
+    delta
+}
+
+/// Applies a delta made by encode_delta to `from`, giving back `to`
+fn decode_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let length = read_varint(delta, &mut pos);
+
+    let mut to = from.to_vec();
+    to.resize(length.max(from.len()), 0);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literal_length = read_varint(delta, &mut pos);
+
+        for &byte in delta[pos..pos + literal_length].iter() {
+            to[i] ^= byte;
+            i += 1;
+        }
+        pos += literal_length;
+    }
+
+    to.truncate(length);
+    to
+}
+
+/// Writes a number seven bits at a time, lowest first, with the top bit saying more follow
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a number written by write_varint, moving past it
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    while let Some(&byte) = data.get(*pos) {
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use machine::MachineConfig;
+    use variant::Variant;
+
+    #[test]
+    fn deltas_turn_one_buffer_into_the_other() {
+        let a = vec![1, 2, 3, 0, 0, 0, 0, 7, 8, 9];
+        let cases = [
+            a.clone(),
+            vec![1, 2, 4, 0, 0, 0, 0, 7, 8, 0],
+            vec![1, 2, 3],
+            vec![1, 2, 3, 0, 0, 0, 0, 7, 8, 9, 10, 0, 300u16 as u8],
+            vec![0xFF; 200],
+            vec![],
+        ];
+
+        for b in cases.iter() {
+            assert_eq!(&decode_delta(&a, &encode_delta(&a, b)), b);
+            assert_eq!(decode_delta(b, &encode_delta(b, &a)), a);
+        }
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for &value in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, usize::MAX].iter() {
+            let mut data = Vec::new();
+            write_varint(&mut data, value);
+            let mut pos = 0;
+            assert_eq!(read_varint(&data, &mut pos), value);
+            assert_eq!(pos, data.len());
+        }
+    }
+
+    #[test]
+    fn rewinding_steps_back_one_snapshot_at_a_time() {
+        // ADD V0, 1; JP 0x200
+        let mut cpu = Cpu::init_from_buffer(vec![0x70, 0x01, 0x12, 0x00], Some(MachineConfig::new(Variant::Chip8))).unwrap();
+        let mut buffer = RewindBuffer::default();
+
+        for _ in 0..5 {
+            buffer.push(&cpu).unwrap();
+            cpu.step().unwrap();
+            cpu.step().unwrap();
+        }
+        assert_eq!(buffer.len(), 4);
+
+        for v0 in (0..4).rev() {
+            assert_eq!(buffer.rewind().unwrap().unwrap().data_registers[0], v0);
+        }
+        assert!(buffer.rewind().unwrap().is_none());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn the_oldest_snapshots_are_dropped_when_full() {
+        // ADD V0, 1; JP 0x200
+        let mut cpu = Cpu::init_from_buffer(vec![0x70, 0x01, 0x12, 0x00], Some(MachineConfig::new(Variant::Chip8))).unwrap();
+        let mut buffer = RewindBuffer::new(usize::MAX);
+        buffer.push(&cpu).unwrap();
+        cpu.step().unwrap();
+        buffer.push(&cpu).unwrap();
+
+        // room for about ten steps
+        let capacity = buffer.size() * 10;
+        buffer = RewindBuffer::new(capacity);
+        for _ in 0..100 {
+            cpu.step().unwrap();
+            buffer.push(&cpu).unwrap();
+        }
+        assert!(buffer.size() <= capacity);
+        assert!(buffer.len() > 1 && buffer.len() < 99);
+
+        // the newest snapshots can still be stepped back to
+        let v0 = cpu.data_registers[0];
+        while let Some(previous) = buffer.rewind().unwrap() {
+            assert!(previous.data_registers[0] <= v0);
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn big_machines_are_kept_less_often() {
+        let mut chip8 = Cpu::init_from_buffer(vec![0x12, 0x00], Some(MachineConfig::new(Variant::Chip8))).unwrap();
+        let mut buffer = RewindBuffer::default();
+        for _ in 0..3 {
+            buffer.end_frame(&chip8).unwrap();
+            chip8.step().unwrap();
+        }
+        assert_eq!(buffer.len(), 2);
+
+        let mega = Cpu::init_from_buffer(vec![0x12, 0x00], Some(MachineConfig::new(Variant::MegaChip))).unwrap();
+        buffer.clear();
+        buffer.end_frame(&mega).unwrap();
+        assert!(buffer.interval > 1);
+        for _ in 1..buffer.interval {
+            buffer.end_frame(&mega).unwrap();
+        }
+        assert!(buffer.is_empty());
+        buffer.end_frame(&mega).unwrap();
+        assert_eq!(buffer.len(), 1);
+    }
+}
@@ -0,0 +1,228 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use font::Font;
+use rotation::Rotation;
+use variant::Variant;
+
+/// Reasons a program's config file couldn't be loaded
+#[derive(Debug)]
+pub enum RomConfigError {
+    IoError(io::Error),
+    /// a line of the config file couldn't be understood
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl From<io::Error> for RomConfigError {
+    fn from(err: io::Error) -> Self {
+        RomConfigError::IoError(err)
+    }
+}
+
+impl fmt::Display for RomConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomConfigError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            RomConfigError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+/// Settings that suit one program in particular, kept in a file next to it.
+/// Anything not set is left to the command line or the defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomConfig {
+    /// the pitch of the buzzer, in Hz
+    pub beep_pitch: Option<f64>,
+    /// the name of a historical font, or the file holding a font, to use instead of the built-in one
+    pub font: Option<String>,
+    /// the variant the program was written for
+    pub variant: Option<Variant>,
+    /// how many instructions run per second
+    pub speed: Option<u32>,
+    /// how many window pixels across each pixel of the display is
+    pub scale: Option<u32>,
+    /// the index of the monitor the window opens on
+    pub monitor: Option<i32>,
+    /// how far the screen is turned as it's drawn, for programs meant to be played sideways
+    pub rotation: Option<Rotation>,
+    /// the name of the program
+    pub title: Option<String>,
+    /// who wrote the program
+    pub author: Option<String>,
+    /// when, or in what, the program was released
+    pub release: Option<String>,
+}
+
+impl Default for RomConfig {
+    fn default() -> RomConfig {
+        RomConfig::new()
+    }
+}
+
+impl RomConfig {
+    /// Construct a config that sets nothing
+    pub fn new() -> RomConfig {
+        RomConfig {
+            beep_pitch: None,
+            font: None,
+            variant: None,
+            speed: None,
+            scale: None,
+            monitor: None,
+            rotation: None,
+            title: None,
+            author: None,
+            release: None,
+        }
+    }
+
+    /// The path a program's config is kept at: next to the program, with a .cfg extension
+    pub fn path_for(program_path: &str) -> PathBuf {
+        Path::new(program_path).with_extension("cfg")
+    }
+
+    /// Describes a program by its title, author and release if they're known,
+    /// or else by the name of its file
+    pub fn describe(&self, program_path: &str) -> String {
+        let mut description = match self.title {
+            Some(ref title) => title.clone(),
+            None => Path::new(program_path).file_name().map_or(program_path.to_owned(), |n| n.to_string_lossy().into_owned()),
+        };
+
+        if let Some(ref author) = self.author {
+            description.push_str(&format!(" by {}", author));
+        }
+
+        if let Some(ref release) = self.release {
+            description.push_str(&format!(" ({})", release));
+        }
+
+        description
+    }
+
+    /// Loads a config from a file. Each line is `<key> = <value>`, where the keys are:
+    ///
+    /// ```text
+    /// beep-pitch = <Hz>
+    /// font = <chip48|vip|dream6800|eti660|schip, or a font file relative to the config file>
+    /// variant = <chip8|hires|eti660|megachip|xochip>
+    /// speed = <instructions per second>
+    /// scale = <window pixels per display pixel>
+    /// monitor = <the monitor to open on, counting from 0 for the primary one>
+    /// rotate = <0|90|180|270 degrees clockwise>
+    /// title = <the name of the program>
+    /// author = <who wrote it>
+    /// release = <when, or in what, it was released>
+    /// ```
+    ///
+    /// Blank lines and lines starting with # are ignored.
+    pub fn load_file(path: &Path) -> Result<RomConfig, RomConfigError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut config = RomConfig::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parse_error = |message: &str| RomConfigError::Parse { line: i + 1, message: message.to_owned() };
+
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(k), Some(v)) => (k.trim(), v.trim()),
+                _ => return Err(parse_error("expected <key> = <value>")),
+            };
+
+            match key {
+                "beep-pitch" => config.beep_pitch = match value.parse() {
+                    Ok(pitch) if pitch > 0.0 => Some(pitch),
+                    _ => return Err(parse_error("expected a pitch in Hz")),
+                },
+                "font" if Font::from_name(value).is_some() => config.font = Some(value.to_owned()),
+                "font" if !value.is_empty() => {
+                    let font_path = path.parent().unwrap_or_else(|| Path::new("")).join(value);
+                    config.font = Some(font_path.to_string_lossy().into_owned());
+                },
+                "font" => return Err(parse_error("expected a font name or file")),
+                "variant" => config.variant = match Variant::from_name(value) {
+                    Some(v) => Some(v),
+                    None => return Err(parse_error("expected one of chip8, hires, eti660, megachip, xochip")),
+                },
+                "speed" => config.speed = match value.parse() {
+                    Ok(speed) => Some(speed),
+                    Err(_) => return Err(parse_error("expected a number of instructions per second")),
+                },
+                "scale" => config.scale = match value.parse() {
+                    Ok(scale) if scale > 0 => Some(scale),
+                    _ => return Err(parse_error("expected a whole number of window pixels per display pixel")),
+                },
+                "monitor" => config.monitor = match value.parse() {
+                    Ok(monitor) if monitor >= 0 => Some(monitor),
+                    _ => return Err(parse_error("expected a monitor number, counting from 0")),
+                },
+                "rotate" => config.rotation = match value.parse().ok().and_then(Rotation::from_degrees) {
+                    Some(rotation) => Some(rotation),
+                    None => return Err(parse_error("expected one of 0, 90, 180, 270")),
+                },
+                "title" => config.title = Some(value.to_owned()),
+                "author" => config.author = Some(value.to_owned()),
+                "release" => config.release = Some(value.to_owned()),
+                _ => return Err(parse_error(&format!("unknown key {}", key))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Saves the config to a file in the form load_file reads. A font file is
+    /// written as it was found, so it should be relative to the config file.
+    pub fn save_file(&self, path: &Path) -> Result<(), RomConfigError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        if let Some(pitch) = self.beep_pitch {
+            writeln!(writer, "beep-pitch = {}", pitch)?;
+        }
+        if let Some(ref font) = self.font {
+            writeln!(writer, "font = {}", font)?;
+        }
+        if let Some(variant) = self.variant {
+            writeln!(writer, "variant = {}", variant.name())?;
+        }
+        if let Some(speed) = self.speed {
+            writeln!(writer, "speed = {}", speed)?;
+        }
+        if let Some(scale) = self.scale {
+            writeln!(writer, "scale = {}", scale)?;
+        }
+        if let Some(monitor) = self.monitor {
+            writeln!(writer, "monitor = {}", monitor)?;
+        }
+        if let Some(rotation) = self.rotation {
+            writeln!(writer, "rotate = {}", rotation.degrees())?;
+        }
+        if let Some(ref title) = self.title {
+            writeln!(writer, "title = {}", title)?;
+        }
+        if let Some(ref author) = self.author {
+            writeln!(writer, "author = {}", author)?;
+        }
+        if let Some(ref release) = self.release {
+            writeln!(writer, "release = {}", release)?;
+        }
+
+        Ok(())
+    }
+}
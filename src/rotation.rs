@@ -0,0 +1,55 @@
+//
+// Author: Joshua Holmes
+//
+
+/// How far the screen is turned, clockwise, when it's drawn. Only the picture
+/// turns; the program still sees its screen the right way up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    /// Looks up a rotation by its angle in degrees, which has to be a multiple of 90
+    pub fn from_degrees(degrees: u32) -> Option<Rotation> {
+        match degrees % 360 {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Quarter),
+            180 => Some(Rotation::Half),
+            270 => Some(Rotation::ThreeQuarters),
+            _ => None,
+        }
+    }
+
+    /// The angle of this rotation in degrees
+    pub fn degrees(&self) -> u32 {
+        match *self {
+            Rotation::None => 0,
+            Rotation::Quarter => 90,
+            Rotation::Half => 180,
+            Rotation::ThreeQuarters => 270,
+        }
+    }
+
+    /// The rotation a further quarter turn clockwise, back round to None after three
+    pub fn next(&self) -> Rotation {
+        match *self {
+            Rotation::None => Rotation::Quarter,
+            Rotation::Quarter => Rotation::Half,
+            Rotation::Half => Rotation::ThreeQuarters,
+            Rotation::ThreeQuarters => Rotation::None,
+        }
+    }
+
+    /// Whether the screen is on its side, so its width runs up and down the window
+    pub fn is_sideways(&self) -> bool {
+        match *self {
+            Rotation::Quarter | Rotation::ThreeQuarters => true,
+            Rotation::None | Rotation::Half => false,
+        }
+    }
+}
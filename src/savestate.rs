@@ -0,0 +1,589 @@
+//
+// Author: Joshua Holmes
+//
+
+use bincode;
+#[cfg(feature = "compress")]
+use flate2::Compression;
+#[cfg(feature = "compress")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "compress")]
+use flate2::write::GzEncoder;
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use cpu::Cpu;
+use machine::{FetchCheck, IOverflow, KeyOrder, MachineConfig, Quirks, SpriteOverrun, SysBehavior};
+use variant::Variant;
+use xochip::XoChip;
+use xorshift::Xorshift;
+
+/// The bytes every save-state file starts with
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"C8SS";
+/// The version of the save-state format written by this build. Bump this whenever
+/// the serialized machine state changes shape, and teach `load_state` to migrate.
+pub const SAVE_STATE_VERSION: u16 = 9;
+/// The bytes a gzip stream starts with, which is what compressed save-state files are
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Describes the machine a save-state was taken from, so it can be checked before
+/// the (much bigger) machine state is decoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveStateHeader {
+    /// the kind of machine the state was taken from
+    pub variant: Variant,
+    /// the memory layout and behavior switches in effect when the state was taken
+    pub config: MachineConfig,
+    /// the checksum of the program the state was taken from, if known
+    pub program_checksum: Option<u32>,
+}
+
+/// The header written by version 1, before program checksums were recorded
+#[derive(Deserialize)]
+struct SaveStateHeaderV1 {
+    variant: Variant,
+    config: OldMachineConfig<QuirksV2>,
+}
+
+impl From<SaveStateHeaderV1> for SaveStateHeader {
+    fn from(header: SaveStateHeaderV1) -> Self {
+        SaveStateHeader {
+            variant: header.variant,
+            config: header.config.into(),
+            program_checksum: None,
+        }
+    }
+}
+
+/// The header written by versions 2 and up, with whichever quirks that version had
+#[derive(Deserialize)]
+struct OldSaveStateHeader<Q> {
+    variant: Variant,
+    config: OldMachineConfig<Q>,
+    program_checksum: Option<u32>,
+}
+
+impl<Q: Into<Quirks>> From<OldSaveStateHeader<Q>> for SaveStateHeader {
+    fn from(header: OldSaveStateHeader<Q>) -> Self {
+        SaveStateHeader {
+            variant: header.variant,
+            config: header.config.into(),
+            program_checksum: header.program_checksum,
+        }
+    }
+}
+
+/// The machine configuration written by older versions, which only differed in their quirks
+#[derive(Deserialize)]
+struct OldMachineConfig<Q> {
+    variant: Variant,
+    memory_length: usize,
+    program_start_addr: usize,
+    execution_start_addr: usize,
+    font_start_addr: usize,
+    quirks: Q,
+    history_length: usize,
+}
+
+impl<Q: Into<Quirks>> From<OldMachineConfig<Q>> for MachineConfig {
+    fn from(config: OldMachineConfig<Q>) -> Self {
+        MachineConfig {
+            variant: config.variant,
+            memory_length: config.memory_length,
+            program_start_addr: config.program_start_addr,
+            execution_start_addr: config.execution_start_addr,
+            font_start_addr: config.font_start_addr,
+            quirks: config.quirks.into(),
+            history_length: config.history_length,
+        }
+    }
+}
+
+/// The quirks written by versions 1 and 2, before ADD I, Vx's effect on VF was a quirk
+#[derive(Deserialize)]
+struct QuirksV2 {
+    memory_protection: bool,
+    sprite_overrun: SpriteOverrun,
+}
+
+impl From<QuirksV2> for QuirksV3 {
+    fn from(quirks: QuirksV2) -> Self {
+        QuirksV3 {
+            memory_protection: quirks.memory_protection,
+            sprite_overrun: quirks.sprite_overrun,
+            i_overflow: IOverflow::Clear,
+        }
+    }
+}
+
+impl From<QuirksV2> for Quirks {
+    fn from(quirks: QuirksV2) -> Self {
+        QuirksV3::from(quirks).into()
+    }
+}
+
+/// The quirks written by version 3, before what SYS does was a quirk
+#[derive(Deserialize)]
+struct QuirksV3 {
+    memory_protection: bool,
+    sprite_overrun: SpriteOverrun,
+    i_overflow: IOverflow,
+}
+
+impl From<QuirksV3> for QuirksV4 {
+    fn from(quirks: QuirksV3) -> Self {
+        QuirksV4 {
+            memory_protection: quirks.memory_protection,
+            sprite_overrun: quirks.sprite_overrun,
+            i_overflow: quirks.i_overflow,
+            sys: SysBehavior::Ignore,
+        }
+    }
+}
+
+impl From<QuirksV3> for Quirks {
+    fn from(quirks: QuirksV3) -> Self {
+        QuirksV4::from(quirks).into()
+    }
+}
+
+/// The quirks written by version 4, before LD Vx, K's choice of key was a quirk
+#[derive(Deserialize)]
+struct QuirksV4 {
+    memory_protection: bool,
+    sprite_overrun: SpriteOverrun,
+    i_overflow: IOverflow,
+    sys: SysBehavior,
+}
+
+impl From<QuirksV4> for QuirksV5 {
+    fn from(quirks: QuirksV4) -> Self {
+        QuirksV5 {
+            memory_protection: quirks.memory_protection,
+            sprite_overrun: quirks.sprite_overrun,
+            i_overflow: quirks.i_overflow,
+            sys: quirks.sys,
+            key_order: KeyOrder::Lowest,
+        }
+    }
+}
+
+impl From<QuirksV4> for Quirks {
+    fn from(quirks: QuirksV4) -> Self {
+        QuirksV5::from(quirks).into()
+    }
+}
+
+/// The quirks written by versions 5 and 6, before misaligned fetches could be caught
+#[derive(Deserialize)]
+struct QuirksV5 {
+    memory_protection: bool,
+    sprite_overrun: SpriteOverrun,
+    i_overflow: IOverflow,
+    sys: SysBehavior,
+    key_order: KeyOrder,
+}
+
+impl From<QuirksV5> for Quirks {
+    fn from(quirks: QuirksV5) -> Self {
+        Quirks {
+            memory_protection: quirks.memory_protection,
+            sprite_overrun: quirks.sprite_overrun,
+            i_overflow: quirks.i_overflow,
+            sys: quirks.sys,
+            key_order: quirks.key_order,
+            misaligned_fetch: FetchCheck::Ignore,
+        }
+    }
+}
+
+/// Reasons a save-state couldn't be written or loaded
+#[derive(Debug)]
+pub enum SaveStateError {
+    IoError(io::Error),
+    /// the file doesn't start with the save-state magic bytes
+    NotASaveState,
+    /// the file was written by a newer build, or an older format we can no longer read
+    UnsupportedVersion(u16),
+    /// the state was taken from a different kind of machine than the one running
+    VariantMismatch {
+        expected: Variant,
+        found: Variant,
+    },
+    /// the state was taken while running a different program
+    ProgramMismatch,
+    /// the header or machine state couldn't be decoded
+    Corrupt(String),
+    /// the file is compressed, and this build can't decompress it
+    Compressed,
+}
+
+impl From<io::Error> for SaveStateError {
+    fn from(err: io::Error) -> Self {
+        SaveStateError::IoError(err)
+    }
+}
+
+impl From<bincode::Error> for SaveStateError {
+    fn from(err: bincode::Error) -> Self {
+        match *err {
+            bincode::ErrorKind::Io(e) => SaveStateError::IoError(e),
+            e => SaveStateError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveStateError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            SaveStateError::NotASaveState => write!(f, "not a save-state file"),
+            SaveStateError::UnsupportedVersion(v) => write!(f, "unsupported save-state version {} (this build reads version {})",
+                v, SAVE_STATE_VERSION),
+            SaveStateError::VariantMismatch { expected, found } => write!(f, "save-state is for {:?} but the running machine is {:?}",
+                found, expected),
+            SaveStateError::ProgramMismatch => write!(f, "save-state was taken from a different program"),
+            SaveStateError::Corrupt(ref msg) => write!(f, "save-state is corrupt: {}", msg),
+            SaveStateError::Compressed => write!(f, "save-state is compressed, which this build can't read (rebuild with --features compress)"),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+/// The path a program's save-state is kept at: next to the program, with a .state extension
+pub fn state_path(program_path: &str) -> PathBuf {
+    Path::new(program_path).with_extension("state")
+}
+
+/// The path the state is automatically saved to on exit
+pub fn autosave_path(program_path: &str) -> PathBuf {
+    Path::new(program_path).with_extension("autosave.state")
+}
+
+/// A 32-bit FNV-1a hash of the program, used to tell whether a state belongs to it
+pub fn program_checksum(program: &[u8]) -> u32 {
+    let mut hash = Fnv::new();
+    hash.write(program);
+    hash.0
+}
+
+/// A 32-bit FNV-1a hash fed a slice at a time, for hashing state without first
+/// copying it all into one buffer
+pub struct Fnv(pub u32);
+
+impl Default for Fnv {
+    fn default() -> Fnv {
+        Fnv::new()
+    }
+}
+
+impl Fnv {
+    /// Starts a hash of nothing
+    pub fn new() -> Fnv {
+        Fnv(0x811C9DC5)
+    }
+
+    /// Folds the bytes into the hash
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |hash, &byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    }
+}
+
+/// Writes the magic bytes, format version, header and machine state
+pub fn save_state<W: Write>(cpu: &Cpu, program_checksum: Option<u32>, writer: &mut W) -> Result<(), SaveStateError> {
+    let header = SaveStateHeader {
+        variant: cpu.config.variant,
+        config: cpu.config,
+        program_checksum: program_checksum,
+    };
+
+    writer.write_all(&SAVE_STATE_MAGIC)?;
+    writer.write_all(&[(SAVE_STATE_VERSION >> 8) as u8, SAVE_STATE_VERSION as u8])?;
+    bincode::serialize_into(&mut *writer, &header)?;
+    bincode::serialize_into(&mut *writer, cpu)?;
+
+    Ok(())
+}
+
+/// Reads a save-state, refusing it if it isn't one, was written in a format version
+/// we can't read, or was taken from a different variant or program than the ones given
+pub fn load_state<R: Read>(reader: &mut R, variant: Option<Variant>, program_checksum: Option<u32>) -> Result<Cpu, SaveStateError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != SAVE_STATE_MAGIC {
+        return Err(SaveStateError::NotASaveState);
+    }
+
+    let mut version = [0u8; 2];
+    reader.read_exact(&mut version)?;
+    let version = ((version[0] as u16) << 8) | version[1] as u16;
+
+    // older versions get migrated here as the format changes
+    let header: SaveStateHeader = match version {
+        1 => bincode::deserialize_from::<_, SaveStateHeaderV1>(&mut *reader)?.into(),
+        2 => bincode::deserialize_from::<_, OldSaveStateHeader<QuirksV2>>(&mut *reader)?.into(),
+        3 => bincode::deserialize_from::<_, OldSaveStateHeader<QuirksV3>>(&mut *reader)?.into(),
+        4 => bincode::deserialize_from::<_, OldSaveStateHeader<QuirksV4>>(&mut *reader)?.into(),
+        5 | 6 => bincode::deserialize_from::<_, OldSaveStateHeader<QuirksV5>>(&mut *reader)?.into(),
+        7 | 8 | SAVE_STATE_VERSION => bincode::deserialize_from(&mut *reader)?,
+        v => return Err(SaveStateError::UnsupportedVersion(v)),
+    };
+
+    if let Some(expected) = variant {
+        if header.variant != expected {
+            return Err(SaveStateError::VariantMismatch { expected: expected, found: header.variant });
+        }
+    }
+
+    if let (Some(expected), Some(found)) = (program_checksum, header.program_checksum) {
+        if expected != found {
+            return Err(SaveStateError::ProgramMismatch);
+        }
+    }
+
+    // the XO-CHIP state was added to the end of the machine state in version 6, and
+    // ended with the sound flag until version 8. The random number generator was
+    // added after it in version 9; older states get one started from somewhere random.
+    let rng = bincode::serialize(&Xorshift::from_entropy())?;
+    let xo = [bincode::serialize(&XoChip::new())?, rng.clone()].concat();
+    let cpu: Cpu = match version {
+        1 | 2 => load_migrated_cpu::<_, OldMachineConfig<QuirksV2>>(reader, &header.config, &xo)?,
+        3 => load_migrated_cpu::<_, OldMachineConfig<QuirksV3>>(reader, &header.config, &xo)?,
+        4 => load_migrated_cpu::<_, OldMachineConfig<QuirksV4>>(reader, &header.config, &xo)?,
+        5 => load_migrated_cpu::<_, OldMachineConfig<QuirksV5>>(reader, &header.config, &xo)?,
+        6 => load_migrated_cpu::<_, OldMachineConfig<QuirksV5>>(&mut read_without_sound_flag(reader)?.as_slice(), &header.config, &rng)?,
+        7 => bincode::deserialize_from(read_without_sound_flag(reader)?.as_slice().chain(rng.as_slice()))?,
+        8 => bincode::deserialize_from((&mut *reader).chain(rng.as_slice()))?,
+        _ => bincode::deserialize_from(&mut *reader)?,
+    };
+
+    if cpu.config != header.config {
+        return Err(SaveStateError::Corrupt("header doesn't match the machine state".to_owned()));
+    }
+
+    Ok(cpu)
+}
+
+/// Reads the machine state written by an older version. It starts with the machine
+/// configuration in the old shape, which is swapped for the migrated one from the
+/// header, and is missing the fields added since, which are read from `missing`.
+/// The machine state has to be the last thing in the reader.
+fn load_migrated_cpu<R: Read, C: DeserializeOwned>(reader: &mut R, config: &MachineConfig, missing: &[u8]) -> Result<Cpu, SaveStateError> {
+    bincode::deserialize_from::<_, C>(&mut *reader)?;
+    let config = bincode::serialize(config)?;
+
+    Ok(bincode::deserialize_from(config.as_slice().chain(reader).chain(missing))?)
+}
+
+/// Reads the rest of a machine state written by version 6 or 7, leaving off the
+/// XO-CHIP sound flag it ended with
+fn read_without_sound_flag<R: Read>(reader: &mut R) -> Result<Vec<u8>, SaveStateError> {
+    let mut state = Vec::new();
+    reader.read_to_end(&mut state)?;
+    state.pop();
+
+    Ok(state)
+}
+
+/// Saves the machine to the given file, gzipped if this build supports compression
+pub fn save_state_file(cpu: &Cpu, program_checksum: Option<u32>, path: &Path) -> Result<(), SaveStateError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    #[cfg(feature = "compress")]
+    {
+        let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+        save_state(cpu, program_checksum, &mut encoder)?;
+        encoder.finish()?;
+    }
+
+    #[cfg(not(feature = "compress"))]
+    save_state(cpu, program_checksum, &mut writer)?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Loads a machine from the given file, whether it was saved compressed or not
+pub fn load_state_file(path: &Path, variant: Option<Variant>, program_checksum: Option<u32>) -> Result<Cpu, SaveStateError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        #[cfg(feature = "compress")]
+        return load_state(&mut GzDecoder::new(reader), variant, program_checksum);
+
+        #[cfg(not(feature = "compress"))]
+        return Err(SaveStateError::Compressed);
+    }
+
+    load_state(&mut reader, variant, program_checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xochip;
+
+    const CHECKSUM: u32 = 0x1234_5678;
+
+    /// A machine part way through a program, with some quirks changed from the default
+    fn machine() -> Cpu {
+        let mut config = MachineConfig::new(Variant::Chip8);
+        config.quirks.memory_protection = true;
+        config.quirks.sprite_overrun = SpriteOverrun::Wrap;
+
+        // LD V0, 5; LD F, V0; DRW V0, V0, 5; JP 0x206
+        let mut cpu = Cpu::init_from_buffer(vec![0x60, 0x05, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06], Some(config)).unwrap();
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    /// Writes the machine as the given older version of the format did
+    fn old_state(cpu: &Cpu, version: u16) -> Vec<u8> {
+        let c = cpu.config;
+        let q = c.quirks;
+        let quirks = match version {
+            1 | 2 => bincode::serialize(&(q.memory_protection, q.sprite_overrun)),
+            3 => bincode::serialize(&(q.memory_protection, q.sprite_overrun, q.i_overflow)),
+            4 => bincode::serialize(&(q.memory_protection, q.sprite_overrun, q.i_overflow, q.sys)),
+            5 | 6 => bincode::serialize(&(q.memory_protection, q.sprite_overrun, q.i_overflow, q.sys, q.key_order)),
+            _ => bincode::serialize(&q),
+        }.unwrap();
+        let mut config = bincode::serialize(&(c.variant, c.memory_length, c.program_start_addr, c.execution_start_addr, c.font_start_addr)).unwrap();
+        config.extend(quirks);
+        config.extend(bincode::serialize(&c.history_length).unwrap());
+
+        let mut state = SAVE_STATE_MAGIC.to_vec();
+        state.extend(&version.to_be_bytes());
+        state.extend(bincode::serialize(&c.variant).unwrap());
+        state.extend(&config);
+        if version > 1 {
+            state.extend(bincode::serialize(&Some(CHECKSUM)).unwrap());
+        }
+
+        // the machine state starts with the configuration and ends with the XO-CHIP
+        // state, which was missing before version 6 and had the sound flag until version
+        // 8, and then the random number generator, which was missing before version 9
+        let machine = bincode::serialize(cpu).unwrap();
+        let xo = bincode::serialize(&cpu.xo).unwrap();
+        let rng = bincode::serialize(&Xorshift::from_entropy()).unwrap();
+        let start = bincode::serialize(&c).unwrap().len();
+        state.extend(&config);
+        state.extend(&machine[start..machine.len() - xo.len() - rng.len()]);
+        if version >= 6 {
+            state.extend(&xo);
+        }
+        if version == 6 || version == 7 {
+            state.push(1);
+        }
+
+        state
+    }
+
+    fn assert_same_machine(loaded: &Cpu, cpu: &Cpu) {
+        assert_eq!(loaded.config, cpu.config);
+        assert_eq!(loaded.data_registers, cpu.data_registers);
+        assert_eq!(loaded.i_register, cpu.i_register);
+        assert_eq!(loaded.program_counter, cpu.program_counter);
+        assert_eq!(loaded.memory, cpu.memory);
+        assert_eq!(&loaded.vram[..], &cpu.vram[..]);
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let mut cpu = machine();
+        cpu.xo.pitch = 100;
+        let mut state = Vec::new();
+        save_state(&cpu, Some(CHECKSUM), &mut state).unwrap();
+
+        let loaded = load_state(&mut state.as_slice(), Some(Variant::Chip8), Some(CHECKSUM)).unwrap();
+        assert_same_machine(&loaded, &cpu);
+        assert_eq!(loaded.xo.pitch, 100);
+    }
+
+    #[test]
+    fn loaded_states_roll_the_same_numbers() {
+        let mut cpu = machine();
+        cpu.seed_rng(42);
+        cpu.get_random_byte();
+        let mut state = Vec::new();
+        save_state(&cpu, Some(CHECKSUM), &mut state).unwrap();
+
+        let mut loaded = load_state(&mut state.as_slice(), Some(Variant::Chip8), Some(CHECKSUM)).unwrap();
+        for _ in 0..100 {
+            assert_eq!(loaded.get_random_byte(), cpu.get_random_byte());
+        }
+    }
+
+    #[test]
+    fn old_versions_are_migrated() {
+        let cpu = machine();
+
+        for version in 1..SAVE_STATE_VERSION {
+            let state = old_state(&cpu, version);
+            match load_state(&mut state.as_slice(), Some(Variant::Chip8), Some(CHECKSUM)) {
+                Ok(loaded) => assert_same_machine(&loaded, &cpu),
+                Err(e) => panic!("version {} didn't load: {}", version, e),
+            }
+        }
+    }
+
+    #[test]
+    fn old_versions_get_the_quirks_they_ran_with() {
+        let mut cpu = machine();
+        cpu.config.quirks.i_overflow = IOverflow::SetVf;
+        cpu.config.quirks.sys = SysBehavior::Fault;
+        cpu.config.quirks.key_order = KeyOrder::Last;
+        cpu.config.quirks.misaligned_fetch = FetchCheck::Fault;
+
+        let state = old_state(&cpu, 2);
+        let quirks = load_state(&mut state.as_slice(), None, None).unwrap().config.quirks;
+        assert_eq!(quirks, Quirks { memory_protection: true, sprite_overrun: SpriteOverrun::Wrap, ..Quirks::default() });
+        assert_eq!(quirks.i_overflow, IOverflow::Clear);
+
+        let state = old_state(&cpu, 5);
+        let quirks = load_state(&mut state.as_slice(), None, None).unwrap().config.quirks;
+        assert_eq!(quirks, Quirks { misaligned_fetch: FetchCheck::Ignore, ..cpu.config.quirks });
+    }
+
+    #[test]
+    fn versions_before_xo_chip_get_its_power_on_state() {
+        let mut cpu = machine();
+        cpu.xo.pitch = 100;
+
+        let state = old_state(&cpu, 5);
+        assert_eq!(load_state(&mut state.as_slice(), None, None).unwrap().xo.pitch, xochip::DEFAULT_PITCH);
+
+        let state = old_state(&cpu, 7);
+        assert_eq!(load_state(&mut state.as_slice(), None, None).unwrap().xo.pitch, 100);
+    }
+
+    #[test]
+    fn states_for_something_else_are_refused() {
+        let cpu = machine();
+        let mut state = Vec::new();
+        save_state(&cpu, Some(CHECKSUM), &mut state).unwrap();
+
+        match load_state(&mut state.as_slice(), Some(Variant::XoChip), None) {
+            Err(SaveStateError::VariantMismatch { expected: Variant::XoChip, found: Variant::Chip8 }) => (),
+            r => panic!("expected a variant mismatch, got {:?}", r.err()),
+        }
+        match load_state(&mut state.as_slice(), None, Some(CHECKSUM + 1)) {
+            Err(SaveStateError::ProgramMismatch) => (),
+            r => panic!("expected a program mismatch, got {:?}", r.err()),
+        }
+
+        state[5] = (SAVE_STATE_VERSION + 1) as u8;
+        match load_state(&mut state.as_slice(), None, None) {
+            Err(SaveStateError::UnsupportedVersion(v)) if v == SAVE_STATE_VERSION + 1 => (),
+            r => panic!("expected an unsupported version, got {:?}", r.err()),
+        }
+    }
+}
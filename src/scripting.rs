@@ -0,0 +1,168 @@
+//
+// Author: Joshua Holmes
+//
+
+use rhai::{Engine, EvalAltResult, Scope, AST, INT};
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+
+use cpu::{Cpu, NUM_REGISTERS};
+
+/// The hooks a script may define
+const ON_FRAME: &str = "on_frame";
+const ON_INSTRUCTION: &str = "on_instruction";
+const ON_DRAW: &str = "on_draw";
+
+/// A user script with hooks that run as the program executes. While a hook runs, the
+/// machine is lent to the script, which can read and write registers, memory and the screen
+/// through functions like `peek`, `poke`, `reg` and `set_reg`, and switch tracing on
+/// and off with `trace(true)` and `trace(false)`.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// the machine the script functions act on; holds a spare machine between hooks
+    cpu: Rc<RefCell<Cpu>>,
+    /// whether the script asked for tracing to be switched on or off, until the frontend sees to it
+    trace_request: Rc<Cell<Option<bool>>>,
+    has_on_frame: bool,
+    has_on_instruction: bool,
+    has_on_draw: bool,
+}
+
+impl Script {
+    /// Compiles a script and runs its top level. `spare` is swapped with the real machine
+    /// whenever a hook runs, so it only needs to be a machine of the same kind.
+    pub fn load_file(path: &Path, spare: Cpu) -> Result<Script, Box<EvalAltResult>> {
+        let cpu = Rc::new(RefCell::new(spare));
+        let trace_request = Rc::new(Cell::new(None));
+        let mut engine = Engine::new();
+        register_api(&mut engine, &cpu);
+
+        let t = trace_request.clone();
+        engine.register_fn("trace", move |enabled: bool| t.set(Some(enabled)));
+
+        let ast = engine.compile_file(path.to_path_buf())?;
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+
+        let has_fn = |name: &str| ast.iter_functions().any(|f| f.name == name);
+
+        Ok(Script {
+            has_on_frame: has_fn(ON_FRAME),
+            has_on_instruction: has_fn(ON_INSTRUCTION),
+            has_on_draw: has_fn(ON_DRAW),
+            engine: engine,
+            ast: ast,
+            scope: scope,
+            cpu: cpu,
+            trace_request: trace_request,
+        })
+    }
+
+    /// Runs the script's on_frame hook, once per 60Hz frame
+    pub fn on_frame(&mut self, cpu: &mut Cpu) -> Result<(), Box<EvalAltResult>> {
+        if self.has_on_frame {
+            self.call(cpu, ON_FRAME, ())?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the script's on_instruction hook with the address and value of the instruction just executed
+    pub fn on_instruction(&mut self, cpu: &mut Cpu, pc: usize, instruction: u16) -> Result<(), Box<EvalAltResult>> {
+        if self.has_on_instruction {
+            self.call(cpu, ON_INSTRUCTION, (pc as INT, instruction as INT))?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the script's on_draw hook, after the screen has been redrawn
+    pub fn on_draw(&mut self, cpu: &mut Cpu) -> Result<(), Box<EvalAltResult>> {
+        if self.has_on_draw {
+            self.call(cpu, ON_DRAW, ())?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the script asked for tracing to be switched on or off since last time
+    pub fn take_trace_request(&self) -> Option<bool> {
+        self.trace_request.take()
+    }
+
+    /// Lends the machine to the script functions and calls a hook
+    fn call<A: rhai::FuncArgs>(&mut self, cpu: &mut Cpu, name: &str, args: A) -> Result<(), Box<EvalAltResult>> {
+        mem::swap(cpu, &mut *self.cpu.borrow_mut());
+        let result = self.engine.call_fn::<rhai::Dynamic>(&mut self.scope, &self.ast, name, args);
+        mem::swap(cpu, &mut *self.cpu.borrow_mut());
+
+        result.map(|_| ())
+    }
+}
+
+/// Registers the functions scripts use to inspect and change the machine
+fn register_api(engine: &mut Engine, cpu: &Rc<RefCell<Cpu>>) {
+    let c = cpu.clone();
+    engine.register_fn("peek", move |addr: INT| -> INT {
+        let cpu = c.borrow();
+        cpu.memory.get(addr as usize).map_or(0, |&b| b as INT)
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("poke", move |addr: INT, value: INT| {
+        let mut cpu = c.borrow_mut();
+        if let Some(b) = cpu.memory.get_mut(addr as usize) {
+            *b = value as u8;
+        }
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("reg", move |n: INT| -> INT {
+        c.borrow().data_registers[n as usize % NUM_REGISTERS] as INT
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("set_reg", move |n: INT, value: INT| {
+        c.borrow_mut().data_registers[n as usize % NUM_REGISTERS] = value as u8;
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("reg_i", move || -> INT { c.borrow().i_register as INT });
+
+    let c = cpu.clone();
+    engine.register_fn("set_reg_i", move |value: INT| { c.borrow_mut().i_register = value as usize; });
+
+    let c = cpu.clone();
+    engine.register_fn("pc", move || -> INT { c.borrow().program_counter as INT });
+
+    let c = cpu.clone();
+    engine.register_fn("delay_timer", move || -> INT { c.borrow().delay_timer as INT });
+
+    let c = cpu.clone();
+    engine.register_fn("set_delay_timer", move |value: INT| { c.borrow_mut().delay_timer = value as u8; });
+
+    let c = cpu.clone();
+    engine.register_fn("sound_timer", move || -> INT { c.borrow().sound_timer as INT });
+
+    let c = cpu.clone();
+    engine.register_fn("set_sound_timer", move |value: INT| { c.borrow_mut().sound_timer = value as u8; });
+
+    let c = cpu.clone();
+    engine.register_fn("key", move |key: INT| -> bool { c.borrow().keyboard.is_pressed(key as u8 & 0xF) });
+
+    let c = cpu.clone();
+    engine.register_fn("pixel", move |x: INT, y: INT| -> bool {
+        c.borrow().pixel(x as usize, y as usize)
+    });
+
+    let c = cpu.clone();
+    engine.register_fn("set_pixel", move |x: INT, y: INT, on: bool| {
+        let mut cpu = c.borrow_mut();
+        cpu.set_pixel(x as usize, y as usize, on);
+        cpu.draw_flag = true;
+    });
+}
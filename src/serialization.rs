@@ -0,0 +1,61 @@
+//
+// Author: Joshua Holmes
+//
+
+/// Serializes the VRAM as one bitmask per row, with the leftmost pixel in the highest bit
+pub mod vram {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    use cpu::HIRES_DISPLAY_HEIGHT;
+
+    pub fn serialize<S>(vram: &[u64; HIRES_DISPLAY_HEIGHT], serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_seq(vram.iter())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u64; HIRES_DISPLAY_HEIGHT], D::Error>
+        where D: Deserializer<'de>
+    {
+        let rows = Vec::<u64>::deserialize(deserializer)?;
+
+        if rows.len() != HIRES_DISPLAY_HEIGHT {
+            return Err(D::Error::invalid_length(rows.len(), &"one bitmask per VRAM row"));
+        }
+
+        let mut vram = [0u64; HIRES_DISPLAY_HEIGHT];
+        vram.copy_from_slice(&rows);
+
+        Ok(vram)
+    }
+}
+
+/// Serializes the Mega-Chip palette as a plain sequence of ARGB colors
+pub mod palette {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    use megachip::PALETTE_LENGTH;
+
+    pub fn serialize<S>(palette: &[u32; PALETTE_LENGTH], serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_seq(palette.iter())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u32; PALETTE_LENGTH], D::Error>
+        where D: Deserializer<'de>
+    {
+        let colors = Vec::<u32>::deserialize(deserializer)?;
+
+        if colors.len() != PALETTE_LENGTH {
+            return Err(D::Error::invalid_length(colors.len(), &"a full Mega-Chip palette"));
+        }
+
+        let mut palette = [0u32; PALETTE_LENGTH];
+        palette.copy_from_slice(&colors);
+
+        Ok(palette)
+    }
+}
@@ -0,0 +1,177 @@
+//
+// Author: Joshua Holmes
+//
+
+extern crate sdl2;
+
+use gl::types::{GLint, GLuint};
+use sdl2::VideoSubsystem;
+use sdl2::rect::Rect;
+use sdl2::render::{Renderer, Texture};
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chip8::rotation::Rotation;
+use glsl::{compile, program_log};
+
+/// The vertex shader every post-processing shader is linked with. It covers the
+/// part of the window the screen goes in, and hands the fragment shader where in
+/// the screen each fragment is.
+const VERTEX_SHADER: &str = "#version 120
+attribute vec2 position;
+attribute vec2 texcoord;
+varying vec2 uv;
+
+void main() {
+    uv = texcoord;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+";
+
+/// A user's GLSL fragment shader that the screen is drawn through on its way to
+/// the window, for effects like CRT curvature or bloom. It's linked with a vertex
+/// shader that provides:
+///
+/// ```text
+/// varying vec2 uv;              where in the screen the fragment is, 0 to 1 each way
+/// uniform sampler2D screen;     the screen; sample it at uv * texture_scale
+/// uniform vec2 texture_scale;   how far across the texture the screen reaches
+/// uniform vec2 screen_size;     the size of the emulated screen, in its own pixels
+/// uniform vec2 output_size;     the size of the part of the window it covers, in window pixels
+/// ```
+///
+/// Shaders only run on SDL's OpenGL renderer, drawing into its context, so GLSL
+/// 1.20 is the newest that's safe to write.
+pub struct PostShader {
+    program: GLuint,
+    /// the vertex attributes, either of which is -1 if the linker found it unused
+    attributes: [GLint; 2],
+    screen: GLint,
+    texture_scale: GLint,
+    screen_size: GLint,
+    output_size: GLint,
+}
+
+impl PostShader {
+    /// Compiles the fragment shader in the given file for the given renderer, which
+    /// has to be SDL's OpenGL one
+    pub fn load(video: &VideoSubsystem, renderer: &Renderer, path: &Path) -> Result<PostShader, String> {
+        let driver = renderer.info().name;
+        if driver != "opengl" {
+            return Err(format!("the {} renderer can't run shaders", driver));
+        }
+
+        let mut source = String::new();
+        File::open(path).and_then(|mut f| f.read_to_string(&mut source)).map_err(|e| e.to_string())?;
+
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const _);
+        if !gl::CreateProgram::is_loaded() || !gl::VertexAttribPointer::is_loaded() {
+            return Err("the OpenGL driver doesn't support shaders".to_owned());
+        }
+
+        unsafe {
+            let vertex = compile(gl::VERTEX_SHADER, VERTEX_SHADER)?;
+            let fragment = match compile(gl::FRAGMENT_SHADER, &source) {
+                Ok(f) => f,
+                Err(e) => {
+                    gl::DeleteShader(vertex);
+                    return Err(e);
+                },
+            };
+
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, vertex);
+            gl::AttachShader(program, fragment);
+            gl::LinkProgram(program);
+            gl::DeleteShader(vertex);
+            gl::DeleteShader(fragment);
+
+            let mut linked = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut linked);
+            if linked == 0 {
+                let log = program_log(program);
+                gl::DeleteProgram(program);
+                return Err(format!("the shader didn't link: {}", log));
+            }
+
+            let attribute = |name: &str| gl::GetAttribLocation(program, CString::new(name).unwrap().as_ptr());
+            let uniform = |name: &str| gl::GetUniformLocation(program, CString::new(name).unwrap().as_ptr());
+
+            Ok(PostShader {
+                program: program,
+                attributes: [attribute("position"), attribute("texcoord")],
+                screen: uniform("screen"),
+                texture_scale: uniform("texture_scale"),
+                screen_size: uniform("screen_size"),
+                output_size: uniform("output_size"),
+            })
+        }
+    }
+
+    /// Draws a screen of the given size from the texture through the shader, filling
+    /// the given part of a window of the given size, turned as given. Whatever
+    /// OpenGL state SDL relies on is put back afterwards.
+    pub fn draw(&self, texture: &mut Texture, screen_size: (u32, u32), outline: Rect, window_size: (u32, u32), rotation: Rotation) {
+        let (window_width, window_height) = (window_size.0 as f32, window_size.1 as f32);
+        let left = outline.x() as f32 / window_width * 2.0 - 1.0;
+        let right = (outline.x() + outline.width() as i32) as f32 / window_width * 2.0 - 1.0;
+        let top = 1.0 - outline.y() as f32 / window_height * 2.0;
+        let bottom = 1.0 - (outline.y() + outline.height() as i32) as f32 / window_height * 2.0;
+
+        // the window's corners clockwise from the top left, and the corners of the
+        // screen that land on them, which move round one for each quarter turn
+        let positions = [left, top, right, top, right, bottom, left, bottom];
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let turns = (rotation.degrees() / 90) as usize;
+        let mut texcoords = [0.0f32; 8];
+        for (i, texcoord) in texcoords.chunks_mut(2).enumerate() {
+            texcoord.copy_from_slice(&corners[(i + 4 - turns) % 4]);
+        }
+
+        texture.gl_with_bind(|scale_x, scale_y| unsafe {
+            let mut previous_program = 0;
+            let mut previous_buffer = 0;
+            let mut was_enabled = [0; 2];
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut previous_program);
+            gl::GetIntegerv(gl::ARRAY_BUFFER_BINDING, &mut previous_buffer);
+
+            gl::UseProgram(self.program);
+            gl::Uniform1i(self.screen, 0);
+            gl::Uniform2f(self.texture_scale, scale_x, scale_y);
+            gl::Uniform2f(self.screen_size, screen_size.0 as f32, screen_size.1 as f32);
+            gl::Uniform2f(self.output_size, outline.width() as f32, outline.height() as f32);
+
+            // the vertices come straight from memory rather than a buffer
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            for (i, data) in [&positions, &texcoords].iter().enumerate() {
+                if self.attributes[i] >= 0 {
+                    let attribute = self.attributes[i] as GLuint;
+                    gl::GetVertexAttribiv(attribute, gl::VERTEX_ATTRIB_ARRAY_ENABLED, &mut was_enabled[i]);
+                    gl::EnableVertexAttribArray(attribute);
+                    gl::VertexAttribPointer(attribute, 2, gl::FLOAT, gl::FALSE, 0, data.as_ptr() as *const _);
+                }
+            }
+
+            gl::DrawArrays(gl::TRIANGLE_FAN, 0, 4);
+
+            // SDL draws with the same arrays, so the ones it had switched on stay on
+            for (i, &enabled) in was_enabled.iter().enumerate() {
+                if self.attributes[i] >= 0 && enabled == 0 {
+                    gl::DisableVertexAttribArray(self.attributes[i] as GLuint);
+                }
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, previous_buffer as GLuint);
+            gl::UseProgram(previous_program as GLuint);
+        });
+    }
+}
+
+impl Drop for PostShader {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteProgram(self.program);
+        }
+    }
+}
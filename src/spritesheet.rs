@@ -0,0 +1,68 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use analysis::SpriteRef;
+use png;
+
+/// How many sprites go across a sheet before the next row of them starts
+const SHEET_COLUMNS: usize = 8;
+/// How many sheet pixels across each sprite pixel is
+const SHEET_SCALE: usize = 4;
+/// How many sheet pixels apart the sprites are, and from the edges
+const SHEET_GAP: usize = 4;
+/// The shade of a lit sprite pixel
+const LIT: u8 = 0xFF;
+/// The shade of an unlit sprite pixel, a little lighter than the gaps so the sprite's extent shows
+const UNLIT: u8 = 0x30;
+/// The shade of the gaps between sprites
+const GAP: u8 = 0x00;
+
+/// Lays the given sprites out on a sheet, in order, left to right and then top
+/// to bottom, each in a cell as tall as the tallest. Returns the sheet's width,
+/// height and 8-bit grey pixels, row by row.
+pub fn sprite_sheet(memory: &[u8], sprites: &[SpriteRef]) -> (u32, u32, Vec<u8>) {
+    let rows = sprites.iter().map(|s| s.height).max().unwrap_or(0);
+    let columns_wide = sprites.iter().map(|s| s.width).max().unwrap_or(8);
+    let cell_width = columns_wide * SHEET_SCALE + SHEET_GAP;
+    let cell_height = rows * SHEET_SCALE + SHEET_GAP;
+    let columns = sprites.len().clamp(1, SHEET_COLUMNS);
+    let lines = sprites.len().div_ceil(SHEET_COLUMNS).max(1);
+
+    let width = columns * cell_width + SHEET_GAP;
+    let height = lines * cell_height + SHEET_GAP;
+    let mut pixels = vec![GAP; width * height];
+
+    for (n, sprite) in sprites.iter().enumerate() {
+        let left = SHEET_GAP + (n % SHEET_COLUMNS) * cell_width;
+        let top = SHEET_GAP + (n / SHEET_COLUMNS) * cell_height;
+
+        let row_length = sprite.width / 8;
+        for row in 0..sprite.height {
+            for x in 0..sprite.width * SHEET_SCALE {
+                let column = x / SHEET_SCALE;
+                // anything past the end of memory is drawn blank, as DRW would clamp it
+                let byte = memory.get(sprite.addr + row * row_length + column / 8).cloned().unwrap_or(0);
+                let shade = if byte & (0x80 >> (column % 8)) != 0 { LIT } else { UNLIT };
+                for y in 0..SHEET_SCALE {
+                    pixels[(top + row * SHEET_SCALE + y) * width + left + x] = shade;
+                }
+            }
+        }
+    }
+
+    (width as u32, height as u32, pixels)
+}
+
+/// Saves the given sprites to a sheet in a PNG file
+pub fn save_sprite_sheet(memory: &[u8], sprites: &[SpriteRef], path: &Path) -> io::Result<()> {
+    let (width, height, pixels) = sprite_sheet(memory, sprites);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    png::write_grayscale(&mut writer, width, height, &pixels)?;
+    writer.flush()
+}
@@ -0,0 +1,130 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+use analysis::{self, ByteUse};
+use cpu::EmulationError;
+use disassembler;
+use emulator::Emulator;
+use observer::NullObserver;
+use opcode::OpCode;
+use variant::Variant;
+
+/// How many times each family of instruction turns up, where a family is an
+/// instruction with its operands generalised, like "LD Vx, byte" or "DRW Vx, Vy, n"
+#[derive(Debug, Clone, Default)]
+pub struct OpcodeStats {
+    counts: BTreeMap<String, usize>,
+}
+
+impl OpcodeStats {
+    /// Construct stats with nothing counted
+    pub fn new() -> OpcodeStats {
+        OpcodeStats::default()
+    }
+
+    /// Counts the instructions on every path through a program, as far as
+    /// analysis::classify can follow them
+    pub fn of_program(program: &[u8], origin: usize, variant: Variant) -> OpcodeStats {
+        let mut memory = vec![0; origin];
+        memory.extend_from_slice(program);
+
+        let reached: Vec<(usize, usize)> = analysis::classify(&memory, origin, variant.start_addr(), variant).into_iter()
+            .filter(|r| r.kind == ByteUse::Code || r.kind == ByteUse::CodeAndSprite)
+            .map(|r| (r.start, r.end))
+            .collect();
+
+        let mut stats = OpcodeStats::new();
+        for line in disassembler::disassemble(program, origin, variant) {
+            if line.bytes.len() >= 2 && reached.iter().any(|&(start, end)| (start..end).contains(&line.addr)) {
+                stats.record(((line.bytes[0] as u16) << 8) | (line.bytes[1] as u16), variant);
+            }
+        }
+
+        stats
+    }
+
+    /// Runs the emulator's program for some 60Hz frames as fast as possible, with
+    /// no keys pressed, counting every instruction it executes. Stops early if the
+    /// program finishes, or with the error that stopped it, counting the
+    /// instruction that did.
+    pub fn record_run(&mut self, emulator: &mut Emulator, frames: u32) -> Result<(), EmulationError> {
+        let per_frame = cmp::max(1, emulator.speed() / 60);
+        let variant = emulator.cpu.config.variant;
+
+        for _ in 0..frames {
+            for _ in 0..per_frame {
+                let cpu = &emulator.cpu;
+                let pc = cpu.program_counter;
+                if pc >= cpu.config.program_start_addr + cpu.program().len() || pc + 1 >= cpu.memory.len() {
+                    return Ok(());
+                }
+                self.record(((cpu.memory[pc] as u16) << 8) | (cpu.memory[pc + 1] as u16), variant);
+
+                if !emulator.cycle(&mut NullObserver)? {
+                    return Ok(());
+                }
+            }
+            emulator.tick_timers(&mut NullObserver);
+        }
+
+        Ok(())
+    }
+
+    /// Counts an instruction
+    pub fn record(&mut self, instruction: u16, variant: Variant) {
+        *self.counts.entry(family(instruction, variant)).or_insert(0) += 1;
+    }
+
+    /// How many instructions of a family were counted
+    pub fn count(&self, family: &str) -> usize {
+        self.counts.get(family).cloned().unwrap_or(0)
+    }
+
+    /// How many instructions were counted altogether
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// Every family counted, with its count, in alphabetical order
+    pub fn families(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.counts.iter().map(|(f, &n)| (f.as_str(), n))
+    }
+}
+
+/// The family an instruction belongs to. SYS keeps its address, as 00FF, 00FD and
+/// the like mark extensions like SUPER-CHIP, and an instruction that isn't one in
+/// the variant keeps everything but its x, like "Fx75", for the same reason.
+pub fn family(instruction: u16, variant: Variant) -> String {
+    let disasm = match OpCode::from_u16(instruction, variant) {
+        Some(o) => o.disasm_str,
+        None => return format!("unknown {:X}x{:02X}", instruction >> 12, instruction & 0xFF),
+    };
+
+    let (mnemonic, operands) = match disasm.find(' ') {
+        Some(n) => (&disasm[..n], &disasm[n + 1..]),
+        None => return disasm,
+    };
+    if mnemonic == "SYS" {
+        return disasm;
+    }
+
+    let mut registers = ["Vx", "Vy"].iter();
+    let operands: Vec<&str> = operands.split(", ").map(|op| {
+        let is_hex = op.chars().all(|c| c.is_ascii_hexdigit());
+        match op.len() {
+            2 if op.starts_with('V') && op[1..].chars().all(|c| c.is_ascii_hexdigit()) => registers.next().cloned().unwrap_or(op),
+            1 if is_hex => "n",
+            2 if is_hex => "byte",
+            3 if is_hex => "addr",
+            // LDHI I, 12xxxx
+            _ if op.ends_with("xxxx") => "addr",
+            _ => op,
+        }
+    }).collect();
+
+    format!("{} {}", mnemonic, operands.join(", "))
+}
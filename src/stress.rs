@@ -0,0 +1,131 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::cmp;
+use std::panic::{self, AssertUnwindSafe};
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use batch;
+use cpu::EmulationError;
+use emulator::EmulatorBuilder;
+use observer::NullObserver;
+use opcode::OpCode;
+use variant::Variant;
+
+/// How many instructions each random program has
+const PROGRAM_WORDS: usize = 128;
+/// How many instructions each random program runs for, at most
+const STEPS: u64 = 20000;
+
+/// Something that went wrong in the interpreter running a random program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StressFailure {
+    /// the seed the program was made from, and run with
+    pub seed: u64,
+    pub program: Vec<u8>,
+    /// how many instructions had run, counting the one responsible
+    pub steps: u64,
+    /// where the instruction responsible is
+    pub addr: usize,
+    pub disasm: String,
+    /// what went wrong: a panic, or an invariant the instruction broke
+    pub problem: String,
+}
+
+/// Makes a random program from a seed. Every word is an instruction the variant
+/// knows, other than SYS, and every JP, CALL and LD I points somewhere in the
+/// program, so the program goes somewhere interesting more often than random
+/// bytes would. It runs from the start address onwards, and anything before that
+/// is random instructions too.
+pub fn generate(seed: u64, variant: Variant) -> Vec<u8> {
+    let mut rng = rng_for(seed);
+    let load_addr = variant.load_addr();
+    let len = variant.start_addr() - load_addr + PROGRAM_WORDS * 2;
+    let mut program = Vec::with_capacity(len);
+
+    while program.len() < len {
+        let instruction = loop {
+            let mut instruction: u16 = rng.gen();
+            if let 0x1000 | 0x2000 | 0xA000 | 0xB000 = instruction & 0xF000 {
+                let target = load_addr + rng.gen_range(0, len / 2) * 2;
+                instruction = (instruction & 0xF000) | target as u16;
+            }
+
+            match OpCode::from_u16(instruction, variant) {
+                Some(ref o) if !o.disasm_str.starts_with("SYS") => break instruction,
+                _ => (),
+            }
+        };
+
+        program.push((instruction >> 8) as u8);
+        program.push(instruction as u8);
+    }
+
+    program
+}
+
+/// Runs the random program made from a seed, with random keys pressed, on an
+/// emulator built the way the builder says for the variant given, in paranoid
+/// mode, so the machine's invariants are checked after every instruction. Errors
+/// the interpreter reports are the program's doing, and end the run as they
+/// should; panics and broken invariants are the interpreter's, and are returned.
+pub fn run(builder: &EmulatorBuilder, variant: Variant, seed: u64) -> Option<StressFailure> {
+    let program = generate(seed, variant);
+    let mut emulator = match builder.clone().variant(variant).seed(seed).paranoid(true).build_from_buffer(program.clone()) {
+        Ok(e) => e,
+        Err(e) => panic!("Couldn't load a random program: {}", e),
+    };
+    let mut rng = rng_for(seed ^ 0xFFFF_FFFF);
+    let per_frame = cmp::max(1, emulator.speed() / 60) as u64;
+
+    for steps in 1..STEPS + 1 {
+        let pc = emulator.cpu.program_counter;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| emulator.cycle(&mut NullObserver)));
+        emulator.cpu.warnings.clear();
+
+        let problem = match result {
+            Ok(Ok(true)) => None,
+            Ok(Err(EmulationError::InvariantViolation(_, problem, _))) => Some(problem),
+            Ok(_) => return None,
+            Err(e) => Some(match (e.downcast_ref::<&str>(), e.downcast_ref::<String>()) {
+                (Some(message), _) => format!("panicked: {}", message),
+                (_, Some(message)) => format!("panicked: {}", message),
+                _ => "panicked".to_owned(),
+            }),
+        };
+
+        if let Some(problem) = problem {
+            let instruction = emulator.cpu.current_instruction;
+            return Some(StressFailure {
+                seed: seed,
+                program: program,
+                steps: steps,
+                addr: pc,
+                disasm: OpCode::from_u16(instruction, variant).map(|o| o.disasm_str).unwrap_or_else(|| format!("{:04X}", instruction)),
+                problem: problem,
+            });
+        }
+
+        if steps % per_frame == 0 {
+            emulator.tick_timers(&mut NullObserver);
+            emulator.cpu.keyboard.set_keypad_state(rng.gen());
+        }
+    }
+
+    None
+}
+
+/// Runs the given number of random programs, made from the seeds counting up
+/// from the one given, spread over up to jobs threads, and returns the failures
+/// in seed order
+pub fn run_many(builder: &EmulatorBuilder, variant: Variant, first_seed: u64, programs: u64, jobs: usize) -> Vec<StressFailure> {
+    let seeds = (0..programs).map(|n| first_seed.wrapping_add(n)).collect();
+    batch::run(seeds, jobs, |seed| run(builder, variant, seed)).into_iter().flatten().collect()
+}
+
+/// A random number generator started from a seed, the same way the CPU's is
+fn rng_for(seed: u64) -> XorShiftRng {
+    XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9E3779B9, 0x7F4A7C15])
+}
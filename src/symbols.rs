@@ -0,0 +1,178 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use cheats::parse_hex;
+use opcode::OpCode;
+use variant::Variant;
+
+/// Reasons a symbol file couldn't be loaded
+#[derive(Debug)]
+pub enum SymbolError {
+    IoError(io::Error),
+    /// a line of the symbol file couldn't be understood
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl From<io::Error> for SymbolError {
+    fn from(err: io::Error) -> Self {
+        SymbolError::IoError(err)
+    }
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SymbolError::IoError(ref e) => write!(f, "I/O error: {}", e),
+            SymbolError::Parse { line, ref message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for SymbolError {}
+
+/// The names of addresses in a program, like the labels it was assembled from,
+/// for showing in place of the bare addresses
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    names: BTreeMap<usize, String>,
+    addresses: HashMap<String, usize>,
+}
+
+impl SymbolTable {
+    /// Construct a table with no symbols in it
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// The path a program's symbols are saved to: next to the program, with a .sym extension
+    pub fn path_for(program_path: &str) -> PathBuf {
+        Path::new(program_path).with_extension("sym")
+    }
+
+    /// Loads symbols from a file. Each line pairs a name with a hex address, in
+    /// either order, optionally with = or : between them, as assemblers including
+    /// Octo list their labels:
+    ///
+    /// ```text
+    /// main 0x200
+    /// draw_player = 0x2A4
+    /// 0x3F0 level_data
+    /// ```
+    ///
+    /// Where both could be an address, the one with a 0x or $ in front of it is,
+    /// or else the second. Blank lines and lines starting with # or ; are ignored.
+    pub fn load_file(path: &Path) -> Result<SymbolTable, SymbolError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut symbols = SymbolTable::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == '=' || c == ':')
+                .filter(|w| !w.is_empty())
+                .collect();
+            if words.len() != 2 {
+                return Err(SymbolError::Parse { line: i + 1, message: "expected a name and an address".to_owned() });
+            }
+
+            let marked = |w: &str| w.starts_with("0x") || w.starts_with("0X") || w.starts_with('$');
+            let address = |w: &str| parse_hex(w.trim_start_matches('$'));
+
+            let (name, addr) = match (address(words[0]), address(words[1])) {
+                (Some(a), _) if marked(words[0]) && !marked(words[1]) => (words[1], a),
+                (_, Some(a)) => (words[0], a),
+                (Some(a), None) => (words[1], a),
+                (None, None) => return Err(SymbolError::Parse { line: i + 1, message: "expected a hex address".to_owned() }),
+            };
+
+            symbols.insert(name, addr);
+        }
+
+        Ok(symbols)
+    }
+
+    /// Saves the symbols in a form load_file reads back, one name and address to a
+    /// line, lowest address first
+    pub fn save_file(&self, path: &Path) -> io::Result<()> {
+        let mut symbols: Vec<(&String, &usize)> = self.addresses.iter().collect();
+        symbols.sort_by_key(|&(name, &addr)| (addr, name));
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (name, addr) in symbols {
+            writeln!(writer, "{} 0x{:03X}", name, addr)?;
+        }
+        writer.flush()
+    }
+
+    /// Names an address. An address keeps the first name it's given, though every
+    /// name can still be looked up.
+    pub fn insert(&mut self, name: &str, addr: usize) {
+        self.names.entry(addr).or_insert_with(|| name.to_owned());
+        self.addresses.insert(name.to_owned(), addr);
+    }
+
+    /// Whether there are no symbols at all
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// The name of an address, if it has one
+    pub fn name_of(&self, addr: usize) -> Option<&str> {
+        self.names.get(&addr).map(|n| n.as_str())
+    }
+
+    /// The address with the given name, if there is one
+    pub fn addr_of(&self, name: &str) -> Option<usize> {
+        self.addresses.get(name).cloned()
+    }
+
+    /// Parses an address given by name, or failing that as a hex number
+    pub fn parse_addr(&self, s: &str) -> Option<usize> {
+        self.addr_of(s).or_else(|| parse_hex(s))
+    }
+
+    /// An address as hex, followed by its name in brackets if it has one, like "0x2A4 (draw_player)"
+    pub fn describe(&self, addr: usize) -> String {
+        match self.name_of(addr) {
+            Some(name) => format!("0x{:03X} ({})", addr, name),
+            None => format!("0x{:03X}", addr),
+        }
+    }
+
+    /// Disassembles an instruction, naming the address it refers to, if it refers
+    /// to one with a name
+    pub fn disasm(&self, instruction: u16, variant: Variant) -> String {
+        let disasm = match OpCode::from_u16(instruction, variant) {
+            Some(o) => o.disasm_str,
+            None => return "???".to_owned(),
+        };
+
+        // JP, CALL, LD I and JP V0 end with their address
+        let addr = (instruction & 0x0FFF) as usize;
+        match (instruction & 0xF000, self.name_of(addr)) {
+            (0x1000, Some(name)) | (0x2000, Some(name)) | (0xA000, Some(name)) | (0xB000, Some(name)) => {
+                match disasm.strip_suffix(&format!("{:03X}", addr)) {
+                    Some(start) => format!("{}{}", start, name),
+                    None => disasm,
+                }
+            },
+            _ => disasm,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use cpu::Cpu;
+use symbols::SymbolTable;
+
+/// Writes a line to a file for every instruction executed while it's switched on,
+/// so it can be switched on just around the part of a program worth looking at.
+/// Each line shows the instruction and the registers once it had executed, and
+/// the instructions at named addresses come after a line with the name:
+///
+/// ```text
+/// draw_player:
+/// 0x206: D015  DRW V0, V1, 5             V: 08 04 00 00 00 00 00 00 00 00 00 00 00 00 00 00  I: 000  SP: 0  DT: 00  ST: 00
+/// ```
+pub struct Tracer {
+    writer: BufWriter<File>,
+    /// whether instructions are being written
+    enabled: bool,
+    /// the names of the program's addresses, to show in place of them
+    symbols: SymbolTable,
+}
+
+impl Tracer {
+    /// Creates the trace file, with tracing switched on or off to begin with
+    pub fn create(path: &Path, enabled: bool) -> io::Result<Tracer> {
+        Ok(Tracer {
+            writer: BufWriter::new(File::create(path)?),
+            enabled: enabled,
+            symbols: SymbolTable::new(),
+        })
+    }
+
+    /// Whether instructions are being written
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Switches tracing on or off. The file is flushed on switching off, so
+    /// whatever's been traced so far can be looked at straight away.
+    pub fn set_enabled(&mut self, enabled: bool) -> io::Result<()> {
+        self.enabled = enabled;
+        if enabled {
+            Ok(())
+        } else {
+            self.writer.flush()
+        }
+    }
+
+    /// Names the program's addresses, so they're shown by name
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Writes a line for the instruction at pc, which the machine has just executed,
+    /// if tracing is switched on
+    pub fn record(&mut self, cpu: &Cpu, pc: usize, instruction: u16) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        if let Some(name) = self.symbols.name_of(pc) {
+            writeln!(self.writer, "{}:", name)?;
+        }
+
+        let disasm = self.symbols.disasm(instruction, cpu.config.variant);
+        let registers: Vec<String> = cpu.data_registers.iter().map(|v| format!("{:02X}", v)).collect();
+
+        writeln!(self.writer, "0x{:03X}: {:04X}  {:<24}  V: {}  I: {:03X}  SP: {:X}  DT: {:02X}  ST: {:02X}",
+            pc, instruction, disasm, registers.join(" "), cpu.i_register, cpu.stack_pointer, cpu.delay_timer, cpu.sound_timer)
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
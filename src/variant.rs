@@ -0,0 +1,104 @@
+//
+// Author: Joshua Holmes
+//
+
+use cpu;
+use megachip;
+use xochip;
+
+/// The different flavours of CHIP-8 machine that can be emulated
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Variant {
+    /// The original COSMAC VIP CHIP-8 interpreter
+    Chip8,
+    /// The two-page hi-res CHIP-8 interpreter, with a 64x64 display
+    HiresChip8,
+    /// The ETI-660 learner's computer, which loads programs at 0x600
+    Eti660,
+    /// Mega-Chip: a 256x192 indexed-color screen, sprite blitting and digitized sound
+    MegaChip,
+    /// XO-CHIP: 64K of memory and a programmable buzzer. Only the buzzer is emulated so far.
+    XoChip,
+}
+
+impl Variant {
+    /// Looks up a variant by the name used on the command line
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Variant::Chip8),
+            "hires" | "hires-chip8" => Some(Variant::HiresChip8),
+            "eti660" | "eti-660" => Some(Variant::Eti660),
+            "megachip" | "mega-chip" => Some(Variant::MegaChip),
+            "xochip" | "xo-chip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The name used for this variant on the command line
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Variant::Chip8 => "chip8",
+            Variant::HiresChip8 => "hires",
+            Variant::Eti660 => "eti660",
+            Variant::MegaChip => "megachip",
+            Variant::XoChip => "xochip",
+        }
+    }
+
+    /// Guesses the variant a program was written for by looking at its first instruction.
+    /// Hi-res programs all begin with a jump over the interpreter patch they carry.
+    pub fn detect(buf: &[u8]) -> Variant {
+        if buf.len() >= 2 && buf[0] == 0x12 && buf[1] == 0x60 {
+            Variant::HiresChip8
+        } else {
+            Variant::Chip8
+        }
+    }
+
+    /// How many bytes of system memory this variant has
+    pub fn memory_length(&self) -> usize {
+        match *self {
+            Variant::Chip8 | Variant::HiresChip8 | Variant::Eti660 => cpu::MEMORY_LENGTH,
+            Variant::MegaChip => megachip::MEGACHIP_MEMORY_LENGTH,
+            Variant::XoChip => xochip::XOCHIP_MEMORY_LENGTH,
+        }
+    }
+
+    /// The address at which the program is loaded into memory
+    pub fn load_addr(&self) -> usize {
+        match *self {
+            Variant::Chip8 | Variant::HiresChip8 | Variant::MegaChip | Variant::XoChip => cpu::USER_PROGRAM_START_ADDR,
+            Variant::Eti660 => cpu::ETI660_PROGRAM_START_ADDR,
+        }
+    }
+
+    /// The address at which execution begins
+    pub fn start_addr(&self) -> usize {
+        match *self {
+            Variant::HiresChip8 => cpu::HIRES_PROGRAM_START_ADDR,
+            _ => self.load_addr(),
+        }
+    }
+
+    /// The number of pixels in the height of the monochrome display
+    pub fn display_height(&self) -> usize {
+        match *self {
+            Variant::Chip8 | Variant::Eti660 | Variant::MegaChip | Variant::XoChip => cpu::VIRTUAL_DISPLAY_HEIGHT,
+            Variant::HiresChip8 => cpu::HIRES_DISPLAY_HEIGHT,
+        }
+    }
+
+    /// Whether DRW with a height of 0 draws a 16x16 sprite on this variant, as it
+    /// does wherever the display is hi-res
+    pub fn has_big_sprites(&self) -> bool {
+        self.display_height() == cpu::HIRES_DISPLAY_HEIGHT
+    }
+
+    /// Whether this variant has SUPER-CHIP's big digits after the font, and LD HF, Vx to point at them
+    pub fn has_big_font(&self) -> bool {
+        match *self {
+            Variant::MegaChip | Variant::XoChip => true,
+            Variant::Chip8 | Variant::HiresChip8 | Variant::Eti660 => false,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use assembler;
+use octo;
+
+/// How often the source is looked at to see if it's been saved
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches a program's source for saves, by looking at when it was last modified
+#[derive(Debug, Clone)]
+pub struct SourceWatcher {
+    path: PathBuf,
+    /// when the source was last modified, as of the last look
+    modified: Option<SystemTime>,
+    /// when we last looked
+    last_poll: Instant,
+}
+
+impl SourceWatcher {
+    /// Starts watching the source at a path, as it is now
+    pub fn new(path: &Path) -> SourceWatcher {
+        SourceWatcher {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// The path of the source being watched
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the source has been saved since the last time this said so. It's
+    /// only looked at every so often, so this is cheap to ask every frame. While
+    /// the file's missing, as it can be for a moment while an editor saves it,
+    /// it hasn't changed.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        match modified(&self.path) {
+            Some(m) if Some(m) != self.modified => {
+                self.modified = Some(m);
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Reads and assembles the source at a path into a program loaded at the given
+/// address. Source with an .8o extension is Octo, and anything else is written
+/// with the mnemonics the disassembler shows. If it doesn't assemble, the error
+/// is every problem found, each with the line it's on.
+pub fn assemble_file(path: &Path, origin: usize) -> Result<Vec<u8>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}. Error message: {}", path.display(), e))?;
+
+    let is_octo = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("8o"));
+    let assembled = if is_octo { octo::assemble(&source, origin) } else { assembler::assemble(&source, origin) };
+    assembled.map_err(|errors| {
+        let mut message = String::new();
+        for e in errors.iter() {
+            message.push_str(&format!("{}: {}\n{}\n", path.display(), e, e.excerpt(&source)));
+        }
+        message.push_str(&format!("{} problem(s) found.", errors.len()));
+        message
+    })
+}
+
+/// When the file at a path was last modified, if it's there
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
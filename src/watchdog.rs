@@ -0,0 +1,109 @@
+//
+// Author: Joshua Holmes
+//
+
+use cpu::{Cpu, EmulationError};
+use emulator::Emulator;
+use frontend::Frontend;
+use observer::Observer;
+
+/// How long a run may go on for before the watchdog stops it. Anything not set is unlimited.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct RunLimits {
+    /// how many instructions may run
+    pub max_instructions: Option<u64>,
+    /// how many 60Hz frames may run
+    pub max_frames: Option<u64>,
+}
+
+impl RunLimits {
+    /// Construct limits that never stop a run
+    pub fn new() -> RunLimits {
+        RunLimits {
+            max_instructions: None,
+            max_frames: None,
+        }
+    }
+}
+
+/// A frontend that passes everything through to another one, but stops the
+/// emulator once it has run for as long as the limits allow, so automated runs
+/// of programs that loop forever still come to an end
+pub struct Watchdog<F> {
+    /// the frontend being watched over
+    pub inner: F,
+    limits: RunLimits,
+    /// how many instructions have run so far
+    instructions: u64,
+    /// how many frames have run so far
+    frames: u64,
+    /// the flag that says the watchdog stopped the run
+    pub expired: bool,
+}
+
+impl<F: Frontend> Watchdog<F> {
+    /// Construct a watchdog over the given frontend
+    pub fn new(inner: F, limits: RunLimits) -> Watchdog<F> {
+        Watchdog {
+            inner: inner,
+            limits: limits,
+            instructions: 0,
+            frames: 0,
+            expired: false,
+        }
+    }
+}
+
+impl<F: Frontend> Observer for Watchdog<F> {
+    fn on_draw(&mut self, cpu: &Cpu) {
+        self.inner.on_draw(cpu);
+    }
+
+    fn on_sound_start(&mut self) {
+        self.inner.on_sound_start();
+    }
+
+    fn on_sound_stop(&mut self) {
+        self.inner.on_sound_stop();
+    }
+
+    fn on_pitch_change(&mut self, rate: f64) {
+        self.inner.on_pitch_change(rate);
+    }
+
+    fn on_key_wait(&mut self) {
+        self.inner.on_key_wait();
+    }
+
+    fn on_halt(&mut self, error: Option<&EmulationError>) {
+        self.inner.on_halt(error);
+    }
+}
+
+impl<F: Frontend> Frontend for Watchdog<F> {
+    fn before_cycle(&mut self, emulator: &mut Emulator) -> bool {
+        self.inner.before_cycle(emulator)
+    }
+
+    fn after_cycle(&mut self, emulator: &mut Emulator) -> bool {
+        let keep_running = self.inner.after_cycle(emulator);
+
+        self.instructions += 1;
+        if self.limits.max_instructions.is_some_and(|max| self.instructions >= max) {
+            self.expired = true;
+        }
+
+        keep_running && !self.expired
+    }
+
+    fn end_frame(&mut self, emulator: &mut Emulator) -> bool {
+        let keep_running = self.inner.end_frame(emulator);
+
+        self.frames += 1;
+        if self.limits.max_frames.is_some_and(|max| self.frames >= max) {
+            self.expired = true;
+        }
+
+        keep_running && !self.expired
+    }
+}
@@ -0,0 +1,89 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// How many bytes come before the sample data in the files WavWriter writes
+const HEADER_LENGTH: u32 = 44;
+
+/// Writes unsigned 8-bit mono samples, the kind the sound output is made of, as a
+/// WAV file. The header's lengths aren't known until the end, so they're written
+/// as zero to start with and filled in by finish.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    /// how many samples have been written so far
+    samples: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Starts a WAV file at the given path, overwriting whatever was there
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<WavWriter<BufWriter<File>>> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Starts a WAV file by writing its header for the given sample rate
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        // the format chunk: PCM, one channel, one byte per sample
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&8u16.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            writer: writer,
+            samples: 0,
+        })
+    }
+
+    /// Adds samples to the end of the file
+    pub fn write_samples(&mut self, samples: &[u8]) -> io::Result<()> {
+        self.writer.write_all(samples)?;
+        self.samples = self.samples.saturating_add(samples.len() as u32);
+
+        Ok(())
+    }
+
+    /// How many samples have been written so far
+    pub fn len(&self) -> u32 {
+        self.samples
+    }
+
+    /// Says whether no samples have been written yet
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// Fills in the lengths in the header, now they're known, and hands back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        // an odd-length data chunk is padded to keep the chunks word-aligned
+        if self.samples % 2 == 1 {
+            self.writer.write_all(&[0])?;
+        }
+        let padded = self.samples + self.samples % 2;
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_LENGTH - 8 + padded).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_LENGTH as u64 - 4))?;
+        self.writer.write_all(&self.samples.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,290 @@
+//
+// Author: Joshua Holmes
+//
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use cheats::parse_hex;
+use cpu::Cpu;
+use debugger::set_register;
+use symbols::SymbolTable;
+
+/// The page served at /, which drives the JSON API
+const PAGE: &str = include_str!("webdebugger.html");
+/// How many bytes /api/memory returns when no length is given
+const DEFAULT_MEMORY_LENGTH: usize = 0x100;
+/// How many instructions /api/disasm returns when no count is given
+const DEFAULT_DISASM_COUNT: usize = 16;
+/// How long we wait on a slow client before giving up on its request
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A graphical alternative to the terminal debugger: a small web page served over
+/// HTTP that shows the registers, memory and disassembly and can pause, step and
+/// resume emulation. The page is backed by a JSON API:
+///
+/// ```text
+/// GET  /api/state                        registers, stack and whether we're paused
+/// GET  /api/memory?addr=200&len=100      bytes of memory (numbers are hex)
+/// GET  /api/disasm?addr=200&count=10     instructions from an address, the PC if none
+/// POST /api/pause, /api/run, /api/step   control emulation
+/// POST /api/poke?addr=200&bytes=A2,1E    write bytes to memory, only while paused
+/// POST /api/set?reg=v5&value=3F          set a register, only while paused
+///
+/// Neither poke nor set is allowed during netplay or while a movie is being recorded.
+/// ```
+pub struct WebDebugger {
+    listener: TcpListener,
+    /// whether emulation is paused
+    paused: bool,
+    /// the flag that says one instruction should run while paused
+    step_flag: bool,
+    /// the names of the program's addresses, to label the disassembly with
+    symbols: SymbolTable,
+    /// why pokes and register changes are refused, if they are, like "while recording a movie"
+    read_only: Option<&'static str>,
+}
+
+impl WebDebugger {
+    /// Starts listening for browsers on the given address
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<WebDebugger> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(WebDebugger {
+            listener: listener,
+            paused: false,
+            step_flag: false,
+            symbols: SymbolTable::new(),
+            read_only: None,
+        })
+    }
+
+    /// The address the debugger is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Names the program's addresses, so the disassembly shows them by name
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Refuses pokes and register changes, saying why, or allows them again
+    pub fn set_read_only(&mut self, read_only: Option<&'static str>) {
+        self.read_only = read_only;
+    }
+
+    /// Pauses emulation before the next instruction
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Answers any requests waiting to be served
+    pub fn poll(&mut self, cpu: &mut Cpu) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            // a browser that stops talking to us shouldn't stop emulation
+            let _ = self.serve(stream, cpu);
+        }
+    }
+
+    /// Whether the next instruction should wait. While paused, this lets one
+    /// instruction through for every step that was asked for.
+    pub fn should_wait(&mut self) -> bool {
+        if !self.paused {
+            return false;
+        }
+
+        if self.step_flag {
+            self.step_flag = false;
+            return false;
+        }
+
+        true
+    }
+
+    /// Reads one request from the stream and writes the response
+    fn serve(&mut self, stream: TcpStream, cpu: &mut Cpu) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // skip the headers, since nothing we serve depends on them
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+
+        let words: Vec<&str> = request_line.split_whitespace().collect();
+        let (method, target) = match (words.first(), words.get(1)) {
+            (Some(m), Some(t)) => (*m, *t),
+            _ => return respond(reader.get_mut(), "400 Bad Request", "text/plain", "Bad request"),
+        };
+
+        let (path, query) = match target.find('?') {
+            Some(i) => (&target[..i], &target[i + 1..]),
+            None => (target, ""),
+        };
+
+        let stream = reader.get_mut();
+        match (method, path) {
+            ("GET", "/") => respond(stream, "200 OK", "text/html", PAGE),
+            ("GET", "/api/state") => respond(stream, "200 OK", "application/json", &self.state_json(cpu)),
+            ("GET", "/api/memory") => {
+                let addr = query_hex(query, "addr").unwrap_or(0);
+                let length = query_hex(query, "len").unwrap_or(DEFAULT_MEMORY_LENGTH);
+                respond(stream, "200 OK", "application/json", &memory_json(cpu, addr, length))
+            },
+            ("GET", "/api/disasm") => {
+                let addr = query_hex(query, "addr").unwrap_or(cpu.program_counter);
+                let count = query_hex(query, "count").unwrap_or(DEFAULT_DISASM_COUNT);
+                respond(stream, "200 OK", "application/json", &disasm_json(cpu, &self.symbols, addr, count))
+            },
+            ("POST", "/api/pause") => {
+                self.paused = true;
+                respond(stream, "200 OK", "application/json", &self.state_json(cpu))
+            },
+            ("POST", "/api/run") => {
+                self.paused = false;
+                respond(stream, "200 OK", "application/json", &self.state_json(cpu))
+            },
+            ("POST", "/api/step") => {
+                self.paused = true;
+                self.step_flag = true;
+                respond(stream, "200 OK", "application/json", &self.state_json(cpu))
+            },
+            ("POST", "/api/poke") | ("POST", "/api/set") if self.read_only.is_some() => {
+                let message = format!("Can't change the machine {}", self.read_only.unwrap_or_default());
+                respond(stream, "409 Conflict", "text/plain", &message)
+            },
+            ("POST", "/api/poke") => {
+                if !self.paused {
+                    return respond(stream, "409 Conflict", "text/plain", "Pause emulation before editing memory");
+                }
+
+                match (query_hex(query, "addr"), query_bytes(query, "bytes")) {
+                    (Some(addr), Some(bytes)) if !bytes.is_empty() && addr.checked_add(bytes.len()).is_some_and(|end| end <= cpu.memory.len()) => {
+                        cpu.memory[addr..addr + bytes.len()].copy_from_slice(&bytes);
+                        respond(stream, "200 OK", "application/json", &memory_json(cpu, addr, bytes.len()))
+                    },
+                    _ => respond(stream, "400 Bad Request", "text/plain", "Expected addr and bytes that fit in memory"),
+                }
+            },
+            ("POST", "/api/set") => {
+                if !self.paused {
+                    return respond(stream, "409 Conflict", "text/plain", "Pause emulation before setting registers");
+                }
+
+                match (query_value(query, "reg"), query_hex(query, "value")) {
+                    (Some(register), Some(value)) => match set_register(cpu, register, value) {
+                        Ok(()) => respond(stream, "200 OK", "application/json", &self.state_json(cpu)),
+                        Err(e) => respond(stream, "400 Bad Request", "text/plain", &e),
+                    },
+                    _ => respond(stream, "400 Bad Request", "text/plain", "Expected reg and value"),
+                }
+            },
+            _ => respond(stream, "404 Not Found", "text/plain", "Not found"),
+        }
+    }
+
+    /// The registers, stack and pause state as JSON
+    fn state_json(&self, cpu: &Cpu) -> String {
+        let registers: Vec<String> = cpu.data_registers.iter().map(|v| v.to_string()).collect();
+        let stack: Vec<String> = cpu.stack[..cpu.stack_pointer].iter().map(|a| a.to_string()).collect();
+
+        format!("{{\"paused\":{},\"pc\":{},\"i\":{},\"sp\":{},\"dt\":{},\"st\":{},\"v\":[{}],\"stack\":[{}]}}",
+            self.paused, cpu.program_counter, cpu.i_register, cpu.stack_pointer, cpu.delay_timer, cpu.sound_timer,
+            registers.join(","), stack.join(","))
+    }
+}
+
+/// Writes an HTTP response and closes the connection
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body)?;
+    stream.flush()
+}
+
+/// Finds a value in a query string like "addr=200&len=10"
+fn query_value<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(n), Some(v)) if n == name => Some(v),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+/// Finds a hex number in a query string
+fn query_hex(query: &str, name: &str) -> Option<usize> {
+    query_value(query, name).and_then(parse_hex)
+}
+
+/// Finds a comma-separated list of hex bytes in a query string like "bytes=A2,1E",
+/// or None if any of them isn't a byte
+fn query_bytes(query: &str, name: &str) -> Option<Vec<u8>> {
+    query_value(query, name).and_then(|list| list.split(',')
+        .map(|b| parse_hex(b).filter(|&v| v <= 0xFF).map(|v| v as u8))
+        .collect())
+}
+
+/// A range of memory as JSON, cut short at the end of memory
+fn memory_json(cpu: &Cpu, addr: usize, length: usize) -> String {
+    let start = if addr > cpu.memory.len() { cpu.memory.len() } else { addr };
+    let end = if start.saturating_add(length) > cpu.memory.len() { cpu.memory.len() } else { start + length };
+    let bytes: Vec<String> = cpu.memory[start..end].iter().map(|b| b.to_string()).collect();
+
+    format!("{{\"addr\":{},\"bytes\":[{}]}}", start, bytes.join(","))
+}
+
+/// The instructions starting at an address as JSON, with the names of any named
+/// addresses as labels
+fn disasm_json(cpu: &Cpu, symbols: &SymbolTable, addr: usize, count: usize) -> String {
+    let mut lines = Vec::new();
+    if addr >= cpu.memory.len() {
+        return "{\"lines\":[]}".to_owned();
+    }
+
+    let mut pc = addr;
+    while lines.len() < count && cpu.memory.len() - pc >= 2 {
+        let instruction = ((cpu.memory[pc] as u16) << 8) | (cpu.memory[pc + 1] as u16);
+        let disasm = symbols.disasm(instruction, cpu.config.variant);
+        let label = match symbols.name_of(pc) {
+            Some(name) => json_string(name),
+            None => "null".to_owned(),
+        };
+
+        lines.push(format!("{{\"addr\":{},\"instruction\":{},\"text\":{},\"label\":{}}}",
+            pc, instruction, json_string(&disasm), label));
+        pc += 2;
+    }
+
+    format!("{{\"lines\":[{}]}}", lines.join(","))
+}
+
+/// Quotes a string for JSON
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
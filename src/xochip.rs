@@ -0,0 +1,47 @@
+//
+// Author: Joshua Holmes
+//
+
+/// How many bytes of system memory an XO-CHIP machine has
+pub const XOCHIP_MEMORY_LENGTH: usize = 0x10000;
+/// How many bytes make up an audio pattern, at one bit per sample
+pub const PATTERN_LENGTH: usize = 16;
+/// The pitch register at power on, which plays patterns at 4000 samples per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// The extra machine state introduced by the XO-CHIP extensions. Only the audio
+/// is emulated so far: a 128-sample, one-bit pattern that the buzzer loops while
+/// the sound timer runs, at a rate set by the pitch register.
+#[derive(Serialize, Deserialize)]
+pub struct XoChip {
+    /// the pattern loaded by AUDIO, or None to use the plain buzzer until one is
+    pub pattern: Option<[u8; PATTERN_LENGTH]>,
+    /// the pitch register, set by PITCH
+    pub pitch: u8,
+    /// the flag that says whether the pattern or pitch has changed. Not saved, as
+    /// restoring a state always raises it.
+    #[serde(skip)]
+    pub sound_flag: bool,
+}
+
+impl Default for XoChip {
+    fn default() -> XoChip {
+        XoChip::new()
+    }
+}
+
+impl XoChip {
+    /// Construct the XO-CHIP state as it is at power on
+    pub fn new() -> XoChip {
+        XoChip {
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            sound_flag: false,
+        }
+    }
+
+    /// How many samples of the pattern play per second, which doubles every 48 steps of pitch
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+    }
+}
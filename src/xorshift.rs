@@ -0,0 +1,79 @@
+//
+// Author: Joshua Holmes
+//
+
+use rand::{self, Rng};
+
+/// The random number generator behind RND: the same xorshift128 generator as
+/// rand's XorShiftRng, whose state is private, but with its four words out in the
+/// open so it can be saved along with the rest of the machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Xorshift {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
+}
+
+impl Default for Xorshift {
+    fn default() -> Xorshift {
+        Xorshift::from_entropy()
+    }
+}
+
+impl Xorshift {
+    /// Construct a generator starting from the given words, which can't all be zero
+    pub fn from_words(words: [u32; 4]) -> Xorshift {
+        assert!(words != [0; 4], "a xorshift generator can't start from all zeroes");
+
+        Xorshift {
+            x: words[0],
+            y: words[1],
+            z: words[2],
+            w: words[3],
+        }
+    }
+
+    /// Construct a generator starting from somewhere random
+    pub fn from_entropy() -> Xorshift {
+        loop {
+            let words: [u32; 4] = rand::random();
+            if words != [0; 4] {
+                return Xorshift::from_words(words);
+            }
+        }
+    }
+
+    /// Construct a generator that always gives the same sequence for the same seed
+    pub fn from_seed(seed: u64) -> Xorshift {
+        // half of the state is a fixed constant, so it's never all zeroes
+        Xorshift::from_words([seed as u32, (seed >> 32) as u32, 0x9E3779B9, 0x7F4A7C15])
+    }
+}
+
+impl Rng for Xorshift {
+    fn next_u32(&mut self) -> u32 {
+        let t = self.x ^ (self.x << 11);
+        self.x = self.y;
+        self.y = self.z;
+        self.z = self.w;
+        self.w = self.w ^ (self.w >> 19) ^ (t ^ (t >> 8));
+        self.w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    #[test]
+    fn matches_the_generator_it_replaced() {
+        let mut ours = Xorshift::from_seed(0x0123_4567_89AB_CDEF);
+        let mut theirs = XorShiftRng::from_seed([0x89AB_CDEF, 0x0123_4567, 0x9E3779B9, 0x7F4A7C15]);
+
+        for _ in 0..1000 {
+            assert_eq!(ours.next_u32(), theirs.next_u32());
+        }
+    }
+}